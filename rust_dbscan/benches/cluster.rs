@@ -0,0 +1,218 @@
+//! K-D tree construction, range queries and DBSCAN on clustered data
+//!
+//! Run with `cargo bench --bench cluster`. `BENCH_POINTS` sets the size of
+//! the data set (default 100k points).
+//!
+//! `in_range_vs_region_query` runs the query of one point against a K-D tree
+//! (built outside the timed loop) and against the brute force
+//! [`region_query`] for growing point counts. A tree query took ~1-1.5 µs at
+//! every size (~2-2.5 µs before the tree prepared its points for the fast
+//! metric, which also cut `db_scan` of 100k points from ~600 to ~280 ms),
+//! brute force 0.5 µs for 64 points, 1.3 µs for 256 and 4 µs for 1024: the
+//! tree wins from roughly 256 points on, which is where `--index auto` stops
+//! using brute force.
+//!
+//! `density_test` runs the core point test of every point of
+//! [`DENSITY_POINTS`] clustered (dense) and uniformly spread (sparse, nearly
+//! all noise) points, once with `has_in_range`, which stops at `min_points`
+//! neighbours, and once comparing `count_in_range` with `min_points`. On
+//! dense points stopping early took ~2 ms against ~9 ms for counting every
+//! neighbour, on sparse points, where the count never reaches `min_points`,
+//! both took ~2.5 ms.
+//!
+//! `sparse_count` runs a K-D tree query of every point of [`DENSITY_POINTS`]
+//! sparse points, collecting the neighbours and taking the length of the
+//! `Vec` as the density test used to, and counting them with
+//! `count_in_range`: ~2.6 ms against ~2.3 ms, the allocation of every lone
+//! point's neighbour (itself) is all that's saved.
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+
+use rust_dbscan::cluster::{
+    DEGREE_RAD, DistanceMetric, EARTH_R, IndexKind, Point, PointList, SpatialIndex, db_scan,
+    new_kd_tree, region_query,
+};
+
+/// Clustering radius of the benchmarks in kilometers
+const EPS_KM: f64 = 0.1;
+
+/// DBSCAN density threshold of the benchmarks
+const MIN_POINTS: usize = 3;
+
+/// Clusters per group and points per cluster of [`clustered_points`]
+const GROUP_CLUSTERS: usize = 20;
+const CLUSTER_POINTS: usize = 50;
+
+/// Point counts of the crossover benchmark
+const CROSSOVER_SIZES: [usize; 5] = [64, 256, 1024, 4096, 16384];
+
+/// Point count of the density test benchmark
+const DENSITY_POINTS: usize = 10_000;
+
+/// Returns the `BENCH_POINTS` environment variable, 100k by default
+fn bench_points() -> usize {
+    std::env::var("BENCH_POINTS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(100_000)
+}
+
+/// Generates `n` points as a cluster of clusters: groups spread over a few
+/// degrees, each made of [`GROUP_CLUSTERS`] clusters within ~5 km, each of
+/// [`CLUSTER_POINTS`] points within ~50 m
+///
+/// Coordinates come from a fixed xorshift sequence, so runs are comparable.
+fn clustered_points(n: usize) -> PointList {
+    let mut next = xorshift();
+
+    let mut points = PointList::with_capacity(n);
+    while points.len() < n {
+        let group = Point([-74.0 + 4.0 * next(), 40.7 + 3.0 * next()]);
+        for _ in 0..GROUP_CLUSTERS {
+            let cluster = Point([group.0[0] + 0.1 * next(), group.0[1] + 0.08 * next()]);
+            for _ in 0..CLUSTER_POINTS.min(n - points.len()) {
+                points.push(Point([
+                    cluster.0[0] + 0.001 * next(),
+                    cluster.0[1] + 0.0008 * next(),
+                ]));
+            }
+        }
+    }
+    points
+}
+
+/// Generates `n` points spread uniformly over the area of [`clustered_points`],
+/// hardly any of them within eps of another
+fn sparse_points(n: usize) -> PointList {
+    let mut next = xorshift();
+    (0..n)
+        .map(|_| Point([-74.0 + 4.0 * next(), 40.7 + 3.0 * next()]))
+        .collect()
+}
+
+/// Returns a fixed xorshift sequence of numbers in `[-0.5, 0.5)`
+fn xorshift() -> impl FnMut() -> f64 {
+    let mut state = 0x9E37_79B9_7F4A_7C15u64;
+    move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state >> 11) as f64 / (1u64 << 53) as f64 - 0.5
+    }
+}
+
+fn bench_kd_tree(c: &mut Criterion) {
+    let points = clustered_points(bench_points());
+    let eps = EPS_KM / EARTH_R / DEGREE_RAD;
+
+    let mut group = c.benchmark_group(format!("kd_tree_{}_points", points.len()));
+    group.sample_size(10);
+    group.bench_function("new_kd_tree", |b| {
+        b.iter(|| new_kd_tree(black_box(points.clone())))
+    });
+
+    let tree = new_kd_tree(points.clone());
+    let mut i = 0;
+    group.bench_function("in_range", |b| {
+        b.iter(|| {
+            i = (i + 7919) % points.len();
+            tree.in_range(black_box(&points[i]), eps, Vec::new())
+        })
+    });
+    group.finish();
+}
+
+fn bench_db_scan(c: &mut Criterion) {
+    let points = clustered_points(bench_points());
+
+    let mut group = c.benchmark_group(format!("db_scan_{}_points", points.len()));
+    group.sample_size(10);
+    group.bench_function("db_scan", |b| {
+        b.iter(|| db_scan(black_box(&points), EPS_KM, MIN_POINTS))
+    });
+    group.finish();
+}
+
+fn bench_crossover(c: &mut Criterion) {
+    let eps = EPS_KM / EARTH_R / DEGREE_RAD;
+
+    let mut group = c.benchmark_group("in_range_vs_region_query");
+    for n in CROSSOVER_SIZES {
+        let points = clustered_points(n);
+        let tree = new_kd_tree(points.clone());
+        let query = points[n / 2];
+        group.bench_with_input(BenchmarkId::new("kd_tree", n), &query, |b, query| {
+            b.iter(|| tree.in_range(black_box(query), eps, Vec::new()))
+        });
+        group.bench_with_input(BenchmarkId::new("region_query", n), &query, |b, query| {
+            b.iter(|| region_query(&points, black_box(query), eps))
+        });
+    }
+    group.finish();
+}
+
+fn bench_density_test(c: &mut Criterion) {
+    let eps = EPS_KM / EARTH_R / DEGREE_RAD;
+
+    let mut group = c.benchmark_group("density_test");
+    for (name, points) in [
+        ("dense", clustered_points(DENSITY_POINTS)),
+        ("sparse", sparse_points(DENSITY_POINTS)),
+    ] {
+        let metric = DistanceMetric::default();
+        let index = SpatialIndex::build(IndexKind::KdTree, points.clone(), eps, metric);
+        group.bench_function(BenchmarkId::new("has_in_range", name), |b| {
+            b.iter(|| {
+                points
+                    .iter()
+                    .filter(|p| index.has_in_range(black_box(p), eps, MIN_POINTS))
+                    .count()
+            })
+        });
+        group.bench_function(BenchmarkId::new("count_in_range", name), |b| {
+            b.iter(|| {
+                points
+                    .iter()
+                    .filter(|p| index.count_in_range(black_box(p), eps) >= MIN_POINTS)
+                    .count()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_sparse_count(c: &mut Criterion) {
+    let points = sparse_points(DENSITY_POINTS);
+    let eps = EPS_KM / EARTH_R / DEGREE_RAD;
+    let tree = new_kd_tree(points.clone());
+
+    let mut group = c.benchmark_group("sparse_count");
+    group.bench_function("in_range_len", |b| {
+        b.iter(|| {
+            points
+                .iter()
+                .map(|p| tree.in_range(black_box(p), eps, Vec::new()).len())
+                .sum::<usize>()
+        })
+    });
+    group.bench_function("count_in_range", |b| {
+        b.iter(|| {
+            points
+                .iter()
+                .map(|p| tree.count_in_range(black_box(p), eps))
+                .sum::<usize>()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_kd_tree,
+    bench_db_scan,
+    bench_crossover,
+    bench_density_test,
+    bench_sparse_count
+);
+criterion_main!(benches);
@@ -0,0 +1,72 @@
+//! Deduplication of kept points with many duplicate coordinates
+//!
+//! Run with `cargo bench --bench filter`.
+//!
+//! `dedup` runs [`filter_points`] on noise points where every location is
+//! repeated [`COPIES`] times, against the linear search over the kept points
+//! it used before tracking them in a `HashSet`. The set took ~24 µs for 1k
+//! points and ~0.25 ms for 10k, the linear search ~60 µs and ~6 ms: it grows
+//! with the square of the number of distinct points.
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+
+use rust_dbscan::cluster::{Point, PointList};
+use rust_dbscan::filter::{DBSCAN_OUTLIER_INDEX, Keep, filter_points};
+
+/// Point counts of the benchmark
+const SIZES: [usize; 2] = [1_000, 10_000];
+
+/// Number of points at each location
+const COPIES: usize = 10;
+
+/// Generates `n` points at `n / COPIES` locations on a grid, the copies of a
+/// location interleaved with the other locations
+fn duplicate_points(n: usize) -> PointList {
+    let locations = (n / COPIES).max(1);
+    (0..n)
+        .map(|i| {
+            let k = i % locations;
+            Point([
+                -74.0 + (k % 100) as f64 * 0.001,
+                40.7 + (k / 100) as f64 * 0.001,
+            ])
+        })
+        .collect()
+}
+
+/// Keeps the first point of every location, searching the kept points linearly
+fn dedup_linear(points: &[Point]) -> Vec<usize> {
+    let mut filtered = Vec::new();
+    let mut added: Vec<Point> = Vec::new();
+    for (idx, point) in points.iter().enumerate() {
+        if !added.contains(point) {
+            filtered.push(idx);
+            added.push(*point);
+        }
+    }
+    filtered
+}
+
+fn bench_dedup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dedup");
+    for n in SIZES {
+        let points = duplicate_points(n);
+        let labels = vec![DBSCAN_OUTLIER_INDEX; n];
+        assert_eq!(
+            filter_points(&points, &labels, Keep::Noise),
+            dedup_linear(&points)
+        );
+
+        group.bench_with_input(BenchmarkId::new("hash_set", n), &points, |b, points| {
+            b.iter(|| filter_points(black_box(points), &labels, Keep::Noise))
+        });
+        group.bench_with_input(BenchmarkId::new("linear", n), &points, |b, points| {
+            b.iter(|| dedup_linear(black_box(points)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_dedup);
+criterion_main!(benches);
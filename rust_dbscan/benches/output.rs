@@ -0,0 +1,36 @@
+//! Serial vs parallel JSON Lines formatting of 1M rows
+//!
+//! Run with `cargo bench --features rayon`.
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+use std::io;
+
+use rust_dbscan::output;
+
+const ROWS: usize = 1_000_000;
+
+fn bench_jsonl(c: &mut Criterion) {
+    let rows: Vec<(f64, f64, i32)> = (0..ROWS)
+        .map(|i| {
+            let t = i as f64 / ROWS as f64;
+            (40.0 + t, -74.0 - t, (i % 1000) as i32 - 1)
+        })
+        .collect();
+    let labels = output::LabelFormat::default();
+    let format =
+        |&(lat, lon, cluster): &(f64, f64, i32)| output::jsonl_row(lat, lon, cluster, &labels);
+
+    let mut group = c.benchmark_group("jsonl_1m_rows");
+    group.sample_size(10);
+    group.bench_function("serial", |b| {
+        b.iter(|| output::write_rows_serial(&mut io::sink(), black_box(&rows), format))
+    });
+    group.bench_function("parallel", |b| {
+        b.iter(|| output::write_rows_parallel(&mut io::sink(), black_box(&rows), format))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_jsonl);
+criterion_main!(benches);
@@ -0,0 +1,134 @@
+//! Self-timing benchmark over synthetic data sets of increasing size
+//!
+//! Data sets are made of round clusters generated with [`Point::destination`]
+//! plus uniform noise, so that the timings reflect realistic clustered input.
+
+use crate::cluster::{DbscanConfig, IndexKind, Point, PointList, db_scan_with_strategy};
+use crate::filter::{Keep, build_labels, filter_points};
+use std::time::{Duration, Instant};
+
+/// Data set sizes of the benchmark
+pub const BENCHMARK_SIZES: [usize; 4] = [1_000, 10_000, 100_000, 1_000_000];
+
+/// Number of points per synthetic cluster
+pub const CLUSTER_SIZE: usize = 100;
+
+/// Fraction of synthetic points that are uniform noise
+pub const NOISE_FRACTION: f64 = 0.1;
+
+/// Synthetic data is spread over a square of this side per 1000 points, so
+/// density stays the same for all sizes
+const AREA_SIDE_KM_PER_1000: f64 = 10.0;
+
+/// Timings of one benchmark run
+#[derive(Debug, Clone, Copy)]
+pub struct BenchmarkRow {
+    /// Number of points
+    pub points: usize,
+    /// Index used for neighbourhood queries
+    pub index: IndexKind,
+    /// Time to build the spatial index
+    pub build: Duration,
+    /// Time to cluster and filter the points
+    pub cluster: Duration,
+}
+
+impl BenchmarkRow {
+    /// Points processed per second by the whole pipeline
+    pub fn throughput(&self) -> f64 {
+        self.points as f64 / (self.build + self.cluster).as_secs_f64()
+    }
+}
+
+/// Generates `n` points in clusters of [`CLUSTER_SIZE`] points within
+/// `spread_km` of their centre, plus [`NOISE_FRACTION`] of uniform noise
+///
+/// The same `seed` always gives the same points.
+pub fn synthetic_points(n: usize, spread_km: f64, seed: u64) -> PointList {
+    let mut rng = XorShift(seed.max(1));
+    let origin = Point([-74.0, 40.7]);
+    let side_km = AREA_SIDE_KM_PER_1000 * (n as f64 / 1000.0).sqrt();
+    let random_point = |center: &Point, radius_km: f64, rng: &mut XorShift| {
+        center.destination(rng.next_f64() * 360.0, rng.next_f64().sqrt() * radius_km)
+    };
+
+    let num_noise = (n as f64 * NOISE_FRACTION) as usize;
+    let mut points = PointList::with_capacity(n);
+    while points.len() < n - num_noise {
+        let center = random_point(&origin, side_km, &mut rng);
+        for _ in 0..CLUSTER_SIZE.min(n - num_noise - points.len()) {
+            points.push(random_point(&center, spread_km, &mut rng));
+        }
+    }
+    while points.len() < n {
+        points.push(random_point(&origin, side_km, &mut rng));
+    }
+
+    points
+}
+
+/// Runs the clustering pipeline on a data set, timing index build and clustering
+pub fn run_benchmark(points: &PointList, config: &DbscanConfig) -> BenchmarkRow {
+    let config = config.index(config.index.resolve(points));
+
+    let started = Instant::now();
+    let neighbors_of = config.neighbors_fn(points);
+    let build = started.elapsed();
+
+    let started = Instant::now();
+    let min_points = config.core_min_points();
+    let (clusters, _) = db_scan_with_strategy(points.len(), neighbors_of, |neighbors| {
+        neighbors.len() >= min_points
+    });
+    let labels = build_labels(&clusters, points.len());
+    filter_points(points, &labels, Keep::First);
+    let cluster = started.elapsed();
+
+    BenchmarkRow {
+        points: points.len(),
+        index: config.index,
+        build,
+        cluster,
+    }
+}
+
+/// Benchmarks all [`BENCHMARK_SIZES`] and prints a table of the timings
+pub fn print_benchmark(config: &DbscanConfig) {
+    println!(
+        "Benchmark: eps {} km, min_points {}, index {}",
+        config.eps, config.min_points, config.index
+    );
+    println!(
+        "{:>10} {:>10} {:>12} {:>12} {:>14}",
+        "points", "index", "build ms", "cluster ms", "points/sec"
+    );
+
+    // Clusters twice as wide as eps, so that they are dense but not single points
+    let spread_km = (config.eps * 2.0).max(0.01);
+    for (i, &n) in BENCHMARK_SIZES.iter().enumerate() {
+        let points = synthetic_points(n, spread_km, i as u64 + 1);
+        let row = run_benchmark(&points, config);
+        println!(
+            "{:>10} {:>10} {:>12.2} {:>12.2} {:>14.0}",
+            row.points,
+            row.index.to_string(),
+            row.build.as_secs_f64() * 1000.0,
+            row.cluster.as_secs_f64() * 1000.0,
+            row.throughput()
+        );
+    }
+}
+
+/// Minimal xorshift64* generator, good enough for synthetic data
+struct XorShift(u64);
+
+impl XorShift {
+    /// Returns a uniform number in `[0, 1)`
+    fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        let bits = self.0.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11;
+        bits as f64 / (1u64 << 53) as f64
+    }
+}
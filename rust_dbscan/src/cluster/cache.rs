@@ -0,0 +1,88 @@
+//! Precomputed eps-neighbourhoods for repeated clustering of the same points
+
+use super::dbscan::{DbscanConfig, db_scan_with_strategy};
+use super::distance::DistanceMetric;
+use super::point::{Cluster, PointList};
+
+/// Most neighbour entries (summed over all points) a [`NeighborCache`] holds,
+/// ~800 MB of indices
+pub const MAX_CACHED_NEIGHBORS: usize = 100_000_000;
+
+/// Eps-neighbourhood of every point, computed once
+///
+/// Memory is O(total neighbour pairs): every point stores the indices of all
+/// points within eps, including itself. With an eps close to the extent of
+/// the data that is O(n^2), so [`NeighborCache::build`] gives up beyond
+/// [`MAX_CACHED_NEIGHBORS`] entries.
+#[derive(Debug, Clone)]
+pub struct NeighborCache {
+    /// `neighbors[offsets[i]..offsets[i + 1]]` are the neighbours of point `i`
+    offsets: Vec<usize>,
+    neighbors: Vec<usize>,
+}
+
+impl NeighborCache {
+    /// Computes the neighbourhoods of all points within `eps_km` with the given metric
+    ///
+    /// Returns an error when the neighbourhoods would hold more than
+    /// [`MAX_CACHED_NEIGHBORS`] entries, which usually means eps is too large.
+    pub fn build(
+        points: &PointList,
+        eps_km: f64,
+        metric: DistanceMetric,
+    ) -> Result<NeighborCache, String> {
+        let neighbors_of = DbscanConfig::new(eps_km, 1)
+            .metric(metric)
+            .neighbors_fn(points);
+
+        let mut offsets = Vec::with_capacity(points.len() + 1);
+        let mut neighbors = Vec::new();
+        offsets.push(0);
+        for i in 0..points.len() {
+            neighbors.extend(neighbors_of(i));
+            if neighbors.len() > MAX_CACHED_NEIGHBORS {
+                return Err(format!(
+                    "more than {} neighbours after {} of {} points, eps {} km is too large to cache",
+                    MAX_CACHED_NEIGHBORS,
+                    i + 1,
+                    points.len(),
+                    eps_km
+                ));
+            }
+            offsets.push(neighbors.len());
+        }
+
+        Ok(NeighborCache { offsets, neighbors })
+    }
+
+    /// Returns the number of points
+    pub fn len(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    /// Checks if the cache holds no points
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the neighbours of point `i`, including itself
+    pub fn neighbors(&self, i: usize) -> &[usize] {
+        &self.neighbors[self.offsets[i]..self.offsets[i + 1]]
+    }
+}
+
+/// Clusters points from their cached neighbourhoods
+///
+/// Gives the same result as [`db_scan`](super::dbscan::db_scan) with the
+/// cache's eps, without querying a spatial index.
+///
+/// # Returns
+///
+/// A tuple `(clusters, noise)`, see [`db_scan`](super::dbscan::db_scan)
+pub fn db_scan_cached(cache: &NeighborCache, min_points: usize) -> (Vec<Cluster>, Vec<usize>) {
+    db_scan_with_strategy(
+        cache.len(),
+        |i| cache.neighbors(i).to_vec(),
+        |neighbors| neighbors.len() >= min_points,
+    )
+}
@@ -0,0 +1,31 @@
+#[cfg(test)]
+mod tests {
+    use crate::cluster::{DistanceMetric, NeighborCache, Point, db_scan, db_scan_cached};
+
+    #[test]
+    fn test_db_scan_cached() {
+        let points = vec![
+            Point([30.244759, 59.955982]),
+            Point([30.24472, 59.955975]),
+            Point([30.244358, 59.96698]),
+            Point([30.258387, 59.951557]),
+            Point([30.434124, 60.029499]),
+            Point([30.244759, 59.955982]),
+            Point([30.2448, 59.9561]),
+        ];
+
+        let cache = NeighborCache::build(&points, 0.8, DistanceMetric::SphericalFast).unwrap();
+        assert_eq!(cache.len(), points.len());
+        assert!(cache.neighbors(0).contains(&0));
+
+        for min_points in 1..=6 {
+            let (clusters, noise) = db_scan(&points, 0.8, min_points);
+            let (cached, cached_noise) = db_scan_cached(&cache, min_points);
+            assert_eq!(cached_noise, noise, "min_points {}", min_points);
+            assert_eq!(cached.len(), clusters.len(), "min_points {}", min_points);
+            for (a, b) in cached.iter().zip(&clusters) {
+                assert_eq!(a.points, b.points, "min_points {}", min_points);
+            }
+        }
+    }
+}
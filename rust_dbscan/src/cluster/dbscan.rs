@@ -1,7 +1,9 @@
-use super::distance::{DEGREE_RAD, EARTH_R};
-use super::kdtree::new_kd_tree;
+use super::distance::{DistanceMetric, EARTH_R};
+use super::index::{IndexKind, SpatialIndex};
+use super::kdtree::new_kd_tree_with_metric;
 use super::point::{Cluster, Point, PointList};
 use bitvec::prelude::*;
+use std::time::Instant;
 
 // DBSCAN algorithm pseudocode (from <http://en.wikipedia.org/wiki/DBSCAN>):
 //
@@ -30,12 +32,762 @@ use bitvec::prelude::*;
 // regionQuery(P, eps)
 //    return all points within P's eps-neighborhood (including P)
 
+/// Query radius used for `eps == 0.0`: its square is the smallest positive
+/// `f64`, so only points at exactly the same coordinates are neighbours
+pub(crate) const COINCIDENT_EPS: f64 = 1.4916681462400413e-154;
+
+/// Rule assigning cluster ids independently of the order clusters are found in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StableBy {
+    /// Clusters are numbered in ascending order of their smallest point index
+    MinIndex,
+    /// Clusters are numbered in ascending order of centroid longitude, then
+    /// latitude, so ids don't depend on the order of the input either
+    Centroid,
+}
+
+/// Order in which DBSCAN picks unvisited points to start clusters from
+///
+/// Cluster ids and the cluster of a border point within eps of several
+/// clusters depend on that order. Orders other than [`SeedOrder::Input`]
+/// only depend on coordinates, so they don't change when the input is
+/// reordered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SeedOrder {
+    /// Order of the points
+    #[default]
+    Input,
+    /// Ascending latitude, then longitude
+    Lat,
+    /// Ascending longitude, then latitude
+    Lon,
+    /// Z-order curve over longitude and latitude, which keeps points close
+    /// to each other close in the order too
+    Morton,
+}
+
+impl SeedOrder {
+    /// Returns the indices of `points` in visiting order
+    ///
+    /// Points with the same coordinates keep their order.
+    pub fn visit_order(self, points: &PointList) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..points.len()).collect();
+        let by_coords = |first: usize| {
+            move |&a: &usize, &b: &usize| {
+                let (pa, pb) = (&points[a].0, &points[b].0);
+                pa[first]
+                    .total_cmp(&pb[first])
+                    .then(pa[1 - first].total_cmp(&pb[1 - first]))
+            }
+        };
+        match self {
+            SeedOrder::Input => {}
+            SeedOrder::Lat => order.sort_by(by_coords(1)),
+            SeedOrder::Lon => order.sort_by(by_coords(0)),
+            SeedOrder::Morton => order.sort_by_cached_key(|&i| morton_key(&points[i])),
+        }
+        order
+    }
+}
+
+/// Returns the position of a point on the Z-order curve
+///
+/// Longitude and latitude are quantized to 32 bits over their full range
+/// and interleaved, longitude in the even bits.
+fn morton_key(p: &Point) -> u64 {
+    fn quantize(v: f64, max: f64) -> u64 {
+        // NaN becomes 0
+        (((v + max) / (2.0 * max)).clamp(0.0, 1.0) * f64::from(u32::MAX)) as u64
+    }
+    fn spread(mut v: u64) -> u64 {
+        v = (v | (v << 16)) & 0x0000_FFFF_0000_FFFF;
+        v = (v | (v << 8)) & 0x00FF_00FF_00FF_00FF;
+        v = (v | (v << 4)) & 0x0F0F_0F0F_0F0F_0F0F;
+        v = (v | (v << 2)) & 0x3333_3333_3333_3333;
+        (v | (v << 1)) & 0x5555_5555_5555_5555
+    }
+    spread(quantize(p.0[0], 180.0)) | (spread(quantize(p.0[1], 90.0)) << 1)
+}
+
+/// Role of a point in a DBSCAN result
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointType {
+    /// Has at least `min_points` points (itself included) within eps, see
+    /// [`DbscanConfig::core_min_points`]
+    Core,
+    /// Not a core point, but within eps of a core point of its cluster
+    Border,
+    /// In no cluster
+    Noise,
+}
+
+/// Noise point of [`DbscanConfig::run_with_diagnostics`] with the size of its neighbourhood
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoiseInfo {
+    /// Index of the point
+    pub index: usize,
+    /// Number of points within eps, the point itself included, which fell
+    /// short of `min_points`
+    pub neighbor_count: usize,
+}
+
+/// How per-point accuracy changes the distance at which two points are neighbours
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AccuracyMode {
+    /// Neighbours when closer than `eps + acc_i + acc_j`: their uncertainty
+    /// circles may be within eps
+    #[default]
+    Liberal,
+    /// Neighbours when closer than `eps - (acc_i + acc_j)`: they are within
+    /// eps wherever they really are in their uncertainty circles
+    Conservative,
+}
+
+/// DBSCAN parameters
+///
+/// Built with [`DbscanConfig::new`] (or [`Default`]) and the builder methods,
+/// then applied to points with [`DbscanConfig::run`]. `'w` is the lifetime of
+/// the borrowed [`DbscanConfig::weights`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DbscanConfig<'w> {
+    /// Clustering radius in kilometers (coordinate units for
+    /// [planar](DistanceMetric::is_planar) metrics), `0.0` clusters only points
+    /// with exactly the same coordinates
+    pub eps: f64,
+    /// Minimum number of points in eps-neighbourhood (density threshold),
+    /// the point itself included unless [`DbscanConfig::min_points_excludes_self`]
+    pub min_points: usize,
+    /// Whether the point itself doesn't count towards `min_points`, see
+    /// [`DbscanConfig::min_points_excludes_self`]
+    pub min_points_excludes_self: bool,
+    /// Spatial index used for neighbourhood queries
+    pub index: IndexKind,
+    /// Distance metric
+    pub metric: DistanceMetric,
+    /// Radius of the sphere points lie on in kilometers, [`EARTH_R`] by default
+    pub earth_radius: f64,
+    /// Time after which no new clusters are started, see [`DbscanConfig::deadline`]
+    pub deadline: Option<Instant>,
+    /// Cluster id assignment, `None` numbers clusters in the order they are found
+    pub stable_ids: Option<StableBy>,
+    /// Order unvisited points are picked in, see [`DbscanConfig::seed_order`]
+    pub seed_order: SeedOrder,
+    /// Number of observations every point stands for, see [`DbscanConfig::weights`]
+    pub weights: Option<&'w [f64]>,
+}
+
+impl Default for DbscanConfig<'_> {
+    /// Same as `DbscanConfig::new(0.1, 3)`, the command line defaults
+    fn default() -> Self {
+        DbscanConfig::new(0.1, 3)
+    }
+}
+
+impl<'w> DbscanConfig<'w> {
+    /// Creates a config with the K-D tree index and the fast spherical metric
+    pub fn new(eps: f64, min_points: usize) -> Self {
+        DbscanConfig {
+            eps,
+            min_points,
+            min_points_excludes_self: false,
+            index: IndexKind::KdTree,
+            metric: DistanceMetric::SphericalFast,
+            earth_radius: EARTH_R,
+            deadline: None,
+            stable_ids: None,
+            seed_order: SeedOrder::Input,
+            weights: None,
+        }
+    }
+
+    /// Sets the eps-neighbourhood radius
+    pub fn eps(mut self, eps: f64) -> Self {
+        self.eps = eps;
+        self
+    }
+
+    /// Sets the density threshold
+    pub fn min_points(mut self, min_points: usize) -> Self {
+        self.min_points = min_points;
+        self
+    }
+
+    /// Sets whether the point itself doesn't count towards `min_points`
+    ///
+    /// By default a point is a core point when its eps-neighbourhood, which
+    /// includes the point, holds at least `min_points` points. Definitions
+    /// of DBSCAN that count only the other points need one more point:
+    /// setting this subtracts the point itself from the count, so
+    /// `min_points` of 3 then asks for 3 neighbours besides the point. With
+    /// [`DbscanConfig::weights`] one observation of the point is subtracted.
+    pub fn min_points_excludes_self(mut self, excludes: bool) -> Self {
+        self.min_points_excludes_self = excludes;
+        self
+    }
+
+    /// Returns the number of points, the point itself included, a core
+    /// point's eps-neighbourhood holds at least
+    pub fn core_min_points(&self) -> usize {
+        self.min_points + usize::from(self.min_points_excludes_self)
+    }
+
+    /// Sets the number of observations every point stands for
+    ///
+    /// `weights[i]` belongs to `points[i]` of the points the config is run
+    /// on. A point is then a core point when the weights in its
+    /// eps-neighbourhood (itself included) sum up to at least `min_points`,
+    /// see [`DbscanConfig::run_weighted`].
+    pub fn weights<'a>(self, weights: &'a [f64]) -> DbscanConfig<'a> {
+        DbscanConfig {
+            weights: Some(weights),
+            ..self.unweighted()
+        }
+    }
+
+    /// Returns the config without weights
+    pub(crate) fn unweighted(&self) -> DbscanConfig<'static> {
+        DbscanConfig {
+            eps: self.eps,
+            min_points: self.min_points,
+            min_points_excludes_self: self.min_points_excludes_self,
+            index: self.index,
+            metric: self.metric,
+            earth_radius: self.earth_radius,
+            deadline: self.deadline,
+            stable_ids: self.stable_ids,
+            seed_order: self.seed_order,
+            weights: None,
+        }
+    }
+
+    /// Sets the spatial index
+    pub fn index(mut self, index: IndexKind) -> Self {
+        self.index = index;
+        self
+    }
+
+    /// Sets the distance metric
+    pub fn metric(mut self, metric: DistanceMetric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    /// Sets the radius in kilometers of the sphere points lie on
+    ///
+    /// `eps` stays in kilometers on that sphere, e.g. pass `3389.5` to cluster
+    /// positions on Mars.
+    pub fn earth_radius(mut self, km: f64) -> Self {
+        self.earth_radius = km;
+        self
+    }
+
+    /// Sets the time after which clustering stops starting new clusters
+    ///
+    /// Once the deadline passes, the cluster being expanded is completed and
+    /// all points not processed yet are returned as noise, so the result is
+    /// incomplete but every point is still either in a cluster or in noise.
+    /// Use [`DbscanConfig::run_timed`] to find out whether that happened.
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Sets the cluster id assignment rule
+    ///
+    /// With [`StableBy::MinIndex`] ids are a function of cluster membership
+    /// rather than of the order points are visited in, with
+    /// [`StableBy::Centroid`] of cluster positions. Clusters passed to
+    /// [`DbscanConfig::run_with_callback`] keep their visitation order ids.
+    pub fn stable_ids(mut self, by: StableBy) -> Self {
+        self.stable_ids = Some(by);
+        self
+    }
+
+    /// Sets the order in which unvisited points start clusters
+    ///
+    /// Together with [`DbscanConfig::stable_ids`] this makes results
+    /// independent of the input order: ids are numbered by position, and
+    /// border points shared by clusters go to the cluster whose seed comes
+    /// first in a coordinate order. Noise is returned sorted by index either way.
+    pub fn seed_order(mut self, order: SeedOrder) -> Self {
+        self.seed_order = order;
+        self
+    }
+
+    /// Checks that the parameters make sense
+    ///
+    /// `min_points` of 0 would make every point a core point, a negative, NaN
+    /// or infinite eps has no neighbourhood. `eps` of `0.0` is valid, it
+    /// clusters points with the same coordinates.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.min_points == 0 {
+            return Err(
+                "min_points must be at least 1 (a point counts towards its own neighbourhood)"
+                    .to_string(),
+            );
+        }
+        if !self.eps.is_finite() || self.eps < 0.0 {
+            return Err(format!(
+                "eps must be a finite distance >= 0 (0 clusters only identical coordinates), got {}",
+                self.eps
+            ));
+        }
+        if !self.earth_radius.is_finite() || self.earth_radius <= 0.0 {
+            return Err(format!(
+                "earth radius must be a finite distance > 0, got {}",
+                self.earth_radius
+            ));
+        }
+        Ok(())
+    }
+
+    /// Clusters incoming points after [`DbscanConfig::validate`]
+    ///
+    /// # Returns
+    ///
+    /// A tuple `(clusters, noise)`, see [`db_scan`], or why the parameters
+    /// are invalid
+    pub fn try_run(&self, points: &PointList) -> Result<(Vec<Cluster>, Vec<usize>), String> {
+        self.validate()?;
+        Ok(self.run(points))
+    }
+
+    /// Clusters incoming points
+    ///
+    /// # Returns
+    ///
+    /// A tuple `(clusters, noise)`, see [`db_scan`]
+    pub fn run(&self, points: &PointList) -> (Vec<Cluster>, Vec<usize>) {
+        let (clusters, noise, _) = self.run_timed(points);
+        (clusters, noise)
+    }
+
+    /// Clusters incoming points, reporting whether the deadline was hit
+    ///
+    /// # Returns
+    ///
+    /// A tuple `(clusters, noise, timed_out)`, when `timed_out` is true the
+    /// clusters are partial and `noise` includes all unprocessed points
+    pub fn run_timed(&self, points: &PointList) -> (Vec<Cluster>, Vec<usize>, bool) {
+        match self.weights {
+            Some(weights) => self.run_weighted(points, Some(weights)),
+            None => self.collect(points, self.core_neighbors_fn(points), None),
+        }
+    }
+
+    /// Clusters points that each stand for `weights[i]` observations
+    ///
+    /// A point is a core point when the weights in its eps-neighbourhood
+    /// (itself included) sum up to at least `min_points`. With `None` every
+    /// point weighs 1, [`DbscanConfig::weights`] are not used.
+    ///
+    /// # Returns
+    ///
+    /// A tuple `(clusters, noise, timed_out)`, see [`DbscanConfig::run_timed`]
+    pub fn run_weighted(
+        &self,
+        points: &PointList,
+        weights: Option<&[f64]>,
+    ) -> (Vec<Cluster>, Vec<usize>, bool) {
+        let neighbors_of = self.neighbors_fn(points);
+        self.collect(points, |i, _| neighbors_of(i), weights)
+    }
+
+    /// Clusters points with a per-point position uncertainty
+    ///
+    /// `accuracy[i]` is the uncertainty radius of `points[i]` in kilometers,
+    /// `mode` decides whether it widens or narrows the neighbour distance.
+    /// With all accuracies `0.0` this is the same as [`DbscanConfig::run_timed`].
+    ///
+    /// # Returns
+    ///
+    /// A tuple `(clusters, noise, timed_out)`, see [`DbscanConfig::run_timed`]
+    pub fn run_with_accuracy(
+        &self,
+        points: &PointList,
+        accuracy: &[f64],
+        mode: AccuracyMode,
+    ) -> (Vec<Cluster>, Vec<usize>, bool) {
+        let neighbors_of = self.accuracy_neighbors_fn(points, accuracy, mode);
+        self.collect(points, |i, _| neighbors_of(i), None)
+    }
+
+    /// Clusters incoming points, reporting progress to `on_progress`
+    ///
+    /// `on_progress(visited, total)` is called every time another 1% of the
+    /// points has been visited, and once all of them have. [`DbscanConfig::weights`]
+    /// are used when set.
+    ///
+    /// # Returns
+    ///
+    /// A tuple `(clusters, noise, timed_out)`, see [`DbscanConfig::run_timed`]
+    pub fn run_with_progress(
+        &self,
+        points: &PointList,
+        on_progress: impl FnMut(usize, usize),
+    ) -> (Vec<Cluster>, Vec<usize>, bool) {
+        match self.weights {
+            Some(weights) => {
+                let neighbors_of = self.neighbors_fn(points);
+                self.collect(
+                    points,
+                    with_progress(points.len(), |i, _| neighbors_of(i), on_progress),
+                    Some(weights),
+                )
+            }
+            None => self.collect(
+                points,
+                with_progress(points.len(), self.core_neighbors_fn(points), on_progress),
+                None,
+            ),
+        }
+    }
+
+    /// Clusters timestamped points, only points close in time are neighbours
+    ///
+    /// `times[i]` is the time of `points[i]` in seconds, see
+    /// [`DbscanConfig::time_neighbors_fn`] for `time_eps`. [`DbscanConfig::weights`]
+    /// are used when set.
+    ///
+    /// # Returns
+    ///
+    /// A tuple `(clusters, noise, timed_out)`, see [`DbscanConfig::run_timed`]
+    pub fn run_with_times(
+        &self,
+        points: &PointList,
+        times: &[i64],
+        time_eps: u64,
+    ) -> (Vec<Cluster>, Vec<usize>, bool) {
+        let neighbors_of = self.time_neighbors_fn(points, times, time_eps);
+        self.collect(points, |i, _| neighbors_of(i), self.weights)
+    }
+
+    /// Clusters incoming points, reporting each cluster to `on_cluster` as
+    /// soon as it is completed
+    ///
+    /// # Returns
+    ///
+    /// A vector of point indices that are outliers (not in any cluster)
+    pub fn run_with_callback(
+        &self,
+        points: &PointList,
+        mut on_cluster: impl FnMut(&Cluster),
+    ) -> Vec<usize> {
+        scan(points, self, |cluster| on_cluster(&cluster)).noise
+    }
+
+    /// Clusters incoming points, reporting how many neighbours every noise point had
+    ///
+    /// Neighbour counts are the ones DBSCAN computed to decide that a point
+    /// isn't a core point, no extra queries are run. They count points, also
+    /// when [`DbscanConfig::weights`] are set and decide the core test.
+    ///
+    /// # Returns
+    ///
+    /// A tuple `(clusters, noise)`, `noise` sorted by index
+    pub fn run_with_diagnostics(&self, points: &PointList) -> (Vec<Cluster>, Vec<NoiseInfo>) {
+        let (clusters, noise, _) = self.run_with_diagnostics_and_progress(points, |_, _| {});
+        (clusters, noise)
+    }
+
+    /// [`DbscanConfig::run_with_diagnostics`] reporting progress like
+    /// [`DbscanConfig::run_with_progress`]
+    ///
+    /// # Returns
+    ///
+    /// A tuple `(clusters, noise, timed_out)`, see [`DbscanConfig::run_timed`].
+    /// Points left unprocessed by the deadline have a neighbour count of 0.
+    pub fn run_with_diagnostics_and_progress(
+        &self,
+        points: &PointList,
+        on_progress: impl FnMut(usize, usize),
+    ) -> (Vec<Cluster>, Vec<NoiseInfo>, bool) {
+        let neighbors_of = self.neighbors_fn(points);
+        let mut counts = vec![0; points.len()];
+        let counted = |i: usize, _: Vec<usize>| {
+            let neighbors = neighbors_of(i);
+            counts[i] = neighbors.len();
+            neighbors
+        };
+        let (clusters, noise, timed_out) = self.collect(
+            points,
+            with_progress(points.len(), counted, on_progress),
+            self.weights,
+        );
+
+        let noise = noise
+            .into_iter()
+            .map(|index| NoiseInfo {
+                index,
+                neighbor_count: counts[index],
+            })
+            .collect();
+        (clusters, noise, timed_out)
+    }
+
+    /// Clusters incoming points and returns the core points DBSCAN found
+    ///
+    /// Core points are the cluster skeletons: points with enough neighbours
+    /// (see [`DbscanConfig::core_min_points`]) that grew their cluster. The
+    /// other points of clusters are border points. Cores are recorded during
+    /// the scan, no extra queries are run. [`DbscanConfig::weights`] are used
+    /// when set.
+    ///
+    /// # Returns
+    ///
+    /// A tuple `(clusters, noise, cores)`, `cores` sorted by index
+    pub fn run_with_cores(&self, points: &PointList) -> (Vec<Cluster>, Vec<usize>, Vec<usize>) {
+        let (clusters, expanded) = match self.weights {
+            Some(weights) => {
+                let neighbors_of = self.neighbors_fn(points);
+                self.collect_expanded(points, |i, _| neighbors_of(i), Some(weights))
+            }
+            None => self.collect_expanded(points, self.core_neighbors_fn(points), None),
+        };
+        let cores = expanded.cores.iter_ones().collect();
+        (clusters, expanded.noise, cores)
+    }
+
+    /// Clusters incoming points and classifies each of them
+    ///
+    /// Same roles as [`DbscanConfig::classify`], from the core points of
+    /// [`DbscanConfig::run_with_cores`] without extra queries.
+    ///
+    /// # Returns
+    ///
+    /// A tuple `(clusters, noise, types)`, `types[i]` is the role of `points[i]`
+    pub fn run_classified(&self, points: &PointList) -> (Vec<Cluster>, Vec<usize>, Vec<PointType>) {
+        let (clusters, noise, cores) = self.run_with_cores(points);
+        let labels = cluster_labels(&clusters, points.len());
+        let mut types: Vec<PointType> = labels
+            .iter()
+            .map(|&label| match label {
+                -1 => PointType::Noise,
+                _ => PointType::Border,
+            })
+            .collect();
+        for i in cores {
+            types[i] = PointType::Core;
+        }
+        (clusters, noise, types)
+    }
+
+    /// Classifies points as core, border or noise points of `clusters`
+    ///
+    /// Runs one more eps-neighbourhood query per point. Points in a cluster
+    /// are border points unless they are core points. Accuracy isn't taken
+    /// into account, use this with the clusters of [`DbscanConfig::run`].
+    pub fn classify(&self, points: &PointList, clusters: &[Cluster]) -> Vec<PointType> {
+        let labels = cluster_labels(clusters, points.len());
+        let neighbors_of = self.neighbors_fn(points);
+        (0..points.len())
+            .map(|i| {
+                if labels[i] == -1 {
+                    PointType::Noise
+                } else if neighbors_of(i).len() >= self.core_min_points() {
+                    PointType::Core
+                } else {
+                    PointType::Border
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the border points within eps of core points of more than one cluster
+    ///
+    /// DBSCAN assigns such a point to the cluster that reaches it first, so
+    /// its cluster depends on the order of the input. `types` is the result
+    /// of [`DbscanConfig::classify`] for the same points and clusters.
+    pub fn shared_border_points(
+        &self,
+        points: &PointList,
+        clusters: &[Cluster],
+        types: &[PointType],
+    ) -> Vec<usize> {
+        let labels = cluster_labels(clusters, points.len());
+        let neighbors_of = self.neighbors_fn(points);
+        (0..points.len())
+            .filter(|&i| types[i] == PointType::Border)
+            .filter(|&i| {
+                let mut reached_by = neighbors_of(i)
+                    .into_iter()
+                    .filter(|&j| types[j] == PointType::Core)
+                    .map(|j| labels[j]);
+                let first = reached_by.next();
+                reached_by.any(|label| Some(label) != first)
+            })
+            .collect()
+    }
+
+    /// Returns the default neighbourhood function for [`db_scan_with_strategy`]
+    ///
+    /// The returned closure owns a spatial index built over `points` and
+    /// returns the indices of all points within eps of the given point.
+    pub fn neighbors_fn<'a>(
+        &self,
+        points: &'a PointList,
+    ) -> impl Fn(usize) -> Vec<usize> + use<'a> {
+        let eps = self.scaled_eps();
+
+        // Clone points for index construction (index needs ownership)
+        let index = SpatialIndex::build(self.index, points.clone(), eps, self.metric);
+        move |i| index.in_range(&points[i], eps, Vec::new())
+    }
+
+    /// Returns the eps-neighbourhood of core points, nothing for other points
+    ///
+    /// Only the neighbourhoods of core points are expanded, so the density
+    /// test counts neighbours first, stopping at `min_points`, and doesn't
+    /// collect them for points that turn out to be too sparse. Neighbours are
+    /// appended to the given buffer.
+    fn core_neighbors_fn<'a>(
+        &self,
+        points: &'a PointList,
+    ) -> impl Fn(usize, Vec<usize>) -> Vec<usize> + use<'a> {
+        let eps = self.scaled_eps();
+        let min_points = self.core_min_points();
+        let index = SpatialIndex::build(self.index, points.clone(), eps, self.metric);
+        move |i, nodes| {
+            if !index.has_in_range(&points[i], eps, min_points) {
+                nodes
+            } else {
+                index.in_range(&points[i], eps, nodes)
+            }
+        }
+    }
+
+    /// Returns eps in [`DistanceMetric::sq_dist`] units, the query distance
+    /// of the spatial indexes
+    pub fn scaled_eps(&self) -> f64 {
+        if self.eps == 0.0 {
+            COINCIDENT_EPS
+        } else {
+            self.metric
+                .scale_eps_with_radius(self.eps, self.earth_radius)
+        }
+    }
+
+    /// Returns the neighbourhood function of [`DbscanConfig::run_with_accuracy`]
+    ///
+    /// Points `i` and `j` are neighbours when closer than `eps + acc_i + acc_j`
+    /// ([`AccuracyMode::Liberal`]) or `eps - (acc_i + acc_j)`
+    /// ([`AccuracyMode::Conservative`]). Negative accuracies count as `0.0`, a
+    /// point is always its own neighbour.
+    pub fn accuracy_neighbors_fn<'a>(
+        &self,
+        points: &'a PointList,
+        accuracy: &'a [f64],
+        mode: AccuracyMode,
+    ) -> impl Fn(usize) -> Vec<usize> + use<'a> {
+        let (eps_km, metric, radius) = (self.eps, self.metric, self.earth_radius);
+        let scaled = move |km: f64| {
+            if km == 0.0 {
+                COINCIDENT_EPS
+            } else {
+                metric.scale_eps_with_radius(km, radius)
+            }
+        };
+        let acc = move |i: usize| accuracy[i].max(0.0);
+        let max_acc = (0..points.len()).map(acc).fold(0.0, f64::max);
+        let threshold = move |acc_sum: f64| match mode {
+            AccuracyMode::Liberal => eps_km + acc_sum,
+            AccuracyMode::Conservative => eps_km - acc_sum,
+        };
+
+        let cell = match mode {
+            AccuracyMode::Liberal => threshold(2.0 * max_acc),
+            AccuracyMode::Conservative => eps_km,
+        };
+        let index = SpatialIndex::build(self.index, points.clone(), scaled(cell), metric);
+        move |i| {
+            // Widest distance any neighbour of i can be at
+            let search = threshold(
+                acc(i)
+                    + if mode == AccuracyMode::Liberal {
+                        max_acc
+                    } else {
+                        0.0
+                    },
+            );
+            if search < 0.0 {
+                return vec![i];
+            }
+
+            let mut nodes = index.in_range(&points[i], scaled(search), Vec::new());
+            nodes.retain(|&j| {
+                let limit = threshold(acc(i) + acc(j));
+                j == i
+                    || (limit >= 0.0
+                        && metric.sq_dist(&points[i], &points[j]) < scaled(limit).powi(2))
+            });
+            nodes
+        }
+    }
+
+    /// Returns the neighbourhood function of [`DbscanConfig::run_with_times`]
+    ///
+    /// Points `i` and `j` are neighbours when within eps of each other and
+    /// their times differ by at most `time_eps` seconds.
+    pub fn time_neighbors_fn<'a>(
+        &self,
+        points: &'a PointList,
+        times: &'a [i64],
+        time_eps: u64,
+    ) -> impl Fn(usize) -> Vec<usize> + use<'a> {
+        let neighbors_of = self.neighbors_fn(points);
+        move |i| {
+            let mut nodes = neighbors_of(i);
+            nodes.retain(|&j| times[i].abs_diff(times[j]) <= time_eps);
+            nodes
+        }
+    }
+
+    /// Runs DBSCAN with the given neighbourhood, applying deadline and cluster ids
+    fn collect(
+        &self,
+        points: &PointList,
+        neighbors_of: impl FnMut(usize, Vec<usize>) -> Vec<usize>,
+        weights: Option<&[f64]>,
+    ) -> (Vec<Cluster>, Vec<usize>, bool) {
+        let (clusters, expanded) = self.collect_expanded(points, neighbors_of, weights);
+        (clusters, expanded.noise, expanded.timed_out)
+    }
+
+    /// [`DbscanConfig::collect`] with the core points
+    fn collect_expanded(
+        &self,
+        points: &PointList,
+        neighbors_of: impl FnMut(usize, Vec<usize>) -> Vec<usize>,
+        weights: Option<&[f64]>,
+    ) -> (Vec<Cluster>, Expanded) {
+        let min_points = self.core_min_points();
+        let mut clusters = Vec::new();
+        let expanded = expand(
+            &self.seed_order.visit_order(points),
+            neighbors_of,
+            |neighbors| match weights {
+                None => neighbors.len() >= min_points,
+                Some(weights) => {
+                    neighbors.iter().map(|&i| weights[i]).sum::<f64>() >= min_points as f64
+                }
+            },
+            self.deadline,
+            |cluster| clusters.push(cluster),
+        );
+        if let Some(by) = self.stable_ids {
+            renumber_clusters(&mut clusters, points, by);
+        }
+        (clusters, expanded)
+    }
+}
+
 /// Clusters incoming points using DBSCAN algorithm
 ///
 /// # Arguments
 ///
 /// * `points` - List of points to cluster
-/// * `eps` - Clustering radius in kilometers
+/// * `eps` - Clustering radius in kilometers, `0.0` clusters only points
+///   with exactly the same coordinates
 /// * `min_points` - Minimum number of points in eps-neighbourhood (density threshold)
 ///
 /// # Returns
@@ -44,30 +796,478 @@ use bitvec::prelude::*;
 /// - `clusters` is a vector of found clusters
 /// - `noise` is a vector of point indices that are outliers (not in any cluster)
 pub fn db_scan(points: &PointList, eps: f64, min_points: usize) -> (Vec<Cluster>, Vec<usize>) {
-    let mut visited = vec![false; points.len()];
-    let mut members = vec![false; points.len()];
+    DbscanConfig::new(eps, min_points).run(points)
+}
+
+/// Clusters incoming points using DBSCAN and returns the core points
+///
+/// # Returns
+///
+/// A tuple `(clusters, noise, cores)`, see [`DbscanConfig::run_with_cores`]
+pub fn db_scan_with_cores(
+    points: &PointList,
+    eps: f64,
+    min_points: usize,
+) -> (Vec<Cluster>, Vec<usize>, Vec<usize>) {
+    DbscanConfig::new(eps, min_points).run_with_cores(points)
+}
+
+/// Clusters incoming points and returns the cluster label of every point
+///
+/// `labels[i]` is the id of the cluster point `i` belongs to, ids are
+/// `0..n` for `n` clusters as found by [`db_scan`]; noise points are `-1`.
+pub fn label_points(points: &PointList, eps: f64, min_points: usize) -> Vec<i32> {
+    let (clusters, _) = db_scan(points, eps, min_points);
+    cluster_labels(&clusters, points.len())
+}
+
+/// Turns clusters into a label per point
+///
+/// `labels[i]` is [`Cluster::c`] of the cluster point `i` belongs to, or `-1`
+/// when it's in none of them (noise).
+pub fn cluster_labels(clusters: &[Cluster], num_points: usize) -> Vec<i32> {
+    let mut labels = vec![-1; num_points];
+    for cluster in clusters {
+        for &idx in &cluster.points {
+            labels[idx] = cluster.c as i32;
+        }
+    }
+    labels
+}
+
+/// Merges clusters whose centroids are less than `distance_km` kilometers
+/// apart, e.g. two parking lots of the same mall
+///
+/// Centroids are those of [`Cluster::centroid_and_bounds`] and merging is
+/// transitive: clusters linked by a chain of close centroids become one.
+/// A merged cluster takes the place of its first cluster and lists the
+/// points of its clusters in their order, clusters are renumbered
+/// contiguously. Empty clusters are never merged. Centroids are compared by
+/// great-circle distance, so points must be longitude/latitude degrees.
+pub fn merge_close_clusters(
+    clusters: Vec<Cluster>,
+    points: &PointList,
+    distance_km: f64,
+) -> Vec<Cluster> {
+    let non_empty: Vec<usize> = (0..clusters.len())
+        .filter(|&i| !clusters[i].points.is_empty())
+        .collect();
+    let centroids: PointList = non_empty
+        .iter()
+        .map(|&i| clusters[i].centroid_and_bounds(points).0)
+        .collect();
+    let tree = new_kd_tree_with_metric(centroids.clone(), DistanceMetric::Haversine);
+    let eps = DistanceMetric::Haversine.scale_eps(distance_km);
+
+    // Union-find over clusters, the root of a set is its first cluster
+    let mut root: Vec<usize> = (0..clusters.len()).collect();
+    fn find(root: &mut [usize], mut i: usize) -> usize {
+        while root[i] != i {
+            root[i] = root[root[i]];
+            i = root[i];
+        }
+        i
+    }
+    for (j, centroid) in centroids.iter().enumerate() {
+        for k in tree.in_range(centroid, eps, Vec::new()) {
+            let (a, b) = (find(&mut root, non_empty[j]), find(&mut root, non_empty[k]));
+            root[a.max(b)] = a.min(b);
+        }
+    }
+
+    let mut merged: Vec<Cluster> = Vec::with_capacity(clusters.len());
+    let mut new_id = vec![usize::MAX; clusters.len()];
+    for (i, cluster) in clusters.into_iter().enumerate() {
+        let r = find(&mut root, i);
+        if new_id[r] == usize::MAX {
+            new_id[r] = merged.len();
+            merged.push(Cluster {
+                c: merged.len(),
+                points: cluster.points,
+            });
+        } else {
+            merged[new_id[r]].points.extend(cluster.points);
+        }
+    }
+    merged
+}
+
+/// Returns the k-distance curve: the great-circle distance in kilometers of
+/// every point to its `k`-th nearest point (counting the point itself, like
+/// `min_points` does), sorted ascending
+///
+/// A `k` of `0` is treated as `1`, where every distance is `0.0`.
+pub fn k_distances(points: &PointList, k: usize) -> Vec<f64> {
+    k_distances_with_metric(points, k, DistanceMetric::default())
+}
+
+/// Same as [`k_distances`] with neighbours ranked by the given metric
+///
+/// Distances are in the unit of eps under `metric`: great-circle kilometers
+/// for the geographic metrics, the metric's own distance in coordinate units
+/// for [planar](DistanceMetric::is_planar) ones.
+pub fn k_distances_with_metric(points: &PointList, k: usize, metric: DistanceMetric) -> Vec<f64> {
+    let k = k.max(1);
+    let tree = new_kd_tree_with_metric(points.clone(), metric);
+    let dist = |a: &Point, b: &Point| {
+        if metric.is_planar() {
+            a.sq_dist_with(b, &metric).sqrt()
+        } else {
+            a.dist_km(b)
+        }
+    };
+    let mut k_dist: Vec<f64> = points
+        .iter()
+        .map(|p| {
+            // Neighbours are ranked by the metric, take the k-th by the
+            // reported distance among them
+            let mut dists: Vec<f64> = tree
+                .k_nearest(p, k)
+                .into_iter()
+                .map(|(i, _)| dist(p, &points[i]))
+                .collect();
+            dists.sort_by(f64::total_cmp);
+            dists.last().copied().unwrap_or(0.0)
+        })
+        .collect();
+    k_dist.sort_by(f64::total_cmp);
+    k_dist
+}
+
+/// Estimates a good eps in kilometers from the knee of the k-distance curve
+///
+/// Looks at the [`k_distances`] of the points: points inside clusters have small k-distances and noise points large ones, the eps
+/// returned is the k-distance where the curve bends the most: the point of
+/// the normalized curve farthest below the line between its ends. Returns
+/// `0.0` when there are no points.
+pub fn estimate_eps(points: &PointList, k: usize) -> f64 {
+    estimate_eps_with_metric(points, k, DistanceMetric::default())
+}
+
+/// Same as [`estimate_eps`] for the given metric, eps is in the unit of
+/// [`k_distances_with_metric`]
+pub fn estimate_eps_with_metric(points: &PointList, k: usize, metric: DistanceMetric) -> f64 {
+    let k_dist = k_distances_with_metric(points, k, metric);
+    let (Some(&first), Some(&last)) = (k_dist.first(), k_dist.last()) else {
+        return 0.0;
+    };
+    if k_dist.len() < 3 || last == first {
+        return last;
+    }
+
+    let n = (k_dist.len() - 1) as f64;
+    let knee = (0..k_dist.len())
+        .max_by(|&a, &b| {
+            let below = |i: usize| i as f64 / n - (k_dist[i] - first) / (last - first);
+            below(a).total_cmp(&below(b))
+        })
+        .unwrap_or(0);
+    k_dist[knee]
+}
+
+/// Clusters incoming points using DBSCAN algorithm with the given spatial index
+///
+/// Found clusters don't depend on the index, only the speed does.
+/// See [`IndexKind::resolve`] for the way [`IndexKind::Auto`] picks an index.
+pub fn db_scan_with_index(
+    points: &PointList,
+    eps: f64,
+    min_points: usize,
+    index: IndexKind,
+) -> (Vec<Cluster>, Vec<usize>) {
+    DbscanConfig::new(eps, min_points).index(index).run(points)
+}
+
+/// Clusters incoming points on a sphere of `earth_radius` kilometers
+///
+/// `eps` is in kilometers on that sphere, [`db_scan`] uses [`EARTH_R`].
+pub fn db_scan_with_radius(
+    points: &PointList,
+    eps: f64,
+    min_points: usize,
+    earth_radius: f64,
+) -> (Vec<Cluster>, Vec<usize>) {
+    DbscanConfig::new(eps, min_points)
+        .earth_radius(earth_radius)
+        .run(points)
+}
+
+/// Clusters incoming points, reporting the neighbour count of every noise point
+///
+/// See [`DbscanConfig::run_with_diagnostics`], clusters are the ones of [`db_scan`].
+pub fn db_scan_with_diagnostics(
+    points: &PointList,
+    eps: f64,
+    min_points: usize,
+) -> (Vec<Cluster>, Vec<NoiseInfo>) {
+    DbscanConfig::new(eps, min_points).run_with_diagnostics(points)
+}
+
+/// Clusters incoming points that each stand for `weights[i]` observations
+///
+/// `min_points` is compared to the summed weights of a neighbourhood instead
+/// of its number of points, e.g. for `lat,lon,count` rows of aggregated GPS
+/// pings. With `None` this is the same as [`db_scan`].
+pub fn db_scan_weighted(
+    points: &PointList,
+    eps: f64,
+    min_points: usize,
+    weights: Option<&[f64]>,
+) -> (Vec<Cluster>, Vec<usize>) {
+    let config = DbscanConfig::new(eps, min_points);
+    match weights {
+        Some(weights) => config.weights(weights).run(points),
+        None => config.run(points),
+    }
+}
+
+/// Clusters incoming points, reporting progress to `on_progress`
+///
+/// See [`DbscanConfig::run_with_progress`], clusters are the ones of [`db_scan`].
+pub fn db_scan_with_progress(
+    points: &PointList,
+    eps: f64,
+    min_points: usize,
+    on_progress: impl FnMut(usize, usize),
+) -> (Vec<Cluster>, Vec<usize>) {
+    let (clusters, noise, _) =
+        DbscanConfig::new(eps, min_points).run_with_progress(points, on_progress);
+    (clusters, noise)
+}
+
+/// Clusters timestamped points, see [`DbscanConfig::run_with_times`]
+///
+/// Two points at the same place are neighbours only when `times` (in
+/// seconds) differ by at most `time_eps`.
+pub fn db_scan_with_times(
+    points: &PointList,
+    eps: f64,
+    min_points: usize,
+    times: &[i64],
+    time_eps: u64,
+) -> (Vec<Cluster>, Vec<usize>) {
+    let (clusters, noise, _) =
+        DbscanConfig::new(eps, min_points).run_with_times(points, times, time_eps);
+    (clusters, noise)
+}
+
+/// Clusters points of any dimension by Euclidean distance
+///
+/// Unlike [`db_scan`] coordinates aren't geographic: `eps` is in coordinate
+/// units and applies to all dimensions alike, so scale them to a common unit
+/// first, e.g. `[x_km, y_km, hours * km_per_hour]` for spatiotemporal data.
+/// `eps == 0.0` clusters only points with exactly the same coordinates.
+///
+/// # Returns
+///
+/// A tuple `(clusters, noise)`, see [`db_scan`]
+pub fn db_scan_nd<const D: usize>(
+    points: &PointList<D>,
+    eps: f64,
+    min_points: usize,
+) -> (Vec<Cluster>, Vec<usize>) {
+    let eps = if eps == 0.0 { COINCIDENT_EPS } else { eps };
+    let tree = new_kd_tree_with_metric(points.clone(), DistanceMetric::SquaredEuclidean);
+    db_scan_with_strategy(
+        points.len(),
+        |i| tree.in_range(&points[i], eps, Vec::new()),
+        |neighbors| neighbors.len() >= min_points,
+    )
+}
+
+/// Clusters incoming points using DBSCAN algorithm, reporting each cluster
+/// to `on_cluster` as soon as it is completed
+///
+/// Found clusters are the same as the ones returned by [`db_scan`].
+///
+/// # Returns
+///
+/// A vector of point indices that are outliers (not in any cluster)
+pub fn db_scan_with_callback(
+    points: &PointList,
+    eps: f64,
+    min_points: usize,
+    on_cluster: impl FnMut(&Cluster),
+) -> Vec<usize> {
+    DbscanConfig::new(eps, min_points).run_with_callback(points, on_cluster)
+}
+
+/// Clusters points using DBSCAN with a custom expansion policy
+///
+/// DBSCAN variants can swap how clusters grow without forking the main loop:
+///
+/// * `num_points` - Number of points to cluster, they are referred to by index
+/// * `neighbors_of` - Returns the neighbourhood of a point (including the point itself).
+///   [`DbscanConfig::neighbors_fn`] gives the default eps-neighbourhood.
+/// * `is_core` - Decides from a neighbourhood whether its point is a core point
+///   that starts or expands a cluster. Default is `neighbors.len() >= min_points`.
+///
+/// With the defaults this is exactly [`DbscanConfig::run`].
+///
+/// # Returns
+///
+/// A tuple `(clusters, noise)`, see [`db_scan`]
+pub fn db_scan_with_strategy(
+    num_points: usize,
+    mut neighbors_of: impl FnMut(usize) -> Vec<usize>,
+    is_core: impl FnMut(&[usize]) -> bool,
+) -> (Vec<Cluster>, Vec<usize>) {
     let mut clusters = Vec::new();
-    let mut noise = Vec::new();
-    let mut c = 0;
-    // Clone points for KD-tree construction (tree needs ownership)
-    let kd_tree = new_kd_tree(points.clone());
+    let order: Vec<usize> = (0..num_points).collect();
+    let noise = expand(
+        &order,
+        |i, _| neighbors_of(i),
+        is_core,
+        None,
+        |cluster| clusters.push(cluster),
+    )
+    .noise;
+    (clusters, noise)
+}
 
-    // Our SphericalDistanceFast returns distance which is not mutiplied
-    // by EarthR * DegreeRad, adjust eps accordingly
-    let eps = eps / EARTH_R / DEGREE_RAD;
+/// Reorders clusters by the given rule and renumbers them contiguously
+fn renumber_clusters(clusters: &mut [Cluster], points: &PointList, by: StableBy) {
+    match by {
+        StableBy::MinIndex => {
+            clusters.sort_by_key(|cluster| cluster.points.iter().min().copied());
+        }
+        StableBy::Centroid => {
+            // Empty clusters have no centroid, they go first
+            let keys: Vec<[f64; 2]> = clusters
+                .iter()
+                .map(|cluster| {
+                    if cluster.points.is_empty() {
+                        [f64::NEG_INFINITY; 2]
+                    } else {
+                        cluster.centroid_and_bounds(points).0.0
+                    }
+                })
+                .collect();
+            let mut order: Vec<usize> = (0..clusters.len()).collect();
+            order.sort_by(|&a, &b| {
+                let (a, b) = (keys[a], keys[b]);
+                a[0].total_cmp(&b[0]).then(a[1].total_cmp(&b[1]))
+            });
+            let sorted: Vec<Vec<usize>> = order
+                .iter()
+                .map(|&i| std::mem::take(&mut clusters[i].points))
+                .collect();
+            for (cluster, members) in clusters.iter_mut().zip(sorted) {
+                cluster.points = members;
+            }
+        }
+    }
+    for (c, cluster) in clusters.iter_mut().enumerate() {
+        cluster.c = c;
+    }
+}
 
-    let mut neighbor_unique = bitvec![0; points.len()];
+/// DBSCAN with the eps-neighbourhood, `min_points` density rule and weights of `config`
+fn scan(points: &PointList, config: &DbscanConfig, on_cluster: impl FnMut(Cluster)) -> Expanded {
+    let min_points = config.core_min_points();
+    let order = config.seed_order.visit_order(points);
+    match config.weights {
+        None => expand(
+            &order,
+            config.core_neighbors_fn(points),
+            |neighbors| neighbors.len() >= min_points,
+            config.deadline,
+            on_cluster,
+        ),
+        Some(weights) => {
+            let neighbors_of = config.neighbors_fn(points);
+            expand(
+                &order,
+                |i, _| neighbors_of(i),
+                |neighbors| neighbors.iter().map(|&i| weights[i]).sum::<f64>() >= min_points as f64,
+                config.deadline,
+                on_cluster,
+            )
+        }
+    }
+}
 
-    for i in 0..points.len() {
+/// Wraps `neighbors_of` to report the number of visited points
+///
+/// Every point is visited by exactly one neighbourhood query, so counting
+/// queries counts visited points. `on_progress(visited, total)` is called
+/// every `total / 100` (rounded up) points and for the last one.
+fn with_progress(
+    total: usize,
+    mut neighbors_of: impl FnMut(usize, Vec<usize>) -> Vec<usize>,
+    mut on_progress: impl FnMut(usize, usize),
+) -> impl FnMut(usize, Vec<usize>) -> Vec<usize> {
+    let step = total.div_ceil(100).max(1);
+    let mut visited = 0;
+    move |i, nodes| {
+        visited += 1;
+        if visited % step == 0 || visited == total {
+            on_progress(visited, total);
+        }
+        neighbors_of(i, nodes)
+    }
+}
+
+/// Result of [`expand`]
+struct Expanded {
+    /// Points in no cluster, sorted
+    noise: Vec<usize>,
+    /// Whether each point passed the core point test
+    cores: BitVec,
+    /// Whether the deadline passed before all points were processed
+    timed_out: bool,
+}
+
+/// DBSCAN main loop, hands over every completed cluster to `on_cluster`
+///
+/// Unvisited points start clusters in `order`, a permutation of all point
+/// indices (see [`SeedOrder::visit_order`]). `neighbors_of` appends the
+/// neighbourhood of a point to the given empty buffer and returns it, one
+/// buffer is reused for all points whose neighbourhood isn't kept. Every
+/// processed point is queried exactly once, so the core points are known
+/// without further queries.
+fn expand(
+    order: &[usize],
+    mut neighbors_of: impl FnMut(usize, Vec<usize>) -> Vec<usize>,
+    mut is_core: impl FnMut(&[usize]) -> bool,
+    deadline: Option<Instant>,
+    mut on_cluster: impl FnMut(Cluster),
+) -> Expanded {
+    let num_points = order.len();
+    let mut visited = vec![false; num_points];
+    let mut members = vec![false; num_points];
+    let mut cores = bitvec![0; num_points];
+    let mut noise: Vec<usize> = Vec::new();
+    let mut c = 0;
+
+    let mut neighbor_unique = bitvec![0; num_points];
+    let mut scratch = Vec::new();
+
+    for (pos, &i) in order.iter().enumerate() {
         if visited[i] {
             continue;
         }
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            // Unvisited points can't be members of completed clusters, noise
+            // points found before may have become border points since
+            noise.retain(|&k| !members[k]);
+            noise.extend(order[pos..].iter().filter(|&&k| !visited[k]));
+            noise.sort_unstable();
+            return Expanded {
+                noise,
+                cores,
+                timed_out: true,
+            };
+        }
         visited[i] = true;
 
-        let neighbor_pts = kd_tree.in_range(&points[i], eps, Vec::new());
-        if neighbor_pts.len() < min_points {
+        scratch.clear();
+        scratch = neighbors_of(i, scratch);
+        if !is_core(&scratch) {
             noise.push(i);
         } else {
+            cores.set(i, true);
+            let mut neighbor_pts = std::mem::take(&mut scratch);
             let mut cluster = Cluster { c, points: vec![i] };
             members[i] = true;
             c += 1;
@@ -77,16 +1277,17 @@ pub fn db_scan(points: &PointList, eps: f64, min_points: usize) -> (Vec<Cluster>
                 neighbor_unique.set(j, true);
             }
 
-            let mut neighbor_pts = neighbor_pts;
             let mut j = 0;
             // Use while loop to handle dynamic growth of neighbor_pts during iteration
             while j < neighbor_pts.len() {
                 let k = neighbor_pts[j];
                 if !visited[k] {
                     visited[k] = true;
-                    let more_neighbors = kd_tree.in_range(&points[k], eps, Vec::new());
-                    if more_neighbors.len() >= min_points {
-                        for &p in &more_neighbors {
+                    scratch.clear();
+                    scratch = neighbors_of(k, scratch);
+                    if is_core(&scratch) {
+                        cores.set(k, true);
+                        for &p in &scratch {
                             if !neighbor_unique[p] {
                                 neighbor_pts.push(p);
                                 neighbor_unique.set(p, true);
@@ -101,17 +1302,32 @@ pub fn db_scan(points: &PointList, eps: f64, min_points: usize) -> (Vec<Cluster>
                 }
                 j += 1;
             }
-            clusters.push(cluster);
+            on_cluster(cluster);
         }
     }
 
-    (clusters, noise)
+    // Points that failed the core test may have become border points of a
+    // later cluster. Seeds are visited by index unless the order is permuted
+    noise.retain(|&k| !members[k]);
+    noise.sort_unstable();
+    Expanded {
+        noise,
+        cores,
+        timed_out: false,
+    }
 }
 
 /// Simple O(N) way to find points in neighbourhood
 ///
 /// This is roughly equivalent to `kd_tree.in_range(points[i], eps, vec![])`
-#[allow(dead_code)] // Part of public API, may be used by external code
+///
+/// `eps` is in the unit of [`Point::sq_dist`] before squaring, i.e. radians
+/// over [`DEGREE_RAD`] rather than kilometers: divide kilometers by
+/// `EARTH_R * DEGREE_RAD`, or use [`region_query_km`].
+///
+/// The neighbourhood includes every point of `points` equal to `p` (distance
+/// `0 < eps`), so querying with a point of the list includes that point
+/// itself. Use [`region_query_excluding_self`] to count only the other points.
 pub fn region_query(points: &PointList, p: &Point, eps: f64) -> Vec<usize> {
     let mut result = Vec::new();
 
@@ -124,6 +1340,45 @@ pub fn region_query(points: &PointList, p: &Point, eps: f64) -> Vec<usize> {
     result
 }
 
+/// Simple O(N) way to find the points within `eps_km` kilometers of `p`
+///
+/// Same as [`region_query`] with eps converted from kilometers the way
+/// [`db_scan`] does it, so the neighbourhood is the one clustering uses. An
+/// `eps_km` of `0.0` finds the points with the same coordinates as `p`.
+pub fn region_query_km(points: &PointList, p: &Point, eps_km: f64) -> Vec<usize> {
+    region_query(points, p, DbscanConfig::new(eps_km, 1).scaled_eps())
+}
+
+/// Simple O(N) way to find the neighbours of `points[idx]` besides itself
+///
+/// Same as [`region_query`] for `points[idx]` without index `idx`. Other
+/// points with the same coordinates are still included.
+pub fn region_query_excluding_self(points: &PointList, idx: usize, eps: f64) -> Vec<usize> {
+    let mut result = region_query(points, &points[idx], eps);
+    result.retain(|&i| i != idx);
+    result
+}
+
+/// Simple O(N) way to find points in neighbourhood using the given metric
+///
+/// This is roughly equivalent to `new_kd_tree_with_metric(points, metric).in_range(p, eps, vec![])`
+pub fn region_query_with_metric(
+    points: &PointList,
+    p: &Point,
+    eps: f64,
+    metric: DistanceMetric,
+) -> Vec<usize> {
+    let mut result = Vec::new();
+
+    for (i, point) in points.iter().enumerate() {
+        if metric.sq_dist(point, p) < eps * eps {
+            result.push(i);
+        }
+    }
+
+    result
+}
+
 // Re-export with Go-style names for compatibility
 pub use db_scan as DBScan;
 pub use region_query as RegionQuery;
@@ -1,6 +1,15 @@
 #[cfg(test)]
 mod tests {
-    use crate::cluster::{DEGREE_RAD, EARTH_R, Point, db_scan, new_kd_tree, region_query};
+    use crate::cluster::{
+        AccuracyMode, Cluster, DEGREE_RAD, DbscanConfig, DistanceMetric, EARTH_R, IndexKind,
+        NoiseInfo, Point, PointList, PointType, SeedOrder, StableBy, cluster_labels, db_scan,
+        db_scan_nd, db_scan_weighted, db_scan_with_callback, db_scan_with_cores,
+        db_scan_with_diagnostics, db_scan_with_index, db_scan_with_progress, db_scan_with_radius,
+        db_scan_with_strategy, db_scan_with_times, estimate_eps, estimate_eps_with_metric,
+        k_distances, k_distances_with_metric, label_points, merge_close_clusters, new_kd_tree,
+        new_kd_tree_with_metric, region_query, region_query_excluding_self, region_query_km,
+        region_query_with_metric,
+    };
 
     #[test]
     fn test_range_query_kdtree() {
@@ -22,6 +31,19 @@ mod tests {
             pts2.sort();
             assert_eq!(pts1, pts2);
         }
+
+        // Pruning is exact under the Euclidean metric, any mismatch is a traversal bug
+        let metric = DistanceMetric::SquaredEuclidean;
+        let tree = new_kd_tree_with_metric(points.clone(), metric);
+        for pt in &points {
+            for eps in [eps / 4.0, eps, eps * 4.0] {
+                let mut pts1 = tree.in_range(pt, eps, Vec::new());
+                let mut pts2 = region_query_with_metric(&points, pt, eps, metric);
+                pts1.sort();
+                pts2.sort();
+                assert_eq!(pts1, pts2);
+            }
+        }
     }
 
     #[test]
@@ -47,4 +69,955 @@ mod tests {
         }
         assert!(all_points.iter().all(|&b| b));
     }
+
+    #[test]
+    fn test_dbscan_with_callback() {
+        let points = vec![
+            Point([30.244759, 59.955982]),
+            Point([30.24472, 59.955975]),
+            Point([30.244358, 59.96698]),
+            Point([30.258387, 59.951557]),
+            Point([30.434124, 60.029499]),
+        ];
+        let (clusters, noise) = db_scan(&points, 0.8, 2);
+
+        let mut streamed = Vec::new();
+        let streamed_noise =
+            db_scan_with_callback(&points, 0.8, 2, |cluster| streamed.push(cluster.clone()));
+
+        assert_eq!(streamed_noise, noise);
+        assert_eq!(streamed.len(), clusters.len());
+        for (a, b) in streamed.iter().zip(&clusters) {
+            assert_eq!(a.c, b.c);
+            assert_eq!(a.points, b.points);
+        }
+    }
+
+    #[test]
+    fn test_dbscan_empty_and_single_point() {
+        let empty: PointList = Vec::new();
+        let single = vec![Point([30.244759, 59.955982])];
+
+        for index in [IndexKind::KdTree, IndexKind::Grid, IndexKind::BruteForce] {
+            let (clusters, noise) = db_scan_with_index(&empty, 0.1, 3, index);
+            assert!(clusters.is_empty(), "index: {}", index);
+            assert!(noise.is_empty(), "index: {}", index);
+
+            // A point is its own only neighbour
+            let (clusters, noise) = db_scan_with_index(&single, 0.1, 3, index);
+            assert!(clusters.is_empty(), "index: {}", index);
+            assert_eq!(noise, vec![0], "index: {}", index);
+            let (clusters, noise) = db_scan_with_index(&single, 0.1, 1, index);
+            assert_eq!(clusters.len(), 1, "index: {}", index);
+            assert_eq!(clusters[0].points, vec![0], "index: {}", index);
+            assert!(noise.is_empty(), "index: {}", index);
+        }
+
+        assert!(label_points(&empty, 0.1, 3).is_empty());
+        assert_eq!(label_points(&single, 0.1, 3), vec![-1]);
+        assert!(region_query(&empty, &single[0], 0.1).is_empty());
+    }
+
+    #[test]
+    fn test_seed_order_visit_order() {
+        let points = vec![
+            Point([10.0, 50.0]),
+            Point([-10.0, 60.0]),
+            Point([10.0, 40.0]),
+            Point([-10.0, 60.0]),
+            Point([170.0, -80.0]),
+        ];
+        assert_eq!(SeedOrder::Input.visit_order(&points), vec![0, 1, 2, 3, 4]);
+        assert_eq!(SeedOrder::Lat.visit_order(&points), vec![4, 2, 0, 1, 3]);
+        assert_eq!(SeedOrder::Lon.visit_order(&points), vec![1, 3, 2, 0, 4]);
+        // Quadrants: south-east first, then north-west before north-east
+        assert_eq!(SeedOrder::Morton.visit_order(&points), vec![4, 1, 3, 2, 0]);
+        assert!(SeedOrder::Morton.visit_order(&Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn test_seed_order_shared_border_point() {
+        // Two clusters along the equator, ~94 m apart from the border point
+        // at index 4 in between, which has too few neighbours to be core
+        let points = vec![
+            Point([0.0, 0.0]),
+            Point([0.00005, 0.0]),
+            Point([0.0001, 0.0]),
+            Point([0.0002, 0.0]),
+            Point([0.00105, 0.0]),
+            Point([0.0019, 0.0]),
+            Point([0.0020, 0.0]),
+            Point([0.00205, 0.0]),
+            Point([0.0021, 0.0]),
+        ];
+        let reversed: PointList = points.iter().rev().copied().collect();
+        let unreversed = |labels: Vec<i32>| labels.into_iter().rev().collect::<Vec<_>>();
+        let labels = |config: DbscanConfig, points: &PointList| {
+            let (clusters, _) = config.run(points);
+            cluster_labels(&clusters, points.len())
+        };
+
+        // The cluster found first takes the border point
+        let config = DbscanConfig::new(0.1, 4).stable_ids(StableBy::Centroid);
+        let forward = labels(config, &points);
+        let backward = unreversed(labels(config, &reversed));
+        assert_eq!(forward[4], forward[3]);
+        assert_eq!(backward[4], backward[5]);
+
+        // Coordinate orders don't depend on the input order
+        for order in [SeedOrder::Lat, SeedOrder::Lon, SeedOrder::Morton] {
+            let config = config.seed_order(order);
+            let forward = labels(config, &points);
+            assert_eq!(
+                forward,
+                unreversed(labels(config, &reversed)),
+                "{:?}",
+                order
+            );
+            assert_eq!(forward[4], forward[3], "{:?}", order);
+        }
+
+        // Noise stays sorted by index
+        let (_, noise) = DbscanConfig::new(0.1, 10)
+            .seed_order(SeedOrder::Morton)
+            .run(&reversed);
+        assert_eq!(noise, (0..points.len()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_dbscan_zero_eps() {
+        let points = vec![
+            Point([30.244759, 59.955982]),
+            Point([30.24472, 59.955975]),
+            Point([30.244358, 59.96698]),
+        ];
+        let (clusters, noise) = db_scan(&points, 0.0, 1);
+        assert_eq!(clusters.len(), 3);
+        let (clusters, noise2) = db_scan(&points, 0.0, 2);
+        assert!(clusters.is_empty());
+        assert_eq!(noise2, vec![0, 1, 2]);
+        assert!(noise.is_empty());
+
+        // Only exact duplicates are clustered
+        let mut points = points;
+        points.push(Point([30.24472, 59.955975]));
+        let (clusters, noise) = db_scan(&points, 0.0, 2);
+        assert_eq!(clusters.len(), 1);
+        let mut members = clusters[0].points.clone();
+        members.sort();
+        assert_eq!(members, vec![1, 3]);
+        assert_eq!(noise, vec![0, 2]);
+    }
+
+    #[test]
+    fn test_dbscan_giant_eps() {
+        let points = vec![
+            Point([30.244759, 59.955982]),
+            Point([30.24472, 59.955975]),
+            Point([30.244358, 59.96698]),
+            Point([30.258387, 59.951557]),
+            Point([30.434124, 60.029499]),
+        ];
+        let (clusters, noise) = db_scan(&points, 10_000.0, 3);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].points.len(), points.len());
+        assert!(noise.is_empty());
+    }
+
+    #[test]
+    fn test_dbscan_with_strategy() {
+        let points = vec![
+            Point([30.244759, 59.955982]),
+            Point([30.24472, 59.955975]),
+            Point([30.244358, 59.96698]),
+            Point([30.258387, 59.951557]),
+            Point([30.434124, 60.029499]),
+        ];
+        let config = DbscanConfig::new(0.8, 2);
+        let (clusters, noise) = config.run(&points);
+
+        // Default policy reproduces db_scan
+        let (custom, custom_noise) =
+            db_scan_with_strategy(points.len(), config.neighbors_fn(&points), |neighbors| {
+                neighbors.len() >= 2
+            });
+        assert_eq!(custom_noise, noise);
+        assert_eq!(custom.len(), clusters.len());
+        for (a, b) in custom.iter().zip(&clusters) {
+            assert_eq!(a.points, b.points);
+        }
+
+        // Secondary density threshold: only expand through points with 3+ neighbours
+        let (dense, dense_noise) =
+            db_scan_with_strategy(points.len(), config.neighbors_fn(&points), |neighbors| {
+                neighbors.len() >= 3
+            });
+        assert!(dense_noise.len() > noise.len());
+        assert!(dense.iter().all(|c| c.points.len() >= 3));
+    }
+
+    #[test]
+    fn test_dbscan_deadline() {
+        use std::time::{Duration, Instant};
+
+        let points = vec![
+            Point([30.244759, 59.955982]),
+            Point([30.24472, 59.955975]),
+            Point([30.244358, 59.96698]),
+            Point([30.258387, 59.951557]),
+            Point([30.434124, 60.029499]),
+        ];
+        let config = DbscanConfig::new(0.8, 2);
+        let (clusters, noise) = config.run(&points);
+
+        // Far deadline: complete result
+        let far = config.deadline(Instant::now() + Duration::from_secs(3600));
+        let (timed, timed_noise, timed_out) = far.run_timed(&points);
+        assert!(!timed_out);
+        assert_eq!(timed.len(), clusters.len());
+        assert_eq!(timed_noise, noise);
+
+        // Passed deadline: nothing clustered, every point is noise exactly once
+        let passed = config.deadline(Instant::now());
+        let (partial, partial_noise, timed_out) = passed.run_timed(&points);
+        assert!(timed_out);
+        assert!(partial.is_empty());
+        assert_eq!(partial_noise, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_noise_partition() {
+        use std::time::{Duration, Instant};
+
+        // Point 0 fails its own density test, then becomes a border point of
+        // the cluster grown from point 1. Point 5 is far away.
+        let points: PointList = [0.0, 0.95, 1.5, 1.6, 1.7, 10.0]
+            .iter()
+            .map(|&x| Point([x, 0.0]))
+            .collect();
+        let config = DbscanConfig::new(1.0, 3).metric(DistanceMetric::SquaredEuclidean);
+        let assert_partition = |clusters: &[Cluster], noise: &[usize]| {
+            let mut all: Vec<usize> = clusters.iter().flat_map(|c| c.points.clone()).collect();
+            all.extend(noise);
+            all.sort_unstable();
+            assert_eq!(all, (0..points.len()).collect::<Vec<_>>());
+        };
+
+        let (clusters, noise) = config.run(&points);
+        assert_eq!(clusters.len(), 1);
+        let mut members = clusters[0].points.clone();
+        members.sort_unstable();
+        assert_eq!(members, vec![0, 1, 2, 3, 4]);
+        assert_eq!(noise, vec![5]);
+        assert_partition(&clusters, &noise);
+
+        // The deadline passes once the cluster is complete, before point 5
+        let deadline = Instant::now() + Duration::from_millis(50);
+        let (clusters, noise, timed_out) =
+            config
+                .deadline(deadline)
+                .run_with_progress(&points, |visited, _| {
+                    if visited == 5 {
+                        std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
+                    }
+                });
+        assert!(timed_out);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(noise, vec![5]);
+        assert_partition(&clusters, &noise);
+
+        let (clusters, noise, timed_out) = config.deadline(Instant::now()).run_timed(&points);
+        assert!(timed_out);
+        assert_partition(&clusters, &noise);
+    }
+
+    #[test]
+    fn test_dbscan_stable_ids_min_index() {
+        // Cluster X along the 60th parallel: a border point at 0 km, cores at
+        // 0.5 and 1.0 km and a border at 1.4 km. Cluster Y is a tight triple.
+        let km = 1.0 / EARTH_R / DEGREE_RAD;
+        let x = |d: f64| Point([30.0, 60.0 + d * km]);
+        let y = |d: f64| Point([31.0, 60.0 + d * km]);
+        let (x0, x05, x10, x14) = (x(0.0), x(0.5), x(1.0), x(1.4));
+        let (y0, y1, y2) = (y(0.0), y(0.1), y(0.2));
+
+        // Same points in two orders, `ids` maps positions to a point identity.
+        // In `a` the first point is X's border, so DBSCAN finds Y first.
+        let a = vec![x0, y0, y1, y2, x05, x10, x14];
+        let a_ids = [0, 1, 2, 3, 4, 5, 6];
+        let b = vec![x05, x0, y0, y1, y2, x10, x14];
+        let b_ids = [4, 0, 1, 2, 3, 5, 6];
+
+        let labels = |clusters: &[crate::cluster::Cluster], ids: &[usize]| {
+            let mut labels = [usize::MAX; 7];
+            for cluster in clusters {
+                for &i in &cluster.points {
+                    labels[ids[i]] = cluster.c;
+                }
+            }
+            labels
+        };
+
+        let config = DbscanConfig::new(0.6, 3);
+        let (raw_a, _) = config.run(&a);
+        let (raw_b, _) = config.run(&b);
+        assert_ne!(labels(&raw_a, &a_ids), labels(&raw_b, &b_ids));
+
+        let stable = config.stable_ids(StableBy::MinIndex);
+        let (stable_a, _) = stable.run(&a);
+        let (stable_b, _) = stable.run(&b);
+        assert_eq!(labels(&stable_a, &a_ids), [0, 1, 1, 1, 0, 0, 0]);
+        assert_eq!(labels(&stable_a, &a_ids), labels(&stable_b, &b_ids));
+    }
+
+    #[test]
+    fn test_dbscan_stable_ids_centroid() {
+        // Four tight groups of 5 points around the 60th parallel, plus noise
+        let km = 1.0 / EARTH_R / DEGREE_RAD;
+        let mut points = Vec::new();
+        for (lon, lat) in [(30.3, 60.0), (30.0, 59.9), (30.0, 60.1), (31.0, 59.5)] {
+            for i in 0..5 {
+                let d = i as f64 * 0.05 * km;
+                points.push(Point([lon + d, lat - d]));
+            }
+        }
+        points.push(Point([29.0, 59.0]));
+        points.push(Point([32.0, 61.0]));
+
+        // Fixed permutation: 7 is coprime to the 22 points
+        let n = points.len();
+        let order: Vec<usize> = (0..n).map(|i| (i * 7 + 3) % n).collect();
+        let shuffled: Vec<Point> = order.iter().map(|&i| points[i]).collect();
+
+        // Cluster id of every original point
+        let labels = |clusters: &[crate::cluster::Cluster], ids: &[usize]| {
+            let mut labels = vec![-1; n];
+            for cluster in clusters {
+                for &i in &cluster.points {
+                    labels[ids[i]] = cluster.c as i32;
+                }
+            }
+            labels
+        };
+        let identity: Vec<usize> = (0..n).collect();
+
+        let config = DbscanConfig::new(0.5, 3);
+        let (raw_a, _) = config.run(&points);
+        let (raw_b, _) = config.run(&shuffled);
+        assert_ne!(labels(&raw_a, &identity), labels(&raw_b, &order));
+
+        let stable = config.stable_ids(StableBy::Centroid);
+        let (stable_a, noise_a) = stable.run(&points);
+        let (stable_b, _) = stable.run(&shuffled);
+        assert_eq!(labels(&stable_a, &identity), labels(&stable_b, &order));
+        assert_eq!(noise_a, vec![20, 21]);
+
+        // Westmost first, the two groups on the 30th meridian by latitude
+        let first: Vec<usize> = stable_a.iter().map(|c| c.points[0] / 5).collect();
+        assert_eq!(first, [1, 2, 0, 3]);
+    }
+
+    #[test]
+    fn test_dbscan_classify_dumbbell() {
+        // Two dense ends along a meridian joined by a bridge point at 0.7 km,
+        // which is within eps of a core point of each end but isn't core itself
+        let km = 1.0 / EARTH_R / DEGREE_RAD;
+        let at = |d: f64| Point([30.0, 60.0 + d * km]);
+        let points: Vec<Point> = [0.0, 0.1, 0.15, 0.2, 0.7, 1.2, 1.25, 1.3, 1.4, 5.0]
+            .into_iter()
+            .map(at)
+            .collect();
+
+        let config = DbscanConfig::new(0.55, 4);
+        let (clusters, noise, types) = config.run_classified(&points);
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(noise, vec![9]);
+
+        use PointType::{Border, Core, Noise};
+        assert_eq!(
+            types,
+            [
+                Core, Core, Core, Core, Border, Core, Core, Core, Core, Noise
+            ]
+        );
+        // The bridge goes to the end found first, but is reachable from both
+        assert!(clusters[0].points.contains(&4));
+        assert_eq!(config.shared_border_points(&points, &clusters, &types), [4]);
+
+        // A border point of a single cluster isn't shared
+        let one_end = points[..5].to_vec();
+        let (one_clusters, _, one_types) = config.run_classified(&one_end);
+        assert_eq!(one_types[4], Border);
+        let shared = config.shared_border_points(&one_end, &one_clusters, &one_types);
+        assert!(shared.is_empty());
+    }
+
+    #[test]
+    fn test_db_scan_nd_spatiotemporal() {
+        // [x km, y km, hours]: two visits to the same place a day apart and
+        // a point nearby in between
+        let visit =
+            |hour: f64| (0..4).map(move |i| Point([i as f64 * 0.1, 0.0, hour + i as f64 * 0.2]));
+        let mut points: PointList<3> = visit(0.0).chain(visit(24.0)).collect();
+        points.push(Point([0.0, 0.0, 12.0]));
+
+        let (clusters, noise) = db_scan_nd(&points, 0.5, 3);
+        let members: Vec<Vec<usize>> = clusters
+            .into_iter()
+            .map(|mut c| {
+                c.points.sort_unstable();
+                c.points
+            })
+            .collect();
+        assert_eq!(members, [vec![0, 1, 2, 3], vec![4, 5, 6, 7]]);
+        assert_eq!(noise, vec![8]);
+
+        // Without time all visits are one cluster
+        let flat: PointList<2> = points.iter().map(|p| Point([p.0[0], p.0[1]])).collect();
+        let (clusters, noise) = db_scan_nd(&flat, 0.5, 3);
+        assert_eq!(clusters.len(), 1);
+        assert!(noise.is_empty());
+    }
+
+    #[test]
+    fn test_db_scan_weighted() {
+        // Two aggregated rows close together and two single pings far away
+        let km = 1.0 / EARTH_R / DEGREE_RAD;
+        let points = vec![
+            Point([30.0, 60.0]),
+            Point([30.0, 60.0 + 0.05 * km]),
+            Point([31.0, 60.0]),
+            Point([31.0, 60.0 + 0.05 * km]),
+        ];
+        let weights = [10.0, 1.0, 1.0, 1.0];
+
+        let (unweighted, noise) = db_scan(&points, 0.1, 3);
+        assert!(unweighted.is_empty());
+        assert_eq!(noise, vec![0, 1, 2, 3]);
+
+        let (clusters, noise) = db_scan_weighted(&points, 0.1, 3, Some(&weights));
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].points, vec![0, 1]);
+        assert_eq!(noise, vec![2, 3]);
+
+        // Without weights every point counts once
+        let (clusters, noise) = db_scan_weighted(&points, 0.1, 2, None);
+        let (expected, expected_noise) = db_scan(&points, 0.1, 2);
+        assert_eq!(clusters.len(), expected.len());
+        assert_eq!(noise, expected_noise);
+    }
+
+    #[test]
+    fn test_min_points_excludes_self() {
+        // Three points within eps of each other: exactly min_points 3
+        // neighbours, each point itself included
+        let km = 1.0 / EARTH_R / DEGREE_RAD;
+        let mut points = vec![
+            Point([30.0, 60.0]),
+            Point([30.0, 60.0 + 0.02 * km]),
+            Point([30.0, 60.0 + 0.04 * km]),
+            Point([31.0, 60.0]),
+        ];
+        let config = DbscanConfig::new(0.1, 3);
+        assert_eq!(config.core_min_points(), 3);
+        let (clusters, noise) = config.run(&points);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].points, vec![0, 1, 2]);
+        assert_eq!(noise, vec![3]);
+
+        // Without the point itself there are only two
+        let excluding = config.min_points_excludes_self(true);
+        assert_eq!(excluding.core_min_points(), 4);
+        let (clusters, noise) = excluding.run(&points);
+        assert!(clusters.is_empty());
+        assert_eq!(noise, vec![0, 1, 2, 3]);
+        let types = excluding.classify(&points, &clusters);
+        assert!(types.iter().all(|&t| t == PointType::Noise));
+
+        // A third neighbour makes them core points again, like min_points 4
+        points.push(Point([30.0, 60.0 + 0.03 * km]));
+        let (clusters, _) = excluding.run(&points);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].points.len(), 4);
+        assert_eq!(
+            excluding.classify(&points, &clusters),
+            DbscanConfig::new(0.1, 4).classify(&points, &clusters)
+        );
+
+        // One observation of a weighted point is left out
+        let weights = [3.0, 0.0, 0.0, 3.0, 0.0];
+        let (clusters, _) = config.weights(&weights).run(&points);
+        assert_eq!(clusters.len(), 2);
+        let (clusters, noise) = excluding.weights(&weights).run(&points);
+        assert!(clusters.is_empty());
+        assert_eq!(noise, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_dbscan_config_builder() {
+        let points = vec![
+            Point([30.0, 60.0]),
+            Point([30.0, 60.0005]),
+            Point([31.0, 60.0]),
+        ];
+        let weights = [2.0, 1.0, 5.0];
+
+        let config = DbscanConfig::default();
+        assert_eq!(config, DbscanConfig::new(0.1, 3));
+        assert_eq!(config.eps(0.2).min_points(5), DbscanConfig::new(0.2, 5));
+
+        // Weights make the lone heavy point a cluster of its own, in every run mode
+        let weighted = DbscanConfig::default().weights(&weights);
+        assert_eq!(weighted.weights, Some(&weights[..]));
+        let (clusters, noise) = weighted.run(&points);
+        let members: Vec<Vec<usize>> = clusters.iter().map(|c| c.points.clone()).collect();
+        assert_eq!(members, [vec![0, 1], vec![2]]);
+        assert!(noise.is_empty());
+        let (expected, _) = db_scan_weighted(&points, 0.1, 3, Some(&weights));
+        assert_eq!(expected.len(), clusters.len());
+
+        let mut found = Vec::new();
+        let noise = weighted.run_with_callback(&points, |c| found.push(c.points.clone()));
+        assert_eq!(found, members);
+        assert!(noise.is_empty());
+    }
+
+    #[test]
+    fn test_db_scan_with_diagnostics() {
+        // A cluster, a pair and a lone point along a meridian
+        let km = 1.0 / EARTH_R / DEGREE_RAD;
+        let at = |d: f64| Point([30.0, 60.0 + d * km]);
+        let points: Vec<Point> = [0.0, 0.02, 0.04, 1.0, 1.05, 3.0, 0.13]
+            .into_iter()
+            .map(at)
+            .collect();
+
+        let (clusters, noise) = db_scan_with_diagnostics(&points, 0.1, 3);
+        let (expected, _) = db_scan(&points, 0.1, 3);
+        assert_eq!(clusters.len(), expected.len());
+        assert_eq!(clusters[0].points, expected[0].points);
+
+        // The border point at 0.13 km is in the cluster, not in noise
+        assert!(clusters[0].points.contains(&6));
+        let info = |index, neighbor_count| NoiseInfo {
+            index,
+            neighbor_count,
+        };
+        assert_eq!(noise, [info(3, 2), info(4, 2), info(5, 1)]);
+
+        // Weights decide the core test like in run, counts stay point counts
+        let weights = [1.0, 1.0, 1.0, 2.0, 1.0, 1.0, 1.0];
+        let config = DbscanConfig::new(0.1, 3).weights(&weights);
+        let (clusters, noise) = config.run_with_diagnostics(&points);
+        let (expected, expected_noise) = config.run(&points);
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(clusters.len(), expected.len());
+        assert_eq!(clusters[1].points, expected[1].points);
+        assert_eq!(noise, [info(5, 1)]);
+        assert_eq!(expected_noise, vec![5]);
+
+        // Progress is reported for every visited point
+        let mut visited = 0;
+        let (_, _, timed_out) = config.run_with_diagnostics_and_progress(&points, |v, total| {
+            assert_eq!(total, points.len());
+            visited = v;
+        });
+        assert!(!timed_out);
+        assert_eq!(visited, points.len());
+    }
+
+    #[test]
+    fn test_region_query_excluding_self() {
+        let points = vec![
+            Point([30.244759, 59.955982]),
+            Point([30.24472, 59.955975]),
+            Point([30.244358, 59.96698]),
+            Point([30.258387, 59.951557]),
+            Point([30.244759, 59.955982]),
+        ];
+        let eps = 0.8 / EARTH_R / DEGREE_RAD;
+
+        // Non-coincident query point: exactly one fewer neighbour
+        let with_self = region_query(&points, &points[1], eps);
+        let others = region_query_excluding_self(&points, 1, eps);
+        assert!(with_self.contains(&1));
+        assert!(!others.contains(&1));
+        assert_eq!(others.len(), with_self.len() - 1);
+
+        // A duplicate of the query point is not the query point itself
+        let others = region_query_excluding_self(&points, 0, eps);
+        assert!(others.contains(&4));
+        assert!(!others.contains(&0));
+    }
+
+    #[test]
+    fn test_region_query_km() {
+        let points = vec![
+            Point([30.244759, 59.955982]),
+            Point([30.24472, 59.955975]),
+            Point([30.244358, 59.96698]),
+            Point([30.258387, 59.951557]),
+            Point([30.244759, 59.955982]),
+        ];
+
+        // Kilometers are converted the way the caller had to do it by hand
+        for eps_km in [0.01, 0.8, 1.3, 5.0] {
+            let eps = eps_km / EARTH_R / DEGREE_RAD;
+            for p in &points {
+                assert_eq!(
+                    region_query_km(&points, p, eps_km),
+                    region_query(&points, p, eps)
+                );
+            }
+        }
+
+        // Zero finds the points at the same coordinates only
+        assert_eq!(region_query_km(&points, &points[0], 0.0), vec![0, 4]);
+        assert_eq!(region_query_km(&points, &points[1], 0.0), vec![1]);
+    }
+
+    #[test]
+    fn test_config_validate() {
+        let points = vec![
+            Point([30.0, 60.0]),
+            Point([30.0, 60.0]),
+            Point([31.0, 60.0]),
+        ];
+        assert!(DbscanConfig::new(0.1, 3).validate().is_ok());
+        // Zero eps clusters identical coordinates, it isn't an error
+        let (clusters, _) = DbscanConfig::new(0.0, 2).try_run(&points).unwrap();
+        assert_eq!(clusters[0].points, vec![0, 1]);
+
+        let err = DbscanConfig::new(0.1, 0).try_run(&points).unwrap_err();
+        assert!(err.contains("min_points"), "{}", err);
+        for eps in [-0.1, f64::NAN, f64::INFINITY] {
+            let err = DbscanConfig::new(eps, 3).validate().unwrap_err();
+            assert!(err.contains("eps"), "{}", err);
+        }
+        let err = DbscanConfig::new(0.1, 3)
+            .earth_radius(0.0)
+            .validate()
+            .unwrap_err();
+        assert!(err.contains("earth radius"), "{}", err);
+    }
+
+    #[test]
+    fn test_dbscan_with_progress() {
+        // 250 groups of 4 points each
+        let points: Vec<Point> = (0..1000)
+            .map(|i| Point([30.0 + (i / 4) as f64 * 0.01 + (i % 4) as f64 * 1e-5, 60.0]))
+            .collect();
+        let mut reports = Vec::new();
+        let (clusters, noise) = db_scan_with_progress(&points, 0.1, 3, |visited, total| {
+            reports.push((visited, total))
+        });
+
+        // Every 1%, the last report covers all points
+        assert_eq!(reports.len(), 100);
+        assert_eq!(reports[0], (10, 1000));
+        assert_eq!(reports.last(), Some(&(1000, 1000)));
+        assert!(reports.windows(2).all(|w| w[0].0 < w[1].0));
+
+        let (expected, expected_noise) = db_scan(&points, 0.1, 3);
+        assert_eq!(clusters.len(), expected.len());
+        assert_eq!(noise, expected_noise);
+
+        // Fewer points than percent steps report every point
+        let mut count = 0;
+        db_scan_with_progress(&points[..5].to_vec(), 0.1, 3, |_, _| count += 1);
+        assert_eq!(count, 5);
+    }
+
+    #[test]
+    fn test_dbscan_with_times() {
+        // Two visits of the same place three hours apart, a few metres apart
+        let points: Vec<Point> = (0..6)
+            .map(|i| Point([30.0 + (i % 3) as f64 * 1e-5, 60.0]))
+            .collect();
+        let times = [0, 60, 120, 10800, 10860, 10920];
+
+        let (clusters, noise) = db_scan_with_times(&points, 0.1, 3, &times, 600);
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(clusters[0].points, vec![0, 1, 2]);
+        assert_eq!(clusters[1].points, vec![3, 4, 5]);
+        assert!(noise.is_empty());
+
+        // A wide enough time window is plain DBSCAN
+        let (clusters, _) = db_scan_with_times(&points, 0.1, 3, &times, 86400);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].points, db_scan(&points, 0.1, 3).0[0].points);
+
+        // Too short for any neighbour
+        let (clusters, noise) = db_scan_with_times(&points, 0.1, 2, &times, 30);
+        assert!(clusters.is_empty());
+        assert_eq!(noise.len(), 6);
+    }
+
+    #[test]
+    fn test_dbscan_with_accuracy() {
+        // Three points 1 km apart along a meridian
+        let km = 1.0 / EARTH_R / DEGREE_RAD;
+        let points: Vec<Point> = (0..3)
+            .map(|i| Point([30.0, 60.0 + i as f64 * km]))
+            .collect();
+        let labels = |config: DbscanConfig, accuracy: &[f64], mode| {
+            let (clusters, _, _) = config.run_with_accuracy(&points, accuracy, mode);
+            clusters.iter().map(|c| c.points.len()).collect::<Vec<_>>()
+        };
+
+        // Zero accuracy reproduces standard DBSCAN in both modes
+        for (eps, expected) in [(0.9, vec![]), (1.1, vec![3])] {
+            let config = DbscanConfig::new(eps, 2);
+            let (clusters, _) = config.run(&points);
+            assert_eq!(clusters.len(), expected.len());
+            for mode in [AccuracyMode::Liberal, AccuracyMode::Conservative] {
+                assert_eq!(labels(config, &[0.0; 3], mode), expected);
+            }
+        }
+
+        // Liberal: 0.9 + 0.1 + 0.1 km reaches the neighbours
+        let accuracy = [0.1, 0.1, 0.1];
+        let config = DbscanConfig::new(0.9, 2);
+        assert_eq!(labels(config, &accuracy, AccuracyMode::Liberal), vec![3]);
+
+        // Only the first two points are uncertain enough to become neighbours
+        let accuracy = [0.1, 0.1, 0.0];
+        assert_eq!(labels(config, &accuracy, AccuracyMode::Liberal), vec![2]);
+
+        // Conservative: 1.1 - 0.1 - 0.1 km no longer reaches the neighbours
+        let accuracy = [0.1, 0.1, 0.1];
+        let config = DbscanConfig::new(1.1, 2);
+        assert_eq!(
+            labels(config, &accuracy, AccuracyMode::Conservative),
+            Vec::<usize>::new()
+        );
+    }
+
+    #[test]
+    fn test_dbscan_earth_radius() {
+        // 0.05 degrees apart: ~5.6 km on Earth, ~3 km on Mars
+        let points = vec![
+            Point([10.0, 20.0]),
+            Point([10.05, 20.0]),
+            Point([10.1, 20.0]),
+            Point([40.0, 20.0]),
+        ];
+        const MARS_R: f64 = 3389.5;
+
+        let (clusters, _) = db_scan(&points, 4.0, 2);
+        assert!(clusters.is_empty());
+
+        let (clusters, noise) = db_scan_with_radius(&points, 4.0, 2, MARS_R);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].points, vec![0, 1, 2]);
+        assert_eq!(noise, vec![3]);
+
+        // The default radius reproduces db_scan, for every metric
+        for metric in [
+            DistanceMetric::SphericalFast,
+            DistanceMetric::Spherical,
+            DistanceMetric::Haversine,
+        ] {
+            let config = DbscanConfig::new(6.0, 2).metric(metric);
+            assert_eq!(config.earth_radius, EARTH_R);
+            let (clusters, _) = config.run(&points);
+            assert_eq!(clusters.len(), 1);
+            let (clusters, _) = config.earth_radius(MARS_R).run(&points);
+            assert_eq!(clusters.len(), 1);
+            let (clusters, _) = config.earth_radius(2.0 * EARTH_R).run(&points);
+            assert!(clusters.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_dbscan_antimeridian() {
+        // ~3.3 km apart along the equator, two on each side of the antimeridian
+        let points = vec![
+            Point([179.95, 0.0]),
+            Point([179.98, 0.0]),
+            Point([-179.99, 0.0]),
+            Point([-179.96, 0.0]),
+            Point([0.0, 0.0]),
+        ];
+        for index in [IndexKind::KdTree, IndexKind::Grid, IndexKind::BruteForce] {
+            for metric in [
+                DistanceMetric::SphericalFast,
+                DistanceMetric::Spherical,
+                DistanceMetric::Haversine,
+            ] {
+                let (clusters, noise) = DbscanConfig::new(5.0, 2)
+                    .index(index)
+                    .metric(metric)
+                    .run(&points);
+                assert_eq!(clusters.len(), 1, "{} {:?}", index, metric);
+                let mut members = clusters[0].points.clone();
+                members.sort_unstable();
+                assert_eq!(members, vec![0, 1, 2, 3], "{} {:?}", index, metric);
+                assert_eq!(noise, vec![4]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_label_points() {
+        let points = vec![
+            Point([30.0, 60.0]),
+            Point([40.0, 50.0]),
+            Point([30.0001, 60.0]),
+            Point([40.0001, 50.0]),
+            Point([30.0002, 60.0]),
+            Point([40.0002, 50.0]),
+            Point([10.0, 10.0]),
+        ];
+        assert_eq!(label_points(&points, 0.1, 3), vec![0, 1, 0, 1, 0, 1, -1]);
+        assert_eq!(label_points(&points, 0.1, 4), vec![-1; 7]);
+        assert!(label_points(&Vec::new(), 0.1, 3).is_empty());
+    }
+
+    #[test]
+    fn test_estimate_eps() {
+        // Two dense blobs (~10 m spacing) far apart plus a few scattered points
+        let mut points = Vec::new();
+        for (lon, lat) in [(30.0, 60.0), (30.5, 60.2)] {
+            for i in 0..100 {
+                points.push(Point([
+                    lon + (i % 10) as f64 * 0.00018,
+                    lat + (i / 10) as f64 * 0.00009,
+                ]));
+            }
+        }
+        for i in 0..5 {
+            points.push(Point([31.0 + i as f64 * 0.3, 61.0 + i as f64 * 0.1]));
+        }
+
+        let eps = estimate_eps(&points, 4);
+        assert!(eps > 0.009 && eps < 0.1, "{}", eps);
+
+        // The estimate separates blobs from the scattered points
+        let labels = label_points(&points, eps, 4);
+        assert!(labels[..100].iter().all(|&l| l == 0));
+        assert!(labels[100..200].iter().all(|&l| l == 1));
+        assert_eq!(labels[200..], [-1; 5]);
+
+        assert_eq!(estimate_eps(&Vec::new(), 4), 0.0);
+        assert_eq!(estimate_eps(&vec![Point([30.0, 60.0]); 5], 3), 0.0);
+    }
+
+    #[test]
+    fn test_core_points() {
+        // 5x5 grid ~111 m apart at the equator, eps covers the diagonal
+        // neighbours: interior points have 9 points within eps, edge points 6
+        // and corners 4
+        let points: PointList = (0..25)
+            .map(|i| Point([(i % 5) as f64 * 0.001, (i / 5) as f64 * 0.001]))
+            .collect();
+        let (clusters, noise, cores) = db_scan_with_cores(&points, 0.2, 7);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].points.len(), 25);
+        // Borders visited before the first core aren't noise
+        assert!(noise.is_empty());
+        assert_eq!(cores, vec![6, 7, 8, 11, 12, 13, 16, 17, 18]);
+
+        // Edge points are borders, the same roles a separate pass finds
+        let config = DbscanConfig::new(0.2, 7);
+        let (clusters, _, types) = config.run_classified(&points);
+        assert_eq!(types, config.classify(&points, &clusters));
+        assert_eq!(types[0], PointType::Border);
+        assert_eq!(types[2], PointType::Border);
+        assert_eq!(types[12], PointType::Core);
+
+        // Without cores everything is noise
+        let (clusters, noise, cores) = db_scan_with_cores(&points, 0.2, 10);
+        assert!(clusters.is_empty() && cores.is_empty());
+        assert_eq!(noise.len(), 25);
+    }
+
+    #[test]
+    fn test_merge_close_clusters() {
+        // Two small clusters ~220 m apart and one ~11 km away from both
+        let points = vec![
+            Point([30.0, 60.0]),
+            Point([30.0001, 60.0]),
+            Point([30.0, 60.1]),
+            Point([30.0001, 60.1]),
+            Point([30.0, 60.002]),
+            Point([30.0001, 60.002]),
+        ];
+        let clusters = vec![
+            Cluster {
+                c: 0,
+                points: vec![0, 1],
+            },
+            Cluster {
+                c: 1,
+                points: vec![2, 3],
+            },
+            Cluster {
+                c: 2,
+                points: vec![4, 5],
+            },
+        ];
+
+        let merged = merge_close_clusters(clusters.clone(), &points, 0.5);
+        assert_eq!(merged.len(), 2);
+        assert_eq!((merged[0].c, &merged[0].points), (0, &vec![0, 1, 4, 5]));
+        assert_eq!((merged[1].c, &merged[1].points), (1, &vec![2, 3]));
+
+        // Below the centroid distance nothing merges
+        let merged = merge_close_clusters(clusters.clone(), &points, 0.1);
+        let parts: Vec<Vec<usize>> = merged.into_iter().map(|c| c.points).collect();
+        assert_eq!(parts, vec![vec![0, 1], vec![2, 3], vec![4, 5]]);
+        // Chains of close centroids merge transitively
+        let merged = merge_close_clusters(clusters, &points, 11.0);
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].points, vec![0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_k_distances() {
+        // 0.01 degrees of latitude apart, about 1.11 km
+        let points: PointList = [0.0, 0.01, 0.02, 0.05]
+            .iter()
+            .map(|&lat| Point([30.0, 60.0 + lat]))
+            .collect();
+        let step = points[0].dist_km(&points[1]);
+
+        let k_dist = k_distances(&points, 2);
+        let expected = [step, step, step, 3.0 * step];
+        assert_eq!(k_dist.len(), expected.len());
+        for (d, e) in k_dist.iter().zip(expected) {
+            assert!((d - e).abs() < 1e-9, "{:?}", k_dist);
+        }
+
+        // The point itself is its first nearest point
+        assert_eq!(k_distances(&points, 1), vec![0.0; 4]);
+        assert!(k_distances(&Vec::new(), 2).is_empty());
+    }
+
+    #[test]
+    fn test_k_distances_planar() {
+        // Projected coordinates, 100 units apart along x, one point far away
+        let points: PointList = [0.0, 100.0, 200.0, 1000.0]
+            .iter()
+            .map(|&x| Point([x, 50.0]))
+            .collect();
+        for metric in [
+            DistanceMetric::SquaredEuclidean,
+            DistanceMetric::Manhattan,
+            DistanceMetric::Chebyshev,
+        ] {
+            assert_eq!(
+                k_distances_with_metric(&points, 2, metric),
+                vec![100.0, 100.0, 100.0, 800.0]
+            );
+            assert_eq!(estimate_eps_with_metric(&points, 2, metric), 100.0);
+        }
+
+        // Diagonal neighbours differ by metric
+        let points = vec![Point([0.0, 0.0]), Point([3.0, 4.0])];
+        let k_dist = |metric| k_distances_with_metric(&points, 2, metric);
+        assert_eq!(k_dist(DistanceMetric::SquaredEuclidean), vec![5.0, 5.0]);
+        assert_eq!(k_dist(DistanceMetric::Manhattan), vec![7.0, 7.0]);
+        assert_eq!(k_dist(DistanceMetric::Chebyshev), vec![4.0, 4.0]);
+    }
 }
@@ -17,32 +17,68 @@ use super::point::Point;
 /// # Returns
 ///
 /// Distance in kilometers
-#[allow(dead_code)] // Part of public API, may be used by external code
 pub fn distance_spherical(p1: &Point, p2: &Point) -> f64 {
+    distance_spherical_with_radius(p1, p2, EARTH_R)
+}
+
+/// Calculates spherical (optimized) distance between two points on a sphere
+/// of the given radius
+///
+/// # Returns
+///
+/// Distance in the unit of `radius`
+pub fn distance_spherical_with_radius(p1: &Point, p2: &Point, radius: f64) -> f64 {
     let v1 = (p1.0[1] - p2.0[1]) * DEGREE_RAD;
     let v1 = v1 * v1;
 
-    let v2 = (p1.0[0] - p2.0[0]) * DEGREE_RAD * ((p1.0[1] + p2.0[1]) / 2.0 * DEGREE_RAD).cos();
+    let v2 = wrap_lon_delta(p1.0[0] - p2.0[0])
+        * DEGREE_RAD
+        * ((p1.0[1] + p2.0[1]) / 2.0 * DEGREE_RAD).cos();
     let v2 = v2 * v2;
 
-    EARTH_R * (v1 + v2).sqrt()
+    radius * (v1 + v2).sqrt()
+}
+
+/// Wraps a longitude difference in degrees into `[-180, 180]`
+///
+/// Points at longitudes 179.9 and -179.9 are 0.2 degrees apart across the
+/// antimeridian, not 359.8. Differences of longitudes in `[-180, 180]` need
+/// at most one turn.
+pub fn wrap_lon_delta(d_lon: f64) -> f64 {
+    if d_lon > 180.0 {
+        d_lon - 360.0
+    } else if d_lon < -180.0 {
+        d_lon + 360.0
+    } else {
+        d_lon
+    }
+}
+
+/// Returns whether a point at longitude `lon` is found by the query from
+/// longitude `query_lon` rather than from the same query moved across the
+/// antimeridian (`moved`), see [`antimeridian_lon`]
+///
+/// Every point is found by exactly one of both queries.
+pub(crate) fn in_lon_window(query_lon: f64, lon: f64, moved: bool) -> bool {
+    let d_lon = (query_lon - lon).abs();
+    if moved { d_lon < 180.0 } else { d_lon <= 180.0 }
 }
 
 /// Calculates sine approximated to parabola
 ///
 /// Taken from: <http://forum.devmaster.net/t/fast-and-accurate-sine-cosine/9648>
 ///
-/// # Panics
-///
-/// Panics if `x` is outside the range `[-PI, PI]`
+/// Arguments outside of `[-PI, PI]` are reduced into it first.
 pub fn fast_sine(x: f64) -> f64 {
     const B: f64 = 4.0 / PI;
     const C: f64 = -4.0 / (PI * PI);
     const P: f64 = 0.225;
 
-    if !(-PI..=PI).contains(&x) {
-        panic!("out of range");
-    }
+    let x = if (-PI..=PI).contains(&x) {
+        x
+    } else {
+        (x + PI).rem_euclid(2.0 * PI) - PI
+    };
 
     let y = B * x + C * x * x.abs();
     P * (y * y.abs() - y) + y
@@ -50,12 +86,7 @@ pub fn fast_sine(x: f64) -> f64 {
 
 /// Calculates cosine from sine
 pub fn fast_cos(x: f64) -> f64 {
-    let mut x = x + PI / 2.0;
-    while x > PI {
-        x -= 2.0 * PI;
-    }
-
-    fast_sine(x)
+    fast_sine(x + PI / 2.0)
 }
 
 /// Calculates spherical distance with fast cosine
@@ -66,12 +97,461 @@ pub fn fast_cos(x: f64) -> f64 {
 /// In this library eps (distance) is adjusted so that we don't need
 /// to do sqrt and multiplication
 pub fn distance_spherical_fast(p1: &Point, p2: &Point) -> f64 {
-    let v1 = p1.0[1] - p2.0[1];
-    let v2 = (p1.0[0] - p2.0[0]) * fast_cos((p1.0[1] + p2.0[1]) / 2.0 * DEGREE_RAD);
+    distance_spherical_fast_prepared(&PreparedPoint::from(p1), &PreparedPoint::from(p2))
+}
+
+/// Point with half of its latitude in radians computed once
+///
+/// [`distance_spherical_fast`] takes the cosine of the mean latitude of both
+/// points, with the halves at hand that's a single addition per pair.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PreparedPoint {
+    lon: f64,
+    lat: f64,
+    half_lat_rad: f64,
+}
+
+impl From<&Point> for PreparedPoint {
+    fn from(p: &Point) -> Self {
+        PreparedPoint {
+            lon: p.0[0],
+            lat: p.0[1],
+            half_lat_rad: p.0[1] * (DEGREE_RAD / 2.0),
+        }
+    }
+}
+
+impl PreparedPoint {
+    /// Returns the squared `[longitude, latitude]` terms of
+    /// [`distance_spherical_fast_prepared`]
+    pub(crate) fn sq_deltas(&self, other: &PreparedPoint) -> [f64; 2] {
+        let v1 = self.lat - other.lat;
+        let v2 =
+            wrap_lon_delta(self.lon - other.lon) * fast_cos(self.half_lat_rad + other.half_lat_rad);
+
+        [v2 * v2, v1 * v1]
+    }
+}
+
+/// Same as [`distance_spherical_fast`] for prepared points
+pub(crate) fn distance_spherical_fast_prepared(p1: &PreparedPoint, p2: &PreparedPoint) -> f64 {
+    let [d_lon, d_lat] = p1.sq_deltas(p2);
+    d_lat + d_lon
+}
+
+/// Calculates plain squared Euclidean distance between two points
+///
+/// Coordinates are not scaled in any way, which makes K-D tree pruning exact
+/// under this metric.
+pub fn distance_squared_euclidean(p1: &Point, p2: &Point) -> f64 {
+    let v1 = p1.0[0] - p2.0[0];
+    let v2 = p1.0[1] - p2.0[1];
 
     v1 * v1 + v2 * v2
 }
 
+/// Calculates the Manhattan (L1) distance between two points, the sum of
+/// their absolute coordinate differences
+///
+/// Like [`distance_squared_euclidean`], coordinates are not scaled, so it
+/// suits projected coordinates such as meters on a city grid.
+pub fn distance_manhattan(p1: &Point, p2: &Point) -> f64 {
+    (p1.0[0] - p2.0[0]).abs() + (p1.0[1] - p2.0[1]).abs()
+}
+
+/// Calculates the Chebyshev (L∞) distance between two points, the largest
+/// of their absolute coordinate differences
+///
+/// Coordinates are not scaled, see [`distance_manhattan`].
+pub fn distance_chebyshev(p1: &Point, p2: &Point) -> f64 {
+    (p1.0[0] - p2.0[0]).abs().max((p1.0[1] - p2.0[1]).abs())
+}
+
+/// Calculates great-circle distance between two points using the haversine formula
+///
+/// Exact on a sphere at any distance, but slower than the approximations above.
+///
+/// # Returns
+///
+/// Distance in kilometers
+pub fn distance_haversine(p1: &Point, p2: &Point) -> f64 {
+    distance_haversine_with_radius(p1, p2, EARTH_R)
+}
+
+/// Calculates great-circle distance between two points on a sphere of the
+/// given radius using the haversine formula
+///
+/// # Returns
+///
+/// Distance in the unit of `radius`
+pub fn distance_haversine_with_radius(p1: &Point, p2: &Point, radius: f64) -> f64 {
+    let d_lat = (p2.0[1] - p1.0[1]) * DEGREE_RAD;
+    let d_lon = (p2.0[0] - p1.0[0]) * DEGREE_RAD;
+
+    let a = (d_lat / 2.0).sin().powi(2)
+        + (p1.0[1] * DEGREE_RAD).cos() * (p2.0[1] * DEGREE_RAD).cos() * (d_lon / 2.0).sin().powi(2);
+
+    2.0 * radius * a.sqrt().min(1.0).asin()
+}
+
+/// Smallest cosine used when bounding longitude deltas near the poles
+pub(crate) const MIN_COS: f64 = 1e-6;
+
+/// Distance metric used for neighbourhood queries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DistanceMetric {
+    /// [`distance_spherical_fast`]
+    #[default]
+    SphericalFast,
+    /// [`distance_spherical`], equirectangular distance in kilometers
+    Spherical,
+    /// [`distance_haversine`], exact great-circle distance
+    Haversine,
+    /// [`distance_squared_euclidean`], no geographic correction
+    SquaredEuclidean,
+    /// [`distance_manhattan`], no geographic correction
+    Manhattan,
+    /// [`distance_chebyshev`], no geographic correction
+    Chebyshev,
+}
+
+impl DistanceMetric {
+    /// Returns whether the metric compares plain coordinates rather than
+    /// longitude/latitude degrees
+    ///
+    /// Planar metrics measure eps in coordinate units, e.g. meters of a
+    /// projected CRS, and don't wrap around the antimeridian.
+    pub fn is_planar(self) -> bool {
+        matches!(
+            self,
+            DistanceMetric::SquaredEuclidean
+                | DistanceMetric::Manhattan
+                | DistanceMetric::Chebyshev
+        )
+    }
+
+    /// Returns squared (without sqrt & normalization) distance between two points
+    ///
+    /// The unit depends on the metric, use [`DistanceMetric::scale_eps`] to get
+    /// a comparable radius.
+    pub fn sq_dist(self, p1: &Point, p2: &Point) -> f64 {
+        match self {
+            DistanceMetric::SphericalFast => distance_spherical_fast(p1, p2),
+            DistanceMetric::Spherical => {
+                let d = distance_spherical(p1, p2);
+                d * d
+            }
+            DistanceMetric::Haversine => {
+                let d = distance_haversine(p1, p2);
+                d * d
+            }
+            DistanceMetric::SquaredEuclidean => distance_squared_euclidean(p1, p2),
+            DistanceMetric::Manhattan => {
+                let d = distance_manhattan(p1, p2);
+                d * d
+            }
+            DistanceMetric::Chebyshev => {
+                let d = distance_chebyshev(p1, p2);
+                d * d
+            }
+        }
+    }
+
+    /// Converts a radius into the unit of [`DistanceMetric::sq_dist`] (before
+    /// squaring)
+    ///
+    /// The radius is in kilometers for geographic metrics.
+    /// [Planar](DistanceMetric::is_planar) metrics have no geographic unit,
+    /// `eps` is returned as-is and is interpreted in coordinate units.
+    pub fn scale_eps(self, eps: f64) -> f64 {
+        match self {
+            // Our SphericalDistanceFast returns distance which is not mutiplied
+            // by EarthR * DegreeRad, adjust eps accordingly
+            DistanceMetric::SphericalFast => eps / EARTH_R / DEGREE_RAD,
+            _ => eps,
+        }
+    }
+
+    /// Converts a radius in kilometers on a sphere of `earth_radius` km into
+    /// the unit of [`DistanceMetric::sq_dist`] (before squaring)
+    ///
+    /// Geographic distances are proportional to the sphere's radius, so `eps`
+    /// is converted to the same angle on a sphere of [`EARTH_R`] first.
+    /// [Planar](DistanceMetric::is_planar) metrics ignore the radius.
+    pub fn scale_eps_with_radius(self, eps: f64, earth_radius: f64) -> f64 {
+        match self {
+            _ if self.is_planar() => eps,
+            _ => self.scale_eps(eps * EARTH_R / earth_radius),
+        }
+    }
+
+    /// Returns the largest `[lon, lat]` coordinate deltas of two points closer than
+    /// `dist` (in [`DistanceMetric::scale_eps`] units) when neither of them is
+    /// beyond `max_abs_lat` degrees of latitude
+    ///
+    /// The longitude bound is infinite when the points may be on opposite sides of a pole.
+    pub fn max_deltas(self, dist: f64, max_abs_lat: f64) -> [f64; 2] {
+        let max_abs_lat = max_abs_lat.min(90.0) * DEGREE_RAD;
+        match self {
+            DistanceMetric::SphericalFast => [dist / fast_cos(max_abs_lat).max(MIN_COS), dist],
+            DistanceMetric::Spherical => {
+                // The mean latitude of both points is never beyond max_abs_lat
+                let d_lat = dist / EARTH_R / DEGREE_RAD;
+                [d_lat / max_abs_lat.cos().max(MIN_COS), d_lat]
+            }
+            DistanceMetric::Haversine => {
+                // hav(d) = hav(dLat) + cos(lat1) * cos(lat2) * hav(dLon) >= cos^2(maxLat) * hav(dLon)
+                let x = (dist / EARTH_R / 2.0).min(PI / 2.0).sin() / max_abs_lat.cos();
+                let d_lon = if x < 1.0 {
+                    2.0 * x.asin() / DEGREE_RAD
+                } else {
+                    f64::INFINITY
+                };
+                [d_lon, dist / EARTH_R / DEGREE_RAD]
+            }
+            // No coordinate difference exceeds the distance
+            DistanceMetric::SquaredEuclidean
+            | DistanceMetric::Manhattan
+            | DistanceMetric::Chebyshev => [dist, dist],
+        }
+    }
+}
+
+/// Returns the longitude of a query point at `lon` moved by 360 degrees
+/// across the antimeridian, when points up to `d_lon` degrees of longitude
+/// away may be on the other side of it
+///
+/// Spatial indexes compare raw coordinates, so such queries are run again
+/// from the moved point, keeping the points [`in_lon_window`] of each.
+/// Always `None` for [planar](Metric::is_planar) metrics, which don't wrap.
+pub(crate) fn antimeridian_lon(metric: &impl Metric, lon: f64, d_lon: f64) -> Option<f64> {
+    match () {
+        _ if metric.is_planar() => None,
+        // Points beyond 180 degrees of longitude away are on the far side
+        // of the antimeridian closer to the query point
+        _ if lon >= 0.0 && lon + d_lon > 180.0 => Some(lon - 360.0),
+        _ if lon < 0.0 && lon - d_lon < -180.0 => Some(lon + 360.0),
+        _ => None,
+    }
+}
+
+mod sealed {
+    /// Keeps [`Metric`](super::Metric) implementable only in this crate,
+    /// K-D tree pruning relies on the contracts of its hooks
+    pub trait Sealed {}
+
+    impl Sealed for super::SphericalFast {}
+    impl Sealed for super::Euclidean {}
+    impl Sealed for super::Manhattan {}
+    impl Sealed for super::Chebyshev {}
+    impl Sealed for super::DistanceMetric {}
+}
+
+/// Distance between 2D points that K-D trees are queried with
+///
+/// Besides the distance, a metric bounds how close points on the other side
+/// of a splitting plane can be, see the pruning section of
+/// [`KDTree`](super::kdtree::KDTree). A hook that breaks its contract makes
+/// queries silently miss neighbours, so the trait is sealed: it is
+/// implemented by [`SphericalFast`], [`Euclidean`], [`Manhattan`],
+/// [`Chebyshev`] and [`DistanceMetric`], which adapts the metric selected at
+/// run time. The provided methods suit planar metrics that are never below
+/// the difference of a single coordinate (axis-aligned separability).
+pub trait Metric: Copy + sealed::Sealed {
+    /// Returns squared (without sqrt & normalization) distance between two points
+    ///
+    /// Must be symmetric, non-negative and `0.0` for equal points. Points are
+    /// within eps of each other when it is at most the square of
+    /// [`Metric::scale_eps`] of eps.
+    fn sq_dist(&self, p1: &Point, p2: &Point) -> f64;
+
+    /// Converts a radius into the unit of [`Metric::sq_dist`] (before squaring)
+    ///
+    /// The radius is in kilometers for geographic metrics and in coordinate
+    /// units for [planar](Metric::is_planar) ones, which return it as-is.
+    fn scale_eps(&self, eps: f64) -> f64 {
+        eps
+    }
+
+    /// Returns whether the metric compares plain coordinates rather than
+    /// longitude/latitude degrees, see [`DistanceMetric::is_planar`]
+    ///
+    /// Queries of non-planar metrics are repeated across the antimeridian.
+    fn is_planar(&self) -> bool {
+        true
+    }
+
+    /// Returns the largest `[lon, lat]` coordinate deltas of two points closer
+    /// than `dist`, see [`DistanceMetric::max_deltas`]
+    ///
+    /// Must never underestimate: two points of at most `max_abs_lat` degrees
+    /// of latitude with a [`Metric::sq_dist`] of at most `dist * dist` differ
+    /// by no more than the returned deltas (longitudes after
+    /// [`wrap_lon_delta`]). Infinite when there is no bound.
+    fn max_deltas(&self, dist: f64, _max_abs_lat: f64) -> [f64; 2] {
+        [dist, dist]
+    }
+
+    /// Returns the factors of `[lon, lat]` deltas in [`Metric::plane_sq_dist`]
+    /// for points within `r` of `pt`
+    ///
+    /// Only passed back to [`Metric::plane_sq_dist`], the factors must make
+    /// its result a lower bound for any point within `r` of `pt`.
+    fn plane_scales(&self, _pt: &Point, _r: f64) -> [f64; 2] {
+        [1.0, 1.0]
+    }
+
+    /// Returns a lower bound of the squared distance from a query point to
+    /// points on the other side of a plane splitting dimension `split`,
+    /// `diff` degrees (coordinate units) away, given the query point's
+    /// [`Metric::plane_scales`]
+    ///
+    /// Must not exceed [`Metric::sq_dist`] of the query point and any such
+    /// point within the `r` the scales were computed for, and must not
+    /// decrease as `diff` grows, otherwise pruned subtrees may hold
+    /// neighbours.
+    fn plane_sq_dist(&self, diff: f64, split: usize, scales: [f64; 2]) -> f64 {
+        let d = diff * scales[split];
+        d * d
+    }
+
+    /// Returns `p` prepared for faster distances, `None` unless
+    /// [`Metric::sq_dist`] is [`distance_spherical_fast`]
+    ///
+    /// When `Some`, distances of prepared points must equal
+    /// [`Metric::sq_dist`] of the points, the K-D tree uses them instead.
+    fn prepare(&self, _p: &Point) -> Option<PreparedPoint> {
+        None
+    }
+}
+
+/// [`distance_spherical_fast`] as a [`Metric`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SphericalFast;
+
+/// [`distance_squared_euclidean`] as a [`Metric`], eps in coordinate units
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Euclidean;
+
+/// [`distance_manhattan`] as a [`Metric`], eps in coordinate units
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Manhattan;
+
+/// [`distance_chebyshev`] as a [`Metric`], eps in coordinate units
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Chebyshev;
+
+impl Metric for SphericalFast {
+    fn sq_dist(&self, p1: &Point, p2: &Point) -> f64 {
+        distance_spherical_fast(p1, p2)
+    }
+
+    fn scale_eps(&self, eps: f64) -> f64 {
+        DistanceMetric::SphericalFast.scale_eps(eps)
+    }
+
+    fn is_planar(&self) -> bool {
+        false
+    }
+
+    fn max_deltas(&self, dist: f64, max_abs_lat: f64) -> [f64; 2] {
+        DistanceMetric::SphericalFast.max_deltas(dist, max_abs_lat)
+    }
+
+    fn plane_scales(&self, pt: &Point, r: f64) -> [f64; 2] {
+        DistanceMetric::SphericalFast.plane_scales(pt, r)
+    }
+
+    fn prepare(&self, p: &Point) -> Option<PreparedPoint> {
+        Some(PreparedPoint::from(p))
+    }
+}
+
+impl Metric for Euclidean {
+    fn sq_dist(&self, p1: &Point, p2: &Point) -> f64 {
+        distance_squared_euclidean(p1, p2)
+    }
+}
+
+impl Metric for Manhattan {
+    fn sq_dist(&self, p1: &Point, p2: &Point) -> f64 {
+        let d = distance_manhattan(p1, p2);
+        d * d
+    }
+}
+
+impl Metric for Chebyshev {
+    fn sq_dist(&self, p1: &Point, p2: &Point) -> f64 {
+        let d = distance_chebyshev(p1, p2);
+        d * d
+    }
+}
+
+impl Metric for DistanceMetric {
+    fn sq_dist(&self, p1: &Point, p2: &Point) -> f64 {
+        DistanceMetric::sq_dist(*self, p1, p2)
+    }
+
+    fn scale_eps(&self, eps: f64) -> f64 {
+        DistanceMetric::scale_eps(*self, eps)
+    }
+
+    fn is_planar(&self) -> bool {
+        DistanceMetric::is_planar(*self)
+    }
+
+    fn max_deltas(&self, dist: f64, max_abs_lat: f64) -> [f64; 2] {
+        DistanceMetric::max_deltas(*self, dist, max_abs_lat)
+    }
+
+    /// A point across a parallel is at least the latitude delta away. Across
+    /// a meridian the longitude delta shrinks towards the poles, by the
+    /// cosine of the mean latitude of both points for the equirectangular
+    /// metrics. The other point may be anywhere within `r`, so its mean
+    /// latitude with `pt` may be up to `r / 2` farther from the equator than
+    /// `pt`. [`DistanceMetric::Haversine`] takes the cosine of `pt`'s own
+    /// latitude instead, see [`Metric::plane_sq_dist`]. Coordinates of planar
+    /// metrics aren't scaled.
+    fn plane_scales(&self, pt: &Point, r: f64) -> [f64; 2] {
+        let farthest_lat = |r_deg: f64| (pt.0[1].abs() + r_deg / 2.0).min(90.0) * DEGREE_RAD;
+        match self {
+            DistanceMetric::SphericalFast => [fast_cos(farthest_lat(r)).max(0.0), 1.0],
+            DistanceMetric::Spherical => {
+                let km = EARTH_R * DEGREE_RAD;
+                [km * farthest_lat(r / km).cos().max(0.0), km]
+            }
+            DistanceMetric::Haversine => {
+                [(pt.0[1] * DEGREE_RAD).cos().max(0.0), EARTH_R * DEGREE_RAD]
+            }
+            DistanceMetric::SquaredEuclidean
+            | DistanceMetric::Manhattan
+            | DistanceMetric::Chebyshev => [1.0, 1.0],
+        }
+    }
+
+    /// Under [`DistanceMetric::Haversine`] the bound across a meridian is the
+    /// great-circle distance to it, `asin(cos(lat) * sin(d_lon))` (up to
+    /// 90 degrees, beyond which the other points are in the other
+    /// hemisphere), as close to the meridian as points can get.
+    fn plane_sq_dist(&self, diff: f64, split: usize, scales: [f64; 2]) -> f64 {
+        let d = match (self, split) {
+            (DistanceMetric::Haversine, 0) => {
+                let d_lon = diff.abs().min(90.0) * DEGREE_RAD;
+                EARTH_R * (scales[0] * d_lon.sin()).min(1.0).asin()
+            }
+            _ => diff * scales[split],
+        };
+        d * d
+    }
+
+    fn prepare(&self, p: &Point) -> Option<PreparedPoint> {
+        match self {
+            DistanceMetric::SphericalFast => SphericalFast.prepare(p),
+            _ => None,
+        }
+    }
+}
+
 // Re-export with Go-style names for compatibility
 pub use distance_spherical as DistanceSpherical;
 pub use distance_spherical_fast as DistanceSphericalFast;
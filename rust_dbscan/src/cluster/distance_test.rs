@@ -2,7 +2,10 @@
 mod tests {
     use crate::cluster::Point;
     use crate::cluster::distance::{
-        DEGREE_RAD, EARTH_R, distance_spherical, distance_spherical_fast, fast_cos,
+        DEGREE_RAD, DistanceMetric, EARTH_R, PreparedPoint, distance_chebyshev, distance_haversine,
+        distance_haversine_with_radius, distance_manhattan, distance_spherical,
+        distance_spherical_fast, distance_spherical_fast_prepared, distance_spherical_with_radius,
+        fast_cos, fast_sine, wrap_lon_delta,
     };
 
     #[test]
@@ -13,6 +16,32 @@ mod tests {
         assert!((fast_cos(1.0) - 1.0_f64.cos()).abs() < 0.001);
     }
 
+    #[test]
+    fn test_fast_sine_range_reduction() {
+        use std::f64::consts::PI;
+
+        // In-range arguments are untouched
+        assert!(fast_sine(PI).abs() < 1e-12);
+        for i in -20..=20 {
+            let x = i as f64 * 0.5;
+            assert!((fast_sine(x) - x.sin()).abs() < 0.002, "{}", x);
+            assert!((fast_cos(x) - x.cos()).abs() < 0.002, "{}", x);
+            assert!(
+                (fast_sine(x + 2.0 * PI * 1000.0) - x.sin()).abs() < 0.002,
+                "{}",
+                x
+            );
+        }
+        assert!(fast_sine(f64::NAN).is_nan());
+        assert!(fast_sine(f64::INFINITY).is_nan());
+
+        // Out-of-range latitudes across the antimeridian used to panic
+        let p1 = Point([179.9, -300.0]);
+        let p2 = Point([-179.9, -290.0]);
+        assert!(distance_spherical_fast(&p1, &p2).is_finite());
+        assert!(DistanceMetric::SphericalFast.max_deltas(1.0, 300.0)[0].is_finite());
+    }
+
     #[test]
     fn test_distance_spherical() {
         let p1 = Point([30.244759, 59.955982]);
@@ -45,4 +74,239 @@ mod tests {
                 < 0.000001)
         );
     }
+
+    #[test]
+    fn test_prepared_points() {
+        // The mean latitude's cosine as computed before points were prepared
+        let unprepared = |p1: &Point, p2: &Point| {
+            let v1 = p1.0[1] - p2.0[1];
+            let v2 = wrap_lon_delta(p1.0[0] - p2.0[0])
+                * fast_cos((p1.0[1] + p2.0[1]) / 2.0 * DEGREE_RAD);
+            v1 * v1 + v2 * v2
+        };
+
+        let points: Vec<Point> = (0..200)
+            .map(|i| {
+                let t = i as f64;
+                Point([(t * 37.7) % 360.0 - 180.0, (t * 13.3) % 180.0 - 90.0])
+            })
+            .chain([
+                Point([179.9, 0.0]),
+                Point([-179.9, 0.1]),
+                Point([0.0, 89.9]),
+            ])
+            .collect();
+        for p1 in &points {
+            let prepared1 = PreparedPoint::from(p1);
+            for p2 in &points {
+                let prepared2 = PreparedPoint::from(p2);
+                let d = distance_spherical_fast(p1, p2);
+                let expected = unprepared(p1, p2);
+                assert!(
+                    (d - expected).abs() <= expected * 1e-12,
+                    "{:?} {:?}",
+                    p1,
+                    p2
+                );
+                assert_eq!(distance_spherical_fast_prepared(&prepared1, &prepared2), d);
+
+                // The terms are the distances along each axis at the mean latitude
+                let [d_lon, d_lat] = prepared1.sq_deltas(&prepared2);
+                assert_eq!(d_lat + d_lon, d);
+                assert_eq!(d_lat, (p1.0[1] - p2.0[1]).powi(2));
+                let mean_lat = (p1.0[1] + p2.0[1]) / 2.0;
+                let lon_plane =
+                    unprepared(&Point([p1.0[0], mean_lat]), &Point([p2.0[0], mean_lat]));
+                assert!((d_lon - lon_plane).abs() <= lon_plane * 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn test_distance_metric() {
+        let p1 = Point([30.0, 60.0]);
+        let p2 = Point([33.0, 64.0]);
+        assert_eq!(DistanceMetric::SquaredEuclidean.sq_dist(&p1, &p2), 25.0);
+        assert_eq!(
+            DistanceMetric::SphericalFast.sq_dist(&p1, &p2),
+            distance_spherical_fast(&p1, &p2)
+        );
+        assert_eq!(DistanceMetric::default(), DistanceMetric::SphericalFast);
+    }
+
+    #[test]
+    fn test_distance_planar() {
+        let p1 = Point([500_000.0, 4_000_000.0]);
+        let p2 = Point([500_300.0, 3_999_600.0]);
+        assert_eq!(distance_manhattan(&p1, &p2), 700.0);
+        assert_eq!(distance_chebyshev(&p1, &p2), 400.0);
+
+        let manhattan = DistanceMetric::Manhattan;
+        let chebyshev = DistanceMetric::Chebyshev;
+        assert_eq!(manhattan.sq_dist(&p1, &p2), 700.0 * 700.0);
+        assert_eq!(chebyshev.sq_dist(&p1, &p2), 400.0 * 400.0);
+        for metric in [manhattan, chebyshev, DistanceMetric::SquaredEuclidean] {
+            assert!(metric.is_planar());
+            assert_eq!(metric.scale_eps(250.0), 250.0);
+            assert_eq!(metric.max_deltas(250.0, 89.0), [250.0, 250.0]);
+        }
+        assert!(!DistanceMetric::Haversine.is_planar());
+    }
+
+    #[test]
+    fn test_distance_haversine() {
+        let p1 = Point([30.244759, 59.955982]);
+        let p2 = Point([30.24472, 59.955975]);
+        assert!((distance_haversine(&p1, &p2) - distance_spherical(&p1, &p2)).abs() < 1e-9);
+        assert_eq!(distance_haversine(&p1, &p1), 0.0);
+
+        // A quarter of the equator, and pole to pole
+        let quarter = distance_haversine(&Point([0.0, 0.0]), &Point([90.0, 0.0]));
+        assert!((quarter - EARTH_R * std::f64::consts::PI / 2.0).abs() < 1e-6);
+        let poles = distance_haversine(&Point([0.0, 90.0]), &Point([0.0, -90.0]));
+        assert!((poles - EARTH_R * std::f64::consts::PI).abs() < 1e-6);
+
+        let metric = DistanceMetric::Haversine;
+        assert_eq!(metric.scale_eps(1.5), 1.5);
+        assert!((metric.sq_dist(&p1, &p2).sqrt() - distance_haversine(&p1, &p2)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_metric_max_deltas() {
+        // Points closer than dist never differ by more than max_deltas
+        let base = Point([30.0, 75.0]);
+        for metric in [
+            DistanceMetric::SphericalFast,
+            DistanceMetric::Spherical,
+            DistanceMetric::Haversine,
+        ] {
+            let dist = metric.scale_eps(50.0);
+            let [d_lon, d_lat] = metric.max_deltas(dist, 76.0);
+            for i in 0..=40 {
+                let other = Point([30.0 + (i as f64 - 20.0) * 0.2, 75.5]);
+                if metric.sq_dist(&base, &other) < dist * dist {
+                    assert!((other.0[0] - base.0[0]).abs() <= d_lon);
+                    assert!((other.0[1] - base.0[1]).abs() <= d_lat);
+                }
+            }
+        }
+        assert_eq!(
+            DistanceMetric::SquaredEuclidean.max_deltas(2.0, 89.0),
+            [2.0, 2.0]
+        );
+        assert!(DistanceMetric::Haversine.max_deltas(100.0, 90.0)[0].is_infinite());
+    }
+
+    #[test]
+    fn test_spherical_vs_haversine() {
+        // Sub-kilometer separations agree to within a meter
+        let base = Point([30.244759, 59.955982]);
+        for (d_lon, d_lat) in [(0.001, 0.0), (0.0, 0.008), (0.01, 0.005), (-0.012, -0.003)] {
+            let other = Point([base.0[0] + d_lon, base.0[1] + d_lat]);
+            let haversine = distance_haversine(&base, &other);
+            assert!(haversine < 1.0);
+            assert!((haversine - distance_spherical(&base, &other)).abs() < 0.001);
+        }
+
+        // Along a meridian both are exact
+        let north = Point([30.0, 70.0]);
+        let south = Point([30.0, 50.0]);
+        assert!(
+            (distance_spherical(&north, &south) - distance_haversine(&north, &south)).abs() < 1e-6
+        );
+
+        // Along a parallel the flat approximation overestimates, the error grows
+        // with the cube of the distance
+        let west = Point([0.0, 60.0]);
+        let near = distance_spherical(&west, &Point([20.0, 60.0]))
+            - distance_haversine(&west, &Point([20.0, 60.0]));
+        let far = distance_spherical(&west, &Point([40.0, 60.0]))
+            - distance_haversine(&west, &Point([40.0, 60.0]));
+        assert!(distance_haversine(&west, &Point([20.0, 60.0])) > 1000.0);
+        assert!(near > 4.0);
+        assert!(far > 7.0 * near);
+
+        let metric = DistanceMetric::Spherical;
+        assert_eq!(metric.scale_eps(1.5), 1.5);
+        assert!(
+            (metric.sq_dist(&base, &north).sqrt() - distance_spherical(&base, &north)).abs() < 1e-9
+        );
+    }
+
+    #[test]
+    fn test_fast_metric_error_high_latitude() {
+        // East-west pairs 100 m apart: the fast cosine is exact around 60°N
+        // and makes distances shorter further north
+        let fast_km = |p1: &Point, p2: &Point| {
+            DistanceMetric::SphericalFast.sq_dist(p1, p2).sqrt() * EARTH_R * DEGREE_RAD
+        };
+        let pair = |lat: f64| {
+            let p1 = Point([20.0, lat]);
+            (p1, p1.destination(90.0, 0.1))
+        };
+        let rel_error = |lat: f64| {
+            let (p1, p2) = pair(lat);
+            fast_km(&p1, &p2) / distance_haversine(&p1, &p2) - 1.0
+        };
+        assert!(rel_error(60.0).abs() < 1e-4);
+        assert!((-0.0025..-0.0015).contains(&rel_error(70.0)));
+        assert!((-0.007..-0.0055).contains(&rel_error(80.0)));
+
+        // Just outside eps at 80°N, the fast metric takes it for a neighbour
+        let eps = 0.1;
+        let p1 = Point([20.0, 80.0]);
+        let p2 = p1.destination(90.0, eps * 1.003);
+        let fast = DistanceMetric::SphericalFast;
+        let exact = DistanceMetric::Haversine;
+        assert!(fast.sq_dist(&p1, &p2) < fast.scale_eps(eps).powi(2));
+        assert!(exact.sq_dist(&p1, &p2) >= exact.scale_eps(eps).powi(2));
+    }
+
+    #[test]
+    fn test_distance_with_radius() {
+        let p1 = Point([30.0, 60.0]);
+        let p2 = Point([30.5, 60.2]);
+        let half = EARTH_R / 2.0;
+        assert!(
+            (distance_spherical_with_radius(&p1, &p2, half) * 2.0 - distance_spherical(&p1, &p2))
+                .abs()
+                < 1e-9
+        );
+        assert!(
+            (distance_haversine_with_radius(&p1, &p2, half) * 2.0 - distance_haversine(&p1, &p2))
+                .abs()
+                < 1e-9
+        );
+
+        // eps on a smaller sphere covers a larger angle
+        for metric in [DistanceMetric::SphericalFast, DistanceMetric::Haversine] {
+            assert_eq!(
+                metric.scale_eps_with_radius(3.0, EARTH_R),
+                metric.scale_eps(3.0)
+            );
+            assert!(
+                (metric.scale_eps_with_radius(3.0, half) - metric.scale_eps(6.0)).abs() < 1e-12
+            );
+        }
+        assert_eq!(
+            DistanceMetric::SquaredEuclidean.scale_eps_with_radius(3.0, half),
+            3.0
+        );
+    }
+
+    #[test]
+    fn test_distance_across_antimeridian() {
+        assert!((wrap_lon_delta(359.8) + 0.2).abs() < 1e-9);
+        assert_eq!(wrap_lon_delta(-350.0), 10.0);
+        assert_eq!(wrap_lon_delta(180.0), 180.0);
+
+        // 0.2 degrees apart on the equator, not 359.8
+        let p1 = Point([179.9, 0.0]);
+        let p2 = Point([-179.9, 0.0]);
+        let expected = 0.2 * DEGREE_RAD * EARTH_R;
+        assert!((distance_spherical(&p1, &p2) - expected).abs() < 1e-6);
+        assert!((distance_haversine(&p1, &p2) - expected).abs() < 1e-6);
+        let fast = distance_spherical_fast(&p1, &p2).sqrt() * EARTH_R * DEGREE_RAD;
+        assert!((fast - expected).abs() < 1e-6);
+    }
 }
@@ -0,0 +1,157 @@
+//! Fixed-radius grid index
+//!
+//! Points are bucketed into lat/lon cells, range queries only look at the
+//! cells that can possibly hold a point within the query distance.
+
+use super::distance::{DistanceMetric, antimeridian_lon, in_lon_window};
+use super::point::{Point, PointList};
+use std::collections::HashMap;
+
+/// Grid index bucketing points into cells of a fixed size
+///
+/// Cells are as large as the coordinate deltas two points closer than `cell`
+/// can have (see [`DistanceMetric::max_deltas`]), so a query for `cell` only
+/// looks at the 3x3 cells around the query point.
+pub struct GridIndex {
+    /// All points in the index
+    pub points: PointList,
+    /// Metric used by range queries
+    pub metric: DistanceMetric,
+    cell_lon: f64,
+    cell_lat: f64,
+    max_abs_lat: f64,
+    cells: HashMap<(i64, i64), Vec<usize>>,
+}
+
+impl GridIndex {
+    /// Creates a new grid index with cells sized to `cell`
+    ///
+    /// `cell` is expressed in the same units as the `dist` of [`GridIndex::in_range`],
+    /// queries are fastest when both are equal.
+    pub fn new(points: PointList, cell: f64) -> GridIndex {
+        GridIndex::with_metric(points, cell, DistanceMetric::default())
+    }
+
+    /// Creates a new grid index with cells sized to `cell`, queried with the given metric
+    pub fn with_metric(points: PointList, cell: f64, metric: DistanceMetric) -> GridIndex {
+        let max_abs_lat = points.iter().fold(0.0_f64, |m, p| m.max(p.0[1].abs()));
+        let [cell_lon, cell_lat] = metric.max_deltas(cell, max_abs_lat);
+        // Degenerate cells (zero, or unbounded near the poles) fall back to 1 degree / whole globe
+        let cell_lat = if cell_lat > 0.0 { cell_lat } else { 1.0 };
+        let cell_lon = if cell_lon <= 0.0 {
+            cell_lat
+        } else {
+            cell_lon.min(360.0)
+        };
+
+        let mut cells: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+        for (i, p) in points.iter().enumerate() {
+            let key = (
+                (p.0[0] / cell_lon).floor() as i64,
+                (p.0[1] / cell_lat).floor() as i64,
+            );
+            cells.entry(key).or_default().push(i);
+        }
+
+        GridIndex {
+            points,
+            metric,
+            cell_lon,
+            cell_lat,
+            max_abs_lat,
+            cells,
+        }
+    }
+
+    /// Finds all points in the index that are within a given distance from the given point
+    ///
+    /// Returns the same points as [`KDTree::in_range`](super::kdtree::KDTree::in_range),
+    /// found indices are appended to `nodes`.
+    pub fn in_range(&self, pt: &Point, dist: f64, nodes: Vec<usize>) -> Vec<usize> {
+        if dist < 0.0 {
+            return nodes;
+        }
+
+        let deltas = self
+            .metric
+            .max_deltas(dist, self.max_abs_lat.max(pt.0[1].abs()));
+        let Some(moved_lon) = antimeridian_lon(&self.metric, pt.0[0], deltas[0]) else {
+            return self.in_box(pt, dist, deltas, |_| true, nodes);
+        };
+
+        // Cells across the antimeridian are found from the moved query point
+        let moved = Point([moved_lon, pt.0[1]]);
+        let nodes = self.in_box(
+            pt,
+            dist,
+            deltas,
+            |lon| in_lon_window(pt.0[0], lon, false),
+            nodes,
+        );
+        self.in_box(
+            &moved,
+            dist,
+            deltas,
+            |lon| in_lon_window(moved_lon, lon, true),
+            nodes,
+        )
+    }
+
+    /// Appends the points within `dist` of `pt` in cells up to `[d_lon, d_lat]`
+    /// away whose longitude is accepted by `keep`
+    fn in_box(
+        &self,
+        pt: &Point,
+        dist: f64,
+        [d_lon, d_lat]: [f64; 2],
+        keep: impl Fn(f64) -> bool,
+        mut nodes: Vec<usize>,
+    ) -> Vec<usize> {
+        let lon_from = ((pt.0[0] - d_lon) / self.cell_lon).floor() as i64;
+        let lon_to = ((pt.0[0] + d_lon) / self.cell_lon).floor() as i64;
+        let lat_from = ((pt.0[1] - d_lat) / self.cell_lat).floor() as i64;
+        let lat_to = ((pt.0[1] + d_lat) / self.cell_lat).floor() as i64;
+
+        let r2 = dist * dist;
+        let span =
+            (lon_to as f64 - lon_from as f64 + 1.0) * (lat_to as f64 - lat_from as f64 + 1.0);
+        if span > self.cells.len() as f64 {
+            // Query box covers more cells than are occupied, scan occupied ones
+            // (sorted, so that results don't depend on hash order)
+            let mut keys: Vec<_> = self
+                .cells
+                .keys()
+                .filter(|(x, y)| (lon_from..=lon_to).contains(x) && (lat_from..=lat_to).contains(y))
+                .collect();
+            keys.sort();
+            for key in keys {
+                self.push_near(&self.cells[key], pt, r2, &keep, &mut nodes);
+            }
+            return nodes;
+        }
+
+        for x in lon_from..=lon_to {
+            for y in lat_from..=lat_to {
+                if let Some(ids) = self.cells.get(&(x, y)) {
+                    self.push_near(ids, pt, r2, &keep, &mut nodes);
+                }
+            }
+        }
+        nodes
+    }
+
+    fn push_near(
+        &self,
+        ids: &[usize],
+        pt: &Point,
+        r2: f64,
+        keep: impl Fn(f64) -> bool,
+        nodes: &mut Vec<usize>,
+    ) {
+        for &i in ids {
+            if self.metric.sq_dist(&self.points[i], pt) < r2 && keep(self.points[i].0[0]) {
+                nodes.push(i);
+            }
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! Convex hulls of clusters
+
+use super::point::{Cluster, PointList};
+
+/// Returns the indices of the vertices of a cluster's convex hull
+///
+/// Andrew's monotone chain on `[lon, lat]` as planar coordinates, so hulls
+/// of clusters crossing the antimeridian are wrong. The hull runs
+/// counterclockwise from the point with the smallest longitude (then
+/// latitude), without repeating it at the end and without collinear points.
+/// Clusters of fewer than three points are returned as-is, fewer than three
+/// distinct points give the distinct ones.
+pub fn convex_hull(points: &PointList, cluster: &Cluster) -> Vec<usize> {
+    if cluster.points.len() < 3 {
+        return cluster.points.clone();
+    }
+
+    let mut sorted = cluster.points.clone();
+    sorted.sort_by(|&a, &b| {
+        let (a, b) = (points[a].0, points[b].0);
+        a[0].total_cmp(&b[0]).then(a[1].total_cmp(&b[1]))
+    });
+    sorted.dedup_by(|a, b| points[*a] == points[*b]);
+    if sorted.len() < 3 {
+        return sorted;
+    }
+
+    // Cross product of o->a and o->b, positive for a counterclockwise turn
+    let cross = |o: usize, a: usize, b: usize| {
+        let (o, a, b) = (points[o].0, points[a].0, points[b].0);
+        (a[0] - o[0]) * (b[1] - o[1]) - (a[1] - o[1]) * (b[0] - o[0])
+    };
+
+    let mut hull: Vec<usize> = Vec::with_capacity(sorted.len() + 1);
+    // Lower chain left to right
+    for &i in &sorted {
+        while hull.len() >= 2 && cross(hull[hull.len() - 2], hull[hull.len() - 1], i) <= 0.0 {
+            hull.pop();
+        }
+        hull.push(i);
+    }
+    // Upper chain right to left, on top of the lower one
+    let lower_len = hull.len() + 1;
+    for &i in sorted.iter().rev().skip(1) {
+        while hull.len() >= lower_len && cross(hull[hull.len() - 2], hull[hull.len() - 1], i) <= 0.0
+        {
+            hull.pop();
+        }
+        hull.push(i);
+    }
+    // The last point is the first one again
+    hull.pop();
+    hull
+}
@@ -0,0 +1,68 @@
+#[cfg(test)]
+mod tests {
+    use crate::cluster::{Cluster, Point, PointList, convex_hull};
+
+    fn cluster(points: &[usize]) -> Cluster {
+        Cluster {
+            c: 0,
+            points: points.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_convex_hull_square() {
+        // Square corners and an interior point
+        let points: PointList = vec![
+            Point([1.0, 1.0]),
+            Point([0.0, 0.0]),
+            Point([2.0, 0.0]),
+            Point([2.0, 2.0]),
+            Point([0.0, 2.0]),
+        ];
+        assert_eq!(
+            convex_hull(&points, &cluster(&[0, 1, 2, 3, 4])),
+            [1, 2, 3, 4]
+        );
+        // Same orientation however members are ordered
+        assert_eq!(
+            convex_hull(&points, &cluster(&[4, 3, 0, 2, 1])),
+            [1, 2, 3, 4]
+        );
+    }
+
+    #[test]
+    fn test_convex_hull_collinear() {
+        // Edge midpoints, a point on a diagonal and a duplicate corner
+        let points: PointList = vec![
+            Point([0.0, 0.0]),
+            Point([1.0, 0.0]),
+            Point([2.0, 0.0]),
+            Point([2.0, 2.0]),
+            Point([0.0, 2.0]),
+            Point([0.0, 1.0]),
+            Point([1.0, 1.0]),
+            Point([2.0, 2.0]),
+        ];
+        let all: Vec<usize> = (0..points.len()).collect();
+        assert_eq!(convex_hull(&points, &cluster(&all)), [0, 2, 3, 4]);
+        // A triangle with a point on its hypotenuse
+        assert_eq!(convex_hull(&points, &cluster(&[4, 6, 2, 0])), [0, 2, 4]);
+    }
+
+    #[test]
+    fn test_convex_hull_degenerate() {
+        let points: PointList = vec![
+            Point([0.0, 0.0]),
+            Point([1.0, 1.0]),
+            Point([2.0, 2.0]),
+            Point([0.0, 0.0]),
+        ];
+        // Fewer than three points are returned as-is
+        assert!(convex_hull(&points, &cluster(&[])).is_empty());
+        assert_eq!(convex_hull(&points, &cluster(&[1, 0])), [1, 0]);
+        assert_eq!(convex_hull(&points, &cluster(&[3, 0])), [3, 0]);
+        // No area: only the distinct ends remain
+        assert_eq!(convex_hull(&points, &cluster(&[0, 1, 2])), [0, 2]);
+        assert_eq!(convex_hull(&points, &cluster(&[0, 3, 0])), [0]);
+    }
+}
@@ -0,0 +1,346 @@
+//! Incremental DBSCAN for data sets where only a few points change between runs
+
+use super::dbscan::{COINCIDENT_EPS, DbscanConfig};
+use super::kdtree::{KDTree, new_kd_tree_with_metric};
+use super::point::{Cluster, Point, PointList};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+/// DBSCAN result that can be updated when some points move or are added
+///
+/// Created by a full run, then [`IncrementalDbscan::recompute_affected`]
+/// re-labels only the clusters touched by the changed points. Neighbourhoods
+/// are queried in a K-D tree kept up to date as points move or are added, it
+/// is never rebuilt.
+#[derive(Debug, Clone)]
+pub struct IncrementalDbscan {
+    config: DbscanConfig<'static>,
+    points: PointList,
+    /// Cluster of every point, `None` for noise. Ids are not contiguous.
+    labels: Vec<Option<usize>>,
+    /// Index of the points, a moved point is removed and inserted again
+    pub(crate) tree: KDTree,
+    /// Index of every point in `tree`
+    tree_ids: Vec<usize>,
+    /// Point of every index in `tree`, removed ones included
+    owners: Vec<usize>,
+}
+
+impl IncrementalDbscan {
+    /// Clusters `points` from scratch
+    ///
+    /// Weights of `config` are ignored, they couldn't follow changing points.
+    pub fn new(points: PointList, config: DbscanConfig) -> Self {
+        let config = config.unweighted();
+        let (clusters, _) = config.run(&points);
+        let mut labels = vec![None; points.len()];
+        for cluster in &clusters {
+            for &i in &cluster.points {
+                labels[i] = Some(cluster.c);
+            }
+        }
+
+        let tree = new_kd_tree_with_metric(points.clone(), config.metric);
+        IncrementalDbscan {
+            config,
+            tree_ids: (0..points.len()).collect(),
+            owners: (0..points.len()).collect(),
+            points,
+            labels,
+            tree,
+        }
+    }
+
+    /// Returns all points
+    pub fn points(&self) -> &PointList {
+        &self.points
+    }
+
+    /// Moves point `i`, labels are updated by [`IncrementalDbscan::recompute_affected`]
+    pub fn set_point(&mut self, i: usize, p: Point) {
+        self.points[i] = p;
+        self.tree.remove(self.tree_ids[i]);
+        self.tree_ids[i] = self.insert(i);
+    }
+
+    /// Adds a point as noise and returns its index, labels are updated by
+    /// [`IncrementalDbscan::recompute_affected`]
+    pub fn push_point(&mut self, p: Point) -> usize {
+        self.points.push(p);
+        self.labels.push(None);
+        let i = self.points.len() - 1;
+        let id = self.insert(i);
+        self.tree_ids.push(id);
+        i
+    }
+
+    /// Inserts point `i` into the tree and returns its index there
+    fn insert(&mut self, i: usize) -> usize {
+        self.tree.insert(self.points[i]);
+        self.owners.push(i);
+        self.tree.points.len() - 1
+    }
+
+    /// Re-clusters the points affected by changes of the `changed` points
+    ///
+    /// Affected are the changed points, their current eps-neighbours and all
+    /// members of the clusters any of them belonged to. These are re-expanded
+    /// with the usual DBSCAN rule, absorbing every cluster they now connect
+    /// to, while distant clusters keep their labels.
+    ///
+    /// Results match a full re-run in the common case. When a change splits a
+    /// cluster or moves a point out of a neighbourhood, the old clusters of
+    /// points that lost neighbours far from any changed point are not looked
+    /// at again, and border points reachable from several clusters may be
+    /// assigned differently.
+    pub fn recompute_affected(&mut self, changed: &[usize]) {
+        let n = self.points.len();
+        let min_points = self.config.core_min_points();
+        let eps = self.config.scaled_eps();
+        let (tree, points, owners) = (&self.tree, &self.points, &self.owners);
+        let neighbors_of = |i: usize| -> Vec<usize> {
+            tree.in_range(&points[i], eps, Vec::new())
+                .into_iter()
+                .map(|id| owners[id])
+                .collect()
+        };
+        let labels = &mut self.labels;
+
+        let mut affected = vec![false; n];
+        let mut touched = HashSet::new();
+        for &i in changed {
+            affected[i] = true;
+            touched.extend(labels[i]);
+            for j in neighbors_of(i) {
+                affected[j] = true;
+                touched.extend(labels[j]);
+            }
+        }
+        for i in 0..n {
+            if labels[i].is_some_and(|c| touched.contains(&c)) {
+                affected[i] = true;
+            }
+            if affected[i] {
+                labels[i] = None;
+            }
+        }
+
+        // New clusters get ids above all existing ones, so that the points
+        // they absorb from old clusters can be told apart
+        let first_new = labels.iter().flatten().max().map_or(0, |&c| c + 1);
+        let mut next = first_new;
+        let mut visited = vec![false; n];
+        let mut queued = vec![usize::MAX; n];
+
+        for i in 0..n {
+            if !affected[i] || visited[i] || labels[i].is_some() {
+                continue;
+            }
+            visited[i] = true;
+
+            let neighbor_pts = neighbors_of(i);
+            if neighbor_pts.len() < min_points {
+                continue;
+            }
+            let c = next;
+            next += 1;
+            labels[i] = Some(c);
+
+            let mut queue = neighbor_pts;
+            for &k in &queue {
+                queued[k] = c;
+            }
+            let mut j = 0;
+            while j < queue.len() {
+                let k = queue[j];
+                j += 1;
+                if !visited[k] {
+                    visited[k] = true;
+                    let more_neighbors = neighbors_of(k);
+                    if more_neighbors.len() >= min_points {
+                        for p in more_neighbors {
+                            if queued[p] != c {
+                                queued[p] = c;
+                                queue.push(p);
+                            }
+                        }
+                    }
+                }
+
+                // Points of old clusters are absorbed, new clusters keep theirs
+                if labels[k].is_none_or(|l| l < first_new) {
+                    labels[k] = Some(c);
+                }
+            }
+        }
+    }
+
+    /// Returns the current clusters and noise
+    ///
+    /// Clusters are numbered in ascending order of their smallest point index,
+    /// like [`StableBy::MinIndex`](super::dbscan::StableBy::MinIndex).
+    pub fn clusters(&self) -> (Vec<Cluster>, Vec<usize>) {
+        let mut ids = HashMap::new();
+        let mut clusters: Vec<Cluster> = Vec::new();
+        let mut noise = Vec::new();
+
+        for (i, label) in self.labels.iter().enumerate() {
+            match label {
+                Some(l) => {
+                    let c = *ids.entry(*l).or_insert_with(|| {
+                        clusters.push(Cluster {
+                            c: clusters.len(),
+                            points: Vec::new(),
+                        });
+                        clusters.len() - 1
+                    });
+                    clusters[c].points.push(i);
+                }
+                None => noise.push(i),
+            }
+        }
+
+        (clusters, noise)
+    }
+}
+
+/// Adds a point to a clustered K-D tree, updating only the affected labels
+///
+/// `labels` holds the cluster of every point of `tree` (`-1` for noise), as
+/// returned by [`label_points`](super::dbscan::label_points) for the same
+/// `eps` in kilometers and `min_points`. `new` is inserted with
+/// [`KDTree::insert`], its label is pushed to `labels` and its index returned.
+///
+/// Only the new point and its neighbours gain a neighbour, so only they can
+/// become core points. Clusters are only connected through core points within
+/// eps of each other, so the new core points are grouped by a union-find: two
+/// are in one group when within eps of each other or of core points of the
+/// same cluster. `new` only takes part if it is a core point itself. Each group:
+///
+/// - reaching no cluster: forms a new cluster, numbered one above the largest
+///   label
+/// - reaching one cluster: joins it
+/// - reaching several clusters: these are merged into the one with the
+///   smallest label, the other labels are no longer used
+///
+/// The new core points and their noise neighbours get the label of their
+/// group. Border points of other clusters keep theirs, as in a full run they
+/// stay in the cluster that reached them first. A merge relabels every member
+/// of the merged clusters, other points are never touched. If `new` isn't a
+/// core point it joins the cluster of its core neighbour with the smallest
+/// label as a border point, or is noise without one.
+///
+/// The tree isn't rebalanced, rebuild it with
+/// [`new_kd_tree`](super::kdtree::new_kd_tree) once many points were added.
+pub fn incremental_add(
+    tree: &mut KDTree,
+    labels: &mut Vec<i32>,
+    new: Point,
+    eps: f64,
+    min_points: usize,
+) -> usize {
+    tree.insert(new);
+    let id = tree.points.len() - 1;
+    labels.push(-1);
+
+    let r = if eps == 0.0 {
+        COINCIDENT_EPS
+    } else {
+        tree.metric.scale_eps(eps)
+    };
+    let tree = &*tree;
+    let neighbors_of = |i: usize| tree.in_range(&tree.points[i], r, Vec::new());
+    let is_core = |i: usize| neighbors_of(i).len() >= min_points;
+
+    // Old points with exactly min_points neighbours had one too few before
+    let mut new_cores = Vec::new();
+    for q in neighbors_of(id) {
+        let neighbors = neighbors_of(q);
+        if neighbors.len() == min_points || (q == id && neighbors.len() >= min_points) {
+            new_cores.push((q, neighbors));
+        }
+    }
+
+    new_cores.sort_unstable_by_key(|&(q, _)| q);
+    let border_label = |labels: &[i32]| {
+        neighbors_of(id)
+            .into_iter()
+            .filter(|&q| q != id && labels[q] != -1 && is_core(q))
+            .map(|q| labels[q])
+            .min()
+            .unwrap_or(-1)
+    };
+
+    if new_cores.is_empty() {
+        labels[id] = border_label(labels);
+        return id;
+    }
+
+    // Join new core points within eps of each other directly, and through the
+    // first new core point reaching a cluster via an old core point
+    fn find(parent: &mut [usize], mut k: usize) -> usize {
+        while parent[k] != k {
+            parent[k] = parent[parent[k]];
+            k = parent[k];
+        }
+        k
+    }
+    let slot: HashMap<usize, usize> = new_cores
+        .iter()
+        .enumerate()
+        .map(|(k, &(q, _))| (q, k))
+        .collect();
+    let mut parent: Vec<usize> = (0..new_cores.len()).collect();
+    let mut reached: BTreeMap<i32, usize> = BTreeMap::new();
+    for (k, (_, neighbors)) in new_cores.iter().enumerate() {
+        for &x in neighbors {
+            let other = match slot.get(&x) {
+                Some(&j) => j,
+                None if labels[x] != -1 && is_core(x) => *reached.entry(labels[x]).or_insert(k),
+                None => continue,
+            };
+            let (a, b) = (find(&mut parent, k), find(&mut parent, other));
+            parent[a.max(b)] = a.min(b);
+        }
+    }
+
+    // Groups by root, the smallest new core point index of each
+    let mut groups: BTreeMap<usize, (Vec<usize>, BTreeSet<i32>)> = BTreeMap::new();
+    for k in 0..new_cores.len() {
+        groups.entry(find(&mut parent, k)).or_default().0.push(k);
+    }
+    for (&label, &k) in &reached {
+        groups
+            .entry(find(&mut parent, k))
+            .or_default()
+            .1
+            .insert(label);
+    }
+
+    for (members, merged) in groups.values() {
+        let target = match merged.first() {
+            Some(&c) => c,
+            None => labels.iter().max().map_or(0, |&c| c + 1),
+        };
+        if merged.len() > 1 {
+            for label in labels.iter_mut() {
+                if *label != target && merged.contains(label) {
+                    *label = target;
+                }
+            }
+        }
+        for &k in members {
+            let (q, neighbors) = &new_cores[k];
+            labels[*q] = target;
+            for &x in neighbors {
+                if labels[x] == -1 {
+                    labels[x] = target;
+                }
+            }
+        }
+    }
+
+    if !slot.contains_key(&id) {
+        labels[id] = border_label(labels);
+    }
+    id
+}
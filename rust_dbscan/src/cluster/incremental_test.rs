@@ -0,0 +1,170 @@
+#[cfg(test)]
+mod tests {
+    use crate::cluster::{
+        DEGREE_RAD, DbscanConfig, EARTH_R, IncrementalDbscan, Point, PointList, StableBy,
+        incremental_add, label_points, new_kd_tree,
+    };
+
+    /// Clusters of a full re-run as sorted member lists, ordered by smallest member
+    fn full_run(points: &PointList, config: DbscanConfig) -> Vec<Vec<usize>> {
+        let (clusters, _) = config.stable_ids(StableBy::MinIndex).run(points);
+        clusters
+            .into_iter()
+            .map(|c| {
+                let mut members = c.points;
+                members.sort_unstable();
+                members
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_recompute_affected() {
+        // Ten tight groups of five points along a parallel, ~11 km apart
+        let mut points = PointList::new();
+        for group in 0..10 {
+            for k in 0..5 {
+                points.push(Point([30.0 + group as f64 * 0.2, 60.0 + k as f64 * 0.0002]));
+            }
+        }
+        // A lone point between groups 3 and 4
+        points.push(Point([30.7, 60.0]));
+
+        let config = DbscanConfig::new(0.1, 3);
+        let mut incremental = IncrementalDbscan::new(points.clone(), config);
+        let (clusters, noise) = incremental.clusters();
+        assert_eq!(clusters.len(), 10);
+        assert_eq!(noise, vec![50]);
+
+        // Move a member of group 2 a little, pull the lone point into group 4
+        // and add two points that turn a new spot into a cluster with group 7's
+        // first point moved there
+        incremental.set_point(11, Point([30.4, 60.0009]));
+        incremental.set_point(50, Point([30.8, 60.0009]));
+        incremental.set_point(35, Point([31.5, 61.0]));
+        let a = incremental.push_point(Point([31.5, 61.0002]));
+        let b = incremental.push_point(Point([31.5, 61.0004]));
+        incremental.recompute_affected(&[11, 50, 35, a, b]);
+
+        let (clusters, noise) = incremental.clusters();
+        let members: Vec<Vec<usize>> = clusters.into_iter().map(|c| c.points).collect();
+        assert_eq!(members, full_run(incremental.points(), config));
+        assert_eq!(members.len(), 11);
+        assert!(noise.is_empty());
+
+        // Nothing changed: nothing moves
+        incremental.recompute_affected(&[]);
+        let (again, _) = incremental.clusters();
+        let again: Vec<Vec<usize>> = again.into_iter().map(|c| c.points).collect();
+        assert_eq!(again, members);
+    }
+
+    #[test]
+    fn test_recompute_affected_reuses_index() {
+        let mut points = PointList::new();
+        for k in 0..6 {
+            points.push(Point([30.0, 60.0 + k as f64 * 0.0002]));
+        }
+        let config = DbscanConfig::new(0.1, 3);
+        let mut incremental = IncrementalDbscan::new(points, config);
+        assert_eq!(incremental.tree.points.len(), 6);
+
+        // The moved point is removed from the tree and inserted again, the
+        // new one inserted: the tree isn't rebuilt from the points
+        incremental.set_point(5, Point([31.0, 60.0]));
+        let a = incremental.push_point(Point([31.0, 60.0002]));
+        let b = incremental.push_point(Point([31.0, 60.0004]));
+        incremental.recompute_affected(&[5, a, b]);
+        assert_eq!(incremental.tree.points.len(), 9);
+        assert!(incremental.tree.is_removed(5));
+        assert!(!incremental.tree.is_removed(6));
+
+        let (clusters, noise) = incremental.clusters();
+        let members: Vec<Vec<usize>> = clusters.into_iter().map(|c| c.points).collect();
+        assert_eq!(members, full_run(incremental.points(), config));
+        assert_eq!(members, vec![vec![0, 1, 2, 3, 4], vec![5, 6, 7]]);
+        assert!(noise.is_empty());
+    }
+
+    /// Clusters as sorted member lists ordered by smallest member, noise last
+    fn partition(labels: &[i32]) -> (Vec<Vec<usize>>, Vec<usize>) {
+        let mut clusters: Vec<(i32, Vec<usize>)> = Vec::new();
+        let mut noise = Vec::new();
+        for (i, &label) in labels.iter().enumerate() {
+            if label == -1 {
+                noise.push(i);
+                continue;
+            }
+            match clusters.iter_mut().find(|(c, _)| *c == label) {
+                Some((_, members)) => members.push(i),
+                None => clusters.push((label, vec![i])),
+            }
+        }
+        (clusters.into_iter().map(|(_, m)| m).collect(), noise)
+    }
+
+    #[test]
+    fn test_incremental_add() {
+        // Groups A and B along a meridian, 0.12 km apart, and two noise points
+        let km = 1.0 / EARTH_R / DEGREE_RAD;
+        let at = |d: f64| Point([30.0, 60.0 + d * km]);
+        let mut points: PointList = [0.0, 0.02, 0.04, 0.16, 0.18, 0.2, 1.0, 1.05]
+            .into_iter()
+            .map(at)
+            .collect();
+        let (eps, min_points) = (0.1, 3);
+        let mut labels = label_points(&points, eps, min_points);
+        let mut tree = new_kd_tree(points.clone());
+        assert_eq!(labels, [0, 0, 0, 1, 1, 1, -1, -1]);
+
+        let mut add = |d: f64, labels: &mut Vec<i32>| {
+            points.push(at(d));
+            let id = incremental_add(&mut tree, labels, at(d), eps, min_points);
+            assert_eq!(id, points.len() - 1);
+            // Same partition as clustering everything from scratch
+            let full = label_points(&points, eps, min_points);
+            assert_eq!(partition(labels), partition(&full));
+        };
+
+        // Far from everything: noise
+        add(5.0, &mut labels);
+        assert_eq!(labels[8], -1);
+
+        // Next to A but not core itself: border point of A
+        add(-0.09, &mut labels);
+        assert_eq!(labels[9], 0);
+
+        // Between the noise points: they form a new cluster
+        add(1.025, &mut labels);
+        assert_eq!(labels[6..=7], [2, 2]);
+        assert_eq!(labels[10], 2);
+
+        // Between A and B, within eps of cores of both: they merge into A
+        add(0.1, &mut labels);
+        assert_eq!(labels[..6], [0; 6]);
+        assert_eq!(labels[9], 0);
+        assert_eq!(labels[11], 0);
+        assert!(!labels.contains(&1));
+
+        // Noise cluster C merges too once a point bridges the gap
+        for d in [0.28, 0.36, 0.44, 0.52, 0.6, 0.68, 0.76, 0.84, 0.92] {
+            add(d, &mut labels);
+        }
+        assert_eq!(partition(&labels).0.len(), 1);
+
+        // A new point that isn't core makes a core point on either side, which
+        // are out of each other's eps: two clusters sharing it as border point
+        let mut points: PointList = [-1.6, -1.4, -0.9, 0.9, 1.4, 1.6]
+            .into_iter()
+            .map(at)
+            .collect();
+        let (eps, min_points) = (1.0, 4);
+        let mut labels = label_points(&points, eps, min_points);
+        assert_eq!(labels, [-1; 6]);
+        let mut tree = new_kd_tree(points.clone());
+        incremental_add(&mut tree, &mut labels, at(0.0), eps, min_points);
+        points.push(at(0.0));
+        assert_eq!(labels, [0, 0, 0, 1, 1, 1, 0]);
+        assert_eq!(labels, label_points(&points, eps, min_points));
+    }
+}
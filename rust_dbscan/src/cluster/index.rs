@@ -0,0 +1,221 @@
+//! Spatial index selection for eps-neighbourhood queries
+
+use super::distance::DistanceMetric;
+use super::grid::GridIndex;
+use super::kdtree::{KDTree, new_kd_tree_with_metric};
+use super::point::{Point, PointList};
+use std::fmt;
+use std::ops::ControlFlow;
+
+/// Data sets up to this size are scanned by brute force in [`IndexKind::Auto`] mode
+pub const AUTO_BRUTE_FORCE_MAX_POINTS: usize = 256;
+
+/// Data sets starting from this size may use the grid in [`IndexKind::Auto`] mode
+pub const AUTO_GRID_MIN_POINTS: usize = 50_000;
+
+/// Max coefficient of variation of sampled cell counts for uniform density
+const AUTO_UNIFORM_MAX_CV: f64 = 1.0;
+
+/// Number of points sampled for the density check
+const DENSITY_SAMPLE_SIZE: usize = 4096;
+
+/// Density check splits the bounding box into `DENSITY_GRID_SIDE`^2 cells
+const DENSITY_GRID_SIDE: usize = 16;
+
+/// Kind of spatial index used for neighbourhood queries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IndexKind {
+    /// Pick an index based on the data set, see [`IndexKind::resolve`]
+    #[default]
+    Auto,
+    /// K-D tree, good general purpose choice
+    KdTree,
+    /// Fixed-radius grid, fast on large data sets of uniform density
+    Grid,
+    /// Linear scan over all points, fastest on tiny data sets
+    BruteForce,
+}
+
+impl IndexKind {
+    /// Resolves [`IndexKind::Auto`] into a concrete index kind
+    ///
+    /// Auto picks:
+    /// - brute force for up to [`AUTO_BRUTE_FORCE_MAX_POINTS`] points
+    /// - grid for at least [`AUTO_GRID_MIN_POINTS`] points when a sample of the
+    ///   data shows uniform density over its bounding box
+    /// - K-D tree otherwise
+    ///
+    /// Other kinds are returned as-is.
+    pub fn resolve(self, points: &PointList) -> IndexKind {
+        if self != IndexKind::Auto {
+            return self;
+        }
+
+        if points.len() <= AUTO_BRUTE_FORCE_MAX_POINTS {
+            IndexKind::BruteForce
+        } else if points.len() >= AUTO_GRID_MIN_POINTS && has_uniform_density(points) {
+            IndexKind::Grid
+        } else {
+            IndexKind::KdTree
+        }
+    }
+}
+
+impl fmt::Display for IndexKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            IndexKind::Auto => "auto",
+            IndexKind::KdTree => "kdtree",
+            IndexKind::Grid => "grid",
+            IndexKind::BruteForce => "bruteforce",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Spatial index built over a list of points
+pub enum SpatialIndex {
+    /// K-D tree index
+    KdTree(KDTree),
+    /// Grid index
+    Grid(GridIndex),
+    /// No index, every query scans all points
+    BruteForce {
+        /// All points
+        points: PointList,
+        /// Metric used by range queries
+        metric: DistanceMetric,
+    },
+}
+
+impl SpatialIndex {
+    /// Builds an index of the given kind queried with the given metric
+    ///
+    /// `eps` is the query distance the index is tuned for (grid cell size).
+    pub fn build(
+        kind: IndexKind,
+        points: PointList,
+        eps: f64,
+        metric: DistanceMetric,
+    ) -> SpatialIndex {
+        match kind.resolve(&points) {
+            IndexKind::Grid => SpatialIndex::Grid(GridIndex::with_metric(points, eps, metric)),
+            IndexKind::BruteForce => SpatialIndex::BruteForce { points, metric },
+            IndexKind::Auto | IndexKind::KdTree => {
+                SpatialIndex::KdTree(new_kd_tree_with_metric(points, metric))
+            }
+        }
+    }
+
+    /// Returns the kind of this index
+    pub fn kind(&self) -> IndexKind {
+        match self {
+            SpatialIndex::KdTree(_) => IndexKind::KdTree,
+            SpatialIndex::Grid(_) => IndexKind::Grid,
+            SpatialIndex::BruteForce { .. } => IndexKind::BruteForce,
+        }
+    }
+
+    /// Finds all points that are within a given distance from the given point
+    ///
+    /// Found indices are appended to `nodes`.
+    pub fn in_range(&self, pt: &Point, dist: f64, mut nodes: Vec<usize>) -> Vec<usize> {
+        match self {
+            SpatialIndex::KdTree(tree) => tree.in_range(pt, dist, nodes),
+            SpatialIndex::Grid(grid) => grid.in_range(pt, dist, nodes),
+            SpatialIndex::BruteForce { points, metric } => {
+                for (i, point) in points.iter().enumerate() {
+                    if metric.sq_dist(point, pt) < dist * dist {
+                        nodes.push(i);
+                    }
+                }
+                nodes
+            }
+        }
+    }
+
+    /// Counts the points that are within a given distance from the given point
+    ///
+    /// Doesn't allocate for the K-D tree and brute force, the grid counts the
+    /// result of [`GridIndex::in_range`].
+    pub fn count_in_range(&self, pt: &Point, dist: f64) -> usize {
+        match self {
+            SpatialIndex::KdTree(tree) => tree.count_in_range(pt, dist),
+            SpatialIndex::Grid(grid) => grid.in_range(pt, dist, Vec::new()).len(),
+            SpatialIndex::BruteForce { points, metric } => points
+                .iter()
+                .filter(|point| metric.sq_dist(point, pt) < dist * dist)
+                .count(),
+        }
+    }
+
+    /// Returns whether at least `count` points are within a given distance
+    /// from the given point
+    ///
+    /// The K-D tree and brute force stop searching once `count` points are
+    /// found (see [`KDTree::for_each_in_range`]), the grid counts the result
+    /// of [`GridIndex::in_range`].
+    pub fn has_in_range(&self, pt: &Point, dist: f64, count: usize) -> bool {
+        if count == 0 {
+            return true;
+        }
+        match self {
+            SpatialIndex::KdTree(tree) => {
+                let mut found = 0;
+                tree.for_each_in_range(pt, dist, |_| {
+                    found += 1;
+                    if found < count {
+                        ControlFlow::Continue(())
+                    } else {
+                        ControlFlow::Break(())
+                    }
+                });
+                found >= count
+            }
+            SpatialIndex::Grid(grid) => grid.in_range(pt, dist, Vec::new()).len() >= count,
+            SpatialIndex::BruteForce { points, metric } => points
+                .iter()
+                .filter(|point| metric.sq_dist(point, pt) < dist * dist)
+                .nth(count - 1)
+                .is_some(),
+        }
+    }
+}
+
+/// Checks whether a sample of points spreads evenly over the bounding box
+fn has_uniform_density(points: &PointList) -> bool {
+    let mut min = Point([f64::INFINITY, f64::INFINITY]);
+    let mut max = Point([f64::NEG_INFINITY, f64::NEG_INFINITY]);
+    for p in points {
+        for j in 0..2 {
+            min.0[j] = min.0[j].min(p.0[j]);
+            max.0[j] = max.0[j].max(p.0[j]);
+        }
+    }
+
+    let width = max.0[0] - min.0[0];
+    let height = max.0[1] - min.0[1];
+    if width <= 0.0 || height <= 0.0 {
+        return false;
+    }
+
+    let side = DENSITY_GRID_SIDE;
+    let mut counts = vec![0usize; side * side];
+    let step = (points.len() / DENSITY_SAMPLE_SIZE).max(1);
+    let mut sampled = 0;
+    for p in points.iter().step_by(step) {
+        let x = (((p.0[0] - min.0[0]) / width * side as f64) as usize).min(side - 1);
+        let y = (((p.0[1] - min.0[1]) / height * side as f64) as usize).min(side - 1);
+        counts[y * side + x] += 1;
+        sampled += 1;
+    }
+
+    let mean = sampled as f64 / counts.len() as f64;
+    let var = counts
+        .iter()
+        .map(|&c| (c as f64 - mean) * (c as f64 - mean))
+        .sum::<f64>()
+        / counts.len() as f64;
+
+    var.sqrt() / mean <= AUTO_UNIFORM_MAX_CV
+}
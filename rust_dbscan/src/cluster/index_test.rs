@@ -0,0 +1,146 @@
+#[cfg(test)]
+mod tests {
+    use crate::cluster::test_util::lcg;
+    use crate::cluster::{
+        DEGREE_RAD, DistanceMetric, EARTH_R, GridIndex, IndexKind, Point, PointList, SpatialIndex,
+        db_scan_with_index, new_kd_tree, new_kd_tree_with_metric,
+    };
+
+    fn sample_points() -> PointList {
+        let mut points = vec![
+            Point([30.244759, 59.955982]),
+            Point([30.24472, 59.955975]),
+            Point([30.244358, 59.96698]),
+            Point([30.258387, 59.951557]),
+            Point([30.434124, 60.029499]),
+        ];
+        // Spread a deterministic pseudo-random cloud around the samples
+        let mut next = lcg(42);
+        for _ in 0..300 {
+            let dx = next() - 0.5;
+            let dy = next() - 0.5;
+            points.push(Point([30.3 + dx * 0.2, 59.98 + dy * 0.1]));
+        }
+        points
+    }
+
+    #[test]
+    fn test_grid_matches_kdtree() {
+        let points = sample_points();
+        let tree = new_kd_tree(points.clone());
+        let eps = 0.8 / EARTH_R / DEGREE_RAD;
+        let grid = GridIndex::new(points.clone(), eps);
+
+        for pt in &points {
+            for dist in [eps / 3.0, eps, eps * 4.0] {
+                let mut pts1 = tree.in_range(pt, dist, Vec::new());
+                let mut pts2 = grid.in_range(pt, dist, Vec::new());
+                pts1.sort();
+                pts2.sort();
+                assert_eq!(pts1, pts2);
+            }
+        }
+    }
+
+    #[test]
+    fn test_has_in_range() {
+        let points = sample_points();
+        let eps = 0.8 / EARTH_R / DEGREE_RAD;
+        for kind in [IndexKind::KdTree, IndexKind::Grid, IndexKind::BruteForce] {
+            let index = SpatialIndex::build(kind, points.clone(), eps, DistanceMetric::default());
+            for pt in &points {
+                let count = index.count_in_range(pt, eps);
+                for n in 0..=count + 1 {
+                    assert_eq!(
+                        index.has_in_range(pt, eps, n),
+                        n <= count,
+                        "{:?} {}",
+                        kind,
+                        n
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_grid_matches_kdtree_at_edges() {
+        // Chains across the antimeridian and towards the north pole, where
+        // the longitude span of a cell grows with the latitude
+        let mut points: PointList = (0..40)
+            .map(|i| Point([179.9 + i as f64 * 0.005, (i % 5) as f64 * 0.003]))
+            .map(|p| Point([p.0[0] - if p.0[0] > 180.0 { 360.0 } else { 0.0 }, p.0[1]]))
+            .collect();
+        points.extend((0..40).map(|i| Point([(i * 9) as f64, 89.5 + i as f64 * 0.012])));
+
+        for metric in [
+            DistanceMetric::SphericalFast,
+            DistanceMetric::Spherical,
+            DistanceMetric::Haversine,
+        ] {
+            let tree = new_kd_tree_with_metric(points.clone(), metric);
+            let eps = metric.scale_eps(1.0);
+            let grid = GridIndex::with_metric(points.clone(), eps, metric);
+
+            for pt in &points {
+                for dist in [eps / 2.0, eps, eps * 3.0] {
+                    let mut pts1 = tree.in_range(pt, dist, Vec::new());
+                    let mut pts2 = grid.in_range(pt, dist, Vec::new());
+                    pts1.sort();
+                    pts2.sort();
+                    assert_eq!(pts1, pts2, "metric: {:?}, point: {:?}", metric, pt);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_dbscan_same_for_all_indexes() {
+        let points = sample_points();
+        let (expected, expected_noise) = db_scan_with_index(&points, 0.5, 4, IndexKind::KdTree);
+
+        for kind in [IndexKind::Grid, IndexKind::BruteForce, IndexKind::Auto] {
+            let (clusters, noise) = db_scan_with_index(&points, 0.5, 4, kind);
+            assert_eq!(noise, expected_noise, "index: {}", kind);
+            assert_eq!(clusters.len(), expected.len(), "index: {}", kind);
+            for (a, b) in clusters.iter().zip(&expected) {
+                let mut pa = a.points.clone();
+                let mut pb = b.points.clone();
+                pa.sort();
+                pb.sort();
+                assert_eq!(pa, pb, "index: {}", kind);
+            }
+        }
+    }
+
+    #[test]
+    fn test_auto_index_resolution() {
+        let points = sample_points();
+        assert_eq!(
+            IndexKind::Auto.resolve(&points[..5].to_vec()),
+            IndexKind::BruteForce
+        );
+        assert_eq!(IndexKind::Auto.resolve(&points), IndexKind::KdTree);
+        assert_eq!(IndexKind::Grid.resolve(&points), IndexKind::Grid);
+
+        // Large uniform data set goes to the grid, a dense blob to the K-D tree
+        let uniform: PointList = (0..60_000)
+            .map(|i| Point([(i % 300) as f64 * 0.001, (i / 300) as f64 * 0.001]))
+            .collect();
+        assert_eq!(IndexKind::Auto.resolve(&uniform), IndexKind::Grid);
+
+        let mut blob: PointList = (0..60_000)
+            .map(|i| Point([(i % 300) as f64 * 1e-6, (i / 300) as f64 * 1e-6]))
+            .collect();
+        blob.push(Point([10.0, 10.0]));
+        assert_eq!(IndexKind::Auto.resolve(&blob), IndexKind::KdTree);
+
+        let index = SpatialIndex::build(
+            IndexKind::Auto,
+            points[..5].to_vec(),
+            0.1,
+            DistanceMetric::default(),
+        );
+        assert_eq!(index.kind(), IndexKind::BruteForce);
+    }
+}
@@ -3,19 +3,69 @@
 //! Original code is under New BSD License.
 //! Author: Ethan Burns <burns.ethan@gmail.com>
 
+use super::distance::{
+    DistanceMetric, Metric, PreparedPoint, antimeridian_lon, distance_spherical_fast_prepared,
+    in_lon_window,
+};
 use super::point::{Point, PointList};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::ops::ControlFlow;
 
 /// KD-Tree implementation for efficient spatial queries
 ///
 /// Points are separated from nodes. Nodes hold only indices into the Points slice.
-pub struct KDTree {
-    /// All points in the tree
-    pub points: PointList,
+/// Levels split on each of the `D` coordinates in turn. `metric` applies to
+/// 2D points, any [`Metric`] or the [`DistanceMetric`] selected at run time,
+/// points of other dimensions are compared by squared Euclidean distance
+/// (see [`Point::sq_dist`]).
+///
+/// # Pruning
+///
+/// Range queries skip the far side of a split when the splitting plane is
+/// farther from the query point than the radius, as bounded by
+/// [`Metric::plane_sq_dist`]. That is only correct when
+/// two points are never closer than their distance along a single coordinate
+/// (axis-aligned separability). The [planar](DistanceMetric::is_planar)
+/// metrics (Euclidean, Manhattan and Chebyshev, none of which is ever below a
+/// single coordinate difference) and points of other dimensions meet it
+/// exactly, so projected coordinates (e.g. UTM meters) are queried without
+/// any spherical approximation. For the
+/// geographic metrics a parallel is the latitude delta away, a meridian is
+/// bounded at the latitude farthest from the equator a neighbour can have
+/// (the great-circle distance to it for [`DistanceMetric::Haversine`]), so
+/// queries find the same points as a scan of all points, near the poles too.
+///
+/// # Removal
+///
+/// [`KDTree::remove`] only marks a point as removed (a tombstone): its node
+/// keeps splitting the space, queries skip it. Point indices never change,
+/// removed points stay in `points`. Once tombstones make up more than half of
+/// the points in the nodes, the tree is rebuilt from the remaining points,
+/// so a sliding window of inserts and removals doesn't slow queries down.
+#[derive(Debug, Clone)]
+pub struct KDTree<const D: usize = 2, M: Metric = DistanceMetric> {
+    /// All points in the tree, removed ones included
+    pub points: PointList<D>,
     /// Root node of the tree
     pub root: Option<Box<KDTreeNode>>,
+    /// Metric used by range queries
+    pub metric: M,
+    /// Largest absolute latitude of the 2D points
+    max_abs_lat: f64,
+    /// `points` prepared by [`Metric::prepare`], empty with other metrics
+    /// and dimensions
+    prepared: Vec<PreparedPoint>,
+    /// Whether each point is removed, empty until the first removal
+    removed: Vec<bool>,
+    /// Number of removed points
+    removed_count: usize,
+    /// Number of removed points still in the nodes, see [`KDTree::remove`]
+    tombstones: usize,
 }
 
 /// A node in the K-D tree
+#[derive(Debug, Clone)]
 pub struct KDTreeNode {
     /// Index of the point associated with this node
     pub point_id: usize,
@@ -27,12 +77,17 @@ pub struct KDTreeNode {
     right: Option<Box<KDTreeNode>>,
 }
 
-impl KDTree {
+impl<const D: usize, M: Metric> KDTree<D, M> {
     /// Inserts a point into the K-D tree
     ///
     /// Inserting a node that is already a member of a K-D tree invalidates that tree.
-    #[allow(dead_code)] // Part of public API, may be used by external code
-    pub fn insert(&mut self, point: Point) {
+    pub fn insert(&mut self, point: Point<D>) {
+        if let Some(p) = point.as_2d() {
+            self.max_abs_lat = self.max_abs_lat.max(p.0[1].abs());
+        }
+        if let Some(prepared) = self.prepare(&point) {
+            self.prepared.push(prepared);
+        }
         self.points.push(point);
         let point_id = self.points.len() - 1;
         let new_node = KDTreeNode {
@@ -46,6 +101,46 @@ impl KDTree {
         self.root = Some(Box::new(self.insert_node(root, 0, new_node)));
     }
 
+    /// Removes a point from the K-D tree, queries no longer find it
+    ///
+    /// The point is tombstoned and the tree rebuilt once the tombstones
+    /// outnumber the other points in it, see the removal section of
+    /// [`KDTree`]. Removing a point twice does nothing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `point_id` is not the index of a point of the tree
+    pub fn remove(&mut self, point_id: usize) {
+        assert!(point_id < self.points.len(), "no point {}", point_id);
+        if self.is_removed(point_id) {
+            return;
+        }
+        self.removed.resize(self.points.len(), false);
+        self.removed[point_id] = true;
+        self.removed_count += 1;
+        self.tombstones += 1;
+
+        // Points in the nodes: the remaining ones and the tombstones
+        let in_nodes = self.points.len() - self.removed_count + self.tombstones;
+        if 2 * self.tombstones > in_nodes {
+            self.rebuild();
+        }
+    }
+
+    /// Returns whether the point was removed by [`KDTree::remove`]
+    pub fn is_removed(&self, point_id: usize) -> bool {
+        self.removed.get(point_id).copied().unwrap_or(false)
+    }
+
+    /// Rebuilds the nodes from the points that aren't removed
+    fn rebuild(&mut self) {
+        let ids = (0..self.points.len())
+            .filter(|&i| !self.is_removed(i))
+            .collect();
+        self.root = build_tree(0, &pre_sort_ids(&self.points, ids));
+        self.tombstones = 0;
+    }
+
     fn insert_node(
         &self,
         t: Option<Box<KDTreeNode>>,
@@ -54,12 +149,13 @@ impl KDTree {
     ) -> KDTreeNode {
         match t {
             None => {
-                n.split = depth % 2;
+                n.split = depth % D;
                 n
             }
             Some(mut t_node) => {
                 if self.points[n.point_id].0[t_node.split]
-                    < self.points[t_node.point_id].0[t_node.split]
+                    .total_cmp(&self.points[t_node.point_id].0[t_node.split])
+                    .is_lt()
                 {
                     t_node.left =
                         Some(Box::new(self.insert_node(t_node.left.take(), depth + 1, n)));
@@ -77,28 +173,123 @@ impl KDTree {
 
     /// Finds all nodes in the K-D tree that are within a given distance from the given point
     ///
-    /// To avoid allocation, the `nodes` vector can be pre-allocated with a larger
-    /// capacity and re-used across multiple calls.
-    pub fn in_range(&self, pt: &Point, dist: f64, mut nodes: Vec<usize>) -> Vec<usize> {
+    /// Found indices are appended to `nodes`, existing elements are kept. To
+    /// avoid allocation, the `nodes` vector can be pre-allocated with a larger
+    /// capacity and re-used across multiple calls after clearing it.
+    pub fn in_range(&self, pt: &Point<D>, dist: f64, mut nodes: Vec<usize>) -> Vec<usize> {
         if dist < 0.0 {
             return nodes;
         }
-        self.in_range_recursive(self.root.as_deref(), pt, dist, &mut nodes);
+        let _ = self.visit_wrapped(pt, dist, &mut |t| {
+            if self.tombstones == 0 {
+                nodes.push(t.point_id);
+                nodes.extend_from_slice(&t.equal_ids);
+            } else {
+                nodes.extend(t.ids().filter(|&id| !self.is_removed(id)));
+            }
+            ControlFlow::Continue(())
+        });
         nodes
     }
 
-    fn in_range_recursive(
+    /// Calls `f` with the index of every point within a given distance from the given point
+    ///
+    /// Finds the same points as [`KDTree::in_range`] without collecting
+    /// them, in no particular order. The search stops as soon as `f` returns
+    /// [`ControlFlow::Break`], e.g. once a density test has seen enough
+    /// neighbours.
+    pub fn for_each_in_range(
         &self,
-        t: Option<&KDTreeNode>,
-        pt: &Point,
-        r: f64,
-        nodes: &mut Vec<usize>,
+        pt: &Point<D>,
+        dist: f64,
+        mut f: impl FnMut(usize) -> ControlFlow<()>,
     ) {
+        if dist < 0.0 {
+            return;
+        }
+        let _ = self.visit_wrapped(pt, dist, &mut |t| {
+            t.ids()
+                .filter(|&id| self.tombstones == 0 || !self.is_removed(id))
+                .try_for_each(&mut f)
+        });
+    }
+
+    /// Counts the points in the K-D tree that are within a given distance from the given point
+    ///
+    /// Same as `in_range(pt, dist, Vec::new()).len()` without collecting the
+    /// indices, for density checks that only compare the count.
+    pub fn count_in_range(&self, pt: &Point<D>, dist: f64) -> usize {
+        if dist < 0.0 {
+            return 0;
+        }
+        let mut count = 0;
+        let _ = self.visit_wrapped(pt, dist, &mut |t| {
+            count += if self.tombstones == 0 {
+                1 + t.equal_ids.len()
+            } else {
+                t.ids().filter(|&id| !self.is_removed(id)).count()
+            };
+            ControlFlow::Continue(())
+        });
+        count
+    }
+
+    /// Calls `found` with every node within `r` of `pt`, on both sides of the
+    /// antimeridian for geographic 2D points, until it breaks
+    fn visit_wrapped(
+        &self,
+        pt: &Point<D>,
+        r: f64,
+        found: &mut impl FnMut(&KDTreeNode) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        let query = RangeQuery {
+            pt,
+            prepared: self.prepare(pt),
+            scales: self.plane_scales(pt, r),
+            r,
+        };
+        let moved_lon = pt.as_2d().and_then(|p| {
+            let [d_lon, _] = self
+                .metric
+                .max_deltas(r, self.max_abs_lat.max(p.0[1].abs()));
+            antimeridian_lon(&self.metric, p.0[0], d_lon)
+        });
+        let Some(moved_lon) = moved_lon else {
+            return self.visit_in_range(self.root.as_deref(), &query, found);
+        };
+
+        // Moving across the antimeridian keeps the latitude, so the scales
+        let mut moved = *pt;
+        moved.0[0] = moved_lon;
+        let moved_query = RangeQuery {
+            pt: &moved,
+            prepared: self.prepare(&moved),
+            ..query
+        };
+        for (query, is_moved) in [(query, false), (moved_query, true)] {
+            self.visit_in_range(self.root.as_deref(), &query, &mut |t| {
+                if in_lon_window(query.pt.0[0], self.points[t.point_id].0[0], is_moved) {
+                    found(t)?;
+                }
+                ControlFlow::Continue(())
+            })?;
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// Calls `found` with every node within `query.r` of `query.pt`, until it breaks
+    fn visit_in_range(
+        &self,
+        t: Option<&KDTreeNode>,
+        query: &RangeQuery<D>,
+        found: &mut impl FnMut(&KDTreeNode) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
         let t = match t {
-            None => return,
+            None => return ControlFlow::Continue(()),
             Some(t) => t,
         };
 
+        let RangeQuery { pt, r, .. } = *query;
         let diff = pt.0[t.split] - self.points[t.point_id].0[t.split];
 
         let (this_side, other_side) = if diff < 0.0 {
@@ -107,44 +298,235 @@ impl KDTree {
             (t.right.as_deref(), t.left.as_deref())
         };
 
-        let mut p1 = Point([0.0, 0.0]);
-        p1.0[1 - t.split] = (pt.0[1 - t.split] + self.points[t.point_id].0[1 - t.split]) / 2.0;
-        p1.0[t.split] = pt.0[t.split];
+        let dist = self.plane_sq_dist(diff, t.split, query.scales);
 
-        let mut p2 = Point([0.0, 0.0]);
-        p2.0[1 - t.split] = (pt.0[1 - t.split] + self.points[t.point_id].0[1 - t.split]) / 2.0;
-        p2.0[t.split] = self.points[t.point_id].0[t.split];
+        self.visit_in_range(this_side, query, found)?;
+        if dist <= r * r {
+            let sq_dist = match (self.prepared.get(t.point_id), &query.prepared) {
+                (Some(node), Some(prepared)) => distance_spherical_fast_prepared(node, prepared),
+                _ => self.sq_dist(&self.points[t.point_id], pt),
+            };
+            if sq_dist < r * r {
+                found(t)?;
+            }
+            self.visit_in_range(other_side, query, found)?;
+        }
+        ControlFlow::Continue(())
+    }
 
-        let dist = p1.sq_dist(&p2);
+    /// Finds the `k` points in the K-D tree closest to the given point
+    ///
+    /// Returns `(index, squared distance)` pairs sorted by ascending distance
+    /// (ties by index), with distances in [`Metric::sq_dist`] units
+    /// (squared coordinate units for points of other dimensions than 2).
+    /// Points equal to `pt` are included, so when `pt` is a point of the tree
+    /// it comes first with distance `0.0`. All points are returned when the
+    /// tree holds fewer than `k`.
+    ///
+    /// Unlike [`KDTree::in_range`], points across the antimeridian are only
+    /// found when the search happens to reach them.
+    pub fn k_nearest(&self, pt: &Point<D>, k: usize) -> Vec<(usize, f64)> {
+        let mut heap = BinaryHeap::with_capacity(k.min(self.points.len()) + 1);
+        if k > 0 {
+            self.k_nearest_recursive(self.root.as_deref(), pt, k, &mut heap);
+        }
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|c| (c.id, c.dist))
+            .collect()
+    }
 
-        self.in_range_recursive(this_side, pt, r, nodes);
-        if dist <= r * r {
-            if self.points[t.point_id].sq_dist(pt) < r * r {
-                nodes.push(t.point_id);
-                nodes.extend_from_slice(&t.equal_ids);
+    fn k_nearest_recursive(
+        &self,
+        t: Option<&KDTreeNode>,
+        pt: &Point<D>,
+        k: usize,
+        heap: &mut BinaryHeap<Candidate>,
+    ) {
+        let t = match t {
+            None => return,
+            Some(t) => t,
+        };
+
+        let node = &self.points[t.point_id];
+        let diff = pt.0[t.split] - node.0[t.split];
+        let (this_side, other_side) = if diff < 0.0 {
+            (t.left.as_deref(), t.right.as_deref())
+        } else {
+            (t.right.as_deref(), t.left.as_deref())
+        };
+
+        self.k_nearest_recursive(this_side, pt, k, heap);
+
+        // Only points closer than the worst candidate matter once there are k
+        let full = |heap: &BinaryHeap<Candidate>| heap.len() == k;
+        if full(heap)
+            && heap.peek().is_some_and(|worst| {
+                let scales = self.plane_scales(pt, worst.dist.sqrt());
+                self.plane_sq_dist(diff, t.split, scales) > worst.dist
+            })
+        {
+            return;
+        }
+
+        let dist = self.sq_dist(node, pt);
+        for id in t.ids().filter(|&id| !self.is_removed(id)) {
+            let candidate = Candidate { dist, id };
+            if !full(heap) {
+                heap.push(candidate);
+            } else if heap.peek().is_some_and(|worst| candidate < *worst) {
+                heap.pop();
+                heap.push(candidate);
             }
-            self.in_range_recursive(other_side, pt, r, nodes);
+        }
+        self.k_nearest_recursive(other_side, pt, k, heap);
+    }
+
+    /// Returns `pt` prepared for distances to the prepared points, see
+    /// [`Metric::prepare`]
+    fn prepare(&self, pt: &Point<D>) -> Option<PreparedPoint> {
+        pt.as_2d().and_then(|p| self.metric.prepare(&p))
+    }
+
+    /// Returns the squared distance of two points, by `metric` for 2D points
+    fn sq_dist(&self, a: &Point<D>, b: &Point<D>) -> f64 {
+        a.sq_dist_with(b, &self.metric)
+    }
+
+    /// Returns the factors of `[longitude, latitude]` deltas in
+    /// [`KDTree::plane_sq_dist`] for points within `r` of `pt`, see
+    /// [`Metric::plane_scales`]
+    ///
+    /// Coordinates of other dimensions than 2 aren't scaled.
+    fn plane_scales(&self, pt: &Point<D>, r: f64) -> [f64; 2] {
+        match pt.as_2d() {
+            Some(p) => self.metric.plane_scales(&p, r),
+            None => [1.0, 1.0],
         }
     }
 
+    /// Returns a lower bound of the squared distance from a query point to
+    /// points on the other side of a plane splitting dimension `split`,
+    /// `diff` coordinate units away, see [`Metric::plane_sq_dist`]
+    ///
+    /// `scales` are the [`KDTree::plane_scales`] of the query point.
+    fn plane_sq_dist(&self, diff: f64, split: usize, scales: [f64; 2]) -> f64 {
+        if D != 2 {
+            return diff * diff;
+        }
+        self.metric.plane_sq_dist(diff, split, scales)
+    }
+
     /// Returns the height of the K-D tree
-    #[allow(dead_code)] // Part of public API, may be used by external code
     pub fn height(&self) -> usize {
         self.root.as_ref().map_or(0, |r| r.height())
     }
+
+    /// Returns the height of the K-D tree relative to the smallest height
+    /// its nodes fit in, `1.0` when it's perfectly balanced
+    ///
+    /// Equal points share a node. Queries of a tree with a large factor,
+    /// e.g. after inserting points in sorted order, visit more nodes than
+    /// needed. An empty tree is balanced.
+    pub fn balance_factor(&self) -> f64 {
+        let nodes = self.root.as_ref().map_or(0, |r| r.count());
+        if nodes == 0 {
+            return 1.0;
+        }
+        // floor(log2(nodes)) + 1
+        let min_height = usize::BITS - nodes.leading_zeros();
+        self.height() as f64 / f64::from(min_height)
+    }
+}
+
+/// Range query of [`KDTree::visit_in_range`]
+#[derive(Clone, Copy)]
+struct RangeQuery<'a, const D: usize> {
+    pt: &'a Point<D>,
+    /// `pt` prepared by [`KDTree::prepare`]
+    prepared: Option<PreparedPoint>,
+    /// [`KDTree::plane_scales`] of `pt`
+    scales: [f64; 2],
+    r: f64,
+}
+
+/// Point found by [`KDTree::k_nearest`], ordered by distance, then index
+#[derive(Clone, Copy)]
+struct Candidate {
+    dist: f64,
+    id: usize,
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.dist
+            .total_cmp(&other.dist)
+            .then(self.id.cmp(&other.id))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
 impl KDTreeNode {
+    /// Returns the indices of the node's point and the points equal to it
+    fn ids(&self) -> impl Iterator<Item = usize> + '_ {
+        std::iter::once(self.point_id).chain(self.equal_ids.iter().copied())
+    }
+
     fn height(&self) -> usize {
         let ht = self.left.as_ref().map_or(0, |l| l.height());
         let rht = self.right.as_ref().map_or(0, |r| r.height());
         ht.max(rht) + 1
     }
+
+    fn count(&self) -> usize {
+        let left = self.left.as_ref().map_or(0, |l| l.count());
+        let right = self.right.as_ref().map_or(0, |r| r.count());
+        left + right + 1
+    }
 }
 
 /// Creates a new K-D tree built from the given points
-pub fn new_kd_tree(points: PointList) -> KDTree {
-    let mut result = KDTree { points, root: None };
+pub fn new_kd_tree<const D: usize>(points: PointList<D>) -> KDTree<D> {
+    new_kd_tree_with_metric(points, DistanceMetric::default())
+}
+
+/// Creates a new K-D tree built from the given points, queried with the given metric
+pub fn new_kd_tree_with_metric<const D: usize, M: Metric>(
+    points: PointList<D>,
+    metric: M,
+) -> KDTree<D, M> {
+    let max_abs_lat = points
+        .iter()
+        .filter_map(Point::as_2d)
+        .fold(0.0_f64, |m, p| m.max(p.0[1].abs()));
+    let mut result = KDTree {
+        points,
+        root: None,
+        metric,
+        max_abs_lat,
+        prepared: Vec::new(),
+        removed: Vec::new(),
+        removed_count: 0,
+        tombstones: 0,
+    };
+    result.prepared = result
+        .points
+        .iter()
+        .filter_map(|p| result.prepare(p))
+        .collect();
 
     if !result.points.is_empty() {
         result.root = build_tree(0, &pre_sort(&result.points));
@@ -153,9 +535,17 @@ pub fn new_kd_tree(points: PointList) -> KDTree {
     result
 }
 
+/// Subtrees with at least this many points are built in parallel under the
+/// `rayon` feature, smaller ones aren't worth a task
+#[cfg(feature = "rayon")]
+const PARALLEL_BUILD_POINTS: usize = 4096;
+
 /// Builds a tree node by finding the median point and recursively building left and right subtrees
-fn build_tree(depth: usize, nodes: &PreSorted) -> Option<Box<KDTreeNode>> {
-    let split = depth % 2;
+///
+/// Under the `rayon` feature large subtrees are built on all threads, the
+/// tree is the same either way.
+fn build_tree<const D: usize>(depth: usize, nodes: &PreSorted<D>) -> Option<Box<KDTreeNode>> {
+    let split = depth % D;
     match nodes.cur[split].len() {
         0 => None,
         1 => Some(Box::new(KDTreeNode {
@@ -167,66 +557,89 @@ fn build_tree(depth: usize, nodes: &PreSorted) -> Option<Box<KDTreeNode>> {
         })),
         _ => {
             let (med, equal, left, right) = nodes.split_med(split);
+            #[cfg(feature = "rayon")]
+            let (left, right) = if nodes.cur[split].len() >= PARALLEL_BUILD_POINTS {
+                rayon::join(
+                    || build_tree(depth + 1, &left),
+                    || build_tree(depth + 1, &right),
+                )
+            } else {
+                (build_tree(depth + 1, &left), build_tree(depth + 1, &right))
+            };
+            #[cfg(not(feature = "rayon"))]
+            let (left, right) = (build_tree(depth + 1, &left), build_tree(depth + 1, &right));
             Some(Box::new(KDTreeNode {
                 point_id: med,
                 equal_ids: equal,
                 split,
-                left: build_tree(depth + 1, &left),
-                right: build_tree(depth + 1, &right),
+                left,
+                right,
             }))
         }
     }
 }
 
 /// Holds nodes pre-sorted on each dimension
-struct PreSorted {
-    points: PointList,
+///
+/// All splits share the points, only the sorted index lists are split.
+struct PreSorted<'a, const D: usize> {
+    points: &'a PointList<D>,
     /// Currently sorted set of point IDs by dimension
-    cur: [Vec<usize>; 2],
+    cur: [Vec<usize>; D],
+}
+
+/// Compares points on dimension `first`, then on the following ones
+///
+/// Uses [`f64::total_cmp`], so the order is total and the same on every
+/// platform: `-0.0` sorts before `0.0`, NaN after all numbers.
+fn cmp_from<const D: usize>(a: &Point<D>, b: &Point<D>, first: usize) -> Ordering {
+    (0..D)
+        .map(|d| (first + d) % D)
+        .map(|d| a.0[d].total_cmp(&b.0[d]))
+        .find(|ord| ord.is_ne())
+        .unwrap_or(Ordering::Equal)
 }
 
 /// Pre-sorts nodes on each dimension
-fn pre_sort(points: &PointList) -> PreSorted {
+fn pre_sort<const D: usize>(points: &PointList<D>) -> PreSorted<'_, D> {
+    pre_sort_ids(points, (0..points.len()).collect())
+}
+
+/// Pre-sorts the nodes of the points `ids` on each dimension
+fn pre_sort_ids<const D: usize>(points: &PointList<D>, ids: Vec<usize>) -> PreSorted<'_, D> {
     let mut p = PreSorted {
-        points: points.clone(),
-        cur: [Vec::new(), Vec::new()],
+        points,
+        cur: std::array::from_fn(|_| Vec::new()),
     };
-    for i in 0..2 {
-        p.cur[i] = (0..points.len()).collect();
-        p.cur[i].sort_by(|&a, &b| {
-            let a_val = points[a].0[i];
-            let b_val = points[b].0[i];
-            if a_val == b_val {
-                // For equal values, sort by the other dimension
-                // Use unwrap_or_else to handle NaN (though shouldn't occur in valid geo data)
-                points[a].0[1 - i]
-                    .partial_cmp(&points[b].0[1 - i])
-                    .unwrap_or(std::cmp::Ordering::Equal)
-            } else {
-                a_val
-                    .partial_cmp(&b_val)
-                    .unwrap_or(std::cmp::Ordering::Equal)
-            }
-        });
+    for i in 0..D {
+        p.cur[i] = ids.clone();
+        // Equal points are ordered by ID, so no two nodes compare equal
+        let cmp = |&a: &usize, &b: &usize| cmp_from(&points[a], &points[b], i).then(a.cmp(&b));
+        // The order is total, so both sorts give the same one
+        #[cfg(feature = "rayon")]
+        rayon::slice::ParallelSliceMut::par_sort_by(&mut p.cur[i][..], cmp);
+        #[cfg(not(feature = "rayon"))]
+        p.cur[i].sort_by(cmp);
     }
     p
 }
 
-impl PreSorted {
+impl<'a, const D: usize> PreSorted<'a, D> {
     /// Returns the median node on the split dimension and two PreSorted structs
     /// that contain the nodes (still sorted on each dimension) that are less than
     /// and greater than or equal to the median node value on the given splitting dimension.
-    fn split_med(&self, dim: usize) -> (usize, Vec<usize>, PreSorted, PreSorted) {
+    ///
+    /// Values are compared like in [`cmp_from`], points at exactly the same
+    /// coordinates end up together in the median node.
+    fn split_med(&self, dim: usize) -> (usize, Vec<usize>, PreSorted<'a, D>, PreSorted<'a, D>) {
+        let value = |i: usize| self.points[self.cur[dim][i]].0[dim];
         let mut m = self.cur[dim].len() / 2;
-        while m > 0
-            && self.points[self.cur[dim][m - 1]].0[dim] == self.points[self.cur[dim][m]].0[dim]
-        {
+        while m > 0 && value(m - 1).total_cmp(&value(m)).is_eq() {
             m -= 1;
         }
+        let point = |i: usize| &self.points[self.cur[dim][i]];
         let mut mh = m;
-        while mh < self.cur[dim].len() - 1
-            && self.points[self.cur[dim][mh + 1]] == self.points[self.cur[dim][m]]
-        {
+        while mh < self.cur[dim].len() - 1 && cmp_from(point(mh + 1), point(m), dim).is_eq() {
             mh += 1;
         }
         let med = self.cur[dim][m];
@@ -234,40 +647,32 @@ impl PreSorted {
         let pivot = self.points[med].0[dim];
 
         let mut left = PreSorted {
-            points: self.points.clone(),
-            cur: [Vec::new(), Vec::new()],
+            points: self.points,
+            cur: std::array::from_fn(|_| Vec::new()),
         };
         left.cur[dim] = self.cur[dim][..m].to_vec();
 
         let mut right = PreSorted {
-            points: self.points.clone(),
-            cur: [Vec::new(), Vec::new()],
+            points: self.points,
+            cur: std::array::from_fn(|_| Vec::new()),
         };
         right.cur[dim] = self.cur[dim][mh + 1..].to_vec();
 
-        for d in 0..2 {
+        for d in 0..D {
             if d == dim {
                 continue;
             }
 
-            left.cur[d] = Vec::with_capacity(self.cur[d].len());
-            right.cur[d] = Vec::with_capacity(self.cur[d].len());
+            // Same points as on the split dimension, in another order
+            left.cur[d] = Vec::with_capacity(left.cur[dim].len());
+            right.cur[d] = Vec::with_capacity(right.cur[dim].len());
 
             for &n in &self.cur[d] {
-                if n == med {
-                    continue;
-                }
-                let mut skip = false;
-                for &x in &equal {
-                    if n == x {
-                        skip = true;
-                        break;
-                    }
-                }
-                if skip {
+                // The median and the points equal to it stay in the node
+                if cmp_from(&self.points[n], &self.points[med], dim).is_eq() {
                     continue;
                 }
-                if self.points[n].0[dim] < pivot {
+                if self.points[n].0[dim].total_cmp(&pivot).is_lt() {
                     left.cur[d].push(n);
                 } else {
                     right.cur[d].push(n);
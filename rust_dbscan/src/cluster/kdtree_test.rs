@@ -0,0 +1,474 @@
+#[cfg(test)]
+mod tests {
+    use crate::cluster::test_util::lcg;
+    use crate::cluster::{
+        Chebyshev, DistanceMetric, Euclidean, Manhattan, Metric, Point, PointList, SphericalFast,
+        new_kd_tree, new_kd_tree_with_metric,
+    };
+    use std::ops::ControlFlow;
+
+    /// Returns the `k` points closest to `pt` by scanning all of them
+    fn brute_force(points: &PointList, pt: &Point, k: usize) -> Vec<(usize, f64)> {
+        let metric = DistanceMetric::default();
+        let mut all: Vec<(usize, f64)> = points
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (i, metric.sq_dist(p, pt)))
+            .collect();
+        all.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        all.truncate(k);
+        all
+    }
+
+    #[test]
+    fn test_k_nearest() {
+        let points = vec![
+            Point([30.244759, 59.955982]),
+            Point([30.24472, 59.955975]),
+            Point([30.244358, 59.96698]),
+            Point([30.258387, 59.951557]),
+            Point([30.434124, 59.934319]),
+            Point([30.244759, 59.955982]),
+            Point([30.2448, 59.9561]),
+            Point([30.3, 59.94]),
+            Point([30.31, 59.95]),
+            Point([30.25, 59.97]),
+            Point([30.2448, 59.9561]),
+        ];
+        let tree = new_kd_tree(points.clone());
+
+        for pt in points
+            .iter()
+            .chain(&[Point([30.28, 59.95]), Point([30.0, 60.0])])
+        {
+            for k in [1, 2, 3, 5, points.len()] {
+                assert_eq!(tree.k_nearest(pt, k), brute_force(&points, pt, k));
+            }
+        }
+
+        // The query point is included, equal points too
+        let found = tree.k_nearest(&points[0], 3);
+        assert_eq!(found[0], (0, 0.0));
+        assert_eq!(found[1], (5, 0.0));
+
+        // k beyond the number of points returns all of them
+        assert_eq!(tree.k_nearest(&points[4], 100).len(), points.len());
+        assert!(tree.k_nearest(&points[4], 0).is_empty());
+        assert!(new_kd_tree(Vec::new()).k_nearest(&points[4], 3).is_empty());
+    }
+
+    #[test]
+    fn test_kd_tree_empty_and_single_point() {
+        let pt = Point([30.244759, 59.955982]);
+
+        let mut tree = new_kd_tree(Vec::new());
+        assert!(tree.root.is_none());
+        assert_eq!(tree.height(), 0);
+        assert!(tree.in_range(&pt, 0.1, Vec::new()).is_empty());
+        assert_eq!(tree.count_in_range(&pt, 0.1), 0);
+        assert!(tree.k_nearest(&pt, 3).is_empty());
+
+        // Inserting into an empty tree gives the tree built from that point
+        tree.insert(pt);
+        let built = new_kd_tree(vec![pt]);
+        for tree in [&tree, &built] {
+            assert_eq!(tree.height(), 1);
+            assert_eq!(tree.in_range(&pt, 0.1, Vec::new()), vec![0]);
+            assert_eq!(tree.in_range(&pt, 0.0, Vec::new()), Vec::<usize>::new());
+            assert_eq!(tree.count_in_range(&pt, 0.1), 1);
+            assert_eq!(tree.k_nearest(&pt, 3), vec![(0, 0.0)]);
+            assert!(
+                tree.in_range(&Point([0.0, 0.0]), 0.1, Vec::new())
+                    .is_empty()
+            );
+        }
+    }
+
+    #[test]
+    fn test_balance_factor() {
+        assert_eq!(new_kd_tree::<2>(Vec::new()).balance_factor(), 1.0);
+
+        // Built trees split at the median
+        let mut next = lcg(11);
+        let scattered: PointList = (0..1023).map(|_| Point([next(), next()])).collect();
+        let lattice: PointList = (0..1023)
+            .map(|i| Point([(i % 31) as f64, (i / 31) as f64]))
+            .collect();
+        for points in [scattered, lattice] {
+            let tree = new_kd_tree(points);
+            assert!(tree.balance_factor() < 1.2, "{}", tree.balance_factor());
+        }
+
+        // Points equal in the split coordinate all go to the same side, so
+        // collinear points waste every other level
+        let line: PointList = (0..1023).map(|i| Point([i as f64 * 0.001, 0.0])).collect();
+        assert_eq!(new_kd_tree(line.clone()).balance_factor(), 1.8);
+
+        // Equal points share a node
+        let tree = new_kd_tree(vec![Point([1.0, 2.0]); 100]);
+        assert_eq!((tree.height(), tree.balance_factor()), (1, 1.0));
+
+        // Points inserted in sorted order form a list
+        let mut tree = new_kd_tree(Vec::new());
+        for &p in &line[..127] {
+            tree.insert(p);
+        }
+        assert_eq!(tree.height(), 127);
+        assert_eq!(tree.balance_factor(), 127.0 / 7.0);
+    }
+
+    #[test]
+    fn test_k_nearest_grid() {
+        // Many equidistant points exercise tie breaking and pruning
+        let points: PointList = (0..400)
+            .map(|i| Point([(i % 20) as f64, (i / 20) as f64]))
+            .collect();
+        let tree = new_kd_tree_with_metric(points.clone(), DistanceMetric::SquaredEuclidean);
+        let pt = Point([7.0, 12.0]);
+        let found = tree.k_nearest(&pt, 9);
+        let ids: Vec<usize> = found.iter().map(|&(i, _)| i).collect();
+        assert_eq!(ids, vec![247, 227, 246, 248, 267, 226, 228, 266, 268]);
+        assert_eq!(found[8].1, 2.0);
+    }
+
+    #[test]
+    fn test_in_range_projected() {
+        // UTM zone 36N eastings/northings in meters around St. Petersburg
+        let mut next = lcg(7);
+        let points: PointList = (0..500)
+            .map(|_| Point([350_000.0 + next() * 2_000.0, 6_650_000.0 + next() * 1_000.0]))
+            .collect();
+        let metric = DistanceMetric::SquaredEuclidean;
+        let tree = new_kd_tree_with_metric(points.clone(), metric);
+
+        for pt in points.iter().step_by(7) {
+            for dist in [10.0, 75.0, 300.0] {
+                let mut found = tree.in_range(pt, dist, Vec::new());
+                found.sort_unstable();
+                let expected: Vec<usize> = (0..points.len())
+                    .filter(|&i| metric.sq_dist(&points[i], pt) < dist * dist)
+                    .collect();
+                assert_eq!(found, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_metric_implementors() {
+        // Trees over the Metric implementors find what their DistanceMetric does
+        let mut next = lcg(5);
+        let points: PointList = (0..500)
+            .map(|_| Point([30.0 + next() * 0.1, 59.9 + next() * 0.05]))
+            .collect();
+        let fast = new_kd_tree_with_metric(points.clone(), SphericalFast);
+        let fast_enum = new_kd_tree_with_metric(points.clone(), DistanceMetric::SphericalFast);
+        let euclidean = new_kd_tree_with_metric(points.clone(), Euclidean);
+        let euclidean_enum =
+            new_kd_tree_with_metric(points.clone(), DistanceMetric::SquaredEuclidean);
+
+        for pt in points.iter().step_by(11) {
+            for eps in [0.05, 0.3, 2.0] {
+                let r = fast.metric.scale_eps(eps);
+                let mut found = fast.in_range(pt, r, Vec::new());
+                found.sort_unstable();
+                let mut expected = fast_enum.in_range(pt, r, Vec::new());
+                expected.sort_unstable();
+                assert_eq!(found, expected, "{:?} eps {}", pt, eps);
+
+                let r = eps * 0.01;
+                let mut found = euclidean.in_range(pt, r, Vec::new());
+                found.sort_unstable();
+                let mut expected = euclidean_enum.in_range(pt, r, Vec::new());
+                expected.sort_unstable();
+                assert_eq!(found, expected, "{:?} r {}", pt, r);
+            }
+            assert_eq!(fast.k_nearest(pt, 5), fast_enum.k_nearest(pt, 5));
+        }
+    }
+
+    #[test]
+    fn test_kd_tree_3d() {
+        // Deterministic 5x5x5 lattice, with duplicates of every 7th point
+        let mut points: PointList<3> = Vec::new();
+        for i in 0..125 {
+            let p = Point([(i % 5) as f64, ((i / 5) % 5) as f64, (i / 25) as f64 * 0.5]);
+            points.push(p);
+            if i % 7 == 0 {
+                points.push(p);
+            }
+        }
+        let tree = new_kd_tree(points.clone());
+
+        for pt in [
+            Point([2.0, 2.0, 1.0]),
+            Point([0.3, 4.1, 0.0]),
+            Point([9.0, 9.0, 9.0]),
+        ] {
+            let mut found = tree.in_range(&pt, 1.2, Vec::new());
+            found.sort_unstable();
+            let expected: Vec<usize> = (0..points.len())
+                .filter(|&i| points[i].sq_dist(&pt) < 1.2 * 1.2)
+                .collect();
+            assert_eq!(found, expected);
+            assert_eq!(tree.count_in_range(&pt, 1.2), expected.len());
+            // Found indices are appended to what the buffer holds
+            let appended = tree.in_range(&pt, 1.2, vec![usize::MAX]);
+            assert_eq!(appended[0], usize::MAX);
+            assert_eq!(appended.len(), expected.len() + 1);
+
+            let nearest = tree.k_nearest(&pt, 4);
+            let mut all: Vec<(usize, f64)> = (0..points.len())
+                .map(|i| (i, points[i].sq_dist(&pt)))
+                .collect();
+            all.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+            assert_eq!(nearest, all[..4]);
+        }
+    }
+
+    #[test]
+    fn test_remove() {
+        // A 10x10 lattice with a duplicate of its first point
+        let mut points: PointList = (0..100)
+            .map(|i| {
+                Point([
+                    30.0 + 0.001 * (i % 10) as f64,
+                    60.0 + 0.001 * (i / 10) as f64,
+                ])
+            })
+            .collect();
+        points.push(points[0]);
+        let mut tree = new_kd_tree(points.clone());
+        let eps = 0.0025;
+        let in_range = |tree: &crate::cluster::KDTree, pt: &Point| {
+            let mut found = tree.in_range(pt, eps, Vec::new());
+            found.sort();
+            found
+        };
+        let expected = |pt: &Point, removed: &[usize]| {
+            let mut found = in_range(&new_kd_tree(points.clone()), pt);
+            found.retain(|i| !removed.contains(i));
+            found
+        };
+
+        // Tombstoned points are skipped, also as the equal of another node
+        let mut removed = vec![0, 11, 55];
+        for &i in &removed {
+            tree.remove(i);
+        }
+        tree.remove(11);
+        assert!(tree.is_removed(11) && !tree.is_removed(12));
+        for pt in [points[0], points[11], points[55], points[99]] {
+            assert_eq!(in_range(&tree, &pt), expected(&pt, &removed));
+            assert_eq!(tree.count_in_range(&pt, eps), expected(&pt, &removed).len());
+            let mut visited = Vec::new();
+            tree.for_each_in_range(&pt, eps, |i| {
+                visited.push(i);
+                ControlFlow::Continue(())
+            });
+            visited.sort();
+            assert_eq!(visited, expected(&pt, &removed));
+        }
+        assert!(!in_range(&tree, &points[0]).contains(&0));
+        assert!(in_range(&tree, &points[0]).contains(&100));
+        assert_eq!(tree.k_nearest(&points[0], 1)[0].0, 100);
+        assert!(tree.k_nearest(&points[11], 5).iter().all(|&(i, _)| i != 11));
+
+        // Removing most points rebuilds the tree from the remaining ones
+        let height = tree.height();
+        removed = (0..101).filter(|i| i % 3 != 0 || *i == 0).collect();
+        for &i in &removed {
+            tree.remove(i);
+        }
+        assert!(tree.height() < height);
+        assert_eq!(tree.points.len(), 101);
+        for pt in &points {
+            assert_eq!(in_range(&tree, pt), expected(pt, &removed));
+        }
+
+        // A sliding window: new points are found, removed ones aren't
+        tree.insert(Point([30.0, 60.0]));
+        assert_eq!(tree.in_range(&points[0], 0.0001, Vec::new()), vec![101]);
+        for i in 0..102 {
+            tree.remove(i);
+        }
+        assert!(tree.root.is_none());
+        assert!(tree.in_range(&points[0], eps, Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn test_in_range_high_latitude() {
+        // Points from 60 degrees north to the pole, all around it
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 11) as f64 / (1u64 << 53) as f64
+        };
+        let points: PointList = (0..1000)
+            .map(|_| Point([-180.0 + 360.0 * next(), 60.0 + 29.99 * next()]))
+            .collect();
+
+        for metric in [
+            DistanceMetric::Haversine,
+            DistanceMetric::Spherical,
+            DistanceMetric::SphericalFast,
+        ] {
+            let tree = new_kd_tree_with_metric(points.clone(), metric);
+            for eps_km in [20.0, 200.0, 2000.0] {
+                let r = metric.scale_eps(eps_km);
+                for pt in &points {
+                    let mut found = tree.in_range(pt, r, Vec::new());
+                    found.sort();
+                    let expected: Vec<usize> = (0..points.len())
+                        .filter(|&j| metric.sq_dist(pt, &points[j]) < r * r)
+                        .collect();
+                    assert_eq!(found, expected, "{:?} eps {} from {:?}", metric, eps_km, pt);
+                }
+            }
+
+            // Nearest points are exact too
+            for pt in points.iter().step_by(50) {
+                let nearest = tree.k_nearest(pt, 5);
+                let mut all: Vec<(usize, f64)> = (0..points.len())
+                    .map(|j| (j, metric.sq_dist(pt, &points[j])))
+                    .collect();
+                all.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+                assert_eq!(nearest, all[..5], "{:?} from {:?}", metric, pt);
+            }
+        }
+    }
+
+    #[test]
+    fn test_in_range_planar() {
+        // Projected coordinates in metres, far outside any latitude range
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 11) as f64 / (1u64 << 53) as f64
+        };
+        let points: PointList = (0..1000)
+            .map(|_| {
+                Point([
+                    500_000.0 + 10_000.0 * next(),
+                    4_000_000.0 + 10_000.0 * next(),
+                ])
+            })
+            .collect();
+
+        for metric in [
+            DistanceMetric::Manhattan,
+            DistanceMetric::Chebyshev,
+            DistanceMetric::SquaredEuclidean,
+        ] {
+            assert_planar_queries(&points, metric);
+        }
+        assert_planar_queries(&points, Manhattan);
+        assert_planar_queries(&points, Chebyshev);
+        assert_planar_queries(&points, Euclidean);
+    }
+
+    /// Checks queries of a tree over `points` against a scan of all points
+    fn assert_planar_queries<M: Metric + std::fmt::Debug>(points: &PointList, metric: M) {
+        let tree = new_kd_tree_with_metric(points.clone(), metric);
+        for eps in [50.0, 500.0, 5000.0] {
+            let r = metric.scale_eps(eps);
+            for pt in points {
+                let mut found = tree.in_range(pt, r, Vec::new());
+                found.sort();
+                let expected: Vec<usize> = (0..points.len())
+                    .filter(|&j| metric.sq_dist(pt, &points[j]) < r * r)
+                    .collect();
+                assert_eq!(found, expected, "{:?} eps {} from {:?}", metric, eps, pt);
+            }
+        }
+
+        for pt in points.iter().step_by(50) {
+            let nearest = tree.k_nearest(pt, 5);
+            let mut all: Vec<(usize, f64)> = (0..points.len())
+                .map(|j| (j, metric.sq_dist(pt, &points[j])))
+                .collect();
+            all.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+            assert_eq!(nearest, all[..5], "{:?} from {:?}", metric, pt);
+        }
+    }
+
+    #[test]
+    fn test_identical_coordinates() {
+        // Few distinct coordinates, repeated many times, with both signs of zero
+        let distinct = [
+            Point([0.0, 10.0]),
+            Point([-0.0, 10.0]),
+            Point([0.0, -0.0]),
+            Point([0.001, 10.0]),
+            Point([30.0, 60.0]),
+            Point([30.0, 60.0005]),
+        ];
+        let points: PointList = (0..3000)
+            .map(|i| distinct[i * 7 % distinct.len()])
+            .collect();
+
+        for metric in [
+            DistanceMetric::SphericalFast,
+            DistanceMetric::SquaredEuclidean,
+        ] {
+            let tree = new_kd_tree_with_metric(points.clone(), metric);
+            for r in [0.0001, 0.01, 1.0] {
+                for pt in &distinct {
+                    let mut found = tree.in_range(pt, r, Vec::new());
+                    found.sort();
+                    let expected: Vec<usize> = (0..points.len())
+                        .filter(|&j| metric.sq_dist(pt, &points[j]) < r * r)
+                        .collect();
+                    assert_eq!(found, expected, "{:?} r {} from {:?}", metric, r, pt);
+                }
+            }
+
+            // Ties are broken by index
+            let nearest = tree.k_nearest(&distinct[4], 3);
+            let ids: Vec<usize> = nearest.iter().map(|&(i, _)| i).collect();
+            assert_eq!(ids, [4, 10, 16]);
+        }
+    }
+
+    #[test]
+    fn test_for_each_in_range() {
+        // Points on both sides of the antimeridian
+        let points: PointList = (0..200)
+            .map(|i| 179.95 + 0.001 * (i % 100) as f64)
+            .zip((0..200).map(|i| 10.0 + 0.001 * (i / 20) as f64))
+            .map(|(lon, lat)| Point([if lon > 180.0 { lon - 360.0 } else { lon }, lat]))
+            .collect();
+        let tree = new_kd_tree(points.clone());
+        let eps = DistanceMetric::default().scale_eps(15.0);
+
+        for pt in points.iter().step_by(7) {
+            let mut all = Vec::new();
+            tree.for_each_in_range(pt, eps, |i| {
+                all.push(i);
+                ControlFlow::Continue(())
+            });
+            all.sort();
+            let mut expected = tree.in_range(pt, eps, Vec::new());
+            expected.sort();
+            assert_eq!(all, expected, "from {:?}", pt);
+            assert!(expected.len() > 5);
+
+            // Breaking stops the search
+            let mut calls = 0;
+            tree.for_each_in_range(pt, eps, |_| {
+                calls += 1;
+                if calls == 5 {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            });
+            assert_eq!(calls, 5);
+        }
+
+        tree.for_each_in_range(&points[0], -1.0, |_| panic!("negative distance"));
+    }
+}
@@ -1,24 +1,64 @@
 //! Package cluster implements DBScan clustering on (lat, lon) using K-D Tree
+pub mod cache;
 pub mod dbscan;
 pub mod distance;
+pub mod grid;
+pub mod hull;
+pub mod incremental;
+pub mod index;
 pub mod kdtree;
+pub mod optics;
 pub mod point;
+pub mod projection;
+pub mod quality;
+pub mod region;
 
+#[cfg(test)]
+mod cache_test;
 #[cfg(test)]
 mod dbscan_test;
 #[cfg(test)]
 mod distance_test;
 #[cfg(test)]
+mod hull_test;
+#[cfg(test)]
+mod incremental_test;
+#[cfg(test)]
+mod index_test;
+#[cfg(test)]
+mod kdtree_test;
+#[cfg(test)]
+mod optics_test;
+#[cfg(test)]
 mod point_test;
+#[cfg(test)]
+mod projection_test;
+#[cfg(test)]
+mod quality_test;
+#[cfg(test)]
+mod region_test;
+#[cfg(test)]
+mod test_util;
 
-pub use point::{Cluster, Point, PointList};
-// Public API exports - allow unused imports as these are part of the public API
-#[allow(unused_imports)]
-pub use dbscan::{DBScan, RegionQuery, db_scan, region_query};
-#[allow(unused_imports)]
+pub use cache::{NeighborCache, db_scan_cached};
+pub use dbscan::{
+    AccuracyMode, DBScan, DbscanConfig, NoiseInfo, PointType, RegionQuery, SeedOrder, StableBy,
+    cluster_labels, db_scan, db_scan_nd, db_scan_weighted, db_scan_with_callback,
+    db_scan_with_cores, db_scan_with_diagnostics, db_scan_with_index, db_scan_with_progress,
+    db_scan_with_radius, db_scan_with_strategy, db_scan_with_times, estimate_eps,
+    estimate_eps_with_metric, k_distances, k_distances_with_metric, label_points,
+    merge_close_clusters, region_query, region_query_excluding_self, region_query_km,
+    region_query_with_metric,
+};
 pub use distance::{
-    DEGREE_RAD, DegreeRad, DistanceSpherical, DistanceSphericalFast, EARTH_R, EarthR, FastCos,
-    FastSine,
+    Chebyshev, DEGREE_RAD, DegreeRad, DistanceMetric, DistanceSpherical, DistanceSphericalFast,
+    EARTH_R, EarthR, Euclidean, FastCos, FastSine, Manhattan, Metric, SphericalFast,
 };
-#[allow(unused_imports)]
-pub use kdtree::{KDTree, NewKDTree, new_kd_tree};
+pub use grid::GridIndex;
+pub use hull::convex_hull;
+pub use incremental::{IncrementalDbscan, incremental_add};
+pub use index::{IndexKind, SpatialIndex};
+pub use kdtree::{KDTree, NewKDTree, new_kd_tree, new_kd_tree_with_metric};
+pub use optics::{extract_dbscan_clusters, optics, optics_labels, optics_with_metric};
+pub use point::{Cluster, Point, PointKey, PointList, points_from_latlon};
+pub use quality::{clustering_quality, clustering_quality_sampled};
@@ -0,0 +1,174 @@
+//! OPTICS ordering of points, DBSCAN for clusters of varying density
+
+use super::dbscan::cluster_labels;
+use super::distance::DistanceMetric;
+use super::kdtree::new_kd_tree_with_metric;
+use super::point::{Cluster, PointList};
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+/// Orders points by OPTICS with the default metric, see [`optics_with_metric`]
+pub fn optics(points: &PointList, eps_max: f64, min_points: usize) -> Vec<(usize, f64, f64)> {
+    optics_with_metric(points, eps_max, min_points, DistanceMetric::default())
+}
+
+/// Orders points by OPTICS (Ankerst et al., 1999)
+///
+/// The core distance of a point is the distance to its `min_points`-th
+/// nearest point within `eps_max` (counting the point itself), undefined when
+/// there are fewer. Points are visited so that the next one is always the
+/// unvisited point closest to the visited ones, its reachability distance
+/// being the larger of its distance to a visited core point and that point's
+/// core distance. Valleys of the reachability plot are clusters, at any eps up
+/// to `eps_max` (see [`optics_labels`]).
+///
+/// # Returns
+///
+/// `(point index, reachability distance, core distance)` triples in OPTICS
+/// order, distances in kilometers (coordinate units for
+/// [planar](DistanceMetric::is_planar) metrics), `f64::INFINITY` for points
+/// not reachable from any earlier point and undefined core distances
+pub fn optics_with_metric(
+    points: &PointList,
+    eps_max: f64,
+    min_points: usize,
+    metric: DistanceMetric,
+) -> Vec<(usize, f64, f64)> {
+    let r = metric.scale_eps(eps_max);
+    // Distances are computed in sq_dist units and converted at the end
+    let km_per_unit = 1.0 / metric.scale_eps(1.0);
+    let tree = new_kd_tree_with_metric(points.clone(), metric);
+
+    let n = points.len();
+    let mut processed = vec![false; n];
+    let mut reach = vec![f64::INFINITY; n];
+    let mut order = Vec::with_capacity(n);
+    let mut seeds = BinaryHeap::new();
+
+    for start in 0..n {
+        if processed[start] {
+            continue;
+        }
+        seeds.push(Reverse(Seed {
+            reach: f64::INFINITY,
+            id: start,
+        }));
+
+        while let Some(Reverse(seed)) = seeds.pop() {
+            // Seeds are pushed again when their reachability drops
+            if processed[seed.id] {
+                continue;
+            }
+            let p = seed.id;
+            processed[p] = true;
+            order.push((p, reach[p] * km_per_unit, f64::INFINITY));
+
+            let mut neighbors: Vec<(usize, f64)> = tree
+                .in_range(&points[p], r, Vec::new())
+                .into_iter()
+                .map(|o| (o, metric.sq_dist(&points[p], &points[o]).sqrt()))
+                .collect();
+            if neighbors.len() < min_points.max(1) {
+                continue;
+            }
+            let core_dist = {
+                let (_, kth, _) = neighbors
+                    .select_nth_unstable_by(min_points.max(1) - 1, |a, b| a.1.total_cmp(&b.1));
+                kth.1
+            };
+            if let Some(last) = order.last_mut() {
+                last.2 = core_dist * km_per_unit;
+            }
+
+            for (o, dist) in neighbors {
+                if processed[o] {
+                    continue;
+                }
+                let new_reach = core_dist.max(dist);
+                if new_reach < reach[o] {
+                    reach[o] = new_reach;
+                    seeds.push(Reverse(Seed {
+                        reach: new_reach,
+                        id: o,
+                    }));
+                }
+            }
+        }
+    }
+
+    order
+}
+
+/// Extracts DBSCAN clusters at `eps` from an OPTICS ordering
+///
+/// A point whose reachability exceeds `eps` starts a new cluster if its core
+/// distance is at most `eps`, and is noise otherwise. The following points
+/// up to the next one with reachability above `eps` join the last cluster.
+/// This is DBSCAN with the same `eps` (at most the `eps_max`
+/// of the ordering) and `min_points`, except that border points reachable
+/// from several clusters may be assigned differently, so one ordering serves
+/// any number of eps values without another neighbourhood search.
+///
+/// # Returns
+///
+/// Clusters numbered in OPTICS order, their points in OPTICS order
+pub fn extract_dbscan_clusters(ordering: &[(usize, f64, f64)], eps: f64) -> Vec<Cluster> {
+    let mut clusters: Vec<Cluster> = Vec::new();
+    let mut current: Vec<usize> = Vec::new();
+    for &(i, reachability, core_dist) in ordering {
+        if reachability > eps {
+            if !current.is_empty() {
+                clusters.push(Cluster {
+                    c: clusters.len(),
+                    points: std::mem::take(&mut current),
+                });
+            }
+            if core_dist > eps {
+                continue;
+            }
+        }
+        current.push(i);
+    }
+    if !current.is_empty() {
+        clusters.push(Cluster {
+            c: clusters.len(),
+            points: current,
+        });
+    }
+    clusters
+}
+
+/// Labels points by [`extract_dbscan_clusters`], `-1` for noise
+pub fn optics_labels(ordering: &[(usize, f64, f64)], eps: f64) -> Vec<i32> {
+    cluster_labels(&extract_dbscan_clusters(ordering, eps), ordering.len())
+}
+
+/// Point waiting to be visited by [`optics_with_metric`], ordered by
+/// reachability, then index
+#[derive(Clone, Copy)]
+struct Seed {
+    reach: f64,
+    id: usize,
+}
+
+impl Ord for Seed {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.reach
+            .total_cmp(&other.reach)
+            .then(self.id.cmp(&other.id))
+    }
+}
+
+impl PartialOrd for Seed {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Seed {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Seed {}
@@ -0,0 +1,88 @@
+#[cfg(test)]
+mod tests {
+    use crate::cluster::{
+        Cluster, DEGREE_RAD, EARTH_R, Point, PointList, db_scan, extract_dbscan_clusters,
+        label_points, optics, optics_labels,
+    };
+
+    // Groups A and B along a meridian, 0.12 km apart, and two noise points
+    fn points() -> PointList {
+        let km = 1.0 / EARTH_R / DEGREE_RAD;
+        [0.0, 0.02, 0.04, 0.16, 0.18, 0.2, 1.0, 1.5]
+            .into_iter()
+            .map(|d| Point([30.0, 60.0 + d * km]))
+            .collect()
+    }
+
+    #[test]
+    fn test_optics_ordering() {
+        let points = points();
+        let ordering = optics(&points, 0.5, 3);
+
+        let mut seen: Vec<usize> = ordering.iter().map(|&(i, _, _)| i).collect();
+        seen.sort_unstable();
+        assert_eq!(seen, (0..points.len()).collect::<Vec<_>>());
+
+        // A is visited first, then B is reached over the 0.12 km gap
+        let order: Vec<usize> = ordering.iter().map(|&(i, _, _)| i).collect();
+        assert_eq!(order[..6], [0, 1, 2, 3, 4, 5]);
+        assert!(ordering[0].1.is_infinite());
+        assert!((ordering[1].1 - 0.04).abs() < 1e-3);
+        assert!((ordering[3].1 - 0.12).abs() < 1e-3);
+        // Noise is out of reach of everything within eps_max
+        assert!(ordering[6..].iter().all(|&(_, r, _)| r.is_infinite()));
+        // Third nearest of A's first point is 0.04 km away, noise has none
+        assert!((ordering[0].2 - 0.04).abs() < 1e-3);
+        assert!(ordering[7].2.is_infinite());
+    }
+
+    #[test]
+    fn test_optics_labels_match_dbscan() {
+        let points = points();
+        let ordering = optics(&points, 0.5, 3);
+        for eps in [0.05, 0.1, 0.15] {
+            assert_eq!(
+                optics_labels(&ordering, eps),
+                label_points(&points, eps, 3),
+                "eps {}",
+                eps
+            );
+        }
+        assert_eq!(optics_labels(&ordering, 0.1), [0, 0, 0, 1, 1, 1, -1, -1]);
+
+        // Every point is core with min_points 1, isolated ones are clusters
+        let ordering = optics(&points, 0.5, 1);
+        for eps in [0.01, 0.05, 0.15] {
+            assert_eq!(
+                optics_labels(&ordering, eps),
+                label_points(&points, eps, 1),
+                "eps {}",
+                eps
+            );
+        }
+        assert_eq!(optics_labels(&ordering, 0.15), [0, 0, 0, 0, 0, 0, 1, 2]);
+    }
+
+    #[test]
+    fn test_extract_dbscan_clusters() {
+        let points = points();
+        let ordering = optics(&points, 0.5, 3);
+        let sorted = |clusters: Vec<Cluster>| -> Vec<Vec<usize>> {
+            clusters
+                .into_iter()
+                .map(|mut cluster| {
+                    cluster.points.sort_unstable();
+                    cluster.points
+                })
+                .collect()
+        };
+
+        let (expected, _) = db_scan(&points, 0.1, 3);
+        let extracted = extract_dbscan_clusters(&ordering, 0.1);
+        assert_eq!(sorted(extracted), sorted(expected));
+
+        // Over the gap between A and B a single cluster remains
+        let extracted = extract_dbscan_clusters(&ordering, 0.15);
+        assert_eq!(sorted(extracted), [vec![0, 1, 2, 3, 4, 5]]);
+    }
+}
@@ -1,15 +1,60 @@
 //! Package cluster implements DBScan clustering on (lat, lon) using K-D Tree
 
+use super::distance::{DEGREE_RAD, DistanceMetric, EARTH_R, MIN_COS, Metric};
+use std::collections::HashMap;
+
 /// Point represents a geographic coordinate (longitude, latitude)
 ///
 /// The point is stored as [longitude, latitude] where:
 /// - `[0]` is longitude
 /// - `[1]` is latitude
+///
+/// Points with `D` other than 2 hold arbitrary coordinates, e.g.
+/// `[longitude, latitude, time]`, compared by Euclidean distance.
 #[derive(Debug, Clone, Copy, PartialEq)]
-pub struct Point(pub [f64; 2]);
+pub struct Point<const D: usize = 2>(pub [f64; D]);
+
+/// Hashable identity of a [`Point`]'s coordinates, for sets and maps of points
+///
+/// Holds the bit patterns of the coordinates. `-0.0` is stored as `0.0`, so
+/// keys are equal whenever the points are (`==`). All NaNs are stored as the
+/// same NaN: unlike `==` on points, keys of NaN coordinates equal themselves,
+/// as `Eq` requires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PointKey<const D: usize = 2>([u64; D]);
+
+impl<const D: usize> From<&Point<D>> for PointKey<D> {
+    fn from(point: &Point<D>) -> Self {
+        PointKey(point.0.map(|v| {
+            if v == 0.0 {
+                0.0_f64.to_bits()
+            } else if v.is_nan() {
+                f64::NAN.to_bits()
+            } else {
+                v.to_bits()
+            }
+        }))
+    }
+}
 
 /// PointList is a collection of Points
-pub type PointList = Vec<Point>;
+pub type PointList<const D: usize = 2> = Vec<Point<D>>;
+
+impl From<(f64, f64)> for Point {
+    /// Converts a `(lat, lon)` pair, the order coordinates are usually
+    /// written in, to `Point([lon, lat])`
+    fn from((lat, lon): (f64, f64)) -> Self {
+        Point([lon, lat])
+    }
+}
+
+/// Builds a [`PointList`] from `(lat, lon)` pairs
+///
+/// Each pair is converted with `Point::from`, so the stored points have
+/// longitude first as usual.
+pub fn points_from_latlon(v: &[(f64, f64)]) -> PointList {
+    v.iter().map(|&pair| Point::from(pair)).collect()
+}
 
 /// Cluster represents a result of DBScan clustering work
 #[derive(Debug, Clone)]
@@ -20,25 +65,77 @@ pub struct Cluster {
     pub points: Vec<usize>,
 }
 
-impl Point {
+impl<const D: usize> Point<D> {
     /// Returns squared (without sqrt & normalization) distance between two points
-    pub fn sq_dist(&self, b: &Point) -> f64 {
-        use super::distance::DistanceSphericalFast;
-        DistanceSphericalFast(self, b)
+    ///
+    /// Same as [`Point::sq_dist_with`] the default metric, the fast spherical
+    /// distance. Clustering uses the metric it is configured with instead,
+    /// e.g. the exact one selected by `--exact-distance`.
+    pub fn sq_dist(&self, b: &Point<D>) -> f64 {
+        self.sq_dist_with(b, &DistanceMetric::default())
+    }
+
+    /// Returns squared (without sqrt & normalization) distance between two
+    /// points by `metric`
+    ///
+    /// Geographic 2D points use `metric`, points of other dimensions the
+    /// squared Euclidean distance over all coordinates.
+    pub fn sq_dist_with(&self, b: &Point<D>, metric: &impl Metric) -> f64 {
+        match (self.as_2d(), b.as_2d()) {
+            (Some(a), Some(b)) => metric.sq_dist(&a, &b),
+            _ => self
+                .0
+                .iter()
+                .zip(&b.0)
+                .map(|(x, y)| (x - y) * (x - y))
+                .sum(),
+        }
+    }
+
+    /// Returns the point as a geographic point when `D` is 2
+    pub fn as_2d(&self) -> Option<Point> {
+        (D == 2).then(|| Point([self.0[0], self.0[1]]))
     }
 
-    /// Checks if this point is less than or equal to another point
-    /// (a <= b)
-    #[allow(dead_code)] // Part of public API, may be used by external code
-    pub fn less_eq(&self, b: &Point) -> bool {
-        self.0[0] <= b.0[0] && self.0[1] <= b.0[1]
+    /// Checks if this point is less than or equal to another point in all
+    /// coordinates (a <= b)
+    pub fn less_eq(&self, b: &Point<D>) -> bool {
+        self.0.iter().zip(&b.0).all(|(x, y)| x <= y)
     }
 
-    /// Checks if this point is greater than or equal to another point
-    /// (a >= b)
-    #[allow(dead_code)] // Part of public API, may be used by external code
-    pub fn greater_eq(&self, b: &Point) -> bool {
-        self.0[0] >= b.0[0] && self.0[1] >= b.0[1]
+    /// Checks if this point is greater than or equal to another point in all
+    /// coordinates (a >= b)
+    pub fn greater_eq(&self, b: &Point<D>) -> bool {
+        self.0.iter().zip(&b.0).all(|(x, y)| x >= y)
+    }
+}
+
+impl Point {
+    /// Returns the great-circle distance to `other` in kilometers on a sphere
+    /// of [`EARTH_R`]
+    ///
+    /// Unlike [`Point::sq_dist`], which clustering uses, this is a true
+    /// distance for reporting. It uses the haversine formula, exact for any
+    /// distance but a few trigonometric functions slower.
+    pub fn dist_km(&self, other: &Point) -> f64 {
+        super::distance::distance_haversine_with_radius(self, other, EARTH_R)
+    }
+
+    /// Returns the point reached by travelling `distance_km` along a great
+    /// circle, starting with the given bearing (degrees clockwise from north)
+    pub fn destination(&self, bearing_deg: f64, distance_km: f64) -> Point {
+        let (lon1, lat1) = (self.0[0] * DEGREE_RAD, self.0[1] * DEGREE_RAD);
+        let bearing = bearing_deg * DEGREE_RAD;
+        let angle = distance_km / EARTH_R;
+
+        let lat2 = (lat1.sin() * angle.cos() + lat1.cos() * angle.sin() * bearing.cos()).asin();
+        let lon2 = lon1
+            + (bearing.sin() * angle.sin() * lat1.cos())
+                .atan2(angle.cos() - lat1.sin() * lat2.sin());
+
+        // Normalize longitude to [-180, 180)
+        let lon2 = (lon2 / DEGREE_RAD + 540.0).rem_euclid(360.0) - 180.0;
+        Point([lon2, lat2 / DEGREE_RAD])
     }
 }
 
@@ -53,7 +150,6 @@ impl Cluster {
     /// # Panics
     ///
     /// Panics if the cluster is empty
-    #[allow(dead_code)] // Part of public API, may be used by external code
     pub fn centroid_and_bounds(&self, points: &PointList) -> (Point, Point, Point) {
         if self.points.is_empty() {
             panic!("empty cluster");
@@ -84,10 +180,90 @@ impl Cluster {
 
         (center, min, max)
     }
+
+    /// Calculates the smallest bounding box of the cluster on the globe
+    ///
+    /// Unlike [`Cluster::centroid_and_bounds`] this takes the shortest
+    /// longitude span, which may cross the antimeridian. Returns `(min, max)`
+    /// where `min` is the bottom-left (west, south) corner and `max` the
+    /// top-right one. A box crossing the antimeridian has `min.0[0] > max.0[0]`,
+    /// e.g. `179.0` to `-179.0`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cluster is empty
+    pub fn geo_bbox(&self, points: &PointList) -> (Point, Point) {
+        if self.points.is_empty() {
+            panic!("empty cluster");
+        }
+
+        let mut lons: Vec<f64> = self.points.iter().map(|&i| points[i].0[0]).collect();
+        lons.sort_by(f64::total_cmp);
+        let (min_lat, max_lat) = self
+            .points
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &i| {
+                (min.min(points[i].0[1]), max.max(points[i].0[1]))
+            });
+
+        // The box is the complement of the widest gap between longitudes,
+        // starting with the gap across the antimeridian
+        let (mut west, mut east) = (lons[0], lons[lons.len() - 1]);
+        let mut widest = lons[0] + 360.0 - lons[lons.len() - 1];
+        for pair in lons.windows(2) {
+            if pair[1] - pair[0] > widest {
+                widest = pair[1] - pair[0];
+                (west, east) = (pair[1], pair[0]);
+            }
+        }
+
+        (Point([west, min_lat]), Point([east, max_lat]))
+    }
+
+    /// Splits the cluster into one sub-cluster per non-empty grid cell
+    ///
+    /// Cells are `cell_km` high and `cell_km` wide at the latitude of the
+    /// cluster's centroid. Sub-clusters are ordered by the first member of
+    /// each cell and numbered from `0`, callers renumber them to fit into their
+    /// list of clusters. A `cell_km` that isn't positive and finite leaves the
+    /// cluster whole.
+    pub fn split_by_grid(&self, points: &PointList, cell_km: f64) -> Vec<Cluster> {
+        if self.points.is_empty() {
+            return Vec::new();
+        }
+        if !(cell_km > 0.0 && cell_km.is_finite()) {
+            return vec![Cluster {
+                c: 0,
+                points: self.points.clone(),
+            }];
+        }
+
+        let (center, _, _) = self.centroid_and_bounds(points);
+        let cell_lat = cell_km / EARTH_R / DEGREE_RAD;
+        let cell_lon = cell_lat / (center.0[1] * DEGREE_RAD).cos().max(MIN_COS);
+
+        let mut cells: HashMap<(i64, i64), usize> = HashMap::new();
+        let mut result: Vec<Cluster> = Vec::new();
+        for &i in &self.points {
+            let key = (
+                (points[i].0[0] / cell_lon).floor() as i64,
+                (points[i].0[1] / cell_lat).floor() as i64,
+            );
+            let c = *cells.entry(key).or_insert_with(|| {
+                result.push(Cluster {
+                    c: result.len(),
+                    points: Vec::new(),
+                });
+                result.len() - 1
+            });
+            result[c].points.push(i);
+        }
+
+        result
+    }
 }
 
 /// Checks if (innerMin, innerMax) rectangle is inside (outerMin, outerMax) rectangle
-#[allow(dead_code)] // Part of public API, may be used by external code
 pub fn inside(inner_min: &Point, inner_max: &Point, outer_min: &Point, outer_max: &Point) -> bool {
     inner_min.greater_eq(outer_min) && inner_max.less_eq(outer_max)
 }
@@ -22,4 +22,165 @@ mod tests {
         assert_eq!(max.0[0], 30.244759);
         assert_eq!(max.0[1], 59.96698);
     }
+
+    #[test]
+    fn test_split_by_grid() {
+        // Two groups ~1.1 km apart along the meridian, each within a few meters
+        let points = vec![
+            Point([30.0, 60.0001]),
+            Point([30.0, 60.0101]),
+            Point([30.0001, 60.0002]),
+            Point([30.0001, 60.0102]),
+            Point([30.0, 60.0003]),
+        ];
+        let cluster = Cluster {
+            c: 7,
+            points: vec![0, 1, 2, 3, 4],
+        };
+
+        let parts = cluster.split_by_grid(&points, 0.5);
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].c, 0);
+        assert_eq!(parts[0].points, vec![0, 2, 4]);
+        assert_eq!(parts[1].c, 1);
+        assert_eq!(parts[1].points, vec![1, 3]);
+
+        // Cells larger than the cluster keep it whole
+        let whole = cluster.split_by_grid(&points, 100.0);
+        assert_eq!(whole.len(), 1);
+        assert_eq!(whole[0].points, cluster.points);
+        assert_eq!(cluster.split_by_grid(&points, 0.0).len(), 1);
+    }
+
+    #[test]
+    fn test_destination() {
+        use crate::cluster::distance::distance_haversine;
+
+        let start = Point([30.0, 60.0]);
+        for bearing in [0.0, 45.0, 90.0, 200.0] {
+            let end = start.destination(bearing, 12.5);
+            assert!((distance_haversine(&start, &end) - 12.5).abs() < 1e-9);
+        }
+
+        // Due north keeps the longitude, one degree of latitude is ~111.19 km
+        let north = start.destination(0.0, EARTH_R * DEGREE_RAD);
+        assert!((north.0[0] - 30.0).abs() < 1e-9);
+        assert!((north.0[1] - 61.0).abs() < 1e-9);
+
+        // Crossing the antimeridian wraps the longitude
+        let east = Point([179.9, 0.0]).destination(90.0, 2.0 * 0.1 * EARTH_R * DEGREE_RAD);
+        assert!((east.0[0] + 179.9).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_dist_km() {
+        // Published great-circle distances, as (lat, lon) pairs
+        let cases = [
+            // Saint Petersburg Palace Square - Moscow Red Square
+            ((59.9390, 30.3158), (55.7539, 37.6208), 634.0),
+            ((51.5074, -0.1278), (48.8566, 2.3522), 343.5), // London - Paris
+            ((40.7128, -74.0060), (34.0522, -118.2437), 3936.0), // New York - Los Angeles
+            ((-33.8688, 151.2093), (-36.8485, 174.7633), 2156.0), // Sydney - Auckland
+        ];
+        for ((lat1, lon1), (lat2, lon2), km) in cases {
+            let (a, b) = (Point([lon1, lat1]), Point([lon2, lat2]));
+            assert!(
+                (a.dist_km(&b) - km).abs() / km < 0.001,
+                "{} km",
+                a.dist_km(&b)
+            );
+            assert_eq!(a.dist_km(&b), b.dist_km(&a));
+        }
+
+        // Palace Square - Peter and Paul Fortress, across the Neva: 0.011
+        // degrees of latitude (1.223 km) and a few metres east
+        let short = Point([30.3158, 59.9390]).dist_km(&Point([30.3167, 59.9500]));
+        assert!((short - 1.224).abs() < 0.001, "{} km", short);
+        assert_eq!(Point([30.0, 60.0]).dist_km(&Point([30.0, 60.0])), 0.0);
+    }
+
+    #[test]
+    fn test_geo_bbox() {
+        let points = vec![
+            Point([179.5, 10.0]),
+            Point([-179.0, 10.5]),
+            Point([179.0, 9.5]),
+            Point([-179.8, 10.2]),
+        ];
+        let cluster = Cluster {
+            c: 0,
+            points: vec![0, 1, 2, 3],
+        };
+
+        // Naive bounds span nearly the whole globe
+        let (_, min, max) = cluster.centroid_and_bounds(&points);
+        assert!(max.0[0] - min.0[0] > 359.0);
+
+        // Geo bbox wraps over the antimeridian: 2 degrees wide
+        let (min, max) = cluster.geo_bbox(&points);
+        assert_eq!(min, Point([179.0, 9.5]));
+        assert_eq!(max, Point([-179.0, 10.5]));
+
+        // Clusters away from the antimeridian get the plain box
+        let c1 = Cluster {
+            c: 0,
+            points: vec![0, 2],
+        };
+        assert_eq!(
+            c1.geo_bbox(&points),
+            (Point([179.0, 9.5]), Point([179.5, 10.0]))
+        );
+    }
+
+    #[test]
+    fn test_sq_dist_nd() {
+        // Points other than 2D are Euclidean over all coordinates
+        let a = Point([1.0, 2.0, 3.0]);
+        let b = Point([4.0, 6.0, 3.0]);
+        assert_eq!(a.sq_dist(&b), 25.0);
+        assert_eq!(a.as_2d(), None);
+        assert!(a.less_eq(&b) && !a.greater_eq(&b));
+
+        // 2D points stay geographic
+        let p1 = Point([30.0, 60.0]);
+        let p2 = Point([30.0, 61.0]);
+        assert_eq!(p1.as_2d(), Some(p1));
+        assert_eq!(p1.sq_dist(&p2), DistanceSphericalFast(&p1, &p2));
+
+        // By the selected metric
+        let exact = DistanceMetric::Haversine;
+        assert_eq!(p1.sq_dist_with(&p2, &exact), exact.sq_dist(&p1, &p2));
+        assert_eq!(p1.sq_dist_with(&p2, &Euclidean), 1.0);
+        assert_eq!(a.sq_dist_with(&b, &exact), 25.0);
+    }
+
+    #[test]
+    fn test_point_key() {
+        use std::collections::HashSet;
+
+        let key = |lon: f64, lat: f64| PointKey::from(&Point([lon, lat]));
+        assert_eq!(key(30.0, 60.0), key(30.0, 60.0));
+        assert_ne!(key(30.0, 60.0), key(60.0, 30.0));
+        // Signed zeros are equal points, so are their keys
+        assert_eq!(Point([0.0, -0.0]), Point([-0.0, 0.0]));
+        assert_eq!(key(0.0, -0.0), key(-0.0, 0.0));
+        // NaN keys equal themselves, whatever the NaN
+        assert_eq!(key(f64::NAN, 1.0), key(-f64::NAN, 1.0));
+
+        let set: HashSet<PointKey> = [key(1.0, 2.0), key(1.0, 2.0), key(-0.0, 0.0), key(0.0, 0.0)]
+            .into_iter()
+            .collect();
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn test_point_from_latlon() {
+        // Saint Petersburg, written as (lat, lon)
+        let p = Point::from((59.955982, 30.244759));
+        assert_eq!(p.0, [30.244759, 59.955982]);
+
+        let points = points_from_latlon(&[(59.955982, 30.244759), (-33.8688, 151.2093)]);
+        assert_eq!(points, vec![p, Point([151.2093, -33.8688])]);
+        assert!(points_from_latlon(&[]).is_empty());
+    }
 }
@@ -0,0 +1,84 @@
+//! Reprojection of input and output coordinates
+//!
+//! Clustering always works on WGS84 longitude/latitude, projections only
+//! transform coordinates that are read or written.
+
+use super::distance::DEGREE_RAD;
+use super::point::Point;
+use std::f64::consts::PI;
+use std::str::FromStr;
+
+/// Sphere radius of the Web Mercator projection in meters (EPSG:3857)
+pub const WEB_MERCATOR_R: f64 = 6378137.0;
+
+/// Latitudes are clamped to +-this many degrees before the Mercator transform,
+/// which maps the world to a square
+pub const WEB_MERCATOR_MAX_LAT: f64 = 85.051_128_779_806_59;
+
+/// Coordinate reference system of input or output coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Crs {
+    /// WGS84 longitude/latitude in degrees (EPSG:4326), as read
+    #[default]
+    Wgs84,
+    /// Spherical Web Mercator x/y in meters (EPSG:3857)
+    WebMercator,
+}
+
+impl Crs {
+    /// Projects a WGS84 point into this CRS
+    ///
+    /// Returns `Point([x, y])`, i.e. longitude/easting first like [`Point`].
+    /// Web Mercator is the spherical forward transform, latitudes beyond
+    /// [`WEB_MERCATOR_MAX_LAT`] are clamped to it.
+    pub fn project(&self, p: &Point) -> Point {
+        match self {
+            Crs::Wgs84 => *p,
+            Crs::WebMercator => {
+                let lat = p.0[1].clamp(-WEB_MERCATOR_MAX_LAT, WEB_MERCATOR_MAX_LAT);
+                Point([
+                    WEB_MERCATOR_R * p.0[0] * DEGREE_RAD,
+                    WEB_MERCATOR_R * (PI / 4.0 + lat * DEGREE_RAD / 2.0).tan().ln(),
+                ])
+            }
+        }
+    }
+
+    /// Transforms a point of this CRS back to WGS84, the inverse of [`Crs::project`]
+    ///
+    /// Takes `Point([x, y])` and returns `Point([lon, lat])`. Web Mercator is
+    /// the spherical inverse transform, eastings beyond the edge of the map
+    /// wrap around to longitudes in `[-180, 180]`.
+    pub fn unproject(&self, p: &Point) -> Point {
+        match self {
+            Crs::Wgs84 => *p,
+            Crs::WebMercator => {
+                let lon = p.0[0] / WEB_MERCATOR_R / DEGREE_RAD;
+                let lat = 2.0 * (p.0[1] / WEB_MERCATOR_R).exp().atan() - PI / 2.0;
+                let lon = if lon.abs() > 180.0 {
+                    (lon + 180.0).rem_euclid(360.0) - 180.0
+                } else {
+                    lon
+                };
+                Point([lon, lat / DEGREE_RAD])
+            }
+        }
+    }
+}
+
+impl FromStr for Crs {
+    type Err = String;
+
+    /// Parses an EPSG code like `epsg:3857` (case-insensitive), or the name
+    /// `wgs84` or `webmercator`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "epsg:4326" | "wgs84" => Ok(Crs::Wgs84),
+            "epsg:3857" | "webmercator" => Ok(Crs::WebMercator),
+            _ => Err(format!(
+                "unsupported CRS {:?}, expected epsg:4326 or epsg:3857",
+                s
+            )),
+        }
+    }
+}
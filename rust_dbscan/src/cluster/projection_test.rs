@@ -0,0 +1,61 @@
+#[cfg(test)]
+mod tests {
+    use crate::cluster::Point;
+    use crate::cluster::projection::{Crs, WEB_MERCATOR_MAX_LAT};
+
+    #[test]
+    fn test_web_mercator() {
+        // EPSG:3857 reference coordinates of New York City (-74.0060, 40.7128)
+        let p = Crs::WebMercator.project(&Point([-74.0060, 40.7128]));
+        assert!((p.0[0] - -8238310.235647).abs() < 1e-3, "{:?}", p);
+        assert!((p.0[1] - 4970071.579142).abs() < 1e-3, "{:?}", p);
+
+        // The world is a square, poles are clamped onto its edge
+        let max = 20037508.342789244;
+        let corner = Crs::WebMercator.project(&Point([180.0, WEB_MERCATOR_MAX_LAT]));
+        assert!((corner.0[0] - max).abs() < 1e-6);
+        assert!((corner.0[1] - max).abs() < 1e-2);
+        let pole = Crs::WebMercator.project(&Point([0.0, -90.0]));
+        assert!((pole.0[1] + max).abs() < 1e-2);
+        assert!(pole.0[1].is_finite());
+
+        assert_eq!(Crs::Wgs84.project(&Point([1.0, 2.0])), Point([1.0, 2.0]));
+    }
+
+    #[test]
+    fn test_web_mercator_inverse() {
+        let crs = Crs::WebMercator;
+        let nyc = crs.unproject(&Point([-8238310.235647, 4970071.579142]));
+        assert!((nyc.0[0] - -74.0060).abs() < 1e-9, "{:?}", nyc);
+        assert!((nyc.0[1] - 40.7128).abs() < 1e-9, "{:?}", nyc);
+
+        // Round trips within the clamped latitudes
+        for p in [
+            Point([0.0, 0.0]),
+            Point([179.9, -60.0]),
+            Point([-179.9, 85.0]),
+            Point([30.25, 59.95]),
+        ] {
+            let back = crs.unproject(&crs.project(&p));
+            assert!((back.0[0] - p.0[0]).abs() < 1e-9, "{:?} {:?}", p, back);
+            assert!((back.0[1] - p.0[1]).abs() < 1e-9, "{:?} {:?}", p, back);
+        }
+
+        // Beyond the edge of the map eastings wrap around
+        let max = 20037508.342789244;
+        let wrapped = crs.unproject(&Point([max + 1000.0, 0.0]));
+        let inside = crs.unproject(&Point([-max + 1000.0, 0.0]));
+        assert!((wrapped.0[0] - inside.0[0]).abs() < 1e-9, "{:?}", wrapped);
+
+        assert_eq!(Crs::Wgs84.unproject(&Point([1.0, 2.0])), Point([1.0, 2.0]));
+    }
+
+    #[test]
+    fn test_parse_crs() {
+        assert_eq!("epsg:3857".parse(), Ok(Crs::WebMercator));
+        assert_eq!(" EPSG:4326".parse(), Ok(Crs::Wgs84));
+        assert_eq!("webmercator".parse(), Ok(Crs::WebMercator));
+        let err = "epsg:27700".parse::<Crs>().unwrap_err();
+        assert!(err.contains("unsupported CRS \"epsg:27700\""), "{}", err);
+    }
+}
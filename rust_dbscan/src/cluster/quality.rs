@@ -0,0 +1,76 @@
+//! Quality scores of clusterings
+
+use super::point::{Cluster, Point, PointList};
+
+/// Returns an approximate silhouette score of a clustering, from `-1` (points
+/// lie closer to other clusters than to their own) to `1` (compact, well
+/// separated clusters)
+///
+/// Every clustered point scores `(b - a) / max(a, b)`, where `a` is its mean
+/// great-circle distance to the other members of its cluster and `b` its
+/// distance to the nearest centroid of another cluster. Centroids stand in
+/// for all members of the other clusters, which the true silhouette compares
+/// with. Points of single-point clusters score `0`. The score is the mean
+/// over all clustered points: noise points, in no cluster, are excluded.
+///
+/// Returns `None` with fewer than two non-empty clusters, where there is no
+/// other cluster to compare with.
+pub fn clustering_quality(points: &PointList, clusters: &[Cluster]) -> Option<f64> {
+    clustering_quality_sampled(points, clusters, usize::MAX)
+}
+
+/// Same as [`clustering_quality`], scoring only `sample` of the clustered
+/// points, spread evenly over all of them
+///
+/// Each scored point is still compared with all members of its cluster, so
+/// the cost drops from O(n × cluster size) to O(sample × cluster size).
+pub fn clustering_quality_sampled(
+    points: &PointList,
+    clusters: &[Cluster],
+    sample: usize,
+) -> Option<f64> {
+    let clusters: Vec<&Cluster> = clusters.iter().filter(|c| !c.points.is_empty()).collect();
+    if clusters.len() < 2 || sample == 0 {
+        return None;
+    }
+    let centroids: Vec<Point> = clusters
+        .iter()
+        .map(|c| c.centroid_and_bounds(points).0)
+        .collect();
+
+    // (cluster, point) of every clustered point
+    let members: Vec<(usize, usize)> = clusters
+        .iter()
+        .enumerate()
+        .flat_map(|(c, cluster)| cluster.points.iter().map(move |&i| (c, i)))
+        .collect();
+    let scored = sample.min(members.len());
+
+    let total: f64 = (0..scored)
+        .map(|k| members[k * members.len() / scored])
+        .map(|(c, i)| {
+            let cluster = &clusters[c].points;
+            if cluster.len() < 2 {
+                return 0.0;
+            }
+            let a = cluster
+                .iter()
+                .filter(|&&j| j != i)
+                .map(|&j| points[i].dist_km(&points[j]))
+                .sum::<f64>()
+                / (cluster.len() - 1) as f64;
+            let b = centroids
+                .iter()
+                .enumerate()
+                .filter(|&(other, _)| other != c)
+                .map(|(_, centroid)| points[i].dist_km(centroid))
+                .fold(f64::INFINITY, f64::min);
+            if a.max(b) > 0.0 {
+                (b - a) / a.max(b)
+            } else {
+                0.0
+            }
+        })
+        .sum();
+    Some(total / scored as f64)
+}
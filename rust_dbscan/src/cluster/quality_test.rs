@@ -0,0 +1,69 @@
+#[cfg(test)]
+mod tests {
+    use crate::cluster::{
+        Cluster, Point, PointList, clustering_quality, clustering_quality_sampled,
+    };
+
+    fn cluster(c: usize, points: &[usize]) -> Cluster {
+        Cluster {
+            c,
+            points: points.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_clustering_quality() {
+        // Two clusters ~100 m across, 100 km apart, and a noise point
+        let points: PointList = vec![
+            Point([30.0, 60.0]),
+            Point([30.001, 60.0]),
+            Point([30.0, 60.001]),
+            Point([31.8, 60.0]),
+            Point([31.801, 60.0]),
+            Point([31.8, 60.001]),
+            Point([30.9, 60.5]),
+        ];
+        let separated = [cluster(0, &[0, 1, 2]), cluster(1, &[3, 4, 5])];
+        let score = clustering_quality(&points, &separated).unwrap();
+        assert!(score > 0.99 && score <= 1.0, "{}", score);
+
+        // Mixing both clusters gives points closer to the other centroid
+        let mixed = [cluster(0, &[0, 1, 3]), cluster(1, &[2, 4, 5])];
+        let score = clustering_quality(&points, &mixed).unwrap();
+        assert!(score < 0.0, "{}", score);
+
+        // A single-point cluster scores 0, empty clusters don't count
+        let single = [cluster(0, &[0, 1, 2]), cluster(1, &[6]), cluster(2, &[])];
+        let with_single = clustering_quality(&points, &single).unwrap();
+        let first = clustering_quality_sampled(&points, &single[..2], 3).unwrap();
+        assert!((with_single - first * 3.0 / 4.0).abs() < 1e-12);
+
+        assert_eq!(clustering_quality(&points, &separated[..1]), None);
+        assert_eq!(clustering_quality(&points, &[]), None);
+    }
+
+    #[test]
+    fn test_clustering_quality_sampled() {
+        let points: PointList = (0..40)
+            .map(|i| Point([30.0 + (i / 20) as f64 + 0.0001 * i as f64, 60.0]))
+            .collect();
+        let clusters = [
+            cluster(0, &(0..20).collect::<Vec<_>>()),
+            cluster(1, &(20..40).collect::<Vec<_>>()),
+        ];
+
+        // Scoring all points is the full score, fewer points approximate it
+        let full = clustering_quality(&points, &clusters).unwrap();
+        assert_eq!(
+            clustering_quality_sampled(&points, &clusters, 40),
+            Some(full)
+        );
+        assert_eq!(
+            clustering_quality_sampled(&points, &clusters, 1000),
+            Some(full)
+        );
+        let sampled = clustering_quality_sampled(&points, &clusters, 8).unwrap();
+        assert!((sampled - full).abs() < 0.01, "{} vs {}", sampled, full);
+        assert_eq!(clustering_quality_sampled(&points, &clusters, 0), None);
+    }
+}
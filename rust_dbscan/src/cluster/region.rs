@@ -0,0 +1,211 @@
+//! Clustering within polygon regions
+//!
+//! Points are assigned to the first region containing them, then every region
+//! is clustered on its own so that clusters never cross region boundaries.
+
+use super::dbscan::DbscanConfig;
+use super::point::{Cluster, Point, PointList};
+use serde_json::Value;
+
+/// Name of the group holding points outside of all regions
+pub const OUTSIDE_REGION_NAME: &str = "outside";
+
+/// Closed ring of a polygon, the last point may repeat the first one
+pub type Ring = Vec<Point>;
+
+/// Polygon with optional holes
+#[derive(Debug, Clone)]
+pub struct Polygon {
+    /// Outer boundary
+    pub exterior: Ring,
+    /// Holes cut out of the exterior
+    pub holes: Vec<Ring>,
+}
+
+/// Named region made of one or more polygons
+#[derive(Debug, Clone)]
+pub struct Region {
+    /// Region name, used as a prefix of its cluster names
+    pub name: String,
+    /// Polygons of the region
+    pub polygons: Vec<Polygon>,
+}
+
+/// What to do with points that are outside of all regions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutsidePoints {
+    /// Exclude them from clustering and output
+    #[default]
+    Drop,
+    /// Cluster them together as one extra region
+    Group,
+}
+
+/// Result of [`db_scan_in_regions`]
+#[derive(Debug, Clone, Default)]
+pub struct RegionClusters {
+    /// Clusters of all regions, numbered contiguously
+    pub clusters: Vec<Cluster>,
+    /// Noise points of all regions
+    pub noise: Vec<usize>,
+    /// `names[c]` is the name of cluster `c`: `<region>-<id within region>`
+    pub names: Vec<String>,
+    /// Points outside of all regions dropped by [`OutsidePoints::Drop`]
+    pub dropped: Vec<usize>,
+    /// Whether the config deadline passed, clusters are partial then
+    pub timed_out: bool,
+}
+
+/// Checks if the point is inside the ring (ray casting, boundary is unspecified)
+pub fn point_in_ring(p: &Point, ring: &[Point]) -> bool {
+    let mut inside = false;
+    let n = ring.len();
+    if n < 3 {
+        return false;
+    }
+
+    let mut j = n - 1;
+    for i in 0..n {
+        let (a, b) = (ring[i].0, ring[j].0);
+        if (a[1] > p.0[1]) != (b[1] > p.0[1])
+            && p.0[0] < (b[0] - a[0]) * (p.0[1] - a[1]) / (b[1] - a[1]) + a[0]
+        {
+            inside = !inside;
+        }
+        j = i;
+    }
+
+    inside
+}
+
+impl Polygon {
+    /// Checks if the point is inside the polygon and not inside any of its holes
+    pub fn contains(&self, p: &Point) -> bool {
+        point_in_ring(p, &self.exterior) && !self.holes.iter().any(|h| point_in_ring(p, h))
+    }
+}
+
+impl Region {
+    /// Checks if the point is inside any polygon of the region
+    pub fn contains(&self, p: &Point) -> bool {
+        self.polygons.iter().any(|poly| poly.contains(p))
+    }
+}
+
+/// Parses regions from a GeoJSON `FeatureCollection` of `Polygon` and
+/// `MultiPolygon` features
+///
+/// A region is named after the feature's `name` property, or `region-<n>`
+/// when there is none. Features with other geometries are skipped.
+pub fn parse_regions_geojson(json: &str) -> Result<Vec<Region>, String> {
+    let doc: Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let features = doc["features"]
+        .as_array()
+        .ok_or("expected a FeatureCollection with a features array")?;
+
+    let mut regions = Vec::new();
+    for (i, feature) in features.iter().enumerate() {
+        let geometry = &feature["geometry"];
+        let polygons = match geometry["type"].as_str() {
+            Some("Polygon") => vec![parse_polygon(&geometry["coordinates"])?],
+            Some("MultiPolygon") => geometry["coordinates"]
+                .as_array()
+                .ok_or("MultiPolygon coordinates must be an array")?
+                .iter()
+                .map(parse_polygon)
+                .collect::<Result<_, _>>()?,
+            _ => continue,
+        };
+
+        let name = match &feature["properties"]["name"] {
+            Value::String(s) => s.clone(),
+            Value::Null => format!("region-{}", i),
+            other => other.to_string(),
+        };
+        regions.push(Region { name, polygons });
+    }
+
+    Ok(regions)
+}
+
+fn parse_polygon(coords: &Value) -> Result<Polygon, String> {
+    let rings = coords
+        .as_array()
+        .ok_or("Polygon coordinates must be an array")?
+        .iter()
+        .map(parse_ring)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut rings = rings.into_iter();
+    let exterior = rings.next().ok_or("Polygon must have an exterior ring")?;
+    Ok(Polygon {
+        exterior,
+        holes: rings.collect(),
+    })
+}
+
+fn parse_ring(coords: &Value) -> Result<Ring, String> {
+    coords
+        .as_array()
+        .ok_or("ring must be an array of positions")?
+        .iter()
+        .map(|pos| match (pos[0].as_f64(), pos[1].as_f64()) {
+            // GeoJSON positions are [lon, lat], same as Point
+            (Some(lon), Some(lat)) => Ok(Point([lon, lat])),
+            _ => Err(format!("invalid position {}", pos)),
+        })
+        .collect()
+}
+
+/// Clusters points separately within each region
+///
+/// Every point belongs to the first region that contains it. Clusters of all
+/// regions are renumbered contiguously in region order, `names` keeps their
+/// `<region>-<id within region>` names. Points outside of all regions are
+/// either dropped (neither in clusters nor in noise) or clustered as an extra
+/// [`OUTSIDE_REGION_NAME`] region. `weights` are the point weights of
+/// [`DbscanConfig::run_weighted`].
+pub fn db_scan_in_regions(
+    points: &PointList,
+    weights: Option<&[f64]>,
+    regions: &[Region],
+    config: &DbscanConfig,
+    outside: OutsidePoints,
+) -> RegionClusters {
+    let mut members: Vec<Vec<usize>> = vec![Vec::new(); regions.len() + 1];
+    for (i, p) in points.iter().enumerate() {
+        let region = regions
+            .iter()
+            .position(|r| r.contains(p))
+            .unwrap_or(regions.len());
+        members[region].push(i);
+    }
+
+    let mut result = RegionClusters::default();
+    if outside == OutsidePoints::Drop {
+        result.dropped = members.pop().unwrap_or_default();
+    }
+
+    for (region, ids) in members.iter().enumerate() {
+        let name = regions
+            .get(region)
+            .map_or(OUTSIDE_REGION_NAME, |r| r.name.as_str());
+        let sub_points: PointList = ids.iter().map(|&i| points[i]).collect();
+        let sub_weights: Option<Vec<f64>> =
+            weights.map(|weights| ids.iter().map(|&i| weights[i]).collect());
+        let (clusters, noise, timed_out) = config.run_weighted(&sub_points, sub_weights.as_deref());
+        result.timed_out |= timed_out;
+
+        for cluster in clusters {
+            result.names.push(format!("{}-{}", name, cluster.c));
+            result.clusters.push(Cluster {
+                c: result.clusters.len(),
+                points: cluster.points.iter().map(|&i| ids[i]).collect(),
+            });
+        }
+        result.noise.extend(noise.iter().map(|&i| ids[i]));
+    }
+
+    result.noise.sort_unstable();
+    result
+}
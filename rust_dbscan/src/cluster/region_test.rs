@@ -0,0 +1,110 @@
+#[cfg(test)]
+mod tests {
+    use crate::cluster::region::{
+        OutsidePoints, db_scan_in_regions, parse_regions_geojson, point_in_ring,
+    };
+    use crate::cluster::{DbscanConfig, Point};
+
+    const REGIONS: &str = r#"{
+        "type": "FeatureCollection",
+        "features": [
+            {
+                "type": "Feature",
+                "properties": {"name": "west"},
+                "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [[[30.0, 59.0], [30.5, 59.0], [30.5, 60.0], [30.0, 60.0], [30.0, 59.0]]]
+                }
+            },
+            {
+                "type": "Feature",
+                "properties": {"name": "east"},
+                "geometry": {
+                    "type": "MultiPolygon",
+                    "coordinates": [[[[30.5, 59.0], [31.0, 59.0], [31.0, 60.0], [30.5, 60.0], [30.5, 59.0]]]]
+                }
+            },
+            {
+                "type": "Feature",
+                "properties": {},
+                "geometry": {"type": "Point", "coordinates": [30.0, 59.0]}
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn test_point_in_ring() {
+        let square = vec![
+            Point([0.0, 0.0]),
+            Point([1.0, 0.0]),
+            Point([1.0, 1.0]),
+            Point([0.0, 1.0]),
+        ];
+        assert!(point_in_ring(&Point([0.5, 0.5]), &square));
+        assert!(!point_in_ring(&Point([1.5, 0.5]), &square));
+        assert!(!point_in_ring(&Point([0.5, -0.1]), &square));
+        assert!(!point_in_ring(&Point([0.5, 0.5]), &square[..2]));
+    }
+
+    #[test]
+    fn test_parse_regions_geojson() {
+        let regions = parse_regions_geojson(REGIONS).unwrap();
+        assert_eq!(regions.len(), 2);
+        assert_eq!(regions[0].name, "west");
+        assert_eq!(regions[1].name, "east");
+        assert!(regions[0].contains(&Point([30.25, 59.5])));
+        assert!(regions[1].contains(&Point([30.75, 59.5])));
+        assert!(!regions[1].contains(&Point([30.25, 59.5])));
+
+        assert!(parse_regions_geojson("{}").is_err());
+        assert!(parse_regions_geojson("not json").is_err());
+    }
+
+    #[test]
+    fn test_db_scan_in_regions() {
+        let regions = parse_regions_geojson(REGIONS).unwrap();
+        // A tight group straddling the west/east border, ~50 m apart,
+        // plus two points far outside of all regions
+        let points = vec![
+            Point([30.4995, 59.5]),
+            Point([30.4996, 59.5]),
+            Point([30.4997, 59.5]),
+            Point([30.5003, 59.5]),
+            Point([30.5004, 59.5]),
+            Point([30.5005, 59.5]),
+            Point([40.0, 50.0]),
+            Point([40.0001, 50.0]),
+        ];
+
+        // Without regions it's one cluster
+        let (clusters, _) = crate::cluster::db_scan(&points, 0.1, 2);
+        assert_eq!(clusters[0].points.len(), 6);
+
+        let result = db_scan_in_regions(
+            &points,
+            None,
+            &regions,
+            &DbscanConfig::new(0.1, 2),
+            OutsidePoints::Drop,
+        );
+        assert_eq!(result.clusters.len(), 2);
+        assert_eq!(result.names, vec!["west-0", "east-0"]);
+        assert_eq!(result.clusters[0].c, 0);
+        assert_eq!(result.clusters[1].c, 1);
+        let mut east = result.clusters[1].points.clone();
+        east.sort();
+        assert_eq!(east, vec![3, 4, 5]);
+        assert_eq!(result.dropped, vec![6, 7]);
+        assert!(result.noise.is_empty());
+
+        let result = db_scan_in_regions(
+            &points,
+            None,
+            &regions,
+            &DbscanConfig::new(0.1, 2),
+            OutsidePoints::Group,
+        );
+        assert_eq!(result.names, vec!["west-0", "east-0", "outside-0"]);
+        assert!(result.dropped.is_empty());
+    }
+}
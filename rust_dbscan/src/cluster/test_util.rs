@@ -0,0 +1,10 @@
+//! Helpers shared by the cluster tests
+
+/// Deterministic pseudo-random numbers in `[0, 1)`, from a 64-bit LCG
+/// started at `seed`
+pub(crate) fn lcg(mut seed: u64) -> impl FnMut() -> f64 {
+    move || {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+        (seed >> 33) as f64 / (1u64 << 31) as f64
+    }
+}
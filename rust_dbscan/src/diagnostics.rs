@@ -0,0 +1,288 @@
+//! Warnings about the input and summaries of the clustering
+//!
+//! Checks return their warning or nothing, summaries return the lines to
+//! print, so that the caller decides where they go and whether a warning is
+//! fatal.
+
+use crate::cluster::distance::distance_spherical;
+use crate::cluster::{
+    Cluster, DistanceMetric, KDTree, NoiseInfo, Point, PointList, clustering_quality_sampled,
+};
+use crate::input::SkippedRow;
+
+/// Max number of line numbers listed in the column count warning
+const MAX_REPORTED_LINES: usize = 10;
+
+/// Checks that all records have as many fields as the first one (the header
+/// when present)
+///
+/// Returns a warning listing the 1-based line numbers of mismatching rows.
+pub fn check_column_counts(csv_records: &[Vec<String>]) -> Option<String> {
+    let expected = csv_records.first()?.len();
+    let bad_lines: Vec<usize> = csv_records
+        .iter()
+        .enumerate()
+        .filter(|(_, record)| record.len() != expected)
+        .map(|(i, _)| i + 1)
+        .collect();
+
+    if bad_lines.is_empty() {
+        return None;
+    }
+
+    Some(format!(
+        "{} row(s) don't have {} fields like the first row, lines: {}",
+        bad_lines.len(),
+        expected,
+        join_reported(bad_lines.iter().map(|l| l.to_string()))
+    ))
+}
+
+/// Reports rows skipped because of invalid coordinates
+///
+/// Returns a warning listing the 1-based line numbers of skipped rows with
+/// their invalid columns.
+pub fn check_skipped_rows(skipped: &[SkippedRow]) -> Option<String> {
+    if skipped.is_empty() {
+        return None;
+    }
+
+    Some(format!(
+        "{} row(s) with invalid coordinates skipped, lines: {}",
+        skipped.len(),
+        join_reported(skipped.iter().map(|row| format!(
+            "{} ({})",
+            row.line,
+            row.columns.join(", ")
+        )))
+    ))
+}
+
+/// Reports GeoJSON features skipped because they aren't points, see
+/// [`read_geojson_files`](crate::input::read_geojson_files)
+pub fn check_skipped_features(skipped: &[String]) -> Option<String> {
+    if skipped.is_empty() {
+        return None;
+    }
+
+    Some(format!(
+        "{} feature(s) without Point geometry skipped: {}",
+        skipped.len(),
+        join_reported(skipped.iter().cloned())
+    ))
+}
+
+/// Reports rows skipped because of unparseable times, see [`parse_times`](crate::input::parse_times)
+pub fn check_skipped_times(unparseable: &[SkippedRow]) -> Option<String> {
+    if unparseable.is_empty() {
+        return None;
+    }
+
+    Some(format!(
+        "{} row(s) with unparseable times skipped, lines: {}",
+        unparseable.len(),
+        join_reported(unparseable.iter().map(|row| row.line.to_string()))
+    ))
+}
+
+/// Joins up to [`MAX_REPORTED_LINES`] items, summarizing the rest
+fn join_reported(items: impl ExactSizeIterator<Item = String>) -> String {
+    let total = items.len();
+    let mut joined = items
+        .take(MAX_REPORTED_LINES)
+        .collect::<Vec<_>>()
+        .join(", ");
+    if total > MAX_REPORTED_LINES {
+        joined.push_str(&format!(" and {} more", total - MAX_REPORTED_LINES));
+    }
+    joined
+}
+
+/// [`KDTree::balance_factor`] above which [`kd_tree_diagnostics`] suggests the grid index
+pub const UNBALANCED_TREE_FACTOR: f64 = 1.5;
+
+/// Formats the height and balance of a K-D tree
+///
+/// Adds a hint to use the grid index when the tree is more than
+/// [`UNBALANCED_TREE_FACTOR`] times as high as a balanced one, as its queries
+/// visit that many more levels. Trees of collinear points are built ~1.8
+/// times as high.
+pub fn kd_tree_diagnostics(tree: &KDTree) -> Vec<String> {
+    let balance = tree.balance_factor();
+    let mut lines = vec![format!(
+        "K-D tree height: {} ({:.2}x the height of a balanced tree)",
+        tree.height(),
+        balance
+    )];
+    if balance > UNBALANCED_TREE_FACTOR {
+        lines.push(
+            "Hint: the K-D tree is badly unbalanced, e.g. by nearly collinear points; \
+             --index grid may query faster"
+                .to_string(),
+        );
+    }
+    lines
+}
+
+/// Width of the longest bar of [`noise_histogram`]
+pub const HISTOGRAM_WIDTH: usize = 40;
+
+/// Formats a histogram of the neighbour counts of noise points
+///
+/// One line per neighbour count (the point itself included) with the number
+/// of noise points that had it and a bar, so that it shows how close noise
+/// came to `min_points`. No lines without noise.
+pub fn noise_histogram(noise: &[NoiseInfo], min_points: usize) -> Vec<String> {
+    let mut counts = std::collections::BTreeMap::new();
+    for info in noise {
+        *counts.entry(info.neighbor_count).or_insert(0usize) += 1;
+    }
+    let Some(&max) = counts.values().max() else {
+        return Vec::new();
+    };
+
+    let mut lines = vec![format!(
+        "Noise points by neighbour count (min-points {}):",
+        min_points
+    )];
+    for (neighbors, n) in counts {
+        let bar = "#".repeat((n * HISTOGRAM_WIDTH).div_ceil(max));
+        lines.push(format!("{:>6} {:>8} {}", neighbors, n, bar));
+    }
+    lines
+}
+
+/// Summarizes a clustering: number of clusters and noise points, min/mean/max
+/// cluster size, the bounding box diagonal of the largest cluster in km and
+/// the clustering quality
+///
+/// Size and diagonal are left out without clusters, the quality
+/// with fewer than two. It's [`clustering_quality_sampled`] of
+/// `quality_sample` points, all clustered points when `None`.
+pub fn cluster_stats(
+    clusters: &[Cluster],
+    noise: &[usize],
+    points: &PointList,
+    quality_sample: Option<usize>,
+) -> Vec<String> {
+    let mut lines = vec![
+        format!("Clusters: {}", clusters.len()),
+        format!("Noise points: {}", noise.len()),
+    ];
+
+    let Some(largest) = clusters.iter().max_by_key(|c| c.points.len()) else {
+        return lines;
+    };
+    let min = clusters.iter().map(|c| c.points.len()).min().unwrap_or(0);
+    let total: usize = clusters.iter().map(|c| c.points.len()).sum();
+    lines.push(format!(
+        "Cluster size: min {}, mean {:.1}, max {}",
+        min,
+        total as f64 / clusters.len() as f64,
+        largest.points.len()
+    ));
+    if !largest.points.is_empty() {
+        let (_, min, max) = largest.centroid_and_bounds(points);
+        lines.push(format!(
+            "Largest cluster bounding box diagonal: {:.3} km",
+            min.dist_km(&max)
+        ));
+    }
+    let sample = quality_sample.unwrap_or(usize::MAX);
+    if let Some(quality) = clustering_quality_sampled(points, clusters, sample) {
+        lines.push(format!(
+            "Clustering quality: {:.3} (approximate silhouette, noise excluded)",
+            quality
+        ));
+    }
+    lines
+}
+
+/// Detects clustering results that are correct but useless
+///
+/// Returns a diagnostic with the likely parameter fix when all of the
+/// `num_points` clustered points are noise, all are in one cluster, or every
+/// point is a cluster of its own.
+pub fn check_degenerate(clusters: &[Cluster], num_points: usize) -> Option<String> {
+    if num_points < 2 {
+        return None;
+    }
+
+    if clusters.is_empty() {
+        Some(format!(
+            "all {} points are noise: no point has enough neighbours, increase eps or decrease min_points",
+            num_points
+        ))
+    } else if clusters.len() == 1 && clusters[0].points.len() == num_points {
+        Some(format!(
+            "all {} points are in one cluster: decrease eps or increase min_points",
+            num_points
+        ))
+    } else if clusters.len() == num_points {
+        Some(format!(
+            "every one of {} points is a cluster of its own: increase eps or min_points (min_points 1 makes every point a core point)",
+            num_points
+        ))
+    } else {
+        None
+    }
+}
+
+/// Checks `min_points` against the number of points
+///
+/// Returns a warning when `min_points` is so high that most or all points
+/// will end up as noise.
+pub fn check_min_points(min_points: usize, num_points: usize) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    if min_points > num_points {
+        warnings.push(format!(
+            "min_points ({}) exceeds the number of points ({}), every point will be noise; \
+             min_points is likely too high",
+            min_points, num_points
+        ));
+    } else if min_points > num_points / 2 {
+        warnings.push(format!(
+            "min_points ({}) exceeds half the number of points ({}); \
+             min_points is likely too high",
+            min_points, num_points
+        ));
+    }
+
+    warnings
+}
+
+/// Returns the fraction of all points that belong to the largest cluster
+pub fn largest_cluster_fraction(clusters: &[Cluster], num_points: usize) -> f64 {
+    if num_points == 0 {
+        return 0.0;
+    }
+    let largest = clusters.iter().map(|c| c.points.len()).max().unwrap_or(0);
+    largest as f64 / num_points as f64
+}
+
+/// Returns the diagonal of the points' bounding box in kilometers, in
+/// coordinate units under `metric` for [planar](DistanceMetric::is_planar)
+/// metrics
+///
+/// Any two points are at most this far apart.
+pub fn points_extent(points: &PointList, metric: DistanceMetric) -> f64 {
+    if points.is_empty() {
+        return 0.0;
+    }
+
+    let mut min = Point([f64::INFINITY, f64::INFINITY]);
+    let mut max = Point([f64::NEG_INFINITY, f64::NEG_INFINITY]);
+    for p in points {
+        for j in 0..2 {
+            min.0[j] = min.0[j].min(p.0[j]);
+            max.0[j] = max.0[j].max(p.0[j]);
+        }
+    }
+
+    if metric.is_planar() {
+        min.sq_dist_with(&max, &metric).sqrt()
+    } else {
+        distance_spherical(&min, &max)
+    }
+}
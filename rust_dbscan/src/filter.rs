@@ -0,0 +1,279 @@
+//! Picks the points to keep from a clustering
+
+use crate::cluster::{self, Cluster, Point, PointKey, PointList, merge_close_clusters};
+
+/// Label of the points in no cluster
+pub const DBSCAN_OUTLIER_INDEX: i32 = -1;
+
+/// Points kept per cluster by [`filter_points`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keep {
+    /// First point of each cluster
+    First,
+    /// Every point
+    All,
+    /// One point per cluster, see [`filter_points`]
+    Centroid,
+    /// No point of any cluster, only outliers
+    Noise,
+}
+
+/// Filters points based on the filtering logic:
+/// - Keep outliers (label == -1)
+/// - [`Keep::First`]: keep first point in each cluster (idx == 0 or label != labels[idx-1])
+/// - [`Keep::Centroid`]: keep the point with the smallest index of each
+///   cluster, [`centroid_points`] moves it to the cluster's centroid
+/// - [`Keep::All`]: keep every point, duplicates included
+/// - [`Keep::Noise`]: keep no cluster points at all, only outliers
+///
+/// Tracks added points by their coordinates to avoid duplicates, see
+/// [`PointKey`] for signed zeros and NaN
+pub fn filter_points(points: &[Point], labels: &[i32], keep: Keep) -> Vec<usize> {
+    let mut filtered = Vec::new();
+    let mut added = std::collections::HashSet::new(); // Track already added points by coordinates
+    let mut seen_clusters = std::collections::HashSet::new();
+
+    for (idx, &label) in labels.iter().enumerate() {
+        let point = PointKey::from(&points[idx]);
+
+        if let Keep::All = keep {
+            filtered.push(idx);
+            continue;
+        }
+
+        if keep == Keep::Noise && label != DBSCAN_OUTLIER_INDEX {
+            continue;
+        }
+
+        // Skip if point with same coordinates already added
+        if added.contains(&point) {
+            continue;
+        }
+
+        // Keep if it's an outlier
+        if label == DBSCAN_OUTLIER_INDEX {
+            filtered.push(idx);
+            added.insert(point);
+            continue;
+        }
+
+        if let Keep::Centroid = keep {
+            if seen_clusters.insert(label) {
+                filtered.push(idx);
+                added.insert(point);
+            }
+            continue;
+        }
+
+        // Keep if it's the first point (idx == 0)
+        if idx == 0 {
+            filtered.push(idx);
+            added.insert(point);
+            continue;
+        }
+
+        // Keep if it's the first point in a cluster (label != previous label)
+        if label != labels[idx - 1] {
+            filtered.push(idx);
+            added.insert(point);
+        }
+    }
+
+    filtered
+}
+
+/// Creates a labels array from clusters
+///
+/// `labels[i]` = cluster ID for point i, or -1 for points in no cluster (noise)
+pub fn build_labels(clusters: &[Cluster], num_points: usize) -> Vec<i32> {
+    cluster::cluster_labels(clusters, num_points)
+}
+
+/// Filters the points after the first `base_points` like [`filter_points`]
+///
+/// Labels come from clustering all points, but only the later ones are
+/// considered, so [`Keep::First`] keeps the first new point of every cluster
+/// even when the cluster started among the base points. Returns indices into
+/// all of `points`.
+pub fn filter_new_points(
+    points: &[Point],
+    labels: &[i32],
+    keep: Keep,
+    base_points: usize,
+) -> Vec<usize> {
+    filter_points(&points[base_points..], &labels[base_points..], keep)
+        .into_iter()
+        .map(|i| i + base_points)
+        .collect()
+}
+
+/// Returns the clusters and noise without the first `base_points` points
+///
+/// Cluster ids are kept, so they match the labels of the new points.
+/// Clusters made of base points only end up empty, which writers skip.
+pub fn without_base_points(
+    clusters: &[Cluster],
+    noise: &[usize],
+    base_points: usize,
+) -> (Vec<Cluster>, Vec<usize>) {
+    let clusters = clusters
+        .iter()
+        .map(|cluster| Cluster {
+            c: cluster.c,
+            points: cluster
+                .points
+                .iter()
+                .copied()
+                .filter(|&i| i >= base_points)
+                .collect(),
+        })
+        .collect();
+    let noise = noise
+        .iter()
+        .copied()
+        .filter(|&i| i >= base_points)
+        .collect();
+    (clusters, noise)
+}
+
+/// Returns `points` with the kept point of every cluster moved to the
+/// cluster's centroid (see [`Cluster::centroid_and_bounds`])
+///
+/// `kept` are the indices of [`filter_points`] with [`Keep::Centroid`],
+/// outliers stay where they are.
+pub fn centroid_points(
+    points: &PointList,
+    clusters: &[Cluster],
+    labels: &[i32],
+    kept: &[usize],
+) -> PointList {
+    let mut moved = points.clone();
+    for &idx in kept {
+        let Some(cluster) = usize::try_from(labels[idx]).ok().map(|c| &clusters[c]) else {
+            continue;
+        };
+        if !cluster.points.is_empty() {
+            moved[idx] = cluster.centroid_and_bounds(points).0;
+        }
+    }
+    moved
+}
+
+/// Reclassifies clusters with fewer than `min_size` points as noise
+///
+/// Their points are added to `noise`, which stays sorted, and the remaining
+/// clusters are renumbered contiguously. `names` are kept for the remaining
+/// clusters when not empty, like in [`split_oversized_clusters`].
+///
+/// # Returns
+///
+/// A tuple `(clusters, noise, names)`
+pub fn drop_small_clusters(
+    clusters: Vec<Cluster>,
+    mut noise: Vec<usize>,
+    names: &[String],
+    min_size: usize,
+) -> (Vec<Cluster>, Vec<usize>, Vec<String>) {
+    let mut result = Vec::with_capacity(clusters.len());
+    let mut result_names = Vec::new();
+
+    for cluster in clusters {
+        if cluster.points.len() < min_size {
+            noise.extend(cluster.points);
+            continue;
+        }
+        result_names.extend(names.get(cluster.c).cloned());
+        result.push(Cluster {
+            c: result.len(),
+            points: cluster.points,
+        });
+    }
+    noise.sort_unstable();
+
+    (result, noise, result_names)
+}
+
+/// Merges clusters with centroids less than `distance_km` apart, see
+/// [`merge_close_clusters`]
+///
+/// When `names` isn't empty, a merged cluster keeps the name of its first
+/// cluster.
+///
+/// # Returns
+///
+/// A tuple `(clusters, names)`
+pub fn merge_clusters_named(
+    clusters: Vec<Cluster>,
+    names: &[String],
+    points: &PointList,
+    distance_km: f64,
+) -> (Vec<Cluster>, Vec<String>) {
+    if names.is_empty() {
+        return (
+            merge_close_clusters(clusters, points, distance_km),
+            Vec::new(),
+        );
+    }
+
+    // The first point of a merged cluster is the first one of its first
+    // cluster, empty clusters are never merged and keep their order
+    let labels = cluster::cluster_labels(&clusters, points.len());
+    let mut empty_names = clusters
+        .iter()
+        .filter(|cluster| cluster.points.is_empty())
+        .map(|cluster| names[cluster.c].clone())
+        .collect::<Vec<_>>()
+        .into_iter();
+    let merged = merge_close_clusters(clusters, points, distance_km);
+    let merged_names = merged
+        .iter()
+        .map(|cluster| match cluster.points.first() {
+            Some(&i) => names[labels[i] as usize].clone(),
+            None => empty_names.next().unwrap_or_default(),
+        })
+        .collect();
+    (merged, merged_names)
+}
+
+/// Splits clusters with more than `max_size` points by a grid of `cell_km` cells
+///
+/// Cells are sized in kilometers, so points must be longitude/latitude
+/// degrees. Clusters are renumbered contiguously, sub-clusters of a split
+/// cluster take its place. When `names` isn't empty, sub-clusters are named
+/// `<cluster name>.<n>`.
+///
+/// # Returns
+///
+/// A tuple `(clusters, names)`
+pub fn split_oversized_clusters(
+    clusters: Vec<Cluster>,
+    names: &[String],
+    points: &PointList,
+    max_size: usize,
+    cell_km: f64,
+) -> (Vec<Cluster>, Vec<String>) {
+    let mut result = Vec::with_capacity(clusters.len());
+    let mut result_names = Vec::new();
+
+    for cluster in clusters {
+        let name = names.get(cluster.c);
+        if cluster.points.len() <= max_size {
+            result_names.extend(name.cloned());
+            result.push(Cluster {
+                c: result.len(),
+                points: cluster.points,
+            });
+            continue;
+        }
+
+        for part in cluster.split_by_grid(points, cell_km) {
+            result_names.extend(name.map(|name| format!("{}.{}", name, part.c)));
+            result.push(Cluster {
+                c: result.len(),
+                points: part.points,
+            });
+        }
+    }
+
+    (result, result_names)
+}
@@ -0,0 +1,763 @@
+//! Reads points from CSV, plain coordinate and GeoJSON files
+
+use crate::cluster::point::inside;
+use crate::cluster::projection::Crs;
+use crate::cluster::{Point, PointKey, PointList};
+use csv::ReaderBuilder;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// CSV records type alias for readability
+pub type CsvRecords = Vec<Vec<String>>;
+
+/// Data row skipped because its coordinates are invalid
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkippedRow {
+    /// 1-based line number
+    pub line: usize,
+    /// Index of the row in the records
+    pub record: usize,
+    /// Names of the invalid columns, followed by what is wrong unless they
+    /// are unparseable
+    pub columns: Vec<&'static str>,
+}
+
+/// Parses a number cell, with `,` as decimal separator when `decimal_comma`
+/// is set
+pub fn parse_number(cell: &str, decimal_comma: bool) -> Option<f64> {
+    if decimal_comma {
+        cell.replace(',', ".").parse().ok()
+    } else {
+        cell.parse().ok()
+    }
+}
+
+/// Returns whether `record` is a header row: its first cell isn't a number,
+/// with either `.` or `,` as decimal separator
+pub fn is_header(record: &[String]) -> bool {
+    record.first().is_some_and(|cell| {
+        parse_number(cell, false).is_none() && parse_number(cell, true).is_none()
+    })
+}
+
+/// Reads points and CSV records from files in a single pass
+///
+/// Expected format: `latitude,longitude` (header row is optional), see
+/// [`parse_points`] for other coordinate columns. The records of all files
+/// are merged, see [`read_csv_files`].
+///
+/// Rows with an unparseable or invalid coordinate (see [`parse_record`]) are
+/// skipped, unless the coordinate is missing (empty cell) and `fill_missing` gives a `[latitude, longitude]`
+/// default for it.
+///
+/// Coordinates in another `crs` than WGS84 are `y,x` and reprojected to
+/// latitude/longitude (see [`Crs::unproject`]), `fill_missing` is in `crs` too.
+///
+/// # Returns
+///
+/// A tuple `(points, records, skipped)` where:
+/// - `points` are parsed points for clustering
+/// - `records` are raw CSV records for output preservation
+/// - `skipped` lists the skipped rows and their unparseable columns
+pub fn read_points_and_csv(
+    filenames: &[impl AsRef<Path>],
+    fill_missing: Option<[f64; 2]>,
+    crs: Crs,
+) -> Result<(PointList, CsvRecords, Vec<SkippedRow>), Box<dyn std::error::Error>> {
+    let (records, _) = read_csv_files(filenames, b',')?;
+    let (mut points, skipped) =
+        parse_points(&records, [0, 1], fill_missing, false, crs == Crs::Wgs84);
+    unproject_points(&mut points, crs);
+    Ok((points, records, skipped))
+}
+
+/// Reprojects points read in `crs` to WGS84 longitude/latitude in place
+pub fn unproject_points(points: &mut PointList, crs: Crs) {
+    if crs != Crs::Wgs84 {
+        for p in points.iter_mut() {
+            *p = crs.unproject(p);
+        }
+    }
+}
+
+/// Reads all records of a CSV file with the given field delimiter, header
+/// row included
+pub fn read_csv_records(
+    filename: &Path,
+    delimiter: u8,
+) -> Result<CsvRecords, Box<dyn std::error::Error>> {
+    let file = File::open(filename)?;
+    let mut reader = ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(file);
+
+    let mut records = Vec::new();
+    for result in reader.records() {
+        let record = result?;
+        records.push(record.iter().map(|s| s.to_string()).collect());
+    }
+    Ok(records)
+}
+
+/// Reads the records of several CSV files as if they were one
+///
+/// Header rows are detected per file: the first one found goes first, the
+/// others are dropped. Fails when a header differs from the first one, as
+/// their columns wouldn't line up.
+///
+/// # Returns
+///
+/// A tuple `(records, sources)` where `sources[i]` is the index in
+/// `filenames` of the file `records[i]` was read from
+pub fn read_csv_files(
+    filenames: &[impl AsRef<Path>],
+    delimiter: u8,
+) -> Result<(CsvRecords, Vec<usize>), Box<dyn std::error::Error>> {
+    let mut header: Option<(Vec<String>, usize)> = None;
+    let mut data = Vec::new();
+    let mut sources = Vec::new();
+    for (file, filename) in filenames.iter().enumerate() {
+        let filename = filename.as_ref();
+        let mut records = read_csv_records(filename, delimiter)?;
+        if records.first().is_some_and(|r| is_header(r)) {
+            let file_header = records.remove(0);
+            match &header {
+                Some((first, first_file)) if *first != file_header => {
+                    return Err(format!(
+                        "header of {} differs from the one of {}",
+                        filename.display(),
+                        filenames[*first_file].as_ref().display()
+                    )
+                    .into());
+                }
+                Some(_) => {}
+                None => header = Some((file_header, file)),
+            }
+        }
+        sources.extend(std::iter::repeat_n(file, records.len()));
+        data.extend(records);
+    }
+
+    Ok(match header {
+        Some((header, file)) => (
+            std::iter::once(header).chain(data).collect(),
+            std::iter::once(file).chain(sources).collect(),
+        ),
+        None => (data, sources),
+    })
+}
+
+/// Parses points from the `[latitude, longitude]` columns of CSV records
+///
+/// The first record is skipped when it's a header. See [`read_points_and_csv`]
+/// for skipped rows and `fill_missing`, [`parse_number`] for `decimal_comma`
+/// and [`parse_record`] for `geographic`.
+pub fn parse_points(
+    records: &[Vec<String>],
+    columns: [usize; 2],
+    fill_missing: Option<[f64; 2]>,
+    decimal_comma: bool,
+    geographic: bool,
+) -> (PointList, Vec<SkippedRow>) {
+    let mut points = PointList::new();
+    let mut skipped = Vec::new();
+
+    // Determine if first row is header
+    let has_header = records.first().is_some_and(|r| is_header(r));
+
+    // Parse points from records
+    let start_idx = if has_header { 1 } else { 0 };
+
+    for (i, record) in records.iter().enumerate().skip(start_idx) {
+        match parse_record(record, columns, fill_missing, decimal_comma, geographic) {
+            Ok(point) => points.push(point),
+            Err(bad_columns) => skipped.push(SkippedRow {
+                line: i + 1,
+                record: i,
+                columns: bad_columns,
+            }),
+        }
+    }
+
+    (points, skipped)
+}
+
+/// Parses the `[latitude, longitude]` cells of a data record into a point
+///
+/// NaN and infinite coordinates are rejected, and so are latitudes outside
+/// `[-90, 90]` and longitudes outside `[-180, 180]` when `geographic`. Without
+/// it the cells are projected y/x coordinates of any magnitude. Returns the
+/// names of the invalid columns when that fails, see [`parse_points`].
+pub fn parse_record(
+    record: &[String],
+    columns: [usize; 2],
+    fill_missing: Option<[f64; 2]>,
+    decimal_comma: bool,
+    geographic: bool,
+) -> Result<Point, Vec<&'static str>> {
+    let mut coords = [0.0; 2];
+    let mut bad_columns = Vec::new();
+    let checks = [
+        (
+            "latitude",
+            "latitude not finite",
+            "latitude out of range",
+            90.0,
+        ),
+        (
+            "longitude",
+            "longitude not finite",
+            "longitude out of range",
+            180.0,
+        ),
+    ];
+    for (j, (name, not_finite, out_of_range, limit)) in checks.into_iter().enumerate() {
+        let cell = record.get(columns[j]).map_or("", |c| c.trim());
+        match (parse_number(cell, decimal_comma), fill_missing) {
+            (Some(value), _) if !value.is_finite() => bad_columns.push(not_finite),
+            (Some(value), _) if geographic && value.abs() > limit => bad_columns.push(out_of_range),
+            (Some(value), _) => coords[j] = value,
+            (None, Some(fill)) if cell.is_empty() => coords[j] = fill[j],
+            (None, _) => bad_columns.push(name),
+        }
+    }
+
+    if bad_columns.is_empty() {
+        // Point is [2]float64 where [0]=Lon, [1]=Lat
+        Ok(Point([coords[1], coords[0]]))
+    } else {
+        Err(bad_columns)
+    }
+}
+
+/// Returns the index of the record every point was read from
+///
+/// Points are the data rows of `records` (after the header, if any) that
+/// are not in `skipped`, in order.
+pub fn point_records(records: &[Vec<String>], skipped: &[SkippedRow]) -> Vec<usize> {
+    let has_header = records.first().is_some_and(|r| is_header(r));
+    let skipped_records: std::collections::HashSet<usize> =
+        skipped.iter().map(|row| row.record).collect();
+
+    (usize::from(has_header)..records.len())
+        .filter(|i| !skipped_records.contains(i))
+        .collect()
+}
+
+/// Collapses points with exactly the same coordinates into the first of them
+///
+/// Coordinates are compared by [`PointKey`], so `-0.0` equals `0.0`.
+/// `weights[i]` is what `points[i]` stands for, `None` counts every point
+/// once.
+///
+/// # Returns
+///
+/// A tuple `(kept, weights)` where `kept` are the indices of the remaining
+/// points, in order, and `weights[k]` is the summed weight of the points
+/// collapsed into `points[kept[k]]`, itself included
+pub fn dedup_points(points: &PointList, weights: Option<&[f64]>) -> (Vec<usize>, Vec<f64>) {
+    let mut first = std::collections::HashMap::with_capacity(points.len());
+    let mut kept = Vec::new();
+    let mut kept_weights: Vec<f64> = Vec::new();
+    for (i, point) in points.iter().enumerate() {
+        let weight = weights.map_or(1.0, |w| w[i]);
+        match first.entry(PointKey::from(point)) {
+            std::collections::hash_map::Entry::Occupied(k) => kept_weights[*k.get()] += weight,
+            std::collections::hash_map::Entry::Vacant(slot) => {
+                slot.insert(kept.len());
+                kept.push(i);
+                kept_weights.push(weight);
+            }
+        }
+    }
+    (kept, kept_weights)
+}
+
+/// Returns the indices of the points inside a bounding box, in order
+///
+/// `bbox` is `[min_lat, min_lon, max_lat, max_lon]`, the box includes its
+/// edges.
+pub fn points_in_bbox(points: &PointList, bbox: [f64; 4]) -> Vec<usize> {
+    let min = Point([bbox[1], bbox[0]]);
+    let max = Point([bbox[3], bbox[2]]);
+    (0..points.len())
+        .filter(|&i| inside(&points[i], &points[i], &min, &max))
+        .collect()
+}
+
+/// Column selection for a coordinate, see [`resolve_column`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum ColumnRef {
+    /// 0-based column index
+    Index(usize),
+    /// Column name in the header row
+    Name(String),
+}
+
+/// Order of latitude and longitude in CSV columns and plain coordinate lines
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CoordOrder {
+    /// Latitude first
+    Latlon,
+    /// Longitude first, as in GeoJSON and WKT
+    Lonlat,
+}
+
+impl CoordOrder {
+    /// Default `[latitude, longitude]` column indices of the input
+    pub fn columns(self) -> [usize; 2] {
+        self.arrange([0, 1])
+    }
+
+    /// Puts a `[latitude, longitude]` pair into this order, or a pair in this
+    /// order back into `[latitude, longitude]`
+    pub fn arrange<T>(self, [lat, lon]: [T; 2]) -> [T; 2] {
+        match self {
+            CoordOrder::Latlon => [lat, lon],
+            CoordOrder::Lonlat => [lon, lat],
+        }
+    }
+}
+
+/// Points, skipped rows and resolved coordinate columns of [`stream_points`]
+pub type StreamedPoints = (PointList, Vec<SkippedRow>, [usize; 2]);
+
+/// Reads points from a CSV file without keeping its records
+///
+/// Same as [`read_csv_records`] followed by [`resolve_columns`] and
+/// [`parse_points`], but records are dropped once parsed, so memory holds
+/// only the points. Use
+/// [`stream_filtered_points`](crate::output::stream_filtered_points) to write
+/// the output.
+///
+/// # Returns
+///
+/// A tuple `(points, skipped, columns)` with the resolved coordinate columns
+pub fn stream_points(
+    filename: &Path,
+    delimiter: u8,
+    columns: [&ColumnRef; 2],
+    fill_missing: Option<[f64; 2]>,
+    decimal_comma: bool,
+    geographic: bool,
+) -> Result<StreamedPoints, Box<dyn std::error::Error>> {
+    let mut reader = ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_path(filename)?;
+
+    let mut points = PointList::new();
+    let mut skipped = Vec::new();
+    let mut resolved = None;
+    for (i, result) in reader.records().enumerate() {
+        let record: Vec<String> = result?.iter().map(String::from).collect();
+        let columns = match resolved {
+            Some(columns) => columns,
+            None => {
+                let columns = resolve_columns(std::slice::from_ref(&record), columns)?;
+                resolved = Some(columns);
+                if is_header(&record) {
+                    continue;
+                }
+                columns
+            }
+        };
+        match parse_record(&record, columns, fill_missing, decimal_comma, geographic) {
+            Ok(point) => points.push(point),
+            Err(bad_columns) => skipped.push(SkippedRow {
+                line: i + 1,
+                record: i,
+                columns: bad_columns,
+            }),
+        }
+    }
+
+    // Index selections are valid without reading anything
+    let columns = match resolved {
+        Some(columns) => columns,
+        None => resolve_columns(&[], columns)?,
+    };
+    Ok((points, skipped, columns))
+}
+
+/// Resolves the `[latitude, longitude]` column selections to column indices
+///
+/// Names are looked up in the header row, which fails when there is none or
+/// it has no such column.
+pub fn resolve_columns(
+    records: &[Vec<String>],
+    columns: [&ColumnRef; 2],
+) -> Result<[usize; 2], String> {
+    Ok([
+        resolve_column(records, columns[0])?,
+        resolve_column(records, columns[1])?,
+    ])
+}
+
+/// Resolves a column selection to a column index, see [`resolve_columns`]
+pub fn resolve_column(records: &[Vec<String>], column: &ColumnRef) -> Result<usize, String> {
+    match column {
+        ColumnRef::Index(i) => Ok(*i),
+        ColumnRef::Name(name) => {
+            let header = records.first().filter(|r| is_header(r)).ok_or_else(|| {
+                format!(
+                    "column {:?} is selected by name but the input has no header",
+                    name
+                )
+            })?;
+            header
+                .iter()
+                .position(|cell| cell.trim() == name)
+                .ok_or_else(|| format!("no column named {:?} in the header", name))
+        }
+    }
+}
+
+/// Returns the position among the data rows (after the header, if any) of
+/// the record of every point of `indices`
+///
+/// CSV writers address rows this way, skipped rows have no point.
+pub fn data_rows(records: &[Vec<String>], skipped: &[SkippedRow], indices: &[usize]) -> Vec<usize> {
+    let rows = point_records(records, skipped);
+    let has_header = records.first().is_some_and(|r| is_header(r));
+    indices
+        .iter()
+        .map(|&i| rows[i] - usize::from(has_header))
+        .collect()
+}
+
+/// Names the columns of headerless input with the one-line CSV `schema`
+///
+/// The schema is inserted as header row, `skipped` rows are shifted
+/// accordingly. Fails when the input already has a header, or when the
+/// schema has a different number of columns than the first data row.
+/// Schema fields are separated by `delimiter`, like the input.
+pub fn apply_schema(
+    records: &mut CsvRecords,
+    skipped: &mut [SkippedRow],
+    schema: &str,
+    delimiter: u8,
+) -> Result<(), String> {
+    let names: Vec<String> = ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .from_reader(schema.as_bytes())
+        .records()
+        .next()
+        .ok_or("schema file is empty")?
+        .map_err(|e| e.to_string())?
+        .iter()
+        .map(|name| name.trim().to_string())
+        .collect();
+
+    if !is_header(&names) {
+        return Err(format!(
+            "first schema column {:?} is a number, it would be read as data",
+            names[0]
+        ));
+    }
+    let Some(first) = records.first() else {
+        return Ok(());
+    };
+    if is_header(first) {
+        return Err("input already has a header row".to_string());
+    }
+    if names.len() != first.len() {
+        return Err(format!(
+            "schema has {} columns but the data has {}",
+            names.len(),
+            first.len()
+        ));
+    }
+
+    records.insert(0, names);
+    for row in skipped {
+        row.record += 1;
+    }
+    Ok(())
+}
+
+/// Reads the Unix time in seconds of every point from column `col`
+///
+/// Points with a missing or unparseable time are removed from `points` and
+/// their rows added to `skipped`, which stays sorted by record.
+///
+/// # Returns
+///
+/// A tuple `(times, unparseable)`: `times[i]` belongs to `points[i]` of the
+/// remaining points, `unparseable` are the newly skipped rows
+pub fn parse_times(
+    records: &[Vec<String>],
+    points: &mut PointList,
+    skipped: &mut Vec<SkippedRow>,
+    col: usize,
+) -> (Vec<i64>, Vec<SkippedRow>) {
+    let rows = point_records(records, skipped);
+    let mut times = Vec::with_capacity(rows.len());
+    let mut unparseable = Vec::new();
+    let mut kept = 0;
+    for (i, &row) in rows.iter().enumerate() {
+        match records[row].get(col).map(|cell| cell.trim().parse::<i64>()) {
+            Some(Ok(time)) => {
+                times.push(time);
+                points[kept] = points[i];
+                kept += 1;
+            }
+            _ => unparseable.push(SkippedRow {
+                line: row + 1,
+                record: row,
+                columns: vec!["time"],
+            }),
+        }
+    }
+    points.truncate(kept);
+    skipped.extend(unparseable.iter().cloned());
+    skipped.sort_by_key(|row| row.record);
+    (times, unparseable)
+}
+
+/// Keeps only the points `kept`, e.g. of [`dedup_points`] or [`points_in_bbox`]
+///
+/// `rows[i]` is the record of point `i` (see [`point_records`]). The records
+/// of the other points are added to `skipped` for `reason`, which stays
+/// sorted by record.
+pub fn collapse_points(
+    points: &mut PointList,
+    skipped: &mut Vec<SkippedRow>,
+    rows: &[usize],
+    kept: &[usize],
+    reason: &'static str,
+) {
+    let mut is_kept = vec![false; points.len()];
+    for &i in kept {
+        is_kept[i] = true;
+    }
+    skipped.extend(
+        rows.iter()
+            .zip(&is_kept)
+            .filter(|&(_, &keep)| !keep)
+            .map(|(&row, _)| SkippedRow {
+                line: row + 1,
+                record: row,
+                columns: vec![reason],
+            }),
+    );
+    skipped.sort_by_key(|row| row.record);
+    *points = kept.iter().map(|&i| points[i]).collect();
+}
+
+/// Reads a numeric column for every point, missing or unparseable cells are `missing`
+///
+/// `rows[i]` is the record of point `i`, see [`point_records`], and
+/// [`parse_number`] for `decimal_comma`.
+pub fn read_column_values(
+    records: &[Vec<String>],
+    rows: &[usize],
+    col: usize,
+    decimal_comma: bool,
+    missing: f64,
+) -> Vec<f64> {
+    rows.iter()
+        .map(|&row| {
+            records[row]
+                .get(col)
+                .and_then(|cell| parse_number(cell.trim(), decimal_comma))
+                .unwrap_or(missing)
+        })
+        .collect()
+}
+
+/// Reads points from a file of plain coordinate lines
+///
+/// Every non-empty line holds `latitude longitude`, separated by whitespace
+/// and/or a comma, further tokens are ignored. Blank lines and lines starting
+/// with `#` are skipped.
+///
+/// # Returns
+///
+/// A tuple `(points, records)` like [`read_points_and_csv`], `records` holds
+/// the two coordinate tokens of every point, in `order`
+pub fn read_points_plain(
+    filename: &PathBuf,
+    order: CoordOrder,
+) -> Result<(PointList, CsvRecords), Box<dyn std::error::Error>> {
+    let content = std::fs::read_to_string(filename)?;
+
+    let mut points = PointList::new();
+    let mut records = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut tokens = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|t| !t.is_empty());
+        let (Some(first), Some(second)) = (tokens.next(), tokens.next()) else {
+            return Err(format!("line {}: expected latitude and longitude", i + 1).into());
+        };
+        let [lat, lon] = order.arrange([first, second]);
+        let (lat_value, lon_value) = match (lat.parse::<f64>(), lon.parse::<f64>()) {
+            (Ok(lat), Ok(lon))
+                if lat.is_finite()
+                    && lon.is_finite()
+                    && lat.abs() <= 90.0
+                    && lon.abs() <= 180.0 =>
+            {
+                (lat, lon)
+            }
+            _ => return Err(format!("line {}: invalid coordinates {:?}", i + 1, line).into()),
+        };
+
+        // Point is [2]float64 where [0]=Lon, [1]=Lat
+        points.push(Point([lon_value, lat_value]));
+        records.push(vec![first.to_string(), second.to_string()]);
+    }
+
+    Ok((points, records))
+}
+
+/// Reads points from several files of plain coordinate lines
+///
+/// Merges the files like [`read_csv_files`]: returns the points and records
+/// of [`read_points_plain`] for all of them, and the index in `filenames` of
+/// the file of every record.
+pub fn read_plain_files(
+    filenames: &[PathBuf],
+    order: CoordOrder,
+) -> Result<(PointList, CsvRecords, Vec<usize>), Box<dyn std::error::Error>> {
+    let mut points = PointList::new();
+    let mut records = Vec::new();
+    let mut sources = Vec::new();
+    for (file, filename) in filenames.iter().enumerate() {
+        let (file_points, file_records) = read_points_plain(filename, order)?;
+        sources.extend(std::iter::repeat_n(file, file_records.len()));
+        points.extend(file_points);
+        records.extend(file_records);
+    }
+    Ok((points, records, sources))
+}
+
+/// Points, properties and skipped features of [`read_points_geojson`]
+pub type GeojsonPoints = (
+    PointList,
+    Vec<serde_json::Map<String, serde_json::Value>>,
+    Vec<usize>,
+);
+
+/// Points, records, sources and skipped features of [`read_geojson_files`]
+pub type GeojsonRecords = (PointList, CsvRecords, Vec<usize>, Vec<String>);
+
+/// Reads the `Point` features of a GeoJSON `FeatureCollection`
+///
+/// Features with other geometries, or none, are skipped. Coordinates are
+/// validated like the ones of [`read_points_plain`].
+///
+/// # Returns
+///
+/// A tuple `(points, properties, skipped)` where `properties[i]` are the
+/// properties of `points[i]` and `skipped` the 0-based indices of the
+/// skipped features
+pub fn read_points_geojson(
+    filename: &PathBuf,
+) -> Result<GeojsonPoints, Box<dyn std::error::Error>> {
+    let doc: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(filename)?)?;
+    let features = doc["features"]
+        .as_array()
+        .ok_or("expected a FeatureCollection with a features array")?;
+
+    let mut points = PointList::new();
+    let mut properties = Vec::new();
+    let mut skipped = Vec::new();
+    for (i, feature) in features.iter().enumerate() {
+        let geometry = &feature["geometry"];
+        if geometry["type"].as_str() != Some("Point") {
+            skipped.push(i);
+            continue;
+        }
+
+        // GeoJSON positions are [lon, lat], same as Point
+        let coords = &geometry["coordinates"];
+        let point = match (coords[0].as_f64(), coords[1].as_f64()) {
+            (Some(lon), Some(lat)) if lat.abs() <= 90.0 && lon.abs() <= 180.0 => Point([lon, lat]),
+            _ => return Err(format!("feature {}: invalid coordinates {}", i, coords).into()),
+        };
+        points.push(point);
+        properties.push(
+            feature["properties"]
+                .as_object()
+                .cloned()
+                .unwrap_or_default(),
+        );
+    }
+
+    Ok((points, properties, skipped))
+}
+
+/// Reads points from several GeoJSON files, see [`read_points_geojson`]
+///
+/// Records hold the `latitude,longitude` of every point, followed by its
+/// properties. The header row names these columns: `latitude`, `longitude`
+/// and the property names of all features, sorted. Properties
+/// that are strings are stored as-is, missing or `null` ones as empty cells
+/// and others as JSON.
+///
+/// # Returns
+///
+/// A tuple `(points, records, sources, skipped)` where `sources` are the
+/// files of the records like the ones of [`read_csv_files`] and `skipped`
+/// describes the skipped features, e.g. `stops.geojson feature 3`
+pub fn read_geojson_files(
+    filenames: &[PathBuf],
+) -> Result<GeojsonRecords, Box<dyn std::error::Error>> {
+    let mut points = PointList::new();
+    let mut properties = Vec::new();
+    let mut sources = Vec::new();
+    let mut skipped = Vec::new();
+    for (file, filename) in filenames.iter().enumerate() {
+        let (file_points, file_properties, file_skipped) = read_points_geojson(filename)?;
+        sources.extend(std::iter::repeat_n(file, file_points.len()));
+        points.extend(file_points);
+        properties.extend(file_properties);
+        skipped.extend(
+            file_skipped
+                .into_iter()
+                .map(|i| format!("{} feature {}", filename.display(), i)),
+        );
+    }
+
+    let names: std::collections::BTreeSet<&String> =
+        properties.iter().flat_map(|p| p.keys()).collect();
+    let header = ["latitude", "longitude"]
+        .into_iter()
+        .map(String::from)
+        .chain(names.iter().map(|name| name.to_string()))
+        .collect();
+    let rows = points.iter().zip(&properties).map(|(point, props)| {
+        let coords = [point.0[1], point.0[0]].map(|v| v.to_string());
+        let cells = names.iter().map(|&name| match props.get(name) {
+            None | Some(serde_json::Value::Null) => String::new(),
+            Some(serde_json::Value::String(s)) => s.clone(),
+            Some(other) => other.to_string(),
+        });
+        coords.into_iter().chain(cells).collect()
+    });
+    let records = std::iter::once(header).chain(rows).collect();
+
+    // The header row comes from the first file
+    sources.insert(0, 0);
+    Ok((points, records, sources, skipped))
+}
+
+/// Returns the number of points read from the base file
+///
+/// The base file is the first input file, so its points are the first ones.
+/// `sources` are the files of the records (see [`read_csv_files`]), `rows` the
+/// records of the points (see [`point_records`]).
+pub fn base_point_count(sources: &[usize], rows: &[usize]) -> usize {
+    rows.iter().take_while(|&&row| sources[row] == 0).count()
+}
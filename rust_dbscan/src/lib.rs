@@ -0,0 +1,15 @@
+//! DBSCAN geo point clustering
+//!
+//! [`cluster`] implements the clustering, [`input`] reads points from CSV,
+//! plain coordinate and GeoJSON files, [`filter`] keeps the outliers and one
+//! point per cluster and [`output`] writes points and clusters.
+//! [`diagnostics`] words warnings and summaries, [`benchmark`] times the
+//! pipeline on synthetic data. The `rust_dbscan_filter` binary parses the
+//! command line and calls into these.
+
+pub mod benchmark;
+pub mod cluster;
+pub mod diagnostics;
+pub mod filter;
+pub mod input;
+pub mod output;
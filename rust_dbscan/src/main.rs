@@ -3,301 +3,1792 @@
 //! Reads geographic points from CSV files, applies DBSCAN clustering, and filters
 //! results to keep only outliers and the first point in each cluster.
 
-use clap::Parser;
-use csv::{ReaderBuilder, WriterBuilder};
-use std::fs::File;
+use clap::{Parser, ValueEnum};
+use std::borrow::Cow;
+use std::io::Write;
 use std::path::PathBuf;
-
-mod cluster;
+use std::time::{Duration, Instant};
 
 #[cfg(test)]
 mod main_test;
 
-use cluster::{Cluster, DBScan, Point, PointList};
+use rust_dbscan::benchmark;
+use rust_dbscan::diagnostics::{
+    check_column_counts, check_degenerate, check_min_points, check_skipped_features,
+    check_skipped_rows, check_skipped_times, cluster_stats, kd_tree_diagnostics,
+    largest_cluster_fraction, noise_histogram, points_extent,
+};
+use rust_dbscan::filter::{
+    DBSCAN_OUTLIER_INDEX, Keep, build_labels, centroid_points, drop_small_clusters,
+    filter_new_points, merge_clusters_named, split_oversized_clusters, without_base_points,
+};
+use rust_dbscan::input::{
+    ColumnRef, CoordOrder, CsvRecords, SkippedRow, apply_schema, base_point_count, collapse_points,
+    data_rows, dedup_points, parse_points, parse_times, point_records, points_in_bbox,
+    read_column_values, read_csv_files, read_geojson_files, read_plain_files, resolve_column,
+    resolve_columns, stream_points, unproject_points,
+};
+#[cfg(feature = "serde")]
+use rust_dbscan::output::write_cluster_summary_json;
+use rust_dbscan::output::{
+    FeatureLabels, LabelFormat, add_aggregate_columns, add_color_column, add_label_column,
+    add_order_index, add_source_column, open_output, reproject_records, set_record_coords,
+    stream_filtered_points, write_atomically, write_cluster_summary, write_clusters_multipoint,
+    write_filtered_points_to_csv, write_filtered_points_to_stdout, write_jsonl, write_k_distances,
+    write_points_geojson, write_reachability, write_wkt,
+};
+use rust_dbscan::{cluster, output};
 
-const DBSCAN_OUTLIER_INDEX: i32 = -1;
+use cluster::distance::EARTH_R;
+use cluster::projection::Crs;
+use cluster::region::{OutsidePoints, RegionClusters, db_scan_in_regions, parse_regions_geojson};
+use cluster::{
+    AccuracyMode, Cluster, DbscanConfig, DistanceMetric, IndexKind, NoiseInfo, Point, PointList,
+    SeedOrder, StableBy, estimate_eps_with_metric, k_distances_with_metric,
+    new_kd_tree_with_metric, optics_with_metric,
+};
 
 #[derive(Parser)]
 #[command(name = "rust_dbscan")]
 #[command(about = "DBSCAN geo point clustering tool", long_about = None)]
 struct Args {
-    /// Input CSV file with latitude,longitude columns
-    #[arg(short, long, default_value = "points.csv")]
-    input: PathBuf,
+    /// Input CSV files with latitude,longitude columns, clustered together
+    #[arg(short, long, num_args = 1.., default_value = "points.csv")]
+    input: Vec<PathBuf>,
+
+    /// Reference CSV file clustered together with the --input files, only
+    /// the rows of --input are written
+    #[arg(long, conflicts_with = "dedup_input")]
+    base: Option<PathBuf>,
 
     /// Output CSV file with filtered points (default: stdout)
     #[arg(short, long)]
     output: Option<PathBuf>,
 
-    /// DBSCAN epsilon parameter (clustering radius in km)
-    #[arg(short, long, default_value_t = 0.1)]
+    /// CSV file to write one row per cluster to, with its centroid, bounding
+    /// box and point count
+    #[arg(long)]
+    summary: Option<PathBuf>,
+
+    /// JSON file to write an array with one object per cluster to: id, point
+    /// count, centroid, bounding box and diameter in km
+    #[cfg(feature = "serde")]
+    #[arg(long)]
+    summary_json: Option<PathBuf>,
+
+    /// DBSCAN epsilon parameter (clustering radius in km, in coordinate units
+    /// with --metric manhattan or chebyshev)
+    #[arg(short, long, default_value_t = 0.1, allow_negative_numbers = true)]
     eps: f64,
 
     /// DBSCAN minPoints parameter (minimum points in cluster)
     #[arg(short = 'm', long, default_value_t = 3)]
     min_points: usize,
 
+    /// Don't count a point towards its own min-points neighbourhood, so a
+    /// core point needs min-points other points within eps
+    #[arg(long)]
+    min_points_excludes_self: bool,
+
+    /// Print the number of clusters and noise points, cluster sizes and the
+    /// extent of the largest cluster instead of writing any output
+    #[arg(long)]
+    stats: bool,
+
+    /// Score only this many clustered points for the clustering quality of
+    /// `--stats`, for large inputs
+    #[arg(long, requires = "stats")]
+    quality_sample: Option<usize>,
+
+    /// Time the pipeline on synthetic data sets of increasing size and print
+    /// a table, no input is read and no output is written
+    #[arg(long)]
+    benchmark: bool,
+
     /// Enable debug output
     #[arg(short, long)]
     debug: bool,
+
+    /// Treat warnings as errors
+    #[arg(long)]
+    strict: bool,
+
+    /// Append an `original_index` column with each row's 0-based input position
+    #[arg(long)]
+    preserve_order_index: bool,
+
+    /// Append a `cluster` column with each CSV output row's cluster id (-1 for noise)
+    #[arg(long)]
+    emit_labels: bool,
+
+    /// How noise is labelled in the --emit-labels column and the `cluster`
+    /// of JSON outputs
+    #[arg(long, default_value = output::DEFAULT_NOISE_LABEL, allow_hyphen_values = true)]
+    noise_label: String,
+
+    /// Append a `color` column with a `#rrggbb` color per cluster (gray for
+    /// noise), after the `--emit-labels` one
+    #[arg(long)]
+    emit_color: bool,
+
+    /// Append a `source` column with the input file each row was read from
+    #[arg(long)]
+    emit_source: bool,
+
+    /// Spatial index used for neighbourhood queries
+    #[arg(long, value_enum, default_value_t = IndexArg::Auto)]
+    index: IndexArg,
+
+    /// Clustering algorithm, optics writes the reachability plot (point
+    /// index, reachability and core distance in km in OPTICS order) instead
+    /// of filtered points, with --eps as the largest distance considered
+    #[arg(long, value_enum, default_value_t = AlgorithmArg::Dbscan)]
+    algorithm: AlgorithmArg,
+
+    /// Estimate eps from the knee of the k-distance curve (k = min-points),
+    /// overriding --eps
+    #[arg(long)]
+    auto_eps: bool,
+
+    /// Write the k-distance curve (rank and distance in km of every point to
+    /// its k-th nearest point, sorted) instead of clustering, to pick eps by
+    /// hand
+    #[arg(long, value_name = "K", value_parser = clap::value_parser!(u64).range(1..))]
+    kdist: Option<u64>,
+
+    /// Distance metric used for neighbourhood queries
+    #[arg(long, value_enum, default_value_t = MetricArg::Fast)]
+    metric: MetricArg,
+
+    /// Use the exact great-circle distance instead of the fast approximation,
+    /// same as --metric haversine
+    #[arg(long, conflicts_with = "metric")]
+    exact_distance: bool,
+
+    /// Radius of the sphere points lie on in kilometers (e.g. 3389.5 for Mars)
+    #[arg(long, default_value_t = EARTH_R, value_parser = parse_earth_radius)]
+    earth_radius: f64,
+
+    /// Points written per cluster, besides all outliers
+    #[arg(long, value_enum, default_value_t = KeepArg::First)]
+    keep: KeepArg,
+
+    /// Number clusters by ascending centroid longitude, then latitude, instead
+    /// of the order they are found in, so ids don't change when the input is
+    /// reordered
+    #[arg(long)]
+    stable_ids: bool,
+
+    /// Order in which points start clusters, which decides the cluster of
+    /// border points within eps of several clusters
+    #[arg(long, value_enum, default_value_t = SeedOrderArg::Input)]
+    seed_order: SeedOrderArg,
+
+    /// Also write the convex hull of every cluster as a POLYGON with --format wkt
+    #[arg(long)]
+    wkt_hull: bool,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Csv)]
+    format: OutputFormat,
+
+    /// Append member count, centroid and bounding box size (km) of the cluster
+    /// each CSV output row stands for
+    #[arg(long)]
+    aggregate: bool,
+
+    /// Decimal places of coordinates written from computed values (JSON and
+    /// WKT outputs, centroids, reprojected CSV cells), or `full` for full
+    /// precision. CSV cells read from the input are written as-is
+    #[arg(long, visible_alias = "precision", default_value = "7", value_parser = parse_coord_digits)]
+    coord_digits: CoordDigits,
+
+    /// Coordinate reference system of written coordinates
+    #[arg(long, value_enum, default_value_t = CrsArg::Wgs84)]
+    output_crs: CrsArg,
+
+    /// Coordinate reference system of CSV input, reprojected to WGS84 before
+    /// clustering: `epsg:4326` or `epsg:3857` (Web Mercator `y,x` meters)
+    #[arg(long, default_value = "epsg:4326", value_parser = parse_crs)]
+    input_crs: Crs,
+
+    /// One-line CSV file with column names for headerless input
+    #[arg(long)]
+    schema: Option<PathBuf>,
+
+    /// Input format
+    #[arg(long, value_enum, default_value_t = InputFormat::Csv)]
+    input_format: InputFormat,
+
+    /// Keep only points in memory: CSV output re-reads the input instead of
+    /// holding all records
+    #[arg(long)]
+    streaming: bool,
+
+    /// Field delimiter of CSV input and output, a single ASCII character
+    /// (`\t` for tab)
+    #[arg(long, default_value = ",", value_parser = parse_delimiter)]
+    delimiter: u8,
+
+    /// Read `,` as decimal separator in coordinate and accuracy cells
+    #[arg(long)]
+    decimal_comma: bool,
+
+    /// Latitude column of CSV input: 0-based index, or name in the header row
+    /// [default: 0, or 1 with `--coord-order lonlat`]
+    #[arg(long, value_parser = parse_column)]
+    lat_col: Option<ColumnRef>,
+
+    /// Longitude column of CSV input: 0-based index, or name in the header row
+    /// [default: 1, or 0 with `--coord-order lonlat`]
+    #[arg(long, value_parser = parse_column)]
+    lon_col: Option<ColumnRef>,
+
+    /// Order of latitude and longitude in the input columns, plain input lines,
+    /// the stdout list and `--summary` centroids. Rows written to `--output`
+    /// keep the order of the input
+    #[arg(long, value_enum, default_value_t = CoordOrderArg::Latlon)]
+    coord_order: CoordOrderArg,
+
+    /// Default `<lat,lon>` for missing (empty) CSV coordinates, instead of
+    /// skipping the row
+    #[arg(long, value_parser = parse_lat_lon, value_name = "LAT,LON", allow_hyphen_values = true)]
+    fill_missing: Option<[f64; 2]>,
+
+    /// GeoJSON file with Polygon/MultiPolygon regions, each region is clustered separately
+    #[arg(long)]
+    regions: Option<PathBuf>,
+
+    /// What to do with points outside of all regions
+    #[arg(long, value_enum, default_value_t = OutsideArg::Drop)]
+    outside_regions: OutsideArg,
+
+    /// Re-run with the exact haversine metric when the fast metric puts too many
+    /// points into a single cluster
+    #[arg(long)]
+    retry_metric: bool,
+
+    /// Fraction of all points in one cluster that triggers --retry-metric
+    #[arg(long, default_value_t = 0.9)]
+    retry_threshold: f64,
+
+    /// 0-based index of a CSV column holding each point's position accuracy
+    /// (uncertainty radius in kilometers, missing values count as 0)
+    #[arg(long)]
+    accuracy_col: Option<usize>,
+
+    /// How accuracy changes the neighbour distance: eps + accuracies (liberal)
+    /// or eps - accuracies (conservative)
+    #[arg(long, value_enum, default_value_t = AccuracyArg::Liberal)]
+    accuracy_mode: AccuracyArg,
+
+    /// 0-based index of a CSV column holding how many observations each row
+    /// stands for, min-points counts these weights (missing values count as 1)
+    #[arg(long)]
+    weight_col: Option<usize>,
+
+    /// Collapse points with exactly the same coordinates before clustering,
+    /// `weighted` (the default) keeps their number as weight
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "weighted")]
+    dedup_input: Option<DedupArg>,
+
+    /// Only cluster the points inside `<minlat,minlon,maxlat,maxlon>`, the
+    /// others are dropped from the output
+    #[arg(long, value_parser = parse_bbox, value_name = "MINLAT,MINLON,MAXLAT,MAXLON", allow_hyphen_values = true)]
+    bbox: Option<[f64; 4]>,
+
+    /// CSV column holding each point's Unix time in seconds: 0-based index,
+    /// or name in the header row. Rows with unparseable times are skipped
+    #[arg(long, value_parser = parse_column, requires = "time_eps")]
+    time_col: Option<ColumnRef>,
+
+    /// Max time difference in seconds between neighbours, see --time-col
+    #[arg(long, requires = "time_col")]
+    time_eps: Option<u64>,
+
+    /// Stop starting new clusters after this many seconds, the remaining
+    /// points are reported as noise (results are incomplete then)
+    #[arg(long)]
+    timeout_secs: Option<f64>,
+
+    /// Write only outliers, no point of any cluster (overrides --keep)
+    #[arg(long)]
+    noise_only: bool,
+
+    /// Merge clusters whose centroids are less than this many kilometers
+    /// apart after clustering
+    #[arg(long, value_name = "KM")]
+    merge_distance: Option<f64>,
+
+    /// Reclassify clusters with fewer points than this as noise
+    #[arg(long)]
+    min_cluster_size: Option<usize>,
+
+    /// Split clusters with more points than this into grid cells instead of
+    /// keeping them whole
+    #[arg(long)]
+    max_cluster_size: Option<usize>,
+
+    /// Grid cell size in kilometers used by --max-cluster-size (default: eps)
+    #[arg(long)]
+    split_cell_km: Option<f64>,
+
+    /// Threads formatting output and building the K-D tree (default: all cores)
+    #[cfg(feature = "rayon")]
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    jobs: Option<u64>,
 }
 
-fn main() {
-    let args = Args::parse();
+/// Handling of points outside of all regions on the command line
+#[derive(Clone, Copy, ValueEnum)]
+enum OutsideArg {
+    /// Exclude them from clustering and output
+    Drop,
+    /// Cluster them together as an extra "outside" region
+    Group,
+}
 
-    // Read points and CSV records from file (read once, reuse for output)
-    let (points, csv_records) = match read_points_and_csv(&args.input) {
-        Ok(result) => result,
-        Err(e) => {
-            eprintln!("Error reading CSV: {}", e);
-            std::process::exit(1);
+impl From<OutsideArg> for OutsidePoints {
+    fn from(arg: OutsideArg) -> Self {
+        match arg {
+            OutsideArg::Drop => OutsidePoints::Drop,
+            OutsideArg::Group => OutsidePoints::Group,
         }
+    }
+}
+
+/// Decimal places of coordinates in JSON outputs, `None` for full precision
+#[derive(Clone, Copy)]
+struct CoordDigits(Option<u32>);
+
+/// Parses a `--coord-digits` value: a number of decimal places or `full`
+fn parse_coord_digits(value: &str) -> Result<CoordDigits, String> {
+    if value == "full" {
+        return Ok(CoordDigits(None));
+    }
+    match value.parse::<u32>() {
+        Ok(digits) if digits <= output::MAX_COORD_DIGITS => Ok(CoordDigits(Some(digits))),
+        _ => Err(format!(
+            "expected 0..={} or `full`, got {:?}",
+            output::MAX_COORD_DIGITS,
+            value
+        )),
+    }
+}
+
+/// Returns the options that can't be used with `--streaming`, because they
+/// need all records in memory
+fn streaming_conflicts(args: &Args) -> Vec<&'static str> {
+    if !args.streaming {
+        return Vec::new();
+    }
+    [
+        (args.schema.is_some(), "--schema"),
+        (args.preserve_order_index, "--preserve-order-index"),
+        (args.aggregate, "--aggregate"),
+        (args.emit_labels, "--emit-labels"),
+        (args.emit_source, "--emit-source"),
+        (args.emit_color, "--emit-color"),
+        (args.input.len() > 1, "multiple --input files"),
+        (args.base.is_some(), "--base"),
+        (args.accuracy_col.is_some(), "--accuracy-col"),
+        (args.weight_col.is_some(), "--weight-col"),
+        (args.time_col.is_some(), "--time-col"),
+        (args.dedup_input.is_some(), "--dedup-input"),
+        (args.bbox.is_some(), "--bbox"),
+        (
+            matches!(args.format, OutputFormat::Csv) && !matches!(args.output_crs, CrsArg::Wgs84),
+            "--output-crs with csv output",
+        ),
+        (
+            matches!(args.format, OutputFormat::Csv) && matches!(args.keep, KeepArg::Centroid),
+            "--keep centroid with csv output",
+        ),
+        (
+            matches!(args.format, OutputFormat::Geojson),
+            "--format geojson",
+        ),
+        (
+            !matches!(args.input_format, InputFormat::Csv),
+            "--input-format plain or geojson",
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(set, name)| set.then_some(name))
+    .collect()
+}
+
+/// Returns the options that can't be used with a
+/// [planar](DistanceMetric::is_planar) `--metric`, because they measure
+/// distances in kilometers
+fn planar_conflicts(args: &Args) -> Vec<&'static str> {
+    if !DistanceMetric::from(args.metric).is_planar() {
+        return Vec::new();
+    }
+    #[cfg(feature = "serde")]
+    let summary_json = args.summary_json.is_some();
+    #[cfg(not(feature = "serde"))]
+    let summary_json = false;
+    [
+        (args.merge_distance.is_some(), "--merge-distance"),
+        (args.max_cluster_size.is_some(), "--max-cluster-size"),
+        (args.aggregate, "--aggregate"),
+        (summary_json, "--summary-json"),
+        (args.stats, "--stats"),
+    ]
+    .into_iter()
+    .filter_map(|(set, name)| set.then_some(name))
+    .collect()
+}
+
+/// Parses a column selection: a 0-based index, or otherwise a column name
+fn parse_column(value: &str) -> Result<ColumnRef, String> {
+    match value.parse::<usize>() {
+        Ok(index) => Ok(ColumnRef::Index(index)),
+        Err(_) if !value.trim().is_empty() => Ok(ColumnRef::Name(value.trim().to_string())),
+        Err(_) => Err("expected a column index or name".to_string()),
+    }
+}
+
+/// Parses an `--input-crs` value: an EPSG code like `epsg:3857`, see [`Crs`]
+fn parse_crs(value: &str) -> Result<Crs, String> {
+    value.parse()
+}
+
+/// Parses a `--delimiter` value: one ASCII character, or `\t` for tab
+fn parse_delimiter(value: &str) -> Result<u8, String> {
+    match value.as_bytes() {
+        [b'\\', b't'] => Ok(b'\t'),
+        &[byte] if byte.is_ascii() => Ok(byte),
+        _ => Err(format!(
+            "expected a single ASCII character, got {:?}",
+            value
+        )),
+    }
+}
+
+/// Parses an `--earth-radius` value: a positive number of kilometers
+fn parse_earth_radius(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(km) if km.is_finite() && km > 0.0 => Ok(km),
+        _ => Err(format!("expected a positive number of km, got {:?}", value)),
+    }
+}
+
+/// Parses a `<lat,lon>` command line value
+fn parse_lat_lon(value: &str) -> Result<[f64; 2], String> {
+    let (lat, lon) = value
+        .split_once(',')
+        .ok_or_else(|| format!("expected <lat,lon>, got {:?}", value))?;
+    let parse = |v: &str| {
+        v.trim()
+            .parse::<f64>()
+            .map_err(|e| format!("{}: {:?}", e, v))
     };
+    Ok([parse(lat)?, parse(lon)?])
+}
 
-    if points.is_empty() {
-        eprintln!("No points found in CSV file");
-        std::process::exit(1);
+/// Parses a `<minlat,minlon,maxlat,maxlon>` command line value
+fn parse_bbox(value: &str) -> Result<[f64; 4], String> {
+    let corners: Vec<f64> = value
+        .split(',')
+        .map(|v| {
+            v.trim()
+                .parse::<f64>()
+                .map_err(|e| format!("{}: {:?}", e, v))
+        })
+        .collect::<Result<_, _>>()?;
+    match corners[..] {
+        [min_lat, min_lon, max_lat, max_lon] if min_lat <= max_lat && min_lon <= max_lon => {
+            Ok([min_lat, min_lon, max_lat, max_lon])
+        }
+        [_, _, _, _] => Err(format!(
+            "expected minimum coordinates not above the maximum ones, got {:?}",
+            value
+        )),
+        _ => Err(format!(
+            "expected <minlat,minlon,maxlat,maxlon>, got {:?}",
+            value
+        )),
     }
+}
 
-    // Debug output (only if debug flag is set)
-    if args.debug {
-        println!("Read {} points from {:?}", points.len(), args.input);
-        println!(
-            "Running DBSCAN with eps={:.4} km, minPoints={}",
-            args.eps, args.min_points
-        );
+/// Accuracy handling on the command line
+#[derive(Clone, Copy, ValueEnum)]
+enum AccuracyArg {
+    /// Neighbours when closer than eps + acc_i + acc_j
+    Liberal,
+    /// Neighbours when closer than eps - (acc_i + acc_j)
+    Conservative,
+}
+
+impl From<AccuracyArg> for AccuracyMode {
+    fn from(arg: AccuracyArg) -> Self {
+        match arg {
+            AccuracyArg::Liberal => AccuracyMode::Liberal,
+            AccuracyArg::Conservative => AccuracyMode::Conservative,
+        }
     }
+}
 
-    // Run DBSCAN clustering
-    let (clusters, noise) = DBScan(&points, args.eps, args.min_points);
+/// Handling of duplicate coordinates on the command line
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum DedupArg {
+    /// Collapsed points weigh the number of rows (or summed --weight-col
+    /// weights) they stand for
+    Weighted,
+    /// Collapsed points count once
+    Drop,
+}
 
-    if args.debug {
-        println!("Found {} clusters", clusters.len());
-        println!("Found {} noise points", noise.len());
+/// Output coordinate reference system on the command line
+#[derive(Clone, Copy, ValueEnum)]
+enum CrsArg {
+    /// WGS84 latitude/longitude in degrees
+    #[value(alias = "epsg:4326")]
+    Wgs84,
+    /// Spherical Web Mercator (EPSG:3857) in meters
+    #[value(name = "webmercator", alias = "epsg:3857")]
+    WebMercator,
+}
+
+impl From<CrsArg> for Crs {
+    fn from(arg: CrsArg) -> Self {
+        match arg {
+            CrsArg::Wgs84 => Crs::Wgs84,
+            CrsArg::WebMercator => Crs::WebMercator,
+        }
     }
+}
 
-    // Build labels array from clusters and noise for filtering
-    let labels = build_labels(&clusters, &noise, points.len());
+/// Input format on the command line
+#[derive(Clone, Copy, ValueEnum)]
+enum InputFormat {
+    /// CSV with `latitude,longitude` in the first two columns, optional header
+    Csv,
+    /// `lat lon` or `lat,lon` lines, `#` comments and blank lines are skipped
+    Plain,
+    /// GeoJSON `FeatureCollection` of `Point` features, their properties are
+    /// kept as columns
+    Geojson,
+}
 
-    // Filter points based on:
-    // 1. Keep outliers (label == -1)
-    // 2. Keep first point in each cluster (idx == 0 or label != labels[idx-1])
-    let filtered_indices = filter_points(&points, &labels);
+/// Output format on the command line
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    /// Filtered points: CSV file, or `latitude,longitude` list on stdout
+    Csv,
+    /// GeoJSON FeatureCollection with one MultiPoint feature per cluster
+    ClustersMultipoint,
+    /// JSON Lines (NDJSON), one `{"lat","lon","cluster"}` object per filtered point
+    #[value(alias = "ndjson")]
+    Jsonl,
+    /// GeoJSON FeatureCollection with one Point feature per filtered point,
+    /// CSV columns as properties
+    Geojson,
+    /// Well-known text, one MULTIPOINT per cluster and one POINT per noise point
+    Wkt,
+}
 
-    if args.debug {
-        println!("Filtered to {} points", filtered_indices.len());
+/// Points kept per cluster on the command line
+#[derive(Clone, Copy, ValueEnum)]
+enum KeepArg {
+    /// First point of each cluster
+    First,
+    /// Every point
+    All,
+    /// One point per cluster, moved to the cluster's centroid
+    Centroid,
+}
+
+impl From<KeepArg> for Keep {
+    fn from(arg: KeepArg) -> Self {
+        match arg {
+            KeepArg::First => Keep::First,
+            KeepArg::All => Keep::All,
+            KeepArg::Centroid => Keep::Centroid,
+        }
     }
+}
 
-    // Write filtered points to output (stdout or file)
-    match args.output {
-        None => {
-            // Output to stdout as simple list of points
-            if let Err(e) = write_filtered_points_to_stdout(&csv_records, &filtered_indices) {
-                eprintln!("Error writing to stdout: {}", e);
-                std::process::exit(1);
-            }
+/// Clustering algorithm choice on the command line
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum AlgorithmArg {
+    /// Clusters and noise
+    Dbscan,
+    /// Reachability plot, clusters at any eps up to --eps
+    Optics,
+}
+
+/// Spatial index choice on the command line
+#[derive(Clone, Copy, ValueEnum)]
+enum IndexArg {
+    /// Pick an index based on the input size and density
+    Auto,
+    /// K-D tree
+    Kdtree,
+    /// Fixed-radius grid
+    Grid,
+    /// Linear scan over all points
+    Bruteforce,
+}
+
+impl From<IndexArg> for IndexKind {
+    fn from(arg: IndexArg) -> Self {
+        match arg {
+            IndexArg::Auto => IndexKind::Auto,
+            IndexArg::Kdtree => IndexKind::KdTree,
+            IndexArg::Grid => IndexKind::Grid,
+            IndexArg::Bruteforce => IndexKind::BruteForce,
         }
-        Some(output_file) => {
-            // Write filtered points to output CSV file
-            if let Err(e) =
-                write_filtered_points_to_csv(&output_file, &csv_records, &filtered_indices)
-            {
-                eprintln!("Error writing CSV: {}", e);
-                std::process::exit(1);
-            }
-            if args.debug {
-                println!("Filtered points written to {:?}", output_file);
-            }
+    }
+}
+
+/// Seed order choice on the command line
+#[derive(Clone, Copy, ValueEnum)]
+enum SeedOrderArg {
+    /// Order of the input rows
+    Input,
+    /// Ascending latitude, then longitude
+    Lat,
+    /// Ascending longitude, then latitude
+    Lon,
+    /// Z-order curve, nearby points are visited close together
+    Morton,
+}
+
+impl From<SeedOrderArg> for SeedOrder {
+    fn from(arg: SeedOrderArg) -> Self {
+        match arg {
+            SeedOrderArg::Input => SeedOrder::Input,
+            SeedOrderArg::Lat => SeedOrder::Lat,
+            SeedOrderArg::Lon => SeedOrder::Lon,
+            SeedOrderArg::Morton => SeedOrder::Morton,
         }
     }
 }
 
-/// CSV records type alias for readability
-type CsvRecords = Vec<Vec<String>>;
+/// Distance metric choice on the command line
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum MetricArg {
+    /// Flat-earth approximation with a fast cosine, see --exact-distance
+    Fast,
+    /// Flat-earth approximation
+    Spherical,
+    /// Exact great-circle distance
+    Haversine,
+    /// Sum of coordinate differences of projected coordinates, eps in their unit
+    Manhattan,
+    /// Largest coordinate difference of projected coordinates, eps in their unit
+    Chebyshev,
+}
+
+impl From<MetricArg> for DistanceMetric {
+    fn from(arg: MetricArg) -> Self {
+        match arg {
+            MetricArg::Fast => DistanceMetric::SphericalFast,
+            MetricArg::Spherical => DistanceMetric::Spherical,
+            MetricArg::Haversine => DistanceMetric::Haversine,
+            MetricArg::Manhattan => DistanceMetric::Manhattan,
+            MetricArg::Chebyshev => DistanceMetric::Chebyshev,
+        }
+    }
+}
+
+/// Order of latitude and longitude on the command line
+#[derive(Clone, Copy, ValueEnum)]
+enum CoordOrderArg {
+    /// Latitude first
+    Latlon,
+    /// Longitude first, as in GeoJSON and WKT
+    Lonlat,
+}
+
+impl From<CoordOrderArg> for CoordOrder {
+    fn from(arg: CoordOrderArg) -> Self {
+        match arg {
+            CoordOrderArg::Latlon => CoordOrder::Latlon,
+            CoordOrderArg::Lonlat => CoordOrder::Lonlat,
+        }
+    }
+}
 
-/// Reads points and CSV records from a file in a single pass
+/// Points read from the input files with the records they came from, see
+/// [`read_input`]
+struct Input {
+    points: PointList,
+    csv_records: CsvRecords,
+    /// Input file of every record
+    sources: Vec<usize>,
+    skipped: Vec<SkippedRow>,
+    coord_columns: [usize; 2],
+    /// Time of every point with `--time-col`
+    times: Option<Vec<i64>>,
+    /// Number of input points every point stands for with `--dedup-input weighted`
+    dedup_weights: Option<Vec<f64>>,
+}
+
+/// Clusters of the input points, see [`cluster_points`]
+struct Clustering {
+    clusters: Vec<Cluster>,
+    noise: Vec<usize>,
+    /// Name of every cluster with `--regions`, empty otherwise
+    names: Vec<String>,
+    /// Points outside of all regions, left out of the output
+    dropped: Vec<usize>,
+    /// Neighbour counts of the noise points with `--debug`
+    noise_info: Option<Vec<NoiseInfo>>,
+}
+
+/// Points picked for the output and what is written about them, see
+/// [`write_output`]
+struct Selected {
+    filtered_indices: Vec<usize>,
+    labels: Vec<i32>,
+    /// All points in `--output-crs`, kept ones of `--keep centroid` moved
+    output_points: PointList,
+    /// Clusters and noise of the new batch, see [`without_base_points`]
+    clusters: Vec<Cluster>,
+    noise: Vec<usize>,
+    /// Name of every cluster with `--regions`, empty otherwise
+    names: Vec<String>,
+    label_format: LabelFormat,
+    /// Number of columns appended to the records
+    extra_columns: usize,
+}
+
+fn main() {
+    let mut args = Args::parse();
+    configure(&mut args);
+
+    if args.benchmark {
+        let config = DbscanConfig::new(args.eps, args.min_points)
+            .min_points_excludes_self(args.min_points_excludes_self)
+            .index(args.index.into())
+            .metric(args.metric.into())
+            .earth_radius(args.earth_radius);
+        benchmark::print_benchmark(&config);
+        return;
+    }
+
+    let mut input = read_input(&args);
+
+    // The k-distance curve replaces clustering, for picking eps by hand
+    if let Some(k) = args.kdist {
+        write_k_distance_curve(&args, &input.points, k as usize);
+        return;
+    }
+
+    // Catch obviously misconfigured min_points before clustering, weighted
+    // rows may stand for more points than there are
+    if args.weight_col.is_none() && input.dedup_weights.is_none() {
+        for warning in check_min_points(args.min_points, input.points.len()) {
+            warn(&warning, args.strict);
+        }
+    }
+
+    if args.auto_eps {
+        let (radius_scale, unit) = distance_unit(&args);
+        let core_min_points = args.min_points + usize::from(args.min_points_excludes_self);
+        let metric = DistanceMetric::from(args.metric);
+        args.eps = estimate_eps_with_metric(&input.points, core_min_points, metric) * radius_scale;
+        if args.debug {
+            println!(
+                "Estimated eps={:.4} {} from the {}-distance knee",
+                args.eps, unit, core_min_points
+            );
+        }
+    }
+
+    if args.algorithm == AlgorithmArg::Optics {
+        write_optics(&args, &input.points);
+        return;
+    }
+
+    let clustering = cluster_points(&args, &input);
+    let clustering = post_process(&args, &input.points, clustering);
+
+    if args.stats {
+        print_cluster_stats(
+            &clustering.clusters,
+            &clustering.noise,
+            &input.points,
+            args.quality_sample,
+        );
+        return;
+    }
+
+    write_summaries(&args, &clustering.clusters, &input.points);
+    write_output(&args, &mut input, clustering);
+}
+
+/// Checks the command line before reading any input
 ///
-/// Expected format: `latitude,longitude` (header row is optional)
+/// Rejects parameters that would cluster garbage and options that can't be
+/// combined, sets up the thread pool and puts `--base` before the inputs.
+fn configure(args: &mut Args) {
+    if args.exact_distance {
+        args.metric = MetricArg::Haversine;
+    }
+
+    // Reject parameters that would cluster garbage before reading any input
+    let config = DbscanConfig::new(args.eps, args.min_points).earth_radius(args.earth_radius);
+    if let Err(e) = config.validate() {
+        eprintln!("Error: invalid parameters: {}", e);
+        std::process::exit(1);
+    }
+
+    #[cfg(feature = "rayon")]
+    if let Some(jobs) = args.jobs {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(jobs as usize);
+        if let Err(e) = pool.build_global() {
+            eprintln!("Error: can't start {} threads: {}", jobs, e);
+            std::process::exit(1);
+        }
+    }
+
+    // The benchmark reads no input, options about it don't matter
+    if args.benchmark {
+        return;
+    }
+
+    let conflicts = streaming_conflicts(args);
+    if !conflicts.is_empty() {
+        eprintln!(
+            "Error: --streaming can't be combined with {}",
+            conflicts.join(", ")
+        );
+        std::process::exit(1);
+    }
+
+    let conflicts = planar_conflicts(args);
+    if !conflicts.is_empty() {
+        eprintln!(
+            "Error: --metric manhattan or chebyshev can't be combined with {}",
+            conflicts.join(", ")
+        );
+        std::process::exit(1);
+    }
+
+    // The base file is read first, so its points come before the new batch
+    if let Some(base) = &args.base {
+        args.input.insert(0, base.clone());
+    }
+
+    // Plain and GeoJSON input is WGS84 by definition
+    if args.input_crs != Crs::Wgs84 {
+        if !matches!(args.input_format, InputFormat::Csv) {
+            eprintln!("Error: --input-crs only applies to --input-format csv");
+            std::process::exit(1);
+        }
+        if DistanceMetric::from(args.metric).is_planar() {
+            eprintln!("Error: --input-crs can't be combined with --metric manhattan or chebyshev");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Returns the factor from distances on a sphere of [`EARTH_R`] to `--earth-radius`
+/// and the unit of eps
 ///
-/// # Returns
+/// Geographic distances are measured on a sphere of EARTH_R, keep the angle.
+/// Planar ones are in coordinate units.
+fn distance_unit(args: &Args) -> (f64, &'static str) {
+    if DistanceMetric::from(args.metric).is_planar() {
+        (1.0, "units")
+    } else {
+        (args.earth_radius / EARTH_R, "km")
+    }
+}
+
+/// Reads the points of all input files and drops the ones that can't or
+/// shouldn't be clustered
 ///
-/// A tuple `(points, records)` where:
-/// - `points` are parsed points for clustering
-/// - `records` are raw CSV records for output preservation
-fn read_points_and_csv(
-    filename: &PathBuf,
-) -> Result<(PointList, CsvRecords), Box<dyn std::error::Error>> {
-    let file = File::open(filename)?;
-    let mut reader = ReaderBuilder::new().has_headers(false).from_reader(file);
+/// Rows with invalid coordinates or times and points outside `--bbox` are
+/// skipped, duplicates collapsed with `--dedup-input`. Exits when no point is
+/// left.
+fn read_input(args: &Args) -> Input {
+    let (mut points, mut csv_records, sources, mut skipped, coord_columns, input_kind) =
+        read_points(args);
+
+    if let Some(warning) = check_skipped_rows(&skipped) {
+        warn(&warning, args.strict);
+    }
+    unproject_points(&mut points, args.input_crs);
 
-    let mut points = PointList::new();
-    let mut records = Vec::new();
+    // Points without a time can't be gated, they are skipped like invalid coordinates
+    let times = args.time_col.as_ref().map(|column| {
+        let col = resolve_column(&csv_records, column).unwrap_or_else(|e| {
+            eprintln!("Error selecting time column: {}", e);
+            std::process::exit(1);
+        });
+        let (times, unparseable) = parse_times(&csv_records, &mut points, &mut skipped, col);
+        if let Some(warning) = check_skipped_times(&unparseable) {
+            warn(&warning, args.strict);
+        }
+        times
+    });
 
-    // Read all records first
-    for result in reader.records() {
-        let record = result?;
-        let record_vec: Vec<String> = record.iter().map(|s| s.to_string()).collect();
-        records.push(record_vec);
+    // Points outside the box drop out like skipped rows
+    if let Some(bbox) = args.bbox {
+        let rows = point_records(&csv_records, &skipped);
+        let kept = points_in_bbox(&points, bbox);
+        if kept.is_empty() && !points.is_empty() {
+            eprintln!("No points found inside --bbox");
+            std::process::exit(1);
+        }
+        let removed = points.len() - kept.len();
+        collapse_points(&mut points, &mut skipped, &rows, &kept, "outside bbox");
+        if args.debug {
+            println!("Dropped {} points outside --bbox", removed);
+        }
     }
 
-    if records.is_empty() {
-        return Ok((points, records));
+    if points.is_empty() {
+        eprintln!("No points found in {} file", input_kind);
+        std::process::exit(1);
     }
 
-    // Determine if first row is header
-    let has_header = records[0][0].parse::<f64>().is_err();
+    // Collapsed points drop out like skipped rows, so the remaining points
+    // still map to their records
+    let dedup_weights = args.dedup_input.and_then(|mode| {
+        if times.is_some() {
+            eprintln!("Error: --dedup-input can't be combined with --time-col");
+            std::process::exit(1);
+        }
+        let rows = point_records(&csv_records, &skipped);
+        let row_weights = args
+            .weight_col
+            .map(|col| read_column_values(&csv_records, &rows, col, args.decimal_comma, 1.0));
+        let (kept, weights) = dedup_points(&points, row_weights.as_deref());
+        let removed = points.len() - kept.len();
+        collapse_points(&mut points, &mut skipped, &rows, &kept, "duplicate");
+        if args.debug {
+            println!("Collapsed {} duplicate points", removed);
+        }
+        (mode == DedupArg::Weighted).then_some(weights)
+    });
+
+    // Ragged rows would misalign columns in the output
+    if let Some(warning) = check_column_counts(&csv_records) {
+        warn(&warning, args.strict);
+    }
+
+    if args.preserve_order_index {
+        add_order_index(&mut csv_records);
+    }
+
+    if args.emit_source {
+        add_source_column(&mut csv_records, &sources, &args.input);
+    }
+
+    Input {
+        points,
+        csv_records,
+        sources,
+        skipped,
+        coord_columns,
+        times,
+        dedup_weights,
+    }
+}
 
-    // Parse points from records
-    let start_idx = if has_header { 1 } else { 0 };
+/// Points, records, record sources, skipped rows and coordinate columns read
+/// by [`read_points`], with the kind of input for messages
+type ReadPoints = (
+    PointList,
+    CsvRecords,
+    Vec<usize>,
+    Vec<SkippedRow>,
+    [usize; 2],
+    &'static str,
+);
 
-    for record in records.iter().skip(start_idx) {
-        if record.len() < 2 {
-            continue;
+/// Reads the records of all input files in `--input-format` and parses their
+/// coordinates
+fn read_points(args: &Args) -> ReadPoints {
+    // Projected coordinates aren't range checked like latitude/longitude
+    let geographic = !DistanceMetric::from(args.metric).is_planar() && args.input_crs == Crs::Wgs84;
+
+    let [lat_default, lon_default] = CoordOrder::from(args.coord_order)
+        .columns()
+        .map(ColumnRef::Index);
+    let lat_col = args.lat_col.clone().unwrap_or(lat_default);
+    let lon_col = args.lon_col.clone().unwrap_or(lon_default);
+
+    if args.streaming {
+        let streamed = stream_points(
+            &args.input[0],
+            args.delimiter,
+            [&lat_col, &lon_col],
+            args.fill_missing,
+            args.decimal_comma,
+            geographic,
+        );
+        return match streamed {
+            Ok((points, skipped, columns)) => {
+                (points, Vec::new(), Vec::new(), skipped, columns, "CSV")
+            }
+            Err(e) => {
+                eprintln!("Error reading CSV: {}", e);
+                std::process::exit(1);
+            }
+        };
+    }
+
+    // Read points and CSV records from file (read once, reuse for output)
+    let (read, input_kind) = match args.input_format {
+        InputFormat::Csv => (
+            read_csv_files(&args.input, args.delimiter).map(|(r, s)| (None, r, s)),
+            "CSV",
+        ),
+        InputFormat::Plain => (
+            read_plain_files(&args.input, args.coord_order.into())
+                .map(|(points, r, s)| (Some(points), r, s)),
+            "points",
+        ),
+        InputFormat::Geojson => (
+            read_geojson_files(&args.input).map(|(points, r, s, skipped)| {
+                if let Some(warning) = check_skipped_features(&skipped) {
+                    warn(&warning, args.strict);
+                }
+                (Some(points), r, s)
+            }),
+            "GeoJSON",
+        ),
+    };
+    let (plain_points, mut csv_records, mut sources) = match read {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("Error reading {}: {}", input_kind, e);
+            std::process::exit(1);
         }
+    };
 
-        let lat = record[0].parse::<f64>();
-        let lon = record[1].parse::<f64>();
-        if let (Ok(lat), Ok(lon)) = (lat, lon) {
-            // Point is [2]float64 where [0]=Lon, [1]=Lat
-            points.push(Point([lon, lat]));
+    // Schema names have to be in place before columns are looked up by name
+    if let Some(schema_file) = &args.schema {
+        let result = std::fs::read_to_string(schema_file)
+            .map_err(|e| e.to_string())
+            .and_then(|schema| apply_schema(&mut csv_records, &mut [], &schema, args.delimiter));
+        if let Err(e) = result {
+            eprintln!("Error applying schema: {}", e);
+            std::process::exit(1);
         }
+        // The inserted header row comes from the first file
+        sources.insert(0, 0);
     }
 
-    Ok((points, records))
+    // Plain input records hold the coordinates in `--coord-order`,
+    // GeoJSON ones start with `latitude,longitude`
+    let coord_columns = match (plain_points.is_some(), args.input_format) {
+        (true, InputFormat::Plain) => Ok(CoordOrder::from(args.coord_order).columns()),
+        (true, _) => Ok([0, 1]),
+        (false, _) => resolve_columns(&csv_records, [&lat_col, &lon_col]),
+    };
+    let coord_columns = coord_columns.unwrap_or_else(|e| {
+        eprintln!("Error selecting coordinate columns: {}", e);
+        std::process::exit(1);
+    });
+    let (points, skipped) = match plain_points {
+        Some(points) => (points, Vec::new()),
+        None => parse_points(
+            &csv_records,
+            coord_columns,
+            args.fill_missing,
+            args.decimal_comma,
+            geographic,
+        ),
+    };
+    (
+        points,
+        csv_records,
+        sources,
+        skipped,
+        coord_columns,
+        input_kind,
+    )
 }
 
-/// Filters points based on the filtering logic:
-/// - Keep outliers (label == -1)
-/// - Keep first point in each cluster (idx == 0 or label != labels[idx-1])
+/// Writes the k-distance curve of `--kdist` instead of clustering
+fn write_k_distance_curve(args: &Args, points: &PointList, k: usize) {
+    let (radius_scale, unit) = distance_unit(args);
+    let k_dist: Vec<f64> = k_distances_with_metric(points, k, args.metric.into())
+        .into_iter()
+        .map(|d| d * radius_scale)
+        .collect();
+    let result = open_output(args.output.as_ref()).and_then(|mut out| {
+        write_k_distances(&mut out, &k_dist, unit, args.coord_digits.0)?;
+        out.flush()
+    });
+    if let Err(e) = result {
+        eprintln!("Error writing k-distances: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Writes the OPTICS reachability plot of `--algorithm optics` instead of
+/// clustering
+fn write_optics(args: &Args, points: &PointList) {
+    let (radius_scale, unit) = distance_unit(args);
+    if args.debug {
+        println!(
+            "Running OPTICS with eps={:.4} {}, minPoints={}",
+            args.eps, unit, args.min_points
+        );
+    }
+    let ordering: Vec<(usize, f64, f64)> = optics_with_metric(
+        points,
+        args.eps / radius_scale,
+        args.min_points,
+        args.metric.into(),
+    )
+    .into_iter()
+    .map(|(i, reach, core_dist)| (i, reach * radius_scale, core_dist * radius_scale))
+    .collect();
+    let result = open_output(args.output.as_ref()).and_then(|mut out| {
+        write_reachability(&mut out, &ordering, args.coord_digits.0)?;
+        out.flush()
+    });
+    if let Err(e) = result {
+        eprintln!("Error writing reachability plot: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Runs DBSCAN over the input points, within `--regions` when given
 ///
-/// Tracks added points by their coordinates to avoid duplicates
-fn filter_points(points: &PointList, labels: &[i32]) -> Vec<usize> {
-    let mut filtered = Vec::new();
-    let mut added = Vec::new(); // Track already added points by coordinates
+/// Re-runs with the haversine metric for `--retry-metric` and warns about
+/// timed out and degenerate results.
+fn cluster_points(args: &Args, input: &Input) -> Clustering {
+    let Input {
+        points,
+        csv_records,
+        skipped,
+        times,
+        dedup_weights,
+        ..
+    } = input;
 
-    for (idx, &label) in labels.iter().enumerate() {
-        let point = points[idx];
+    // Debug output (only if debug flag is set)
+    let (radius_scale, unit) = distance_unit(args);
+    if args.debug {
+        println!("Read {} points from {:?}", points.len(), args.input);
+        println!(
+            "Running DBSCAN with eps={:.4} {}, minPoints={}",
+            args.eps, unit, args.min_points
+        );
+    }
 
-        // Skip if point with same coordinates already added
-        if added.contains(&point) {
-            continue;
+    let index = IndexKind::from(args.index).resolve(points);
+    if args.debug {
+        println!("Using {} index", index);
+        if index == IndexKind::KdTree {
+            let tree = new_kd_tree_with_metric(points.clone(), args.metric.into());
+            for line in kd_tree_diagnostics(&tree) {
+                println!("{}", line);
+            }
         }
 
-        // Keep if it's an outlier
-        if label == DBSCAN_OUTLIER_INDEX {
-            filtered.push(idx);
-            added.push(point);
-            continue;
+        let extent = points_extent(points, args.metric.into()) * radius_scale;
+        if args.eps > extent && points.len() >= args.min_points {
+            eprintln!(
+                "Warning: eps ({} {}) exceeds the extent of all points ({:.4} {}), \
+                 all points will form a single cluster",
+                args.eps, unit, extent, unit
+            );
         }
+    }
 
-        // Keep if it's the first point (idx == 0)
-        if idx == 0 {
-            filtered.push(idx);
-            added.push(point);
-            continue;
+    let regions = args.regions.as_ref().map(|regions_file| {
+        match std::fs::read_to_string(regions_file)
+            .map_err(|e| e.to_string())
+            .and_then(|json| parse_regions_geojson(&json))
+        {
+            Ok(regions) => regions,
+            Err(e) => {
+                eprintln!("Error reading regions: {}", e);
+                std::process::exit(1);
+            }
         }
+    });
+    if let (true, Some(regions)) = (args.debug, &regions) {
+        println!("Clustering within {} regions", regions.len());
+    }
 
-        // Keep if it's the first point in a cluster (label != previous label)
-        if label != labels[idx - 1] {
-            filtered.push(idx);
-            added.push(point);
+    // Run DBSCAN clustering
+    let mut config = dbscan_config(args, index);
+    let accuracy = args.accuracy_col.map(|col| {
+        if regions.is_some() {
+            eprintln!("Error: --accuracy-col can't be combined with --regions");
+            std::process::exit(1);
         }
+        let rows = point_records(csv_records, skipped);
+        read_column_values(csv_records, &rows, col, args.decimal_comma, 0.0)
+    });
+    if times.is_some() && (regions.is_some() || accuracy.is_some()) {
+        eprintln!("Error: --time-col can't be combined with --regions or --accuracy-col");
+        std::process::exit(1);
     }
+    let weights = dedup_weights.clone().or_else(|| {
+        args.weight_col.map(|col| {
+            let rows = point_records(csv_records, skipped);
+            read_column_values(csv_records, &rows, col, args.decimal_comma, 1.0)
+        })
+    });
+    if weights.is_some() && accuracy.is_some() {
+        let option = match args.weight_col {
+            Some(_) => "--weight-col",
+            None => "--dedup-input weighted",
+        };
+        eprintln!("Error: {} can't be combined with --accuracy-col", option);
+        std::process::exit(1);
+    }
+    // Neighbour counts of noise points come with the debug output's progress
+    let run = |config: &DbscanConfig| match &regions {
+        None => {
+            let config = match &weights {
+                Some(weights) => config.weights(weights),
+                None => *config,
+            };
+            let mut noise_info = None;
+            let (clusters, noise, timed_out) = match (&accuracy, times) {
+                (Some(accuracy), _) => {
+                    config.run_with_accuracy(points, accuracy, args.accuracy_mode.into())
+                }
+                (None, Some(times)) => {
+                    config.run_with_times(points, times, args.time_eps.unwrap_or_default())
+                }
+                (None, None) if args.debug => {
+                    let (clusters, info, timed_out) =
+                        config.run_with_diagnostics_and_progress(points, print_progress);
+                    let noise = info.iter().map(|n| n.index).collect();
+                    noise_info = Some(info);
+                    (clusters, noise, timed_out)
+                }
+                (None, None) => config.run_timed(points),
+            };
+            let result = RegionClusters {
+                clusters,
+                noise,
+                timed_out,
+                ..Default::default()
+            };
+            (result, noise_info)
+        }
+        Some(regions) => {
+            let result = db_scan_in_regions(
+                points,
+                weights.as_deref(),
+                regions,
+                config,
+                args.outside_regions.into(),
+            );
+            (result, None)
+        }
+    };
+    let (mut result, mut noise_info) = run(&config);
 
-    filtered
+    // The fast metric's flat-earth approximation can merge wide-area data into one cluster
+    if args.retry_metric
+        && config.metric == DistanceMetric::SphericalFast
+        && largest_cluster_fraction(&result.clusters, points.len()) > args.retry_threshold
+    {
+        eprintln!(
+            "Warning: one cluster holds more than {:.0}% of points, re-running with the haversine metric",
+            args.retry_threshold * 100.0
+        );
+        config = config.metric(DistanceMetric::Haversine);
+        (result, noise_info) = run(&config);
+    }
+
+    let RegionClusters {
+        clusters,
+        noise,
+        names,
+        dropped,
+        timed_out,
+    } = result;
+
+    if timed_out {
+        eprintln!(
+            "Warning: clustering timed out after {}s, results are incomplete: unprocessed points are reported as noise",
+            args.timeout_secs.unwrap_or_default()
+        );
+    } else if let Some(diagnostic) = check_degenerate(&clusters, points.len() - dropped.len()) {
+        // Degenerate results are valid, so this never fails with --strict
+        eprintln!("Warning: {}", diagnostic);
+    }
+
+    Clustering {
+        clusters,
+        noise,
+        names,
+        dropped,
+        noise_info,
+    }
 }
 
-/// Creates a labels array from clusters and noise
-///
-/// `labels[i]` = cluster ID for point i, or -1 for noise
-fn build_labels(clusters: &[Cluster], _noise: &[usize], num_points: usize) -> Vec<i32> {
-    let mut labels = vec![-1; num_points];
+/// Returns the DBSCAN parameters of the command line, querying `index`
+fn dbscan_config(args: &Args, index: IndexKind) -> DbscanConfig<'static> {
+    let mut config = DbscanConfig::new(args.eps, args.min_points)
+        .min_points_excludes_self(args.min_points_excludes_self)
+        .index(index)
+        .metric(args.metric.into())
+        .earth_radius(args.earth_radius)
+        .seed_order(args.seed_order.into());
+    if args.stable_ids {
+        config = config.stable_ids(StableBy::Centroid);
+    }
+    if let Some(secs) = args.timeout_secs {
+        match Duration::try_from_secs_f64(secs) {
+            Ok(timeout) => config = config.deadline(Instant::now() + timeout),
+            Err(e) => {
+                eprintln!("Error parsing --timeout-secs: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    config
+}
 
-    // Mark cluster points
-    for cluster in clusters {
-        for &idx in &cluster.points {
-            labels[idx] = cluster.c as i32;
+/// Merges, drops and splits clusters as asked for by `--merge-distance`,
+/// `--min-cluster-size` and `--max-cluster-size`
+fn post_process(args: &Args, points: &PointList, clustering: Clustering) -> Clustering {
+    let Clustering {
+        mut clusters,
+        mut noise,
+        mut names,
+        dropped,
+        noise_info,
+    } = clustering;
+
+    if let Some(distance) = args.merge_distance {
+        // Centroids are compared on a sphere of EARTH_R, keep the angle
+        let distance_km = distance * EARTH_R / args.earth_radius;
+        let before = clusters.len();
+        (clusters, names) = merge_clusters_named(clusters, &names, points, distance_km);
+        if args.debug && clusters.len() != before {
+            println!(
+                "Merged {} clusters with centroids within {} km into {} clusters",
+                before,
+                distance,
+                clusters.len()
+            );
         }
     }
 
-    // Noise points are already -1, but we verify they're in the noise list
-    // (they should already be -1 from initialization)
+    if let Some(min_size) = args.min_cluster_size {
+        let before = clusters.len();
+        (clusters, noise, names) = drop_small_clusters(clusters, noise, &names, min_size);
+        if args.debug && clusters.len() != before {
+            println!(
+                "Reclassified {} clusters smaller than {} points as noise",
+                before - clusters.len(),
+                min_size
+            );
+        }
+    }
+
+    if let Some(max_size) = args.max_cluster_size {
+        // Grid cells are sized on a sphere of EARTH_R, keep the angle
+        let cell_km = args.split_cell_km.unwrap_or(args.eps) * EARTH_R / args.earth_radius;
+        let before = clusters.len();
+        (clusters, names) = split_oversized_clusters(clusters, &names, points, max_size, cell_km);
+        if args.debug && clusters.len() != before {
+            println!(
+                "Split clusters larger than {} points into {} clusters",
+                max_size,
+                clusters.len()
+            );
+        }
+    }
+
+    if args.debug {
+        println!("Found {} clusters", clusters.len());
+        println!("Found {} noise points", noise.len());
+
+        // How close noise points came to being core points
+        for line in noise_info
+            .iter()
+            .flat_map(|info| noise_histogram(info, args.min_points))
+        {
+            println!("{}", line);
+        }
+    }
 
-    labels
+    Clustering {
+        clusters,
+        noise,
+        names,
+        dropped,
+        noise_info,
+    }
+}
+
+/// Writes the cluster summaries of `--summary` and `--summary-json`
+fn write_summaries(args: &Args, clusters: &[Cluster], points: &PointList) {
+    if let Some(summary_file) = &args.summary {
+        let result = write_atomically(summary_file, |out| {
+            write_cluster_summary(
+                out,
+                clusters,
+                points,
+                args.delimiter,
+                args.coord_order.into(),
+                args.coord_digits.0,
+            )
+        });
+        if let Err(e) = result {
+            eprintln!("Error writing cluster summary: {}", e);
+            std::process::exit(1);
+        }
+        if args.debug {
+            println!("Cluster summary written to {:?}", summary_file);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    if let Some(summary_file) = &args.summary_json {
+        let result = write_atomically(summary_file, |out| {
+            write_cluster_summary_json(out, clusters, points, args.coord_digits.0)
+                .map_err(Into::into)
+        });
+        if let Err(e) = result {
+            eprintln!("Error writing JSON cluster summary: {}", e);
+            std::process::exit(1);
+        }
+        if args.debug {
+            println!("JSON cluster summary written to {:?}", summary_file);
+        }
+    }
 }
 
-/// Writes filtered points to output CSV
+/// Filters the points and writes them in `--format`
 ///
-/// Uses pre-read CSV records to preserve any additional columns
-fn write_filtered_points_to_csv(
-    output_file: &PathBuf,
-    csv_records: &[Vec<String>],
-    filtered_indices: &[usize],
-) -> Result<(), Box<dyn std::error::Error>> {
-    // Create a set of filtered indices for quick lookup
-    let filtered_set: std::collections::HashSet<usize> = filtered_indices.iter().copied().collect();
-
-    // Write filtered records to output
-    let out_file = File::create(output_file)?;
-    let mut writer = WriterBuilder::new().from_writer(out_file);
-
-    // Determine if first row is header
-    let has_header = if !csv_records.is_empty() {
-        csv_records[0][0].parse::<f64>().is_err()
+/// Records get the extra columns of `--aggregate`, `--emit-labels` and
+/// `--emit-color` and the coordinates of `--keep centroid` and `--output-crs`
+/// first.
+fn write_output(args: &Args, input: &mut Input, clustering: Clustering) {
+    let Input {
+        points,
+        csv_records,
+        sources,
+        skipped,
+        coord_columns,
+        ..
+    } = input;
+    let coord_columns = *coord_columns;
+    let Clustering {
+        clusters,
+        noise,
+        names: cluster_names,
+        dropped,
+        ..
+    } = clustering;
+
+    // Build labels array from clusters for filtering
+    let labels = build_labels(&clusters, points.len());
+
+    // Filter points based on:
+    // 1. Keep outliers (label == -1)
+    // 2. Keep first point in each cluster (idx == 0 or label != labels[idx-1]),
+    //    one point per cluster or all points, see --keep
+    let keep = if args.noise_only {
+        Keep::Noise
     } else {
-        false
+        args.keep.into()
+    };
+    // Base points only shape the clusters, the new batch is filtered on its own
+    let base_points = match args.base {
+        Some(_) => base_point_count(sources, &point_records(csv_records, skipped)),
+        None => 0,
     };
+    let mut filtered_indices = filter_new_points(points, &labels, keep, base_points);
 
-    if has_header {
-        // Write header
-        writer.write_record(&csv_records[0])?;
+    // Points outside of all regions are left out of the output entirely
+    if !dropped.is_empty() {
+        if args.debug {
+            println!("Dropped {} points outside of all regions", dropped.len());
+        }
+        let dropped: std::collections::HashSet<usize> = dropped.into_iter().collect();
+        filtered_indices.retain(|i| !dropped.contains(i));
     }
 
-    // Write filtered data rows
-    let start_idx = if has_header { 1 } else { 0 };
+    if args.debug {
+        println!("Filtered to {} points", filtered_indices.len());
+    }
 
-    for (i, record) in csv_records.iter().enumerate().skip(start_idx) {
-        let point_idx = i - start_idx;
-        if filtered_set.contains(&point_idx) {
-            writer.write_record(record)?;
+    // Kept points of --keep centroid move to their cluster's centroid, the
+    // multipoint output still shows all members where they are
+    let kept_points = match (keep, args.format) {
+        (Keep::Centroid, OutputFormat::ClustersMultipoint | OutputFormat::Wkt)
+        | (Keep::First | Keep::All | Keep::Noise, _) => Cow::Borrowed(&*points),
+        (Keep::Centroid, _) => {
+            let moved = centroid_points(points, &clusters, &labels, &filtered_indices);
+            let rows = point_records(csv_records, skipped);
+            let (moved_rows, moved_points): (Vec<usize>, PointList) = filtered_indices
+                .iter()
+                .filter(|&&i| labels[i] != DBSCAN_OUTLIER_INDEX && i < rows.len())
+                .map(|&i| (rows[i], moved[i]))
+                .unzip();
+            set_record_coords(
+                csv_records,
+                &moved_rows,
+                &moved_points,
+                coord_columns,
+                args.coord_digits.0,
+            );
+            Cow::Owned(moved)
         }
+    };
+
+    // Cluster geometries are left out with --noise-only, only noise remains
+    let written_clusters: &[Cluster] = if args.noise_only { &[] } else { &clusters };
+    let (written_clusters, written_noise) =
+        without_base_points(written_clusters, &noise, base_points);
+
+    // Clustering is done in WGS84, only written coordinates are reprojected
+    let crs = Crs::from(args.output_crs);
+    let output_points: PointList = kept_points.iter().map(|p| crs.project(p)).collect();
+    if crs != Crs::Wgs84 {
+        let rows = point_records(csv_records, skipped);
+        reproject_records(
+            csv_records,
+            &rows,
+            &output_points,
+            coord_columns,
+            args.coord_digits.0,
+        );
     }
 
-    writer.flush()?;
-    Ok(())
-}
+    let mut aggregate_columns = 0;
+    if args.aggregate {
+        let rows = point_records(csv_records, skipped);
+        aggregate_columns = add_aggregate_columns(
+            csv_records,
+            &rows,
+            &clusters,
+            points,
+            args.earth_radius,
+            args.coord_digits.0,
+        );
+    }
 
-/// Writes filtered points to stdout as a simple list
-///
-/// Format: `latitude,longitude` (one point per line)
-///
-/// Uses pre-read CSV records to preserve order
-fn write_filtered_points_to_stdout(
-    csv_records: &[Vec<String>],
-    filtered_indices: &[usize],
-) -> Result<(), Box<dyn std::error::Error>> {
-    // Create a set of filtered indices for quick lookup
-    let filtered_set: std::collections::HashSet<usize> = filtered_indices.iter().copied().collect();
-
-    // Determine if first row is header
-    let has_header = if !csv_records.is_empty() {
-        csv_records[0][0].parse::<f64>().is_err()
-    } else {
-        false
+    // JSON outputs carry the cluster id of every point anyway
+    let label_format = LabelFormat::new(args.noise_label.as_str());
+    let mut label_columns = 0;
+    if args.emit_labels && matches!(args.format, OutputFormat::Csv) {
+        let rows = point_records(csv_records, skipped);
+        add_label_column(csv_records, &rows, &labels, &label_format);
+        label_columns = 1;
+    }
+    if args.emit_color && matches!(args.format, OutputFormat::Csv) {
+        let rows = point_records(csv_records, skipped);
+        add_color_column(csv_records, &rows, &labels);
+        label_columns += 1;
+    }
+
+    let selected = Selected {
+        filtered_indices,
+        labels,
+        output_points,
+        clusters: written_clusters,
+        noise: written_noise,
+        names: cluster_names,
+        label_format,
+        extra_columns: usize::from(args.preserve_order_index)
+            + usize::from(args.emit_source)
+            + aggregate_columns
+            + label_columns,
     };
+    write_points(args, input, &selected);
+}
 
-    // Write filtered points to stdout
-    let start_idx = if has_header { 1 } else { 0 };
+/// Writes the selected points in `--format`
+fn write_points(args: &Args, input: &Input, selected: &Selected) {
+    let Input {
+        csv_records,
+        skipped,
+        coord_columns,
+        ..
+    } = input;
+    let coord_columns = *coord_columns;
+    let Selected {
+        filtered_indices,
+        labels,
+        output_points,
+        clusters: written_clusters,
+        noise: written_noise,
+        names: cluster_names,
+        label_format,
+        extra_columns,
+    } = selected;
 
-    for (i, record) in csv_records.iter().enumerate().skip(start_idx) {
-        let point_idx = i - start_idx;
-        if filtered_set.contains(&point_idx) {
-            // Output as: latitude,longitude
-            if record.len() >= 2 {
-                println!("{},{}", record[0], record[1]);
+    match args.format {
+        OutputFormat::Csv => {
+            // Write filtered points to output (stdout or file)
+            match &args.output {
+                _ if args.streaming => {
+                    let result = stream_filtered_points(
+                        &args.input[0],
+                        args.output.as_deref(),
+                        args.delimiter,
+                        skipped,
+                        filtered_indices,
+                        coord_columns,
+                    );
+                    if let Err(e) = result {
+                        eprintln!("Error writing CSV: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+                None => {
+                    // Output to stdout as simple list of points
+                    if let Err(e) = write_filtered_points_to_stdout(
+                        csv_records,
+                        &data_rows(csv_records, skipped, filtered_indices),
+                        CoordOrder::from(args.coord_order).arrange(coord_columns),
+                        *extra_columns,
+                        args.delimiter,
+                    ) {
+                        eprintln!("Error writing to stdout: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+                Some(output_file) => {
+                    // Write filtered points to output CSV file
+                    if let Err(e) = write_filtered_points_to_csv(
+                        output_file,
+                        csv_records,
+                        &data_rows(csv_records, skipped, filtered_indices),
+                        args.delimiter,
+                    ) {
+                        eprintln!("Error writing CSV: {}", e);
+                        std::process::exit(1);
+                    }
+                    if args.debug {
+                        println!("Filtered points written to {:?}", output_file);
+                    }
+                }
+            }
+        }
+        OutputFormat::ClustersMultipoint => {
+            let result = open_output(args.output.as_ref()).and_then(|mut out| {
+                let labels = FeatureLabels {
+                    ids: &[],
+                    names: cluster_names,
+                    format: label_format,
+                };
+                write_clusters_multipoint(
+                    &mut out,
+                    written_clusters,
+                    written_noise,
+                    output_points,
+                    &labels,
+                    args.coord_digits.0,
+                )?;
+                out.flush()
+            });
+            if let Err(e) = result {
+                eprintln!("Error writing GeoJSON: {}", e);
+                std::process::exit(1);
+            }
+        }
+        OutputFormat::Wkt => {
+            let result = open_output(args.output.as_ref()).and_then(|mut out| {
+                write_wkt(
+                    &mut out,
+                    written_clusters,
+                    written_noise,
+                    output_points,
+                    args.wkt_hull,
+                    args.coord_digits.0,
+                )?;
+                out.flush()
+            });
+            if let Err(e) = result {
+                eprintln!("Error writing WKT: {}", e);
+                std::process::exit(1);
+            }
+        }
+        OutputFormat::Jsonl => {
+            let result = open_output(args.output.as_ref()).and_then(|mut out| {
+                write_jsonl(
+                    &mut out,
+                    filtered_indices,
+                    output_points,
+                    labels,
+                    label_format,
+                    args.coord_digits.0,
+                )?;
+                out.flush()
+            });
+            if let Err(e) = result {
+                eprintln!("Error writing JSON Lines: {}", e);
+                std::process::exit(1);
+            }
+        }
+        OutputFormat::Geojson => {
+            let rows = point_records(csv_records, skipped);
+            let digits = args.coord_digits.0;
+            let rounded: PointList = output_points
+                .iter()
+                .map(|p| Point(p.0.map(|v| output::round_coord(v, digits))))
+                .collect();
+            let labels = FeatureLabels {
+                ids: labels,
+                names: &[],
+                format: label_format,
+            };
+            let result = open_output(args.output.as_ref()).and_then(|mut out| {
+                write_points_geojson(
+                    &mut out,
+                    csv_records,
+                    &rows,
+                    filtered_indices,
+                    &rounded,
+                    &labels,
+                )?;
+                out.flush()
+            });
+            if let Err(e) = result {
+                eprintln!("Error writing GeoJSON: {}", e);
+                std::process::exit(1);
             }
         }
     }
+}
+
+/// Prints the `--debug` progress of clustering to stderr, on a single line
+fn print_progress(visited: usize, total: usize) {
+    eprint!(
+        "\rVisited {}/{} points ({}%)",
+        visited,
+        total,
+        visited * 100 / total
+    );
+    if visited == total {
+        eprintln!();
+    }
+}
 
-    Ok(())
+/// Prints a warning, or exits with an error when `strict` is set
+fn warn(message: &str, strict: bool) {
+    if strict {
+        eprintln!("Error: {}", message);
+        std::process::exit(1);
+    }
+    eprintln!("Warning: {}", message);
+}
+
+/// Prints the lines of [`cluster_stats`]
+fn print_cluster_stats(
+    clusters: &[Cluster],
+    noise: &[usize],
+    points: &PointList,
+    quality_sample: Option<usize>,
+) {
+    for line in cluster_stats(clusters, noise, points, quality_sample) {
+        println!("{}", line);
+    }
 }
@@ -1,7 +1,39 @@
 #[cfg(test)]
 mod tests {
-    use crate::cluster::{Point, PointList, db_scan};
-    use crate::{build_labels, filter_points, read_points_and_csv};
+    use crate::cluster::projection::Crs;
+    use crate::cluster::{
+        Cluster, DbscanConfig, DistanceMetric, EARTH_R, NoiseInfo, Point, PointList, db_scan,
+        new_kd_tree,
+    };
+    use crate::{
+        Args, OutputFormat, output, parse_bbox, parse_column, parse_coord_digits, parse_delimiter,
+        parse_lat_lon, planar_conflicts, streaming_conflicts,
+    };
+    use clap::Parser;
+    use csv::WriterBuilder;
+    use rust_dbscan::benchmark::{run_benchmark, synthetic_points};
+    use rust_dbscan::diagnostics::{
+        check_column_counts, check_degenerate, check_min_points, check_skipped_features,
+        check_skipped_rows, check_skipped_times, cluster_stats, kd_tree_diagnostics,
+        largest_cluster_fraction, noise_histogram, points_extent,
+    };
+    use rust_dbscan::filter::{
+        Keep, build_labels, centroid_points, drop_small_clusters, filter_new_points, filter_points,
+        merge_clusters_named, split_oversized_clusters, without_base_points,
+    };
+    use rust_dbscan::input::{
+        ColumnRef, CoordOrder, apply_schema, base_point_count, collapse_points, data_rows,
+        dedup_points, is_header, parse_points, parse_times, point_records, points_in_bbox,
+        read_column_values, read_csv_files, read_csv_records, read_geojson_files,
+        read_points_and_csv, read_points_plain, resolve_column, resolve_columns, stream_points,
+    };
+    use rust_dbscan::output::{
+        FeatureLabels, LabelFormat, add_aggregate_columns, add_color_column, add_label_column,
+        add_order_index, add_source_column, reproject_records, set_record_coords,
+        stream_filtered_points, write_atomically, write_cluster_summary, write_clusters_multipoint,
+        write_filtered_points_to_csv, write_jsonl, write_k_distances, write_points_geojson,
+        write_reachability, write_wkt,
+    };
     use std::fs;
     use std::path::PathBuf;
 
@@ -22,7 +54,8 @@ mod tests {
         fs::write(&test_file, test_csv).expect("Failed to create test CSV");
 
         // Read points
-        let (points, _) = read_points_and_csv(&test_file).expect("Failed to read CSV");
+        let (points, _, _) =
+            read_points_and_csv(&[&test_file], None, Crs::Wgs84).expect("Failed to read CSV");
 
         assert_eq!(points.len(), 8);
 
@@ -32,8 +65,8 @@ mod tests {
         assert!(!clusters.is_empty() || !noise.is_empty());
 
         // Build labels and test filtering
-        let labels = build_labels(&clusters, &noise, points.len());
-        let filtered_indices = filter_points(&points, &labels);
+        let labels = build_labels(&clusters, points.len());
+        let filtered_indices = filter_points(&points, &labels, Keep::First);
 
         // Verify filtering logic:
         // 1. All outliers should be included
@@ -83,7 +116,7 @@ mod tests {
                 .map(|i| Point([i as f64, i as f64]))
                 .collect();
 
-            let result = filter_points(&points, &labels);
+            let result = filter_points(&points, &labels, Keep::First);
             assert_eq!(result.len(), expected_count, "Test case: {}", name);
             for (i, &expected_idx) in expected_indices.iter().enumerate() {
                 if i < result.len() {
@@ -92,4 +125,1806 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_check_min_points() {
+        assert!(check_min_points(3, 50).is_empty());
+        assert!(check_min_points(25, 50).is_empty());
+
+        let warnings = check_min_points(26, 50);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("half"));
+
+        let warnings = check_min_points(1000, 50);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("every point will be noise"));
+    }
+
+    #[test]
+    fn test_points_extent() {
+        let metric = DistanceMetric::default();
+        assert_eq!(points_extent(&Vec::new(), metric), 0.0);
+        assert_eq!(points_extent(&vec![Point([30.0, 60.0])], metric), 0.0);
+
+        // One degree of latitude is ~111.2 km
+        let points = vec![Point([30.0, 59.5]), Point([30.0, 60.5])];
+        assert!((points_extent(&points, metric) - 111.19).abs() < 0.01);
+
+        // Planar metrics measure the diagonal in coordinate units
+        let points = vec![Point([0.0, 400.0]), Point([300.0, 0.0])];
+        assert_eq!(points_extent(&points, DistanceMetric::Manhattan), 700.0);
+        assert_eq!(points_extent(&points, DistanceMetric::Chebyshev), 400.0);
+    }
+
+    #[test]
+    fn test_order_index_survives_filtering() {
+        let test_csv = "latitude,longitude,name
+40.7128,-74.0060,a
+40.7128,-74.0060,a-duplicate
+40.7130,-74.0062,b
+40.7500,-73.9900,c
+40.7128,-74.0060,a-again
+41.0000,-74.0000,d";
+        let input_lines: Vec<&str> = test_csv.lines().skip(1).collect();
+
+        let test_file = PathBuf::from("test_points_rust_order_index.csv");
+        let out_file = PathBuf::from("test_points_rust_order_index_out.csv");
+        fs::write(&test_file, test_csv).expect("Failed to create test CSV");
+
+        let (points, mut records, _) =
+            read_points_and_csv(&[&test_file], None, Crs::Wgs84).expect("Failed to read CSV");
+        add_order_index(&mut records);
+        assert_eq!(records[0].last().unwrap(), "original_index");
+
+        let (clusters, _) = db_scan(&points, 0.1, 3);
+        let labels = build_labels(&clusters, points.len());
+        let filtered_indices = filter_points(&points, &labels, Keep::First);
+        // Duplicated coordinates are dropped, so the output has gaps
+        assert!(filtered_indices.len() < points.len());
+
+        write_filtered_points_to_csv(&out_file, &records, &filtered_indices, b',')
+            .expect("Failed to write CSV");
+        let output = fs::read_to_string(&out_file).expect("Failed to read output");
+
+        let mut lines = output.lines();
+        assert_eq!(lines.next(), Some("latitude,longitude,name,original_index"));
+        for line in lines {
+            let (original, index) = line.rsplit_once(',').unwrap();
+            let index: usize = index.parse().unwrap();
+            assert_eq!(original, input_lines[index]);
+        }
+
+        fs::remove_file(&test_file).ok();
+        fs::remove_file(&out_file).ok();
+    }
+
+    #[test]
+    fn test_check_column_counts() {
+        let test_csv = "latitude,longitude,name
+40.7128,-74.0060,a
+40.7130,-74.0062,b,extra
+40.7500,-73.9900
+41.0000,-74.0000,d";
+
+        let test_file = PathBuf::from("test_points_rust_columns.csv");
+        fs::write(&test_file, test_csv).expect("Failed to create test CSV");
+
+        // Ragged rows are still read
+        let (points, records, _) =
+            read_points_and_csv(&[&test_file], None, Crs::Wgs84).expect("Failed to read CSV");
+        assert_eq!(points.len(), 4);
+
+        let warning = check_column_counts(&records).expect("Expected a warning");
+        assert!(warning.contains("lines: 3, 4"), "{}", warning);
+
+        assert!(check_column_counts(&records[..3]).is_some());
+        assert!(check_column_counts(&records[..2]).is_none());
+        assert!(check_column_counts(&[]).is_none());
+
+        fs::remove_file(&test_file).ok();
+    }
+
+    #[test]
+    fn test_clusters_multipoint_geojson() {
+        let points = vec![
+            Point([-74.0060, 40.7128]),
+            Point([-74.0062, 40.7130]),
+            Point([-74.0064, 40.7132]),
+            Point([-73.9500, 40.8000]),
+        ];
+        let (clusters, noise) = db_scan(&points, 0.1, 3);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(noise, vec![3]);
+
+        let labels = FeatureLabels {
+            ids: &[],
+            names: &[],
+            format: &LabelFormat::default(),
+        };
+        let mut out = Vec::new();
+        write_clusters_multipoint(&mut out, &clusters, &noise, &points, &labels, None).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).expect("Invalid JSON");
+
+        assert_eq!(json["type"], "FeatureCollection");
+        let features = json["features"].as_array().unwrap();
+        assert_eq!(features.len(), 2);
+
+        let cluster = &features[0];
+        assert_eq!(cluster["geometry"]["type"], "MultiPoint");
+        assert_eq!(cluster["properties"]["cluster"], 0);
+        assert_eq!(cluster["properties"]["size"], 3);
+        let coords = cluster["geometry"]["coordinates"].as_array().unwrap();
+        assert_eq!(coords.len(), 3);
+        let lons: Vec<f64> = coords.iter().map(|c| c[0].as_f64().unwrap()).collect();
+        assert!(lons.iter().all(|&lon| lon < -74.0));
+        let centroid = &cluster["properties"]["centroid"];
+        assert!((centroid[1].as_f64().unwrap() - 40.7130).abs() < 1e-9);
+
+        let noise_feature = &features[1];
+        assert_eq!(noise_feature["properties"]["cluster"], -1);
+        assert_eq!(
+            noise_feature["geometry"]["coordinates"][0],
+            serde_json::json!([-73.95, 40.8])
+        );
+
+        // Round-trip: serializing the parsed value gives the same document
+        let reparsed: serde_json::Value =
+            serde_json::from_str(&serde_json::to_string(&json).unwrap()).unwrap();
+        assert_eq!(reparsed, json);
+
+        // A border point that failed its own density test is only written
+        // with its cluster, also when passed as noise
+        let points: PointList = [0.0, 0.95, 1.5, 1.6, 1.7]
+            .iter()
+            .map(|&d| Point([30.0, 60.0 + d * 0.001]))
+            .collect();
+        let (clusters, noise) = db_scan(&points, 0.111, 3);
+        assert_eq!(clusters.len(), 1);
+        assert!(noise.is_empty());
+        for noise in [&noise[..], &[0]] {
+            let mut out = Vec::new();
+            write_clusters_multipoint(&mut out, &clusters, noise, &points, &labels, None).unwrap();
+            let json: serde_json::Value = serde_json::from_slice(&out).expect("Invalid JSON");
+            let features = json["features"].as_array().unwrap();
+            assert_eq!(features.len(), 1);
+            assert_eq!(features[0]["properties"]["size"], 5);
+        }
+    }
+
+    #[test]
+    fn test_retry_metric_detection() {
+        // A chain of points along the 80th parallel, 1.004 km apart. The fast
+        // cosine underestimates longitude spans at high latitudes by ~0.6%,
+        // so the fast metric sees them closer than eps = 1 km and chains them
+        // into one cluster while the exact haversine metric keeps them apart.
+        let step = 1.004 / (6371.0 * std::f64::consts::PI / 180.0 * 80f64.to_radians().cos());
+        let points: PointList = (0..20)
+            .map(|i| Point([10.0 + i as f64 * step, 80.0]))
+            .collect();
+
+        let config = DbscanConfig::new(1.0, 2);
+        let (fast, _) = config.run(&points);
+        assert_eq!(largest_cluster_fraction(&fast, points.len()), 1.0);
+        assert!(largest_cluster_fraction(&fast, points.len()) > 0.9);
+
+        let (exact, noise) = config.metric(DistanceMetric::Haversine).run(&points);
+        assert_eq!(largest_cluster_fraction(&exact, points.len()), 0.0);
+        assert_eq!(noise.len(), points.len());
+
+        assert_eq!(largest_cluster_fraction(&[], 0), 0.0);
+    }
+
+    #[test]
+    fn test_csv_output_matches_sequential_writer() {
+        // Several chunks of rows that need quoting, and a ragged one
+        let mut records = vec![vec!["latitude".to_string(), "longitude".to_string()]];
+        for i in 0..5000 {
+            let mut record = vec![format!("{}", 40.0 + i as f64 * 1e-4), "-74.0".to_string()];
+            match i % 4 {
+                0 => record.push(format!("name {}; \"quoted\"", i)),
+                1 => record.push("a;b\nc".to_string()),
+                2 => record.push(String::new()),
+                _ => {}
+            }
+            records.push(record);
+        }
+        let filtered: Vec<usize> = (0..5000).filter(|i| i % 3 != 1).collect();
+        let out_file = PathBuf::from("test_points_rust_parallel_csv_out.csv");
+        write_filtered_points_to_csv(&out_file, &records, &filtered, b';').unwrap();
+        let output = fs::read(&out_file).unwrap();
+        fs::remove_file(&out_file).ok();
+
+        let mut writer = WriterBuilder::new()
+            .delimiter(b';')
+            .flexible(true)
+            .from_writer(Vec::new());
+        writer.write_record(&records[0]).unwrap();
+        for &i in &filtered {
+            writer.write_record(&records[i + 1]).unwrap();
+        }
+        assert_eq!(output, writer.into_inner().unwrap());
+    }
+
+    #[test]
+    fn test_jsonl_output() {
+        let points: PointList = (0..150_000)
+            .map(|i| Point([-74.0 - i as f64 * 1e-5, 40.0 + i as f64 * 1e-5]))
+            .collect();
+        let labels: Vec<i32> = (0..points.len()).map(|i| (i % 7) as i32 - 1).collect();
+        let indices: Vec<usize> = (0..points.len()).step_by(2).collect();
+
+        let mut out = Vec::new();
+        write_jsonl(
+            &mut out,
+            &indices,
+            &points,
+            &labels,
+            &LabelFormat::default(),
+            None,
+        )
+        .unwrap();
+
+        // Same bytes as the serial writer, whether formatting ran in parallel or not
+        let mut serial = Vec::new();
+        output::write_rows_serial(&mut serial, &indices, |&idx| {
+            output::jsonl_row(
+                points[idx].0[1],
+                points[idx].0[0],
+                labels[idx],
+                &LabelFormat::default(),
+            )
+        })
+        .unwrap();
+        assert_eq!(out, serial);
+
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), indices.len());
+        let row: serde_json::Value = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(row["lat"], 40.00002);
+        assert_eq!(row["lon"], -74.00002);
+        assert_eq!(row["cluster"], 1);
+    }
+
+    #[test]
+    fn test_ndjson_keeps_input_decimals() {
+        let args = Args::try_parse_from(["rust_dbscan", "--format", "ndjson"]).unwrap();
+        assert!(matches!(args.format, OutputFormat::Jsonl));
+
+        let records: Vec<Vec<String>> = [
+            ["40.7128", "-74.006"],
+            ["51.5", "-0.1275"],
+            ["59.9343", "30.3351"],
+        ]
+        .iter()
+        .map(|r| r.iter().map(|c| c.to_string()).collect())
+        .collect();
+        let (points, _) = parse_points(&records, [0, 1], None, false, true);
+        let indices: Vec<usize> = (0..points.len()).collect();
+
+        // Values print as read, without rounding noise of the default 7 digits
+        let mut out = Vec::new();
+        write_jsonl(
+            &mut out,
+            &indices,
+            &points,
+            &[0, -1, 1],
+            &LabelFormat::default(),
+            args.coord_digits.0,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"lat\":40.7128,\"lon\":-74.006,\"cluster\":0}\n\
+             {\"lat\":51.5,\"lon\":-0.1275,\"cluster\":-1}\n\
+             {\"lat\":59.9343,\"lon\":30.3351,\"cluster\":1}\n"
+        );
+    }
+
+    #[test]
+    fn test_split_oversized_clusters() {
+        // Cluster 0: two groups ~1.1 km apart, cluster 1: small
+        let points = vec![
+            Point([30.0, 60.0001]),
+            Point([30.0, 60.0101]),
+            Point([30.0001, 60.0002]),
+            Point([31.0, 61.0]),
+            Point([31.0, 61.0001]),
+        ];
+        let clusters = vec![
+            Cluster {
+                c: 0,
+                points: vec![0, 1, 2],
+            },
+            Cluster {
+                c: 1,
+                points: vec![3, 4],
+            },
+        ];
+        let names = vec!["a-0".to_string(), "a-1".to_string()];
+
+        let (split, split_names) =
+            split_oversized_clusters(clusters.clone(), &names, &points, 2, 0.5);
+        let ids: Vec<usize> = split.iter().map(|c| c.c).collect();
+        assert_eq!(ids, vec![0, 1, 2]);
+        assert_eq!(split[0].points, vec![0, 2]);
+        assert_eq!(split[1].points, vec![1]);
+        assert_eq!(split[2].points, vec![3, 4]);
+        assert_eq!(split_names, vec!["a-0.0", "a-0.1", "a-1"]);
+
+        // Nothing exceeds the cap
+        let (kept, kept_names) = split_oversized_clusters(clusters, &[], &points, 3, 0.5);
+        assert_eq!(kept.len(), 2);
+        assert!(kept_names.is_empty());
+    }
+
+    #[test]
+    fn test_merge_clusters_named() {
+        // Clusters 0 and 2 ~220 m apart, cluster 1 ~11 km away
+        let points = vec![
+            Point([30.0, 60.0]),
+            Point([30.0, 60.1]),
+            Point([30.0, 60.002]),
+        ];
+        let clusters: Vec<Cluster> = (0..3).map(|c| Cluster { c, points: vec![c] }).collect();
+        let names = vec!["a-0".to_string(), "a-1".to_string(), "b-0".to_string()];
+
+        let (merged, merged_names) = merge_clusters_named(clusters.clone(), &names, &points, 0.5);
+        assert_eq!(merged[0].points, vec![0, 2]);
+        assert_eq!(merged[1].points, vec![1]);
+        assert_eq!(merged_names, vec!["a-0", "a-1"]);
+
+        let (merged, merged_names) = merge_clusters_named(clusters, &[], &points, 0.5);
+        assert_eq!(merged.len(), 2);
+        assert!(merged_names.is_empty());
+    }
+
+    #[test]
+    fn test_drop_small_clusters() {
+        // A cluster of 4 points and one of 2 points ~11 km away, plus noise
+        let points = vec![
+            Point([-74.0060, 40.7128]),
+            Point([-74.0062, 40.7130]),
+            Point([-74.0064, 40.7132]),
+            Point([-74.0066, 40.7134]),
+            Point([-74.0060, 40.8128]),
+            Point([-74.0061, 40.8129]),
+            Point([-73.0000, 41.0000]),
+        ];
+        let (clusters, noise) = db_scan(&points, 0.1, 2);
+        assert_eq!(clusters.len(), 2);
+
+        let (clusters, noise, names) = drop_small_clusters(clusters, noise, &[], 3);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].c, 0);
+        assert_eq!(noise, vec![4, 5, 6]);
+        assert!(names.is_empty());
+
+        // The reclassified points are kept as outliers
+        let labels = build_labels(&clusters, points.len());
+        assert_eq!(labels[4..], [-1, -1, -1]);
+        let filtered = filter_points(&points, &labels, Keep::First);
+        assert_eq!(filtered, vec![0, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_noise_only() {
+        // A cluster of 3 points, a pair and a lone point, the lone one twice
+        let points = vec![
+            Point([-74.0060, 40.7128]),
+            Point([-74.0060, 40.7129]),
+            Point([-74.0060, 40.7130]),
+            Point([-74.0000, 40.5000]),
+            Point([-74.0000, 40.5001]),
+            Point([-74.0000, 41.0000]),
+            Point([-74.0000, 41.0000]),
+        ];
+        let (clusters, _) = db_scan(&points, 0.1, 3);
+        let labels = build_labels(&clusters, points.len());
+        // Coincident outliers are kept once, like with the other modes
+        assert_eq!(filter_points(&points, &labels, Keep::Noise), vec![3, 4, 5]);
+
+        // Clusters reclassified by --min-cluster-size become outliers too
+        let (clusters, noise) = db_scan(&points, 0.1, 2);
+        assert_eq!(clusters.len(), 3);
+        let (clusters, _, _) = drop_small_clusters(clusters, noise, &[], 3);
+        let labels = build_labels(&clusters, points.len());
+        assert_eq!(filter_points(&points, &labels, Keep::Noise), vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn test_read_points_web_mercator() {
+        // EPSG:3857 y,x of New York City and St. Petersburg, far beyond +-90/180
+        let test_csv = "y,x,name
+4970071.579142,-8238310.235647,nyc
+8388614.343828,3367414.596497,spb
+";
+        let test_file = PathBuf::from("test_points_rust_mercator.csv");
+        fs::write(&test_file, test_csv).expect("Failed to create test CSV");
+
+        let (points, records, skipped) =
+            read_points_and_csv(&[&test_file], None, Crs::WebMercator).unwrap();
+        assert!(skipped.is_empty());
+        assert_eq!(points.len(), 2);
+        assert!((points[0].0[0] - -74.0060).abs() < 1e-9, "{:?}", points[0]);
+        assert!((points[0].0[1] - 40.7128).abs() < 1e-9, "{:?}", points[0]);
+        assert!((points[1].0[0] - 30.25).abs() < 1e-9, "{:?}", points[1]);
+        assert!((points[1].0[1] - 59.95).abs() < 1e-9, "{:?}", points[1]);
+        // Records keep the input cells
+        assert_eq!(records[1][0], "4970071.579142");
+
+        // Read as WGS84, the same cells are out of range
+        let (points, _, skipped) = read_points_and_csv(&[&test_file], None, Crs::Wgs84).unwrap();
+        assert!(points.is_empty());
+        assert_eq!(skipped.len(), 2);
+        fs::remove_file(&test_file).ok();
+
+        let args = Args::try_parse_from(["rust_dbscan", "--input-crs", "EPSG:3857"]).unwrap();
+        assert_eq!(args.input_crs, Crs::WebMercator);
+        assert_eq!(
+            Args::try_parse_from(["rust_dbscan"]).unwrap().input_crs,
+            Crs::Wgs84
+        );
+        let err = Args::try_parse_from(["rust_dbscan", "--input-crs", "epsg:27700"])
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("unsupported CRS"), "{}", err);
+        assert!(Args::try_parse_from(["rust_dbscan", "--output-crs", "epsg:3857"]).is_ok());
+    }
+
+    #[test]
+    fn test_read_points_plain() {
+        let test_input = "# lat lon
+40.7128,-74.0060
+40.7130 -74.0062
+
+	# indented comment
+40.7132\t-74.0064	ignored
+ 40.7500 , -73.9900 ,extra,tokens
+";
+        let test_file = PathBuf::from("test_points_rust_plain.txt");
+        fs::write(&test_file, test_input).expect("Failed to create test input");
+
+        let (points, records) =
+            read_points_plain(&test_file, CoordOrder::Latlon).expect("Failed to read points");
+        assert_eq!(
+            points,
+            vec![
+                Point([-74.0060, 40.7128]),
+                Point([-74.0062, 40.7130]),
+                Point([-74.0064, 40.7132]),
+                Point([-73.9900, 40.7500]),
+            ]
+        );
+        assert_eq!(records.len(), points.len());
+        assert_eq!(records[2], vec!["40.7132", "-74.0064"]);
+        assert!(check_column_counts(&records).is_none());
+
+        fs::write(&test_file, "40.7128\nnot a point\n").unwrap();
+        let err = read_points_plain(&test_file, CoordOrder::Latlon).unwrap_err();
+        assert!(err.to_string().contains("line 1"), "{}", err);
+
+        fs::remove_file(&test_file).ok();
+    }
+
+    #[test]
+    fn test_coord_order() {
+        let points: PointList = vec![Point([30.25, 59.95]), Point([-74.006, 40.7128])];
+        let clusters = vec![Cluster {
+            c: 0,
+            points: vec![0, 1],
+        }];
+
+        for order in [CoordOrder::Latlon, CoordOrder::Lonlat] {
+            // Input columns in `order` parse back to the same points
+            let mut records = vec![order.arrange(["lat", "lon"]).map(String::from).to_vec()];
+            records.extend(points.iter().map(|p| {
+                order
+                    .arrange([p.0[1], p.0[0]])
+                    .map(|v| v.to_string())
+                    .to_vec()
+            }));
+            let (parsed, skipped) = parse_points(&records, order.columns(), None, false, true);
+            assert!(skipped.is_empty());
+            assert_eq!(parsed, points, "{:?}", order);
+
+            let test_file = PathBuf::from(format!("test_points_rust_{:?}.txt", order));
+            let lines: Vec<String> = records[1..].iter().map(|r| r.join(" ")).collect();
+            fs::write(&test_file, lines.join("\n")).unwrap();
+            let (plain, plain_records) = read_points_plain(&test_file, order).unwrap();
+            fs::remove_file(&test_file).ok();
+            assert_eq!(plain, points, "{:?}", order);
+            assert_eq!(plain_records, records[1..]);
+
+            // So do the centroids of the summary
+            let mut out = Vec::new();
+            write_cluster_summary(&mut out, &clusters, &points, b',', order, None).unwrap();
+            let summary: Vec<Vec<String>> = String::from_utf8(out)
+                .unwrap()
+                .lines()
+                .map(|line| line.split(',').map(String::from).collect())
+                .collect();
+            let columns = order.columns().map(|c| c + 1);
+            assert_eq!(summary[0][columns[0]], "centroid_lat");
+            assert_eq!(summary[0][columns[1]], "centroid_lon");
+            let (centroid, _) = parse_points(&summary, columns, None, false, true);
+            let (center, _, _) = clusters[0].centroid_and_bounds(&points);
+            assert_eq!(centroid, vec![center], "{:?}", order);
+        }
+
+        let args = Args::try_parse_from(["rust_dbscan", "--coord-order", "lonlat"]).unwrap();
+        assert_eq!(CoordOrder::from(args.coord_order), CoordOrder::Lonlat);
+        assert_eq!(CoordOrder::from(args.coord_order).columns(), [1, 0]);
+        assert_eq!(args.lat_col, None);
+    }
+
+    #[test]
+    fn test_read_geojson_files() {
+        let first = PathBuf::from("test_points_rust_first.geojson");
+        let second = PathBuf::from("test_points_rust_second.geojson");
+        fs::write(
+            &first,
+            r#"{"type":"FeatureCollection","features":[
+{"type":"Feature","geometry":{"type":"Point","coordinates":[-74.006,40.7128]},"properties":{"name":"a","count":3}},
+{"type":"Feature","geometry":{"type":"LineString","coordinates":[[0,0],[1,1]]},"properties":{}},
+{"type":"Feature","geometry":{"type":"Point","coordinates":[-73.95,40.8]},"properties":{"name":"b, \"quoted\"","note":null}}
+]}"#,
+        )
+        .unwrap();
+        fs::write(
+            &second,
+            r#"{"type":"FeatureCollection","features":[
+{"type":"Feature","geometry":null,"properties":{"name":"nowhere"}},
+{"type":"Feature","geometry":{"type":"Point","coordinates":[151.2093,-33.8688]},"properties":{"city":"Sydney"}}
+]}"#,
+        )
+        .unwrap();
+
+        let (points, records, sources, skipped) =
+            read_geojson_files(&[first.clone(), second.clone()]).unwrap();
+        // GeoJSON positions are [lon, lat], like points
+        assert_eq!(
+            points,
+            vec![
+                Point([-74.006, 40.7128]),
+                Point([-73.95, 40.8]),
+                Point([151.2093, -33.8688]),
+            ]
+        );
+        assert_eq!(
+            records,
+            vec![
+                vec!["latitude", "longitude", "city", "count", "name", "note"],
+                vec!["40.7128", "-74.006", "", "3", "a", ""],
+                vec!["40.8", "-73.95", "", "", "b, \"quoted\"", ""],
+                vec!["-33.8688", "151.2093", "Sydney", "", "", ""],
+            ]
+        );
+        assert_eq!(sources, vec![0, 0, 0, 1]);
+        assert_eq!(
+            check_skipped_features(&skipped).unwrap(),
+            "2 feature(s) without Point geometry skipped: \
+             test_points_rust_first.geojson feature 1, test_points_rust_second.geojson feature 0"
+        );
+
+        fs::write(&first, r#"{"type":"Point","coordinates":[0,0]}"#).unwrap();
+        assert!(read_geojson_files(std::slice::from_ref(&first)).is_err());
+
+        fs::remove_file(&first).ok();
+        fs::remove_file(&second).ok();
+    }
+
+    #[test]
+    fn test_skipped_rows_and_fill_missing() {
+        let test_csv = "latitude,longitude
+40.7128,-74.0060
+40.7130,
+,-74.0064
+abc,xyz
+40.7500";
+
+        let test_file = PathBuf::from("test_points_rust_missing.csv");
+        fs::write(&test_file, test_csv).expect("Failed to create test CSV");
+
+        let (points, _, skipped) = read_points_and_csv(&[&test_file], None, Crs::Wgs84).unwrap();
+        assert_eq!(points.len(), 1);
+        let report: Vec<(usize, Vec<&str>)> = skipped
+            .iter()
+            .map(|r| (r.line, r.columns.clone()))
+            .collect();
+        assert_eq!(
+            report,
+            vec![
+                (3, vec!["longitude"]),
+                (4, vec!["latitude"]),
+                (5, vec!["latitude", "longitude"]),
+                (6, vec!["longitude"]),
+            ]
+        );
+        let warning = check_skipped_rows(&skipped).expect("Expected a warning");
+        assert!(warning.contains("4 row(s)"), "{}", warning);
+        assert!(
+            warning.contains("3 (longitude), 4 (latitude)"),
+            "{}",
+            warning
+        );
+        assert!(check_skipped_rows(&[]).is_none());
+
+        // Missing cells are filled, unparseable ones still skip the row
+        let (points, _, skipped) =
+            read_points_and_csv(&[&test_file], Some([1.0, 2.0]), Crs::Wgs84).unwrap();
+        assert_eq!(
+            points,
+            vec![
+                Point([-74.0060, 40.7128]),
+                Point([2.0, 40.7130]),
+                Point([-74.0064, 1.0]),
+                Point([2.0, 40.7500]),
+            ]
+        );
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].line, 5);
+
+        assert_eq!(parse_lat_lon("40.5, -74"), Ok([40.5, -74.0]));
+        assert!(parse_lat_lon("40.5").is_err());
+        assert!(parse_lat_lon("a,b").is_err());
+
+        fs::remove_file(&test_file).ok();
+    }
+
+    #[test]
+    fn test_invalid_coordinates_skipped() {
+        let records: Vec<Vec<String>> = [
+            ["lat", "lon"],
+            ["40.7", "-74.0"],
+            ["NaN", "-74.0"],
+            ["40.7", "inf"],
+            ["90.5", "-74.0"],
+            ["-90", "180"],
+            ["40.7", "-180.1"],
+        ]
+        .iter()
+        .map(|r| r.iter().map(|c| c.to_string()).collect())
+        .collect();
+
+        let (points, skipped) = parse_points(&records, [0, 1], None, false, true);
+        assert_eq!(points, vec![Point([-74.0, 40.7]), Point([180.0, -90.0])]);
+        let report: Vec<(usize, Vec<&str>)> = skipped
+            .iter()
+            .map(|r| (r.line, r.columns.clone()))
+            .collect();
+        assert_eq!(
+            report,
+            vec![
+                (3, vec!["latitude not finite"]),
+                (4, vec!["longitude not finite"]),
+                (5, vec!["latitude out of range"]),
+                (7, vec!["longitude out of range"]),
+            ]
+        );
+        let warning = check_skipped_rows(&skipped).expect("Expected a warning");
+        assert!(
+            warning.contains("3 (latitude not finite), 4 (longitude not finite)"),
+            "{}",
+            warning
+        );
+    }
+
+    #[test]
+    fn test_benchmark_synthetic_points() {
+        let points = synthetic_points(1000, 0.2, 1);
+        assert_eq!(points.len(), 1000);
+        assert_eq!(points, synthetic_points(1000, 0.2, 1));
+        assert_ne!(points, synthetic_points(1000, 0.2, 2));
+
+        // 900 clustered points in clusters of 100, 100 noise points
+        let (clusters, _) = DbscanConfig::new(0.1, 3).run(&points);
+        assert!(clusters.len() >= 9, "{} clusters", clusters.len());
+
+        let row = run_benchmark(&points, &DbscanConfig::new(0.1, 3));
+        assert_eq!(row.points, 1000);
+        assert!(row.throughput() > 0.0);
+    }
+
+    #[test]
+    fn test_write_atomically() {
+        use std::io::Write;
+
+        let out_file = PathBuf::from("test_points_rust_atomic.csv");
+        fs::write(&out_file, "previous,content\n").unwrap();
+
+        // Failing after a partial write leaves the destination unchanged
+        let result = write_atomically(&out_file, |out| {
+            out.write_all(b"latitude,longitude\n40.7128,")?;
+            Err("simulated crash".into())
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&out_file).unwrap(), "previous,content\n");
+        let leftovers = fs::read_dir(".")
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| {
+                e.file_name()
+                    .to_string_lossy()
+                    .starts_with(".test_points_rust_atomic.csv.tmp")
+            })
+            .count();
+        assert_eq!(leftovers, 0);
+
+        // Success replaces it as a whole
+        let records = vec![
+            vec!["latitude".to_string(), "longitude".to_string()],
+            vec!["40.7128".to_string(), "-74.0060".to_string()],
+        ];
+        write_filtered_points_to_csv(&out_file, &records, &[0], b',').unwrap();
+        assert_eq!(
+            fs::read_to_string(&out_file).unwrap(),
+            "latitude,longitude\n40.7128,-74.0060\n"
+        );
+
+        fs::remove_file(&out_file).ok();
+    }
+
+    #[test]
+    fn test_read_column_values() {
+        let test_csv = "latitude,longitude,accuracy
+40.7128,-74.0060,0.05
+bad,-74.0062,0.5
+40.7132,-74.0064,
+40.7500,-73.9900,0.2";
+
+        let test_file = PathBuf::from("test_points_rust_accuracy.csv");
+        fs::write(&test_file, test_csv).expect("Failed to create test CSV");
+
+        let (points, records, skipped) =
+            read_points_and_csv(&[&test_file], None, Crs::Wgs84).unwrap();
+        let rows = point_records(&records, &skipped);
+        assert_eq!(rows, vec![1, 3, 4]);
+        assert_eq!(rows.len(), points.len());
+
+        // The skipped row's value doesn't shift the others, blanks are 0
+        assert_eq!(
+            read_column_values(&records, &rows, 2, false, 0.0),
+            vec![0.05, 0.0, 0.2]
+        );
+        assert_eq!(
+            read_column_values(&records, &rows, 7, false, 0.0),
+            vec![0.0; 3]
+        );
+        assert_eq!(
+            read_column_values(&records, &rows, 7, false, 1.0),
+            vec![1.0; 3]
+        );
+
+        fs::remove_file(&test_file).ok();
+    }
+
+    #[test]
+    fn test_reproject_records() {
+        use crate::cluster::projection::Crs;
+
+        let mut records = vec![
+            vec![
+                "latitude".to_string(),
+                "longitude".to_string(),
+                "name".to_string(),
+            ],
+            vec![
+                "40.7128".to_string(),
+                "-74.0060".to_string(),
+                "nyc".to_string(),
+            ],
+        ];
+        let projected = vec![Crs::WebMercator.project(&Point([-74.0060, 40.7128]))];
+        reproject_records(&mut records, &[1], &projected, [0, 1], None);
+
+        assert_eq!(records[0], vec!["y", "x", "name"]);
+        let y: f64 = records[1][0].parse().unwrap();
+        let x: f64 = records[1][1].parse().unwrap();
+        assert!((x - -8238310.235647).abs() < 1e-3);
+        assert!((y - 4970071.579142).abs() < 1e-3);
+        assert_eq!(records[1][2], "nyc");
+    }
+
+    #[test]
+    fn test_aggregate_columns() {
+        let test_csv = "latitude,longitude,name
+40.7128,-74.0060,a
+40.7130,-74.0062,b
+40.7132,-74.0064,c
+40.8000,-73.9500,d";
+
+        let test_file = PathBuf::from("test_points_rust_aggregate.csv");
+        fs::write(&test_file, test_csv).expect("Failed to create test CSV");
+
+        let (points, mut records, skipped) =
+            read_points_and_csv(&[&test_file], None, Crs::Wgs84).unwrap();
+        let (clusters, _) = db_scan(&points, 0.1, 3);
+        let rows = point_records(&records, &skipped);
+        assert_eq!(
+            add_aggregate_columns(&mut records, &rows, &clusters, &points, EARTH_R, Some(7)),
+            5
+        );
+
+        assert_eq!(
+            records[0],
+            vec![
+                "latitude",
+                "longitude",
+                "name",
+                "cluster_size",
+                "centroid_lat",
+                "centroid_lon",
+                "bbox_width_km",
+                "bbox_height_km"
+            ]
+        );
+
+        // Member count matches the cluster size, noise stands for itself
+        assert_eq!(records[1][3], clusters[0].points.len().to_string());
+        assert_eq!(records[1][3], "3");
+        assert_eq!(records[1][4..6], ["40.713", "-74.0062"]);
+        let height: f64 = records[1][7].parse().unwrap();
+        assert!((height - 0.0445).abs() < 0.001, "{}", height);
+        assert_eq!(records[4][3..], ["1", "40.8", "-73.95", "0", "0"]);
+
+        fs::remove_file(&test_file).ok();
+    }
+
+    #[test]
+    fn test_coord_digits() {
+        let points = vec![
+            Point([-74.00601234567891, 40.71281234567891]),
+            Point([-74.0062, 40.713]),
+            Point([-74.0064, 40.7132]),
+        ];
+        let (clusters, noise) = db_scan(&points, 0.1, 3);
+
+        let labels = FeatureLabels {
+            ids: &[],
+            names: &[],
+            format: &LabelFormat::default(),
+        };
+        let coords = |digits| {
+            let mut out = Vec::new();
+            write_clusters_multipoint(&mut out, &clusters, &noise, &points, &labels, digits)
+                .unwrap();
+            let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+            let feature = &json["features"][0];
+            let mut values: Vec<String> = feature["geometry"]["coordinates"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .flat_map(|c| [c[0].to_string(), c[1].to_string()])
+                .collect();
+            values.extend(
+                feature["properties"]["centroid"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|v| v.to_string()),
+            );
+            values
+        };
+        let decimals = |v: &str| v.split_once('.').map_or(0, |(_, d)| d.len());
+
+        let rounded = coords(Some(7));
+        assert_eq!(rounded[0], "-74.0060123");
+        assert_eq!(rounded[1], "40.7128123");
+        assert_eq!(rounded[2], "-74.0062");
+        assert!(rounded.iter().all(|v| decimals(v) <= 7), "{:?}", rounded);
+        assert!(coords(Some(3)).iter().all(|v| decimals(v) <= 3));
+        assert_eq!(coords(None)[0], "-74.00601234567891");
+
+        let mut out = Vec::new();
+        write_jsonl(
+            &mut out,
+            &[0],
+            &points,
+            &[0, 0, 0],
+            &LabelFormat::default(),
+            Some(2),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"lat\":40.71,\"lon\":-74.01,\"cluster\":0}\n"
+        );
+
+        assert_eq!(parse_coord_digits("full").unwrap().0, None);
+        assert_eq!(parse_coord_digits("7").unwrap().0, Some(7));
+        assert!(parse_coord_digits("-1").is_err());
+        assert!(parse_coord_digits("99").is_err());
+    }
+
+    #[test]
+    fn test_check_degenerate() {
+        let points = vec![
+            Point([-74.0060, 40.7128]),
+            Point([-74.0062, 40.7130]),
+            Point([-74.0064, 40.7132]),
+            Point([-73.9500, 40.8000]),
+        ];
+
+        let (clusters, _) = db_scan(&points, 0.001, 3);
+        let warning = check_degenerate(&clusters, points.len()).expect("all noise");
+        assert!(warning.contains("increase eps"), "{}", warning);
+
+        let (clusters, _) = db_scan(&points, 100.0, 3);
+        let warning = check_degenerate(&clusters, points.len()).expect("one cluster");
+        assert!(warning.contains("decrease eps"), "{}", warning);
+
+        let (clusters, _) = db_scan(&points, 0.001, 1);
+        let warning = check_degenerate(&clusters, points.len()).expect("singletons");
+        assert!(warning.contains("min_points 1"), "{}", warning);
+
+        let (clusters, _) = db_scan(&points, 0.1, 3);
+        assert!(check_degenerate(&clusters, points.len()).is_none());
+        assert!(check_degenerate(&[], 1).is_none());
+    }
+
+    #[test]
+    fn test_schema_names_geojson_properties() {
+        let test_csv = "40.7128,-74.0060,a
+40.7130,-74.0062,b
+40.7132,-74.0064,c
+bad,-74.0064,skipped
+40.8000,-73.9500,d";
+
+        let test_file = PathBuf::from("test_points_rust_schema.csv");
+        fs::write(&test_file, test_csv).expect("Failed to create test CSV");
+
+        let (points, mut records, mut skipped) =
+            read_points_and_csv(&[&test_file], None, Crs::Wgs84).unwrap();
+        apply_schema(&mut records, &mut skipped, "lat,lon,site\n", b',').unwrap();
+        assert_eq!(records[0], vec!["lat", "lon", "site"]);
+        // Reported lines are still lines of the input file
+        assert_eq!(skipped[0].line, 4);
+
+        let (clusters, _) = db_scan(&points, 0.1, 3);
+        let labels = build_labels(&clusters, points.len());
+        let rows = point_records(&records, &skipped);
+        let mut out = Vec::new();
+        let labels = FeatureLabels {
+            ids: &labels,
+            names: &[],
+            format: &LabelFormat::default(),
+        };
+        write_points_geojson(&mut out, &records, &rows, &[0, 3], &points, &labels).unwrap();
+
+        let json: serde_json::Value = serde_json::from_slice(&out).expect("Invalid JSON");
+        let features = json["features"].as_array().unwrap();
+        assert_eq!(features.len(), 2);
+        assert_eq!(
+            features[0]["properties"],
+            serde_json::json!({"lat": "40.7128", "lon": "-74.0060", "site": "a", "cluster": 0})
+        );
+        assert_eq!(features[1]["properties"]["site"], "d");
+        assert_eq!(features[1]["properties"]["cluster"], -1);
+        assert_eq!(
+            features[1]["geometry"]["coordinates"],
+            serde_json::json!([-73.95, 40.8])
+        );
+
+        // Schema errors
+        let (_, mut records, mut skipped) =
+            read_points_and_csv(&[&test_file], None, Crs::Wgs84).unwrap();
+        let err = apply_schema(&mut records, &mut skipped, "lat,lon", b',').unwrap_err();
+        assert!(err.contains("2 columns but the data has 3"), "{}", err);
+        apply_schema(&mut records, &mut skipped, "lat,lon,site", b',').unwrap();
+        let err = apply_schema(&mut records, &mut skipped, "lat,lon,site", b',').unwrap_err();
+        assert!(err.contains("already has a header"), "{}", err);
+
+        fs::remove_file(&test_file).ok();
+    }
+
+    #[test]
+    fn test_label_column() {
+        let test_csv = "latitude,longitude,name
+40.7128,-74.0060,a
+40.7130,-74.0062,b
+bad,-74.0062,skipped
+40.7132,-74.0064,c
+40.8000,-73.9500,d";
+
+        let test_file = PathBuf::from("test_points_rust_labels.csv");
+        let out_file = PathBuf::from("test_points_rust_labels_out.csv");
+        fs::write(&test_file, test_csv).expect("Failed to create test CSV");
+
+        let (points, mut records, skipped) =
+            read_points_and_csv(&[&test_file], None, Crs::Wgs84).unwrap();
+        add_order_index(&mut records);
+        let (clusters, _) = db_scan(&points, 0.1, 3);
+        let labels = build_labels(&clusters, points.len());
+        let rows = point_records(&records, &skipped);
+        add_label_column(&mut records, &rows, &labels, &LabelFormat::default());
+
+        write_filtered_points_to_csv(&out_file, &records, &[0, 1, 2, 3, 4], b',').unwrap();
+        let output = fs::read_to_string(&out_file).expect("Failed to read output");
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "latitude,longitude,name,original_index,cluster");
+        // The label goes after the extra columns, skipped rows get none
+        assert_eq!(lines[1], "40.7128,-74.0060,a,0,0");
+        assert_eq!(lines[3], "bad,-74.0062,skipped,2");
+        assert_eq!(lines[5], "40.8000,-73.9500,d,4,-1");
+
+        fs::remove_file(&test_file).ok();
+        fs::remove_file(&out_file).ok();
+    }
+
+    #[test]
+    fn test_noise_label() {
+        let args = Args::try_parse_from(["rust_dbscan"]).unwrap();
+        assert_eq!(LabelFormat::new(args.noise_label), LabelFormat::default());
+
+        let points = vec![Point([-74.006, 40.7128]), Point([-73.95, 40.8])];
+        let labels = [2, -1];
+        for (noise, text, json) in [
+            ("-1", "-1", "-1"),
+            ("", "", "\"\""),
+            ("noise", "noise", "\"noise\""),
+        ] {
+            let format = LabelFormat::new(noise);
+            assert_eq!(format.text(2), "2");
+            assert_eq!(format.text(-1), text);
+            assert_eq!(format.json(2), "2");
+            assert_eq!(format.json(-1), json);
+
+            let mut records = vec![
+                vec!["latitude".to_string(), "longitude".to_string()],
+                vec!["40.7128".to_string(), "-74.006".to_string()],
+                vec!["40.8".to_string(), "-73.95".to_string()],
+            ];
+            add_label_column(&mut records, &[1, 2], &labels, &format);
+            assert_eq!(records[1][2], "2");
+            assert_eq!(records[2][2], text);
+
+            let mut out = Vec::new();
+            write_jsonl(&mut out, &[1], &points, &labels, &format, None).unwrap();
+            let expected = format!("{{\"lat\":40.8,\"lon\":-73.95,\"cluster\":{}}}\n", json);
+            assert_eq!(String::from_utf8(out).unwrap(), expected);
+
+            let feature_labels = FeatureLabels {
+                ids: &labels,
+                names: &[],
+                format: &format,
+            };
+            let mut out = Vec::new();
+            write_points_geojson(&mut out, &records, &[1, 2], &[1], &points, &feature_labels)
+                .unwrap();
+            let geojson: serde_json::Value = serde_json::from_slice(&out).unwrap();
+            let cluster = &geojson["features"][0]["properties"]["cluster"];
+            assert_eq!(cluster.to_string(), json);
+
+            let mut out = Vec::new();
+            write_clusters_multipoint(&mut out, &[], &[1], &points, &feature_labels, None).unwrap();
+            let geojson: serde_json::Value = serde_json::from_slice(&out).unwrap();
+            let cluster = &geojson["features"][0]["properties"]["cluster"];
+            assert_eq!(cluster.to_string(), json);
+        }
+    }
+
+    #[test]
+    fn test_cluster_color() {
+        assert_eq!(output::cluster_color(-1), output::NOISE_COLOR);
+        assert_eq!(output::cluster_color(0), "#e65050");
+        assert_eq!(output::cluster_color(7), output::cluster_color(7));
+
+        let colors: Vec<String> = (0..100).map(output::cluster_color).collect();
+        for color in &colors {
+            assert_eq!(color.len(), 7);
+            assert!(color.starts_with('#'));
+            assert!(u32::from_str_radix(&color[1..], 16).is_ok());
+            assert_ne!(color, output::NOISE_COLOR);
+        }
+        // Consecutive ids get different colors
+        assert!(colors.windows(2).all(|w| w[0] != w[1]));
+    }
+
+    #[test]
+    fn test_color_column() {
+        let mut records = vec![
+            vec!["latitude".to_string(), "longitude".to_string()],
+            vec!["40.7128".to_string(), "-74.0060".to_string()],
+            vec!["40.8000".to_string(), "-73.9500".to_string()],
+        ];
+        add_label_column(&mut records, &[1, 2], &[3, -1], &LabelFormat::default());
+        add_color_column(&mut records, &[1, 2], &[3, -1]);
+        assert_eq!(
+            records[0],
+            vec!["latitude", "longitude", "cluster", "color"]
+        );
+        assert_eq!(records[1][3], output::cluster_color(3));
+        assert_eq!(records[2][3], "#808080");
+    }
+
+    #[test]
+    fn test_multiple_input_files() {
+        let first = PathBuf::from("test_points_rust_multi_a.csv");
+        let second = PathBuf::from("test_points_rust_multi_b.csv");
+        let third = PathBuf::from("test_points_rust_multi_c.csv");
+        fs::write(&first, "40.7128,-74.0060\n40.7130,-74.0062\n").unwrap();
+        fs::write(&second, "latitude,longitude\n40.7132,-74.0064\n").unwrap();
+        fs::write(&third, "latitude,longitude\n40.8000,-73.9500\n").unwrap();
+
+        // Headers are detected per file, the first one goes first
+        let (mut records, sources) = read_csv_files(&[&first, &second, &third], b',').unwrap();
+        assert_eq!(records[0], vec!["latitude", "longitude"]);
+        assert_eq!(records.len(), 5);
+        assert_eq!(sources, vec![1, 0, 0, 1, 2]);
+
+        let (points, _, skipped) =
+            read_points_and_csv(&[&first, &second, &third], None, Crs::Wgs84).unwrap();
+        assert_eq!(points.len(), 4);
+        assert!(skipped.is_empty());
+        // Points of the later files can join the clusters of the first one
+        let (clusters, _) = db_scan(&points, 0.1, 3);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].points, vec![0, 1, 2]);
+
+        let files = [first.clone(), second.clone(), third.clone()];
+        add_source_column(&mut records, &sources, &files);
+        assert_eq!(records[0].last().unwrap(), "source");
+        assert_eq!(records[1].last().unwrap(), "test_points_rust_multi_a.csv");
+        assert_eq!(records[4].last().unwrap(), "test_points_rust_multi_c.csv");
+
+        fs::write(&third, "lat,lon\n40.8000,-73.9500\n").unwrap();
+        let err = read_csv_files(&[&first, &second, &third], b',').unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("header of test_points_rust_multi_c.csv")
+        );
+
+        for file in &files {
+            fs::remove_file(file).ok();
+        }
+    }
+
+    #[test]
+    fn test_base_file() {
+        let base = PathBuf::from("test_points_rust_base.csv");
+        let batch = PathBuf::from("test_points_rust_batch.csv");
+        fs::write(
+            &base,
+            "latitude,longitude\n40.7128,-74.0060\n40.7130,-74.0062\n40.7132,-74.0064\n",
+        )
+        .unwrap();
+        fs::write(
+            &batch,
+            "latitude,longitude\n40.8000,-73.9500\n40.7131,-74.0063\n40.7129,-74.0061\n",
+        )
+        .unwrap();
+
+        let (records, sources) = read_csv_files(&[&base, &batch], b',').unwrap();
+        let (points, skipped) = parse_points(&records, [0, 1], None, false, true);
+        let rows = point_records(&records, &skipped);
+        assert_eq!(base_point_count(&sources, &rows), 3);
+
+        // The new points join the cluster of the base points, the batch alone
+        // would be all noise
+        let (clusters, noise) = db_scan(&points, 0.1, 3);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(noise, vec![3]);
+        assert!(db_scan(&points[3..].to_vec(), 0.1, 3).0.is_empty());
+
+        // Only new points are kept, the first new one of the cluster included
+        let labels = build_labels(&clusters, points.len());
+        assert_eq!(
+            filter_new_points(&points, &labels, Keep::First, 3),
+            vec![3, 4]
+        );
+        assert_eq!(
+            filter_new_points(&points, &labels, Keep::All, 3),
+            vec![3, 4, 5]
+        );
+        assert_eq!(
+            filter_new_points(&points, &labels, Keep::First, 0),
+            filter_points(&points, &labels, Keep::First)
+        );
+
+        let (new_clusters, new_noise) = without_base_points(&clusters, &noise, 3);
+        assert_eq!(new_clusters[0].c, clusters[0].c);
+        let mut new_members = new_clusters[0].points.clone();
+        new_members.sort_unstable();
+        assert_eq!(new_members, vec![4, 5]);
+        assert_eq!(new_noise, vec![3]);
+
+        fs::remove_file(&base).ok();
+        fs::remove_file(&batch).ok();
+    }
+
+    #[test]
+    fn test_dedup_points() {
+        let points = vec![
+            Point([-74.0060, 40.7128]),
+            Point([-73.9500, 40.8000]),
+            Point([-74.0060, 40.7128]),
+            Point([0.0, 0.0]),
+            Point([-0.0, 0.0]),
+        ];
+        let (kept, weights) = dedup_points(&points, None);
+        assert_eq!(kept, vec![0, 1, 3]);
+        assert_eq!(weights, vec![2.0, 1.0, 2.0]);
+
+        let (kept, weights) = dedup_points(&points, Some(&[1.0, 2.0, 3.0, 4.0, 5.0]));
+        assert_eq!(kept, vec![0, 1, 3]);
+        assert_eq!(weights, vec![4.0, 2.0, 9.0]);
+    }
+
+    #[test]
+    fn test_bbox() {
+        assert_eq!(
+            parse_bbox("40.5, -74.1,40.9,-73.7"),
+            Ok([40.5, -74.1, 40.9, -73.7])
+        );
+        assert!(parse_bbox("40.5,-74.1,40.9").is_err());
+        assert!(parse_bbox("40.9,-74.1,40.5,-73.7").is_err());
+        assert!(parse_bbox("a,b,c,d").is_err());
+
+        let test_csv = "latitude,longitude,name
+40.7128,-74.0060,inside
+bad,-74.0060,skipped
+51.5074,-0.1278,outside
+40.9000,-73.7000,edge";
+        let test_file = PathBuf::from("test_points_rust_bbox.csv");
+        fs::write(&test_file, test_csv).unwrap();
+
+        let (mut points, records, mut skipped) =
+            read_points_and_csv(&[&test_file], None, Crs::Wgs84).unwrap();
+        let rows = point_records(&records, &skipped);
+        let kept = points_in_bbox(&points, [40.5, -74.1, 40.9, -73.7]);
+        assert_eq!(kept, vec![0, 2]);
+        collapse_points(&mut points, &mut skipped, &rows, &kept, "outside bbox");
+        assert_eq!(
+            points,
+            vec![Point([-74.0060, 40.7128]), Point([-73.7, 40.9])]
+        );
+        assert_eq!(skipped[1].columns, vec!["outside bbox"]);
+
+        // Clustered points still map to their own rows
+        assert_eq!(data_rows(&records, &skipped, &[0, 1]), vec![0, 3]);
+
+        fs::remove_file(&test_file).ok();
+    }
+
+    #[test]
+    fn test_collapse_points_keeps_rows() {
+        let test_csv = "latitude,longitude,name
+40.7128,-74.0060,a
+bad,-74.0060,skipped
+40.7128,-74.0060,a-duplicate
+40.8000,-73.9500,b";
+        let test_file = PathBuf::from("test_points_rust_dedup.csv");
+        let out_file = PathBuf::from("test_points_rust_dedup_out.csv");
+        fs::write(&test_file, test_csv).unwrap();
+
+        let (mut points, records, mut skipped) =
+            read_points_and_csv(&[&test_file], None, Crs::Wgs84).unwrap();
+        let rows = point_records(&records, &skipped);
+        let (kept, _) = dedup_points(&points, None);
+        collapse_points(&mut points, &mut skipped, &rows, &kept, "duplicate");
+        assert_eq!(points.len(), 2);
+        assert_eq!(point_records(&records, &skipped), vec![1, 4]);
+
+        // Points are written from their own rows, past skipped and collapsed ones
+        let rows = data_rows(&records, &skipped, &[0, 1]);
+        assert_eq!(rows, vec![0, 3]);
+        write_filtered_points_to_csv(&out_file, &records, &rows, b',').unwrap();
+        let output = fs::read_to_string(&out_file).unwrap();
+        assert_eq!(
+            output,
+            "latitude,longitude,name\n40.7128,-74.0060,a\n40.8000,-73.9500,b\n"
+        );
+
+        fs::remove_file(&test_file).ok();
+        fs::remove_file(&out_file).ok();
+    }
+
+    #[test]
+    fn test_parse_times() {
+        let test_csv = "latitude,longitude,ts
+40.7128,-74.0060,1700000000
+bad,-74.0060,1700000060
+40.7130,-74.0062,noon
+40.7132,-74.0064,
+40.7134,-74.0066, 1700010800 ";
+        let test_file = PathBuf::from("test_points_rust_times.csv");
+        fs::write(&test_file, test_csv).unwrap();
+
+        let (mut points, records, mut skipped) =
+            read_points_and_csv(&[&test_file], None, Crs::Wgs84).unwrap();
+        let col = resolve_column(&records, &ColumnRef::Name("ts".to_string())).unwrap();
+        let (times, unparseable) = parse_times(&records, &mut points, &mut skipped, col);
+        assert_eq!(times, vec![1_700_000_000, 1_700_010_800]);
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[1], Point([-74.0066, 40.7134]));
+        let lines: Vec<usize> = unparseable.iter().map(|row| row.line).collect();
+        assert_eq!(lines, vec![4, 5]);
+        // Rows stay in input order, so points still map to their records
+        let skipped_lines: Vec<usize> = skipped.iter().map(|row| row.line).collect();
+        assert_eq!(skipped_lines, vec![3, 4, 5]);
+        assert_eq!(point_records(&records, &skipped), vec![1, 5]);
+        assert_eq!(
+            check_skipped_times(&unparseable).unwrap(),
+            "2 row(s) with unparseable times skipped, lines: 4, 5"
+        );
+
+        fs::remove_file(&test_file).ok();
+    }
+
+    #[test]
+    fn test_named_coordinate_columns() {
+        let test_csv = "id,name,lat,lng,timestamp
+1,a,40.7128,-74.0060,100
+2,b,40.7130,-74.0062,101
+3,c,,-74.0064,102";
+
+        let test_file = PathBuf::from("test_points_rust_named_columns.csv");
+        fs::write(&test_file, test_csv).expect("Failed to create test CSV");
+        let records = read_csv_records(&test_file, b',').unwrap();
+
+        let lat = parse_column("lat").unwrap();
+        let lon = parse_column("3").unwrap();
+        assert_eq!(lon, ColumnRef::Index(3));
+        let columns = resolve_columns(&records, [&lat, &lon]).unwrap();
+        assert_eq!(columns, [2, 3]);
+
+        let (points, skipped) = parse_points(&records, columns, None, false, true);
+        assert_eq!(
+            points,
+            vec![Point([-74.0060, 40.7128]), Point([-74.0062, 40.7130])]
+        );
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].line, 4);
+        assert_eq!(skipped[0].columns, vec!["latitude"]);
+        // All other columns are kept for output
+        assert_eq!(records[1], vec!["1", "a", "40.7128", "-74.0060", "100"]);
+
+        // Reprojection writes to the selected columns
+        let mut records = records;
+        reproject_records(&mut records, &[1], &vec![Point([1.0, 2.0])], columns, None);
+        assert_eq!(records[0], vec!["id", "name", "y", "x", "timestamp"]);
+        assert_eq!(records[1], vec!["1", "a", "2", "1", "100"]);
+
+        let err =
+            resolve_columns(&records, [&parse_column("latitude").unwrap(), &lon]).unwrap_err();
+        assert!(err.contains("no column named \"latitude\""), "{}", err);
+        let headerless = vec![vec!["40.7".to_string(), "-74.0".to_string()]];
+        let err = resolve_columns(&headerless, [&lat, &lon]).unwrap_err();
+        assert!(err.contains("no header"), "{}", err);
+        assert!(parse_column(" ").is_err());
+
+        fs::remove_file(&test_file).ok();
+    }
+
+    #[test]
+    fn test_semicolon_decimal_comma() {
+        let test_csv = "latitude;longitude;name
+40,7128;-74,0060;a
+40,7130;-74,0062;b
+x;-74,0064;c";
+
+        let test_file = PathBuf::from("test_points_rust_semicolon.csv");
+        let out_file = PathBuf::from("test_points_rust_semicolon_out.csv");
+        fs::write(&test_file, test_csv).expect("Failed to create test CSV");
+
+        let records = read_csv_records(&test_file, b';').unwrap();
+        assert!(is_header(&records[0]));
+        assert!(!is_header(&records[1]));
+        assert_eq!(records[1], vec!["40,7128", "-74,0060", "a"]);
+
+        let (points, skipped) = parse_points(&records, [0, 1], None, true, true);
+        assert_eq!(
+            points,
+            vec![Point([-74.006, 40.7128]), Point([-74.0062, 40.713])]
+        );
+        assert_eq!(skipped.len(), 1);
+        // Without --decimal-comma the cells don't parse
+        let (points, _) = parse_points(&records, [0, 1], None, false, true);
+        assert!(points.is_empty());
+
+        // Headerless input is still detected as such
+        let headerless = read_csv_records(&test_file, b';').unwrap().split_off(1);
+        assert!(!is_header(&headerless[0]));
+
+        // Output keeps the delimiter and the cells as written
+        write_filtered_points_to_csv(&out_file, &records, &[1], b';').unwrap();
+        let output = fs::read_to_string(&out_file).expect("Failed to read output");
+        assert_eq!(output, "latitude;longitude;name\n40,7130;-74,0062;b\n");
+
+        assert_eq!(parse_delimiter(";"), Ok(b';'));
+        assert_eq!(parse_delimiter("\\t"), Ok(b'\t'));
+        assert!(parse_delimiter(";;").is_err());
+        assert!(parse_delimiter("§").is_err());
+
+        fs::remove_file(&test_file).ok();
+        fs::remove_file(&out_file).ok();
+    }
+
+    #[test]
+    fn test_streaming_matches_in_memory() {
+        let test_csv = "id,lat,lon
+1,40.7128,-74.0060
+2,40.7129,-74.0061
+3,bad,-74.0062
+4,40.7130,-74.0062
+5,41.0000,-75.0000";
+
+        let test_file = PathBuf::from("test_points_rust_streaming.csv");
+        let out_file = PathBuf::from("test_points_rust_streaming_out.csv");
+        fs::write(&test_file, test_csv).expect("Failed to create test CSV");
+
+        let lat = parse_column("lat").unwrap();
+        let lon = parse_column("lon").unwrap();
+        let records = read_csv_records(&test_file, b',').unwrap();
+        let columns = resolve_columns(&records, [&lat, &lon]).unwrap();
+        let (points, skipped) = parse_points(&records, columns, None, false, true);
+
+        let (streamed, streamed_skipped, streamed_columns) =
+            stream_points(&test_file, b',', [&lat, &lon], None, false, true).unwrap();
+        assert_eq!(streamed, points);
+        assert_eq!(streamed_skipped, skipped);
+        assert_eq!(streamed_columns, [1, 2]);
+
+        // Point 2 comes after the skipped row, so it's the 4th data row
+        stream_filtered_points(
+            &test_file,
+            Some(&out_file),
+            b',',
+            &skipped,
+            &[0, 2, 3],
+            columns,
+        )
+        .unwrap();
+        let output = fs::read_to_string(&out_file).expect("Failed to read output");
+        assert_eq!(
+            output,
+            "id,lat,lon\n1,40.7128,-74.0060\n4,40.7130,-74.0062\n5,41.0000,-75.0000\n"
+        );
+
+        let err = stream_points(
+            &test_file,
+            b',',
+            [&parse_column("x").unwrap(), &lon],
+            None,
+            false,
+            true,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("no column named"), "{}", err);
+
+        fs::remove_file(&test_file).ok();
+        fs::remove_file(&out_file).ok();
+    }
+
+    #[test]
+    fn test_streaming_conflicts() {
+        let args = Args::parse_from(["rust_dbscan_filter", "--aggregate"]);
+        assert!(streaming_conflicts(&args).is_empty());
+        let args = Args::parse_from(["rust_dbscan_filter", "--streaming", "--format", "jsonl"]);
+        assert!(streaming_conflicts(&args).is_empty());
+        let args = Args::parse_from([
+            "rust_dbscan_filter",
+            "--streaming",
+            "--aggregate",
+            "--output-crs",
+            "webmercator",
+        ]);
+        assert_eq!(
+            streaming_conflicts(&args),
+            vec!["--aggregate", "--output-crs with csv output"]
+        );
+    }
+
+    #[test]
+    fn test_planar_conflicts() {
+        let args = Args::parse_from(["rust_dbscan_filter", "--metric", "manhattan"]);
+        assert!(planar_conflicts(&args).is_empty());
+        let args = Args::parse_from(["rust_dbscan_filter", "--aggregate", "--stats"]);
+        assert!(planar_conflicts(&args).is_empty());
+        let args = Args::parse_from([
+            "rust_dbscan_filter",
+            "--metric",
+            "chebyshev",
+            "--stats",
+            "--merge-distance",
+            "1",
+            "--aggregate",
+            "--max-cluster-size",
+            "10",
+        ]);
+        assert_eq!(
+            planar_conflicts(&args),
+            vec![
+                "--merge-distance",
+                "--max-cluster-size",
+                "--aggregate",
+                "--stats"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keep_modes() {
+        let points = vec![
+            Point([30.0, 60.0]),
+            Point([30.0002, 60.0]),
+            Point([10.0, 10.0]),
+            Point([30.0001, 60.0003]),
+            Point([30.0, 60.0]),
+        ];
+        // Interleaved members: `first` keeps both runs of cluster 0
+        let labels = vec![0, 0, -1, 0, 0];
+        let clusters = vec![Cluster {
+            c: 0,
+            points: vec![0, 1, 3, 4],
+        }];
+
+        assert_eq!(filter_points(&points, &labels, Keep::First), vec![0, 2, 3]);
+        assert_eq!(
+            filter_points(&points, &labels, Keep::All),
+            vec![0, 1, 2, 3, 4]
+        );
+        let kept = filter_points(&points, &labels, Keep::Centroid);
+        assert_eq!(kept, vec![0, 2]);
+
+        let moved = centroid_points(&points, &clusters, &labels, &kept);
+        let (centroid, _, _) = clusters[0].centroid_and_bounds(&points);
+        assert_eq!(moved[0], centroid);
+        assert_eq!(moved[1..], points[1..]);
+
+        let mut records = vec![
+            vec!["lat".to_string(), "lon".to_string()],
+            vec!["60.0".to_string(), "30.0".to_string()],
+        ];
+        set_record_coords(&mut records, &[1], &vec![centroid], [0, 1], None);
+        assert_eq!(records[0], vec!["lat", "lon"]);
+        assert_eq!(
+            records[1],
+            vec![centroid.0[1].to_string(), centroid.0[0].to_string()]
+        );
+        // Averages carry float noise, --precision rounds it off
+        set_record_coords(&mut records, &[1], &vec![centroid], [0, 1], Some(7));
+        assert_eq!(records[1], vec!["60.000075", "30.000075"]);
+    }
+
+    #[test]
+    fn test_kd_tree_diagnostics() {
+        let lattice: PointList = (0..63)
+            .map(|i| Point([(i % 9) as f64 * 0.001, (i / 9) as f64 * 0.001]))
+            .collect();
+        assert_eq!(
+            kd_tree_diagnostics(&new_kd_tree(lattice)),
+            ["K-D tree height: 7 (1.17x the height of a balanced tree)"]
+        );
+
+        // Collinear points waste levels on the constant coordinate
+        let line: PointList = (0..1023).map(|i| Point([i as f64 * 0.001, 0.0])).collect();
+        let lines = kd_tree_diagnostics(&new_kd_tree(line));
+        assert_eq!(
+            lines[0],
+            "K-D tree height: 18 (1.80x the height of a balanced tree)"
+        );
+        assert!(lines[1].contains("--index grid"));
+    }
+
+    #[test]
+    fn test_write_cluster_summary() {
+        let points: PointList = vec![
+            Point([30.0, 60.0]),
+            Point([30.2, 60.1]),
+            Point([30.1, 59.9]),
+            Point([10.0, 50.0]),
+        ];
+        let clusters = vec![
+            Cluster {
+                c: 0,
+                points: vec![0, 1, 2],
+            },
+            Cluster {
+                c: 1,
+                points: vec![],
+            },
+            Cluster {
+                c: 2,
+                points: vec![3],
+            },
+        ];
+
+        let mut out = Vec::new();
+        write_cluster_summary(
+            &mut out,
+            &clusters,
+            &points,
+            b',',
+            CoordOrder::Latlon,
+            Some(3),
+        )
+        .unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+
+        // The empty cluster is skipped instead of panicking
+        assert_eq!(
+            lines,
+            [
+                "cluster_id,centroid_lat,centroid_lon,min_lat,min_lon,max_lat,max_lon,point_count",
+                "0,60,30.1,59.9,30,60.1,30.2,3",
+                "2,50,10,50,10,50,10,1",
+            ]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_write_cluster_summary_json() {
+        let points: PointList = vec![
+            Point([30.0, 60.0]),
+            Point([30.2, 60.1]),
+            Point([30.1, 59.9]),
+            Point([10.0, 50.0]),
+        ];
+        let clusters = vec![
+            Cluster {
+                c: 0,
+                points: vec![0, 1, 2],
+            },
+            Cluster {
+                c: 1,
+                points: vec![],
+            },
+        ];
+
+        let mut out = Vec::new();
+        crate::write_cluster_summary_json(&mut out, &clusters, &points, Some(3)).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).expect("Invalid JSON");
+
+        // The empty cluster is skipped, the diagonal is measured on the sphere
+        let diameter = Point([30.0, 59.9]).dist_km(&Point([30.2, 60.1]));
+        assert!((diameter - 24.86).abs() < 0.01, "{}", diameter);
+        assert_eq!(
+            json,
+            serde_json::json!([{
+                "id": 0,
+                "count": 3,
+                "centroid": [30.1, 60.0],
+                "bbox": [[30.0, 59.9], [30.2, 60.1]],
+                "diameter_km": diameter,
+            }])
+        );
+    }
+
+    #[test]
+    fn test_write_wkt() {
+        let points: PointList = vec![
+            Point([30.0, 60.0]),
+            Point([30.2, 60.0]),
+            Point([30.1, 60.1]),
+            Point([30.1, 60.05]),
+            Point([10.0, 50.0]),
+            Point([11.0, 51.0]),
+            Point([-1.5, 40.25]),
+        ];
+        let clusters = vec![
+            Cluster {
+                c: 0,
+                points: vec![0, 1, 2, 3],
+            },
+            Cluster {
+                c: 1,
+                points: vec![4, 5],
+            },
+        ];
+
+        // Point 3 of cluster 0 is written once, though passed as noise too
+        let mut out = Vec::new();
+        write_wkt(&mut out, &clusters, &[3, 6], &points, false, Some(3)).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "MULTIPOINT ((30 60), (30.2 60), (30.1 60.1), (30.1 60.05))\n\
+             MULTIPOINT ((10 50), (11 51))\n\
+             POINT (-1.5 40.25)\n"
+        );
+
+        // Hulls are closed rings, the interior point is left out
+        let mut out = Vec::new();
+        write_wkt(&mut out, &clusters, &[], &points, true, Some(3)).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[1], "POLYGON ((30 60, 30.2 60, 30.1 60.1, 30 60))");
+        assert_eq!(lines[3], "LINESTRING (10 50, 11 51)");
+    }
+
+    #[test]
+    fn test_noise_histogram() {
+        assert!(noise_histogram(&[], 3).is_empty());
+
+        let noise: Vec<NoiseInfo> = [2, 1, 2, 2, 1, 2, 2, 2]
+            .iter()
+            .enumerate()
+            .map(|(index, &neighbor_count)| NoiseInfo {
+                index,
+                neighbor_count,
+            })
+            .collect();
+        let lines = noise_histogram(&noise, 3);
+        assert_eq!(
+            lines,
+            [
+                "Noise points by neighbour count (min-points 3):".to_string(),
+                format!("     1        2 {}", "#".repeat(14)),
+                format!("     2        6 {}", "#".repeat(40)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_reachability() {
+        let ordering = [
+            (2, f64::INFINITY, 0.25),
+            (0, 0.123456, f64::INFINITY),
+            (1, 0.5, 0.5),
+        ];
+        let mut out = Vec::new();
+        write_reachability(&mut out, &ordering, Some(3)).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "index,reachability_km,core_distance_km\n2,,0.25\n0,0.123,\n1,0.5,0.5\n"
+        );
+    }
+
+    #[test]
+    fn test_write_k_distances() {
+        let mut out = Vec::new();
+        write_k_distances(&mut out, &[0.0, 0.123456, 2.5], "km", Some(3)).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "rank,distance_km\n1,0\n2,0.123\n3,2.5\n"
+        );
+    }
+
+    #[test]
+    fn test_cluster_stats() {
+        let points: PointList = vec![
+            Point([0.0, 0.0]),
+            Point([0.01, 0.0]),
+            Point([0.0, 0.01]),
+            Point([10.0, 10.0]),
+            Point([10.001, 10.0]),
+            Point([20.0, 20.0]),
+        ];
+        let clusters = vec![
+            Cluster {
+                c: 0,
+                points: vec![0, 1, 2],
+            },
+            Cluster {
+                c: 1,
+                points: vec![3, 4],
+            },
+        ];
+
+        assert_eq!(
+            cluster_stats(&clusters, &[5], &points, None),
+            [
+                "Clusters: 2",
+                "Noise points: 1",
+                "Cluster size: min 2, mean 2.5, max 3",
+                "Largest cluster bounding box diagonal: 1.573 km",
+                "Clustering quality: 0.999 (approximate silhouette, noise excluded)",
+            ]
+        );
+        assert_eq!(
+            cluster_stats(&clusters, &[5], &points, Some(1))
+                .last()
+                .unwrap(),
+            "Clustering quality: 0.999 (approximate silhouette, noise excluded)"
+        );
+        assert_eq!(cluster_stats(&clusters[..1], &[], &points, None).len(), 4);
+        assert_eq!(
+            cluster_stats(&[], &[0, 1, 2, 3, 4, 5], &points, None),
+            ["Clusters: 0", "Noise points: 6"]
+        );
+    }
 }
@@ -0,0 +1,984 @@
+//! Output formatting and writers
+//!
+//! Rows are formatted into string fragments, in parallel under the `rayon`
+//! feature, and then written sequentially so that the output order is kept.
+//! Writers cover the filtered points (CSV, GeoJSON, JSON Lines), clusters
+//! (summaries, GeoJSON, WKT) and the extra columns added to CSV records.
+
+use crate::cluster::distance::distance_haversine_with_radius;
+use crate::cluster::{self, Cluster, Point, PointList, convex_hull};
+use crate::filter::DBSCAN_OUTLIER_INDEX;
+use crate::input::{CoordOrder, CsvRecords, SkippedRow, is_header};
+use csv::{ReaderBuilder, WriterBuilder};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Number of rows formatted at once before being written, bounds memory use
+#[cfg(feature = "rayon")]
+const FORMAT_BATCH_ROWS: usize = 65_536;
+
+/// Writes `format(row)` for every row, in order
+///
+/// Formatting runs on all threads under the `rayon` feature, the output is
+/// byte-identical to [`write_rows_serial`] either way.
+pub fn write_rows<T: Sync>(
+    out: &mut impl Write,
+    rows: &[T],
+    format: impl Fn(&T) -> String + Sync,
+) -> io::Result<()> {
+    #[cfg(feature = "rayon")]
+    return write_rows_parallel(out, rows, format);
+    #[cfg(not(feature = "rayon"))]
+    write_rows_serial(out, rows, format)
+}
+
+/// Writes `format(row)` for every row, in order, formatting on the current thread
+pub fn write_rows_serial<T>(
+    out: &mut impl Write,
+    rows: &[T],
+    format: impl Fn(&T) -> String,
+) -> io::Result<()> {
+    for row in rows {
+        out.write_all(format(row).as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Writes `format(row)` for every row, in order, formatting batches of rows
+/// on all threads
+#[cfg(feature = "rayon")]
+pub fn write_rows_parallel<T: Sync>(
+    out: &mut impl Write,
+    rows: &[T],
+    format: impl Fn(&T) -> String + Sync,
+) -> io::Result<()> {
+    use rayon::prelude::*;
+
+    for batch in rows.chunks(FORMAT_BATCH_ROWS) {
+        let fragments: Vec<String> = batch.par_iter().map(&format).collect();
+        for fragment in fragments {
+            out.write_all(fragment.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// Most decimal places [`round_coord`] rounds to, f64 has ~15 significant digits
+pub const MAX_COORD_DIGITS: u32 = 15;
+
+/// Rounds a coordinate to `digits` decimal places, `None` keeps full precision
+///
+/// Rounded values print without trailing zeros, e.g. `40.7128` rather than
+/// `40.7128000` for 7 digits.
+pub fn round_coord(value: f64, digits: Option<u32>) -> f64 {
+    match digits {
+        Some(digits) => {
+            let scale = 10f64.powi(digits.min(MAX_COORD_DIGITS) as i32);
+            (value * scale).round() / scale
+        }
+        None => value,
+    }
+}
+
+/// Label [`LabelFormat::default`] renders noise as
+pub const DEFAULT_NOISE_LABEL: &str = "-1";
+
+/// Rendering of cluster labels in output, with noise (negative labels) as a
+/// configurable string, e.g. `""` or `"noise"` instead of `-1`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelFormat {
+    noise: String,
+}
+
+impl LabelFormat {
+    /// Creates a format rendering noise as `noise`
+    pub fn new(noise: impl Into<String>) -> Self {
+        LabelFormat {
+            noise: noise.into(),
+        }
+    }
+
+    /// Returns the text of a label, e.g. for a CSV column
+    pub fn text(&self, label: i32) -> Cow<'_, str> {
+        if label < 0 {
+            Cow::Borrowed(&self.noise)
+        } else {
+            Cow::Owned(label.to_string())
+        }
+    }
+
+    /// Returns the JSON value of a label
+    ///
+    /// Cluster ids are numbers. The noise label is a number when it is an
+    /// integer, like the default `-1`, and a string otherwise.
+    pub fn json(&self, label: i32) -> String {
+        if label >= 0 {
+            return label.to_string();
+        }
+        match self.noise.parse::<i64>() {
+            Ok(number) => number.to_string(),
+            Err(_) => serde_json::Value::from(self.noise.as_str()).to_string(),
+        }
+    }
+}
+
+impl Default for LabelFormat {
+    fn default() -> Self {
+        LabelFormat::new(DEFAULT_NOISE_LABEL)
+    }
+}
+
+/// Formats one JSON Lines row of a point with its cluster label
+pub fn jsonl_row(lat: f64, lon: f64, cluster: i32, labels: &LabelFormat) -> String {
+    format!(
+        "{{\"lat\":{},\"lon\":{},\"cluster\":{}}}\n",
+        lat,
+        lon,
+        labels.json(cluster)
+    )
+}
+
+/// Color [`cluster_color`] gives noise points
+pub const NOISE_COLOR: &str = "#808080";
+
+/// Returns the `#rrggbb` color of a cluster id, noise (negative ids) is [`NOISE_COLOR`]
+///
+/// The hue steps around the HSV wheel by the golden ratio of a turn per id,
+/// so neighbouring ids get clearly different colors, at fixed saturation and
+/// value. Colors only depend on the id, not on the run or the input.
+pub fn cluster_color(id: i32) -> String {
+    if id < 0 {
+        return NOISE_COLOR.to_string();
+    }
+
+    let (saturation, value) = (0.65, 0.9);
+    let hue = (f64::from(id) * 0.618_033_988_749_895).fract() * 6.0;
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let byte = |c: f64| ((c + value - chroma) * 255.0).round() as u8;
+    format!("#{:02x}{:02x}{:02x}", byte(r), byte(g), byte(b))
+}
+
+/// Header of the column added by [`add_order_index`]
+pub const ORDER_INDEX_COLUMN: &str = "original_index";
+
+/// Header of the column added by [`add_label_column`]
+pub const LABEL_COLUMN: &str = "cluster";
+
+/// Header of the column added by [`add_color_column`]
+pub const COLOR_COLUMN: &str = "color";
+
+/// Header of the column added by [`add_source_column`]
+pub const SOURCE_COLUMN: &str = "source";
+
+/// Appends the 0-based position of every data row as an extra column
+///
+/// The header row, when present, gets an `original_index` cell. The column
+/// travels with its record, so it survives any filtering or reordering.
+pub fn add_order_index(csv_records: &mut CsvRecords) {
+    let has_header = !csv_records.is_empty() && is_header(&csv_records[0]);
+    let start_idx = if has_header { 1 } else { 0 };
+
+    if has_header {
+        csv_records[0].push(ORDER_INDEX_COLUMN.to_string());
+    }
+    for (i, record) in csv_records.iter_mut().skip(start_idx).enumerate() {
+        record.push(i.to_string());
+    }
+}
+
+/// Appends the input file of every data row as an extra column
+///
+/// `sources[i]` is the index in `files` of the file of `csv_records[i]` (see
+/// [`read_csv_files`](crate::input::read_csv_files)), the header row, when
+/// present, gets a `source` cell. The column travels with its record, like
+/// the one of [`add_order_index`].
+pub fn add_source_column(csv_records: &mut CsvRecords, sources: &[usize], files: &[PathBuf]) {
+    let has_header = csv_records.first().is_some_and(|r| is_header(r));
+    for (i, (record, &source)) in csv_records.iter_mut().zip(sources).enumerate() {
+        if i == 0 && has_header {
+            record.push(SOURCE_COLUMN.to_string());
+        } else {
+            record.push(files[source].display().to_string());
+        }
+    }
+}
+
+/// Appends the cluster id of every point as an extra column
+///
+/// Noise points get `-1`. `rows[i]` is the record of point `i` (see
+/// [`point_records`](crate::input::point_records)), the header row, when
+/// present, gets a `cluster` cell. The column goes after all cells a record
+/// already has.
+pub fn add_label_column(
+    records: &mut CsvRecords,
+    rows: &[usize],
+    labels: &[i32],
+    format: &LabelFormat,
+) {
+    if let Some(header) = records.first_mut()
+        && is_header(header)
+    {
+        header.push(LABEL_COLUMN.to_string());
+    }
+    for (&row, &label) in rows.iter().zip(labels) {
+        records[row].push(format.text(label).into_owned());
+    }
+}
+
+/// Appends the [`cluster_color`] of every point as an extra column
+///
+/// Like [`add_label_column`], the header row, when present, gets a `color`
+/// cell.
+pub fn add_color_column(records: &mut CsvRecords, rows: &[usize], labels: &[i32]) {
+    if let Some(header) = records.first_mut()
+        && is_header(header)
+    {
+        header.push(COLOR_COLUMN.to_string());
+    }
+    for (&row, &label) in rows.iter().zip(labels) {
+        records[row].push(cluster_color(label));
+    }
+}
+
+/// Names of the columns appended by [`add_aggregate_columns`]
+pub const AGGREGATE_COLUMNS: [&str; 5] = [
+    "cluster_size",
+    "centroid_lat",
+    "centroid_lon",
+    "bbox_width_km",
+    "bbox_height_km",
+];
+
+/// Appends statistics of the cluster every point belongs to as extra columns
+///
+/// Columns are [`AGGREGATE_COLUMNS`]: member count, centroid and the size of
+/// the cluster's bounding box on the globe (see [`Cluster::geo_bbox`]). Noise
+/// points count as clusters of their own. `rows[i]` is the record of point
+/// `i` (see [`point_records`](crate::input::point_records)), a header row
+/// gets the column names. Box sizes are measured on a sphere of
+/// `earth_radius` km.
+///
+/// Returns the number of appended columns.
+pub fn add_aggregate_columns(
+    records: &mut CsvRecords,
+    rows: &[usize],
+    clusters: &[Cluster],
+    points: &PointList,
+    earth_radius: f64,
+    digits: Option<u32>,
+) -> usize {
+    if let Some(header) = records.first_mut()
+        && is_header(header)
+    {
+        header.extend(AGGREGATE_COLUMNS.iter().map(|c| c.to_string()));
+    }
+
+    let stats: Vec<[f64; 5]> = clusters
+        .iter()
+        .map(|cluster| {
+            if cluster.points.is_empty() {
+                return [0.0; 5];
+            }
+            let (center, _, _) = cluster.centroid_and_bounds(points);
+            let (min, max) = cluster.geo_bbox(points);
+            let width = distance_haversine_with_radius(
+                &Point([min.0[0], center.0[1]]),
+                &Point([max.0[0], center.0[1]]),
+                earth_radius,
+            );
+            let height = distance_haversine_with_radius(
+                &Point([center.0[0], min.0[1]]),
+                &Point([center.0[0], max.0[1]]),
+                earth_radius,
+            );
+            [
+                cluster.points.len() as f64,
+                center.0[1],
+                center.0[0],
+                width,
+                height,
+            ]
+        })
+        .collect();
+
+    let labels = cluster::cluster_labels(clusters, points.len());
+    for (i, &row) in rows.iter().enumerate() {
+        let [size, lat, lon, width, height] = match usize::try_from(labels[i]) {
+            Ok(c) => stats[c],
+            Err(_) => [1.0, points[i].0[1], points[i].0[0], 0.0, 0.0],
+        };
+        records[row].extend([
+            size.to_string(),
+            round_coord(lat, digits).to_string(),
+            round_coord(lon, digits).to_string(),
+            width.to_string(),
+            height.to_string(),
+        ]);
+    }
+
+    AGGREGATE_COLUMNS.len()
+}
+
+/// Replaces the latitude/longitude cells of point records with projected `y`/`x`
+///
+/// `rows[i]` is the record of point `i` (see
+/// [`point_records`](crate::input::point_records)) and `projected[i]` its
+/// projected `Point([x, y])`, `columns` are the `[latitude, longitude]`
+/// column indices. A header row, when present, gets `y`/`x` column names.
+/// Values are rounded to `digits` decimal places.
+pub fn reproject_records(
+    records: &mut CsvRecords,
+    rows: &[usize],
+    projected: &PointList,
+    columns: [usize; 2],
+    digits: Option<u32>,
+) {
+    let [lat, lon] = columns;
+    if let Some(header) = records.first_mut()
+        && is_header(header)
+        && header.len() > lat.max(lon)
+    {
+        header[lat] = "y".to_string();
+        header[lon] = "x".to_string();
+    }
+
+    set_record_coords(records, rows, projected, columns, digits);
+}
+
+/// Writes `points[i]` into the `[latitude, longitude]` cells of record `rows[i]`,
+/// rounded to `digits` decimal places
+pub fn set_record_coords(
+    records: &mut CsvRecords,
+    rows: &[usize],
+    points: &PointList,
+    columns: [usize; 2],
+    digits: Option<u32>,
+) {
+    let [lat, lon] = columns;
+    for (&row, p) in rows.iter().zip(points) {
+        let record = &mut records[row];
+        // Filled in missing cells may be beyond the end of the record
+        if record.len() <= lat.max(lon) {
+            record.resize(lat.max(lon) + 1, String::new());
+        }
+        record[lat] = round_coord(p.0[1], digits).to_string();
+        record[lon] = round_coord(p.0[0], digits).to_string();
+    }
+}
+
+/// Writes filtered points to output CSV
+///
+/// Uses pre-read CSV records to preserve any additional columns
+pub fn write_filtered_points_to_csv(
+    output_file: &Path,
+    csv_records: &[Vec<String>],
+    filtered_indices: &[usize],
+    delimiter: u8,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Create a set of filtered indices for quick lookup
+    let filtered_set: std::collections::HashSet<usize> = filtered_indices.iter().copied().collect();
+
+    // Determine if first row is header
+    let has_header = if !csv_records.is_empty() {
+        is_header(&csv_records[0])
+    } else {
+        false
+    };
+    let start_idx = if has_header { 1 } else { 0 };
+
+    // Header and filtered data rows, in input order
+    let rows: Vec<&Vec<String>> = csv_records[..start_idx]
+        .iter()
+        .chain(
+            csv_records
+                .iter()
+                .enumerate()
+                .skip(start_idx)
+                .filter(|(i, _)| filtered_set.contains(&(i - start_idx)))
+                .map(|(_, record)| record),
+        )
+        .collect();
+
+    write_atomically(output_file, |out_file| {
+        // Chunks are formatted in parallel under the rayon feature and
+        // written in order
+        let chunks: Vec<&[&Vec<String>]> = rows.chunks(CSV_CHUNK_ROWS).collect();
+        write_rows(out_file, &chunks, |chunk| {
+            format_csv_records(chunk, delimiter)
+        })?;
+        out_file.flush()?;
+        Ok(())
+    })
+}
+
+/// Records formatted at once by [`write_filtered_points_to_csv`], so that the
+/// CSV writer of a chunk is set up once for many rows
+const CSV_CHUNK_ROWS: usize = 1024;
+
+/// Formats records as CSV rows, the way a single CSV writer would write them
+fn format_csv_records(records: &[&Vec<String>], delimiter: u8) -> String {
+    let mut writer = WriterBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_writer(Vec::new());
+    for record in records {
+        writer
+            .write_record(*record)
+            .expect("writing CSV to memory can't fail");
+    }
+    let bytes = writer
+        .into_inner()
+        .expect("writing CSV to memory can't fail");
+    // Records are strings, so their CSV is valid UTF-8
+    String::from_utf8(bytes).expect("CSV of strings is valid UTF-8")
+}
+
+/// Writes filtered points by reading the CSV input a second time
+///
+/// Counterpart of [`stream_points`](crate::input::stream_points): the output
+/// is the one of [`write_filtered_points_to_csv`] (with `output`) or
+/// [`write_filtered_points_to_stdout`] without extra columns, records are
+/// matched to point indices by skipping the header and `skipped` rows.
+pub fn stream_filtered_points(
+    input: &Path,
+    output: Option<&Path>,
+    delimiter: u8,
+    skipped: &[SkippedRow],
+    filtered_indices: &[usize],
+    columns: [usize; 2],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let filtered_set: std::collections::HashSet<usize> = filtered_indices.iter().copied().collect();
+    let skipped_records: std::collections::HashSet<usize> =
+        skipped.iter().map(|row| row.record).collect();
+
+    // Calls `write` with the header and every filtered record
+    let for_each_row = |write: &mut dyn FnMut(&csv::StringRecord, bool) -> io::Result<()>| {
+        let mut reader = ReaderBuilder::new()
+            .delimiter(delimiter)
+            .has_headers(false)
+            .flexible(true)
+            .from_path(input)?;
+        let mut point_idx = 0;
+        for (i, result) in reader.records().enumerate() {
+            let record = result?;
+            if i == 0 && is_header(&record.iter().map(String::from).collect::<Vec<_>>()) {
+                write(&record, true)?;
+                continue;
+            }
+            if skipped_records.contains(&i) {
+                continue;
+            }
+            if filtered_set.contains(&point_idx) {
+                write(&record, false)?;
+            }
+            point_idx += 1;
+        }
+        Ok::<_, Box<dyn std::error::Error>>(())
+    };
+
+    match output {
+        Some(path) => write_atomically(path, |out_file| {
+            let mut writer = WriterBuilder::new()
+                .delimiter(delimiter)
+                .flexible(true)
+                .from_writer(out_file);
+            for_each_row(&mut |record, _| Ok(writer.write_record(record)?))?;
+            writer.flush()?;
+            Ok(())
+        }),
+        None => {
+            let mut out = BufWriter::new(io::stdout().lock());
+            let separator = delimiter as char;
+            for_each_row(&mut |record, is_header| {
+                if let (false, Some(lat), Some(lon)) =
+                    (is_header, record.get(columns[0]), record.get(columns[1]))
+                {
+                    writeln!(out, "{}{}{}", lat, separator, lon)?;
+                }
+                Ok(())
+            })?;
+            out.flush()?;
+            Ok(())
+        }
+    }
+}
+
+/// Writes a file through `write`, so that it either appears complete or not at all
+///
+/// Data goes to a temporary file in the same directory, which is renamed
+/// into place when `write` succeeds and removed otherwise. An existing file
+/// at `path` is left untouched on error.
+pub fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<(), Box<dyn std::error::Error>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let file_name = path.file_name().ok_or("output path has no file name")?;
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(file_name);
+    tmp_name.push(format!(".tmp-{}", std::process::id()));
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let result = File::create(&tmp_path)
+        .map_err(Into::into)
+        .and_then(|file| {
+            let mut out = BufWriter::new(file);
+            write(&mut out)?;
+            let file = out.into_inner().map_err(|e| e.into_error())?;
+            file.sync_all()?;
+            Ok(())
+        })
+        .and_then(|()| std::fs::rename(&tmp_path, path).map_err(Into::into));
+
+    if result.is_err() {
+        std::fs::remove_file(&tmp_path).ok();
+    }
+    result
+}
+
+/// Writes filtered points to stdout as a simple list
+///
+/// Format: the two `columns` cells, e.g. `latitude,longitude` (one point per
+/// line), followed by the last `extra_columns` cells of the record
+/// (`original_index`, aggregate columns), separated by `delimiter`
+///
+/// Uses pre-read CSV records to preserve order
+pub fn write_filtered_points_to_stdout(
+    csv_records: &[Vec<String>],
+    filtered_indices: &[usize],
+    columns: [usize; 2],
+    extra_columns: usize,
+    delimiter: u8,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Create a set of filtered indices for quick lookup
+    let filtered_set: std::collections::HashSet<usize> = filtered_indices.iter().copied().collect();
+
+    // Determine if first row is header
+    let has_header = if !csv_records.is_empty() {
+        is_header(&csv_records[0])
+    } else {
+        false
+    };
+
+    // Write filtered points to stdout
+    let separator = &(delimiter as char).to_string();
+    let start_idx = if has_header { 1 } else { 0 };
+
+    for (i, record) in csv_records.iter().enumerate().skip(start_idx) {
+        let point_idx = i - start_idx;
+        if filtered_set.contains(&point_idx) {
+            // Output as: latitude,longitude[,extra columns]
+            let [first, second] = columns;
+            let (Some(first), Some(second)) = (record.get(first), record.get(second)) else {
+                continue;
+            };
+            if record.len() >= 2 + extra_columns {
+                let mut line = vec![first.as_str(), second.as_str()];
+                line.extend(
+                    record[record.len() - extra_columns..]
+                        .iter()
+                        .map(String::as_str),
+                );
+                println!("{}", line.join(separator));
+            } else {
+                println!("{}{}{}", first, separator, second);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Opens the output file, or stdout when no file is given
+pub fn open_output(output_file: Option<&PathBuf>) -> io::Result<Box<dyn Write>> {
+    Ok(match output_file {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    })
+}
+
+/// Writes an OPTICS ordering as `index,reachability_km,core_distance_km` CSV
+/// rows
+///
+/// Distances are rounded to `digits` decimal places, undefined (infinite)
+/// ones are left empty.
+pub fn write_reachability<W: Write>(
+    out: &mut W,
+    ordering: &[(usize, f64, f64)],
+    digits: Option<u32>,
+) -> io::Result<()> {
+    let field = |dist: f64| {
+        if dist.is_finite() {
+            round_coord(dist, digits).to_string()
+        } else {
+            String::new()
+        }
+    };
+    writeln!(out, "index,reachability_km,core_distance_km")?;
+    for &(i, reach, core_dist) in ordering {
+        writeln!(out, "{},{},{}", i, field(reach), field(core_dist))?;
+    }
+    Ok(())
+}
+
+/// Writes the k-distance curve as `rank,distance_<unit>` CSV rows
+///
+/// Ranks start at 1, distances are rounded to `digits` decimal places.
+/// `unit` is `km` for geographic metrics and `units` for planar ones.
+pub fn write_k_distances<W: Write>(
+    out: &mut W,
+    k_dist: &[f64],
+    unit: &str,
+    digits: Option<u32>,
+) -> io::Result<()> {
+    writeln!(out, "rank,distance_{}", unit)?;
+    for (rank, &dist) in k_dist.iter().enumerate() {
+        writeln!(out, "{},{}", rank + 1, round_coord(dist, digits))?;
+    }
+    Ok(())
+}
+
+/// Writes one CSV row per cluster with its centroid, bounding box and size
+///
+/// Columns are `cluster_id,centroid_lat,centroid_lon,min_lat,min_lon,max_lat,max_lon,point_count`,
+/// with every latitude/longitude pair in `order`. Coordinates are rounded to
+/// `digits` decimal places. Empty clusters have no centroid and are skipped.
+pub fn write_cluster_summary(
+    out: &mut impl Write,
+    clusters: &[Cluster],
+    points: &PointList,
+    delimiter: u8,
+    order: CoordOrder,
+    digits: Option<u32>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(out);
+    let mut header = vec!["cluster_id"];
+    for pair in [
+        ["centroid_lat", "centroid_lon"],
+        ["min_lat", "min_lon"],
+        ["max_lat", "max_lon"],
+    ] {
+        header.extend(order.arrange(pair));
+    }
+    header.push("point_count");
+    writer.write_record(&header)?;
+
+    for cluster in clusters {
+        if cluster.points.is_empty() {
+            continue;
+        }
+        let (center, min, max) = cluster.centroid_and_bounds(points);
+        let mut row = vec![cluster.c.to_string()];
+        for pt in [center, min, max] {
+            let coords = order.arrange([pt.0[1], pt.0[0]]);
+            row.extend(coords.map(|v| round_coord(v, digits).to_string()));
+        }
+        row.push(cluster.points.len().to_string());
+        writer.write_record(&row)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// One cluster of [`write_cluster_summary_json`], coordinates are `[lon, lat]`
+/// as in GeoJSON
+#[cfg(feature = "serde")]
+#[derive(Debug, serde::Serialize)]
+pub struct ClusterSummary {
+    /// Cluster id
+    pub id: usize,
+    /// Number of points
+    pub count: usize,
+    /// Centroid, see [`Cluster::centroid_and_bounds`]
+    pub centroid: [f64; 2],
+    /// `[[min_lon, min_lat], [max_lon, max_lat]]`
+    pub bbox: [[f64; 2]; 2],
+    /// Great-circle length of the bounding box diagonal
+    pub diameter_km: f64,
+}
+
+/// Writes a JSON array with one [`ClusterSummary`] per cluster
+///
+/// Same clusters and values as [`write_cluster_summary`], coordinates are
+/// rounded to `digits` decimal places. Empty clusters are skipped.
+#[cfg(feature = "serde")]
+pub fn write_cluster_summary_json(
+    out: &mut impl Write,
+    clusters: &[Cluster],
+    points: &PointList,
+    digits: Option<u32>,
+) -> serde_json::Result<()> {
+    let round = |pt: Point| [round_coord(pt.0[0], digits), round_coord(pt.0[1], digits)];
+    let summaries: Vec<ClusterSummary> = clusters
+        .iter()
+        .filter(|cluster| !cluster.points.is_empty())
+        .map(|cluster| {
+            let (center, min, max) = cluster.centroid_and_bounds(points);
+            ClusterSummary {
+                id: cluster.c,
+                count: cluster.points.len(),
+                centroid: round(center),
+                bbox: [round(min), round(max)],
+                diameter_km: min.dist_km(&max),
+            }
+        })
+        .collect();
+    serde_json::to_writer(out, &summaries)
+}
+
+/// Cluster labels in the properties of GeoJSON features
+pub struct FeatureLabels<'a> {
+    /// Cluster label of every point, `-1` for noise
+    pub ids: &'a [i32],
+    /// Names of clusters indexed by cluster id, none when empty
+    pub names: &'a [String],
+    /// Rendering of the labels
+    pub format: &'a LabelFormat,
+}
+
+/// Writes clusters as a GeoJSON `FeatureCollection` of `MultiPoint` features
+///
+/// Every cluster becomes one feature holding the `[lon, lat]` coordinates of all
+/// its members, with `cluster`, `size` and `centroid` properties, plus `name`
+/// when `labels.names` is not empty. Noise points, if any, are gathered in a
+/// single feature with `cluster` set to the noise label of `labels.format`.
+/// Points of `noise` that are members of a cluster are written only there.
+pub fn write_clusters_multipoint(
+    out: &mut impl Write,
+    clusters: &[Cluster],
+    noise: &[usize],
+    points: &PointList,
+    labels: &FeatureLabels,
+    digits: Option<u32>,
+) -> io::Result<()> {
+    write!(out, "{{\"type\":\"FeatureCollection\",\"features\":[")?;
+
+    let mut first = true;
+    for cluster in clusters {
+        if cluster.points.is_empty() {
+            continue;
+        }
+        if !first {
+            write!(out, ",")?;
+        }
+        first = false;
+
+        let (center, _, _) = cluster.centroid_and_bounds(points);
+        write_multipoint_feature(out, &cluster.points, points, digits)?;
+        write!(
+            out,
+            ",\"properties\":{{\"cluster\":{},\"size\":{},\"centroid\":[{},{}]",
+            cluster.c,
+            cluster.points.len(),
+            round_coord(center.0[0], digits),
+            round_coord(center.0[1], digits)
+        )?;
+        if let Some(name) = labels.names.get(cluster.c) {
+            write!(out, ",\"name\":{}", serde_json::Value::from(name.as_str()))?;
+        }
+        write!(out, "}}}}")?;
+    }
+
+    let in_cluster = cluster::cluster_labels(clusters, points.len());
+    let noise: Vec<usize> = noise
+        .iter()
+        .copied()
+        .filter(|&i| in_cluster[i] == DBSCAN_OUTLIER_INDEX)
+        .collect();
+    if !noise.is_empty() {
+        if !first {
+            write!(out, ",")?;
+        }
+        write_multipoint_feature(out, &noise, points, digits)?;
+        write!(
+            out,
+            ",\"properties\":{{\"cluster\":{},\"size\":{}}}}}",
+            labels.format.json(DBSCAN_OUTLIER_INDEX),
+            noise.len()
+        )?;
+    }
+
+    writeln!(out, "]}}")
+}
+
+/// Writes the opening of a `MultiPoint` feature up to (excluding) its properties
+fn write_multipoint_feature(
+    out: &mut impl Write,
+    indices: &[usize],
+    points: &PointList,
+    digits: Option<u32>,
+) -> io::Result<()> {
+    write!(
+        out,
+        "{{\"type\":\"Feature\",\"geometry\":{{\"type\":\"MultiPoint\",\"coordinates\":["
+    )?;
+    let coord = |idx: usize, j: usize| round_coord(points[idx].0[j], digits);
+    if let Some((&first, rest)) = indices.split_first() {
+        write!(out, "[{},{}]", coord(first, 0), coord(first, 1))?;
+        write_rows(out, rest, |&idx| {
+            format!(",[{},{}]", coord(idx, 0), coord(idx, 1))
+        })?;
+    }
+    write!(out, "]}}")
+}
+
+/// Writes clusters as well-known text, one geometry per line
+///
+/// Every cluster becomes a `MULTIPOINT` of its members, followed by a
+/// `POLYGON` of its convex hull (see [`convex_hull`]) with `hull`.
+/// Hulls of fewer than three distinct points are written as `POINT` or
+/// `LINESTRING`. Noise points follow as individual `POINT`s, points of
+/// `noise` that are members of a cluster are written only there. Coordinates
+/// are `lon lat` as per WKT convention.
+pub fn write_wkt(
+    out: &mut impl Write,
+    clusters: &[Cluster],
+    noise: &[usize],
+    points: &PointList,
+    hull: bool,
+    digits: Option<u32>,
+) -> io::Result<()> {
+    let coord = |idx: usize| {
+        let p = points[idx];
+        format!(
+            "{} {}",
+            round_coord(p.0[0], digits),
+            round_coord(p.0[1], digits)
+        )
+    };
+    let coords = |indices: &[usize], wrap: bool| {
+        let coords: Vec<String> = indices
+            .iter()
+            .map(|&idx| {
+                if wrap {
+                    format!("({})", coord(idx))
+                } else {
+                    coord(idx)
+                }
+            })
+            .collect();
+        coords.join(", ")
+    };
+
+    for cluster in clusters {
+        if cluster.points.is_empty() {
+            continue;
+        }
+        writeln!(out, "MULTIPOINT ({})", coords(&cluster.points, true))?;
+        if hull {
+            let mut ring = convex_hull(points, cluster);
+            match ring.len() {
+                1 => writeln!(out, "POINT ({})", coord(ring[0]))?,
+                2 if points[ring[0]] == points[ring[1]] => {
+                    writeln!(out, "POINT ({})", coord(ring[0]))?
+                }
+                2 => writeln!(out, "LINESTRING ({})", coords(&ring, false))?,
+                _ => {
+                    ring.push(ring[0]);
+                    writeln!(out, "POLYGON (({}))", coords(&ring, false))?;
+                }
+            }
+        }
+    }
+    let in_cluster = cluster::cluster_labels(clusters, points.len());
+    let noise: Vec<usize> = noise
+        .iter()
+        .copied()
+        .filter(|&i| in_cluster[i] == DBSCAN_OUTLIER_INDEX)
+        .collect();
+    write_rows(out, &noise, |&idx| format!("POINT ({})\n", coord(idx)))
+}
+
+/// Writes the given points as a GeoJSON `FeatureCollection` of `Point` features
+///
+/// Properties are the cells of each point's record, named after the header
+/// row (`column<n>` without a header or beyond it), plus its `cluster` label
+/// from `labels.ids`. `rows[i]` is the record of point `i`, see
+/// [`point_records`](crate::input::point_records). Coordinates are written
+/// as-is, round them with [`round_coord`] beforehand.
+pub fn write_points_geojson(
+    out: &mut impl Write,
+    records: &[Vec<String>],
+    rows: &[usize],
+    indices: &[usize],
+    points: &PointList,
+    labels: &FeatureLabels,
+) -> io::Result<()> {
+    let header = records
+        .first()
+        .filter(|r| is_header(r))
+        .map_or(&[][..], |r| r.as_slice());
+    let names: Vec<String> = (0..records.iter().map(Vec::len).max().unwrap_or(0))
+        .map(|j| {
+            let name = header
+                .get(j)
+                .cloned()
+                .unwrap_or_else(|| format!("column{}", j));
+            serde_json::Value::from(name).to_string()
+        })
+        .collect();
+
+    write!(out, "{{\"type\":\"FeatureCollection\",\"features\":[")?;
+    write_rows(out, indices, |&idx| {
+        let mut feature = format!(
+            "{}{{\"type\":\"Feature\",\"geometry\":{{\"type\":\"Point\",\"coordinates\":[{},{}]}},\"properties\":{{",
+            if idx == indices[0] { "" } else { "," },
+            points[idx].0[0],
+            points[idx].0[1]
+        );
+        for (name, cell) in names.iter().zip(&records[rows[idx]]) {
+            feature.push_str(&format!(
+                "{}:{},",
+                name,
+                serde_json::Value::from(cell.as_str())
+            ));
+        }
+        feature.push_str(&format!(
+            "\"cluster\":{}}}}}",
+            labels.format.json(labels.ids[idx])
+        ));
+        feature
+    })?;
+    writeln!(out, "]}}")
+}
+
+/// Writes the given points as JSON Lines with their cluster labels
+///
+/// # Arguments
+///
+/// * `out` - Output to write to
+/// * `indices` - Indices of points to write
+/// * `points` - All points
+/// * `labels` - Cluster label of every point, `-1` for noise
+/// * `format` - Rendering of the labels
+/// * `digits` - Decimal places of coordinates, `None` for full precision
+pub fn write_jsonl(
+    out: &mut impl Write,
+    indices: &[usize],
+    points: &PointList,
+    labels: &[i32],
+    format: &LabelFormat,
+    digits: Option<u32>,
+) -> io::Result<()> {
+    write_rows(out, indices, |&idx| {
+        jsonl_row(
+            round_coord(points[idx].0[1], digits),
+            round_coord(points[idx].0[0], digits),
+            labels[idx],
+            format,
+        )
+    })
+}